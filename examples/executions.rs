@@ -2,15 +2,16 @@ use ibapi::orders::ExecutionFilter;
 use ibapi::Client;
 
 fn main() -> anyhow::Result<()> {
-    let mut filter = ExecutionFilter::default();
-
-    filter.client_id = Some(32);
-    // filter.account_code = account_code.to_owned();
-    // filter.time = time.to_owned();
-    // filter.symbol = symbol.to_owned();
-    // filter.security_type = security_type.to_owned();
-    // filter.exchange = exchange.to_owned();
-    // filter.side = side.to_owned();
+    let filter = ExecutionFilter {
+        client_id: Some(32),
+        // account_code: account_code.to_owned(),
+        // time: time.to_owned(),
+        // symbol: symbol.to_owned(),
+        // security_type: security_type.to_owned(),
+        // exchange: exchange.to_owned(),
+        // side: side.to_owned(),
+        ..Default::default()
+    };
 
     let client = Client::connect("127.0.0.1:4002", 100)?;
 
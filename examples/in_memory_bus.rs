@@ -0,0 +1,81 @@
+//! Runs the client over an in-memory MessageBus answering with canned responses, without TWS.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crossbeam::channel::{self, Receiver, Sender};
+use ibapi::client::{GlobalResponseIterator, MessageBus, ResponseIterator};
+use ibapi::messages::{OutgoingMessages, RequestMessage, ResponseMessage};
+use ibapi::{Client, Error};
+
+struct InMemoryBus {
+    // replies read before messages are processed
+    replies: VecDeque<ResponseMessage>,
+    current_time: (Sender<ResponseMessage>, Arc<Receiver<ResponseMessage>>),
+}
+
+impl InMemoryBus {
+    fn new() -> Self {
+        let (sender, receiver) = channel::unbounded();
+        InMemoryBus {
+            replies: VecDeque::new(),
+            current_time: (sender, Arc::new(receiver)),
+        }
+    }
+
+    fn reply(&mut self, data: &str) {
+        self.replies.push_back(ResponseMessage::from_string(data.to_owned()));
+    }
+}
+
+impl MessageBus for InMemoryBus {
+    fn read_message(&mut self) -> Result<ResponseMessage, Error> {
+        self.replies.pop_front().ok_or(Error::Shutdown)
+    }
+
+    fn write_message(&mut self, _packet: &RequestMessage) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn send_generic_message(&mut self, _request_id: i32, _packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+
+    fn send_durable_message(&mut self, _request_id: i32, _packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+
+    fn send_order_message(&mut self, _request_id: i32, _packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+
+    fn request_current_time(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        assert_eq!(message[0], (OutgoingMessages::RequestCurrentTime as i32).to_string());
+
+        let (sender, receiver) = &self.current_time;
+        sender.send(ResponseMessage::from_string("49\x001\x001678740829\x00".into())).unwrap();
+        Ok(GlobalResponseIterator::new(Arc::clone(receiver)))
+    }
+
+    // answers the handshake with the server version and connection time
+    fn write(&mut self, _packet: &str) -> Result<(), Error> {
+        self.reply("165\x0020230405 22:20:39 PST\x00");
+        Ok(())
+    }
+
+    // answers with the next valid order id and managed accounts
+    fn start_api(&mut self, _message: &RequestMessage) -> Result<(), Error> {
+        self.reply("9\x001\x0090\x00");
+        self.reply("15\x001\x00DU1234567\x00");
+        Ok(())
+    }
+}
+
+fn main() {
+    let client = Client::with_message_bus(Box::new(InMemoryBus::new()), 100).expect("connection failed");
+
+    println!("server_version: {}", client.server_version());
+    println!("managed_accounts: {}", client.managed_accounts());
+    println!("next_order_id: {}", client.next_order_id());
+    println!("server_time: {:?}", client.server_time().expect("request failed"));
+}
@@ -28,7 +28,7 @@ fn main() {
     }
 
     if let Some(symbol) = matches.get_one::<String>("all_last") {
-        stream_all_last(&mut client, &symbol.to_uppercase()).unwrap();
+        stream_all_last(&client, &symbol.to_uppercase()).unwrap();
     }
 
     if let Some(symbol) = matches.get_one::<String>("bid_ask") {
@@ -43,7 +43,7 @@ fn main() {
 }
 
 fn stream_last(client: &mut Client, symbol: &str) -> anyhow::Result<()> {
-    let contract = contract_gc();
+    let contract = contract(symbol);
     let ticks = client.tick_by_tick_last(&contract, 0, false)?;
 
     for (i, tick) in ticks.enumerate() {
@@ -53,6 +53,16 @@ fn stream_last(client: &mut Client, symbol: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Futures of the symbols with a sample contract, otherwise the stock.
+fn contract(symbol: &str) -> Contract {
+    match symbol {
+        "ES" => contract_es(),
+        "GC" => contract_gc(),
+        "ZN" => contract_zn(),
+        _ => Contract::stock(symbol),
+    }
+}
+
 fn contract_es() -> Contract {
     let mut contract = Contract::futures("ES");
     contract.local_symbol = "ESU3".to_string();
@@ -77,7 +87,7 @@ fn contract_zn() -> Contract {
 }
 
 fn stream_all_last(client: &Client, symbol: &str) -> anyhow::Result<()> {
-    let contract = contract_es();
+    let contract = contract(symbol);
     let ticks = client.tick_by_tick_all_last(&contract, 0, false)?;
 
     for (i, tick) in ticks.enumerate().take(60) {
@@ -88,7 +98,7 @@ fn stream_all_last(client: &Client, symbol: &str) -> anyhow::Result<()> {
 }
 
 fn stream_bid_ask(client: &mut Client, symbol: &str) -> anyhow::Result<()> {
-    let contract = contract_es();
+    let contract = contract(symbol);
     let ticks = client.tick_by_tick_bid_ask(&contract, 0, false)?;
 
     for (i, tick) in ticks.enumerate() {
@@ -99,7 +109,7 @@ fn stream_bid_ask(client: &mut Client, symbol: &str) -> anyhow::Result<()> {
 }
 
 fn stream_mid_point(client: &mut Client, symbol: &str) -> anyhow::Result<()> {
-    let contract = contract_es();
+    let contract = contract(symbol);
     let ticks = client.tick_by_tick_midpoint(&contract, 0, false)?;
 
     for (i, tick) in ticks.enumerate().take(60) {
@@ -1,12 +1,16 @@
+use std::collections::{HashMap, HashSet};
+
 use log::error;
 
 use crate::client::transport::GlobalResponseIterator;
 use crate::contracts::Contract;
 use crate::messages::IncomingMessages;
-use crate::{server_versions, Client, Error};
+use crate::{server_versions, Client, Error, ToField};
 
 mod decoders;
-mod encoders;
+pub(crate) mod encoders;
+#[cfg(test)]
+mod tests;
 
 #[derive(Debug, Default)]
 pub struct Position {
@@ -20,6 +24,20 @@ pub struct Position {
     pub average_cost: f64,
 }
 
+/// Position in a contract netted across all accessible accounts.
+#[derive(Debug, Default)]
+pub struct NetPosition {
+    /// Contract
+    pub contract: Contract,
+    /// Sum of the position sizes across accounts. Long and short positions offset each other.
+    pub position: f64,
+    /// Average cost of the net position, weighted by the size held in each account. None for flat positions, and when some accounts
+    /// are long and others short, as the cost of a position netting both sides has no meaning.
+    pub average_cost: Option<f64>,
+    /// Accounts holding the contract
+    pub accounts: Vec<String>,
+}
+
 #[derive(Debug, Default)]
 pub struct FamilyCode {
     /// Account ID
@@ -28,16 +46,95 @@ pub struct FamilyCode {
     pub family_code: String,
 }
 
+/// Financial Advisor configuration data types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaDataType {
+    /// Account groups.
+    Groups = 1,
+    /// Allocation profiles.
+    Profiles = 2,
+    /// Account aliases.
+    Aliases = 3,
+}
+
+impl ToField for FaDataType {
+    fn to_field(&self) -> String {
+        (*self as i32).to_string()
+    }
+}
+
+// Acknowledgement of a completed FA configuration replacement.
+#[derive(Debug, Default)]
+pub(crate) struct ReplaceFaEnd {
+    pub request_id: i32,
+    pub text: String,
+}
+
 // Subscribes to position updates for all accessible accounts.
 // All positions sent initially, and then only updates as positions change.
 pub(crate) fn positions(client: &Client) -> Result<impl Iterator<Item = Position> + '_, Error> {
+    request_positions(client)
+}
+
+fn request_positions(client: &Client) -> Result<PositionIterator<'_>, Error> {
     client.check_server_version(server_versions::ACCOUNT_SUMMARY, "It does not support position requests.")?;
 
     let message = encoders::request_positions()?;
 
     let messages = client.request_positions(message)?;
 
-    Ok(PositionIterator { client, messages })
+    Ok(PositionIterator {
+        client,
+        messages,
+        ended: false,
+    })
+}
+
+// Aggregates the initial position snapshot into net positions keyed by contract id.
+pub(crate) fn net_positions(client: &Client, include_flat: bool) -> Result<HashMap<i32, NetPosition>, Error> {
+    let mut positions = request_positions(client)?;
+
+    let mut net_positions: HashMap<i32, NetPosition> = HashMap::new();
+    let mut total_costs: HashMap<i32, f64> = HashMap::new();
+    // contracts held long, and held short, in some account
+    let mut long: HashSet<i32> = HashSet::new();
+    let mut short: HashSet<i32> = HashSet::new();
+
+    for position in positions.by_ref() {
+        let contract_id = position.contract.contract_id;
+
+        let net_position = net_positions.entry(contract_id).or_insert_with(|| NetPosition {
+            contract: position.contract.clone(),
+            ..Default::default()
+        });
+        net_position.position += position.position;
+        net_position.accounts.push(position.account);
+
+        *total_costs.entry(contract_id).or_default() += position.position * position.average_cost;
+        if position.position > 0.0 {
+            long.insert(contract_id);
+        } else if position.position < 0.0 {
+            short.insert(contract_id);
+        }
+    }
+
+    // Without the end marker the snapshot may be missing positions.
+    if !positions.ended {
+        return Err(Error::Timeout);
+    }
+
+    for (contract_id, net_position) in net_positions.iter_mut() {
+        let mixed = long.contains(contract_id) && short.contains(contract_id);
+        if net_position.position != 0.0 && !mixed {
+            net_position.average_cost = Some(total_costs[contract_id] / net_position.position);
+        }
+    }
+
+    if !include_flat {
+        net_positions.retain(|_, net_position| net_position.position != 0.0);
+    }
+
+    Ok(net_positions)
 }
 
 pub(crate) fn cancel_positions(client: &Client) -> Result<(), Error> {
@@ -64,10 +161,41 @@ pub(crate) fn family_codes(client: &Client) -> Result<Vec<FamilyCode>, Error> {
         Ok(Vec::default())
     }
 }
+
+// Replaces the Financial Advisor configuration and waits for the server to acknowledge the change.
+pub(crate) fn replace_fa(client: &Client, fa_data_type: FaDataType, xml: &str) -> Result<String, Error> {
+    client.check_server_version(server_versions::REPLACE_FA_END, "It does not support replace FA end.")?;
+
+    let request_id = client.next_request_id();
+    let message = encoders::encode_replace_fa(client.server_version(), request_id, fa_data_type, xml)?;
+
+    let mut messages = client.send_request(request_id, message)?;
+
+    if let Some(mut message) = messages.next_response()? {
+        match message.message_type() {
+            IncomingMessages::ReplaceFAEnd => {
+                let replace_fa_end = decoders::decode_replace_fa_end(&mut message)?;
+                if replace_fa_end.request_id != request_id {
+                    error!(
+                        "replace FA end for request {} received for request {request_id}",
+                        replace_fa_end.request_id
+                    );
+                    return Err(Error::UnexpectedResponse(IncomingMessages::ReplaceFAEnd));
+                }
+                Ok(replace_fa_end.text)
+            }
+            message => Err(Error::UnexpectedResponse(message)),
+        }
+    } else {
+        Err(Error::Disconnected)
+    }
+}
 // Supports iteration over [Position].
 pub(crate) struct PositionIterator<'a> {
     client: &'a Client,
     messages: GlobalResponseIterator,
+    // Set once PositionEnd marks the end of the initial snapshot.
+    ended: bool,
 }
 
 impl<'a> Iterator for PositionIterator<'a> {
@@ -85,6 +213,7 @@ impl<'a> Iterator for PositionIterator<'a> {
                         }
                     },
                     IncomingMessages::PositionEnd => {
+                        self.ended = true;
                         if let Err(e) = cancel_positions(self.client) {
                             error!("error cancelling positions: {e}")
                         }
@@ -2,7 +2,7 @@ use crate::contracts::SecurityType;
 use crate::messages::ResponseMessage;
 use crate::Error;
 
-use super::{FamilyCode, Position};
+use super::{FamilyCode, Position, ReplaceFaEnd};
 
 pub(crate) fn decode_position(message: &mut ResponseMessage) -> Result<Position, Error> {
     message.skip(); // message type
@@ -53,7 +53,6 @@ pub(crate) fn decode_family_codes(message: &mut ResponseMessage) -> Result<Vec<F
         let family_code = FamilyCode {
             account_id: message.next_string()?,
             family_code: message.next_string()?,
-            ..Default::default()
         };
         family_codes.push(family_code);
     }
@@ -61,11 +60,22 @@ pub(crate) fn decode_family_codes(message: &mut ResponseMessage) -> Result<Vec<F
     Ok(family_codes)
 }
 
+pub(crate) fn decode_replace_fa_end(message: &mut ResponseMessage) -> Result<ReplaceFaEnd, Error> {
+    message.skip(); // message type
+
+    Ok(ReplaceFaEnd {
+        request_id: message.next_int()?,
+        text: message.next_string()?,
+    })
+}
+
 mod tests {
 
     #[test]
     fn decode_positions() {
-        let mut message = super::ResponseMessage::from("61\03\0DU1236109\076792991\0TSLA\0STK\0\00.0\0\0\0NASDAQ\0USD\0TSLA\0NMS\0500\0196.77\0");
+        let mut message = super::ResponseMessage::from(
+            "61\x003\x00DU1236109\x0076792991\x00TSLA\x00STK\x00\x000.0\x00\x00\x00NASDAQ\x00USD\x00TSLA\x00NMS\x00500\x00196.77\x00",
+        );
 
         let results = super::decode_position(&mut message);
 
@@ -92,13 +102,13 @@ mod tests {
             assert_eq!(position.position, 500.0, "position.position");
             assert_eq!(position.average_cost, 196.77, "position.average_cost");
         } else if let Err(err) = results {
-            assert!(false, "error decoding position: {err}");
+            panic!("error decoding position: {err}");
         }
     }
 
     #[test]
     fn decode_family_codes() {
-        let mut message = super::ResponseMessage::from("78\01\0*\0\0");
+        let mut message = super::ResponseMessage::from("78\x001\x00*\x00\x00");
 
         let results = super::decode_family_codes(&mut message);
 
@@ -109,4 +119,18 @@ mod tests {
             panic!("Error decoding family_codes: {}", err);
         }
     }
+
+    #[test]
+    fn decode_replace_fa_end() {
+        let mut message = super::ResponseMessage::from("103\x009000\x00FA groups replaced\x00");
+
+        let results = super::decode_replace_fa_end(&mut message);
+
+        if let Ok(replace_fa_end) = results {
+            assert_eq!(replace_fa_end.request_id, 9000, "replace_fa_end.request_id");
+            assert_eq!(replace_fa_end.text, "FA groups replaced", "replace_fa_end.text");
+        } else if let Err(err) = results {
+            panic!("Error decoding replace_fa_end: {}", err);
+        }
+    }
 }
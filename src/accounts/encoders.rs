@@ -2,6 +2,8 @@ use crate::messages::OutgoingMessages;
 use crate::messages::RequestMessage;
 use crate::Error;
 
+use super::FaDataType;
+
 pub(crate) fn request_positions() -> Result<RequestMessage, Error> {
     encode_simple(OutgoingMessages::RequestPositions, 1)
 }
@@ -23,9 +25,20 @@ pub(crate) fn request_family_codes() -> Result<RequestMessage, Error> {
     encode_simple(OutgoingMessages::RequestFamilyCodes, 1)
 }
 
+pub(crate) fn encode_replace_fa(server_version: i32, request_id: i32, fa_data_type: FaDataType, xml: &str) -> Result<RequestMessage, Error> {
+    let mut message = RequestMessage::new();
+
+    message.push_message_header(OutgoingMessages::ReplaceFA, server_version);
+    message.push_field(&fa_data_type);
+    message.push_field(&xml);
+    message.push_field(&request_id);
+
+    Ok(message)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::ToField;
+    use crate::{server_versions, ToField};
 
     use super::*;
 
@@ -39,7 +52,7 @@ mod tests {
                 assert_eq!(message[1], "1", "message.version");
             }
             Err(err) => {
-                assert!(false, "error encoding request positions: {err}");
+                panic!("error encoding request positions: {err}");
             }
         }
     }
@@ -54,7 +67,7 @@ mod tests {
                 assert_eq!(message[1], "1", "message.version");
             }
             Err(err) => {
-                assert!(false, "error encoding cancel positions: {err}");
+                panic!("error encoding cancel positions: {err}");
             }
         }
     }
@@ -69,7 +82,25 @@ mod tests {
                 assert_eq!(message[1], "1", "message.version");
             }
             Err(err) => {
-                assert!(false, "error encoding request family codes: {err}");
+                panic!("error encoding request family codes: {err}");
+            }
+        }
+    }
+
+    #[test]
+    fn encode_replace_fa() {
+        let results = super::encode_replace_fa(server_versions::REPLACE_FA_END, 9000, FaDataType::Groups, "<xml/>");
+
+        match results {
+            Ok(message) => {
+                assert_eq!(message[0], OutgoingMessages::ReplaceFA.to_field(), "message.type");
+                assert_eq!(message[1], "1", "message.version");
+                assert_eq!(message[2], "1", "message.fa_data_type");
+                assert_eq!(message[3], "<xml/>", "message.xml");
+                assert_eq!(message[4], "9000", "message.request_id");
+            }
+            Err(err) => {
+                panic!("error encoding replace fa: {err}");
             }
         }
     }
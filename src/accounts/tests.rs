@@ -0,0 +1,157 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+use super::*;
+
+use crate::client::transport::MessageBus;
+use crate::messages::ResponseMessage;
+use crate::stubs::{MessageBusStub, RequestMessages};
+
+#[test]
+fn replace_fa() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["103|9000|FA groups replaced|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let results = client.replace_fa(FaDataType::Groups, "<ListOfGroups/>");
+
+    let request_messages = client.message_bus().request_messages();
+
+    assert_eq!(request_messages[0].encode_simple(), "19|1|1|<ListOfGroups/>|9000|");
+
+    assert!(results.is_ok(), "failed to replace fa: {:?}", results.err());
+    assert_eq!(results.unwrap(), "FA groups replaced");
+}
+
+#[test]
+fn replace_fa_request_id_mismatch() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["103|9001|FA groups replaced|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let results = client.replace_fa(FaDataType::Groups, "<ListOfGroups/>");
+
+    assert!(
+        matches!(results, Err(Error::UnexpectedResponse(IncomingMessages::ReplaceFAEnd))),
+        "expected replace fa to fail for another request: {results:?}"
+    );
+}
+
+#[test]
+fn replace_fa_error() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["4|2|9000|555|Invalid FA configuration|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let results = client.replace_fa(FaDataType::Groups, "<ListOfGroups/>");
+
+    assert!(results.is_err(), "expected replace fa to fail");
+    assert!(
+        matches!(results, Err(Error::TwsError { code: 555, ref message }) if message == "Invalid FA configuration"),
+        "{results:?}"
+    );
+}
+
+#[test]
+fn net_positions() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "61|3|DU1234567|76792991|TSLA|STK||0||||USD|TSLA|NMS|100|200|".to_owned(),
+            "61|3|DU7654321|76792991|TSLA|STK||0||||USD|TSLA|NMS|300|240|".to_owned(),
+            "61|3|DU1234567|265598|AAPL|STK||0||||USD|AAPL|NMS|50|150|".to_owned(),
+            "61|3|DU7654321|265598|AAPL|STK||0||||USD|AAPL|NMS|-50|160|".to_owned(),
+            "62|1|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let positions = client.net_positions(false).expect("failed to request net positions");
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0].encode_simple(), "61|1|");
+
+    assert_eq!(positions.len(), 1, "flat positions should be excluded");
+
+    let tesla = &positions[&76792991];
+    assert_eq!(tesla.contract.symbol, "TSLA");
+    assert_eq!(tesla.position, 400.0);
+    assert_eq!(tesla.average_cost, Some(230.0));
+    assert_eq!(tesla.accounts, vec!["DU1234567", "DU7654321"]);
+
+    let positions = client.net_positions(true).expect("failed to request net positions");
+
+    assert_eq!(positions.len(), 2, "flat positions should be included");
+
+    let apple = &positions[&265598];
+    assert_eq!(apple.position, 0.0);
+    assert_eq!(apple.average_cost, None);
+}
+
+#[test]
+fn net_positions_long_and_short() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "61|3|DU1234567|76792991|TSLA|STK||0||||USD|TSLA|NMS|100|10|".to_owned(),
+            "61|3|DU7654321|76792991|TSLA|STK||0||||USD|TSLA|NMS|-90|12|".to_owned(),
+            "61|3|DU1234567|265598|AAPL|STK||0||||USD|AAPL|NMS|-20|150|".to_owned(),
+            "61|3|DU7654321|265598|AAPL|STK||0||||USD|AAPL|NMS|-30|160|".to_owned(),
+            "62|1|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let positions = client.net_positions(false).expect("failed to request net positions");
+
+    let tesla = &positions[&76792991];
+    assert_eq!(tesla.position, 10.0);
+    assert_eq!(tesla.average_cost, None, "no average cost netting long and short positions");
+
+    let apple = &positions[&265598];
+    assert_eq!(apple.position, -50.0);
+    assert_eq!(apple.average_cost, Some(156.0), "short positions are averaged");
+}
+
+#[test]
+fn net_positions_without_position_end() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "61|3|DU1234567|76792991|TSLA|STK||0||||USD|TSLA|NMS|100|200|".to_owned(),
+            "61|3|DU7654321|76792991|TSLA|STK||0||||USD|TSLA|NMS|300|240|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let results = client.net_positions(false);
+
+    assert!(matches!(results, Err(Error::Timeout)), "{results:?}");
+}
+
+#[test]
+fn decode_position_with_trailing_fields() {
+    // newer servers may append fields to a message version this library knows
+    let mut message = ResponseMessage::from(
+        "61\x003\x00DU1234567\x0076792991\x00TSLA\x00STK\x00\x000\x00\x00\x00\x00USD\x00TSLA\x00NMS\x00100\x00200\x00new\x00fields\x00",
+    );
+
+    let position = decoders::decode_position(&mut message).expect("failed to decode position");
+
+    assert_eq!(position.contract.symbol, "TSLA");
+    assert_eq!(position.position, 100.0);
+    assert_eq!(position.average_cost, 200.0);
+    assert_eq!(message.unparsed_fields(), vec!["new", "fields"]);
+}
@@ -0,0 +1,364 @@
+//! Requests are futures and subscriptions are streams, so no thread is blocked while waiting for TWS. The client must be
+//! connected and used from within a tokio runtime.
+//!
+//! ```no_run
+//! use ibapi::async_client::Client;
+//! use ibapi::contracts::Contract;
+//!
+//! # async fn run() {
+//! let client = Client::connect("127.0.0.1:4002", 100).await.expect("connection failed");
+//!
+//! let server_time = client.server_time().await.expect("request failed");
+//! println!("server time: {server_time}");
+//!
+//! let contract_details = client.contract_details(&Contract::stock("TSLA")).await.expect("request failed");
+//! println!("contract details: {contract_details:?}");
+//! # }
+//! ```
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{error, info};
+use time::OffsetDateTime;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::client::transport::DEFAULT_REQUEST_TIMEOUT;
+use crate::client::{check_server_version, decode_current_time, encode_handshake, parse_connection_time, startup_timed_out, DEFAULT_STARTUP_TIMEOUT};
+use crate::contracts::{self, Contract, ContractDescription, ContractDetails};
+#[cfg(feature = "market_data")]
+use crate::market_data::realtime::{self, Bar, BarSize, WhatToShow};
+#[cfg(feature = "market_data")]
+use crate::messages::ResponseMessage;
+use crate::messages::{IncomingMessages, RequestMessage};
+use crate::{server_versions, Error};
+
+use transport::{read_message, write_message, Connection};
+pub use transport::{ResponseStream, Subscription};
+
+mod transport;
+
+#[cfg(test)]
+mod tests;
+
+/// An async client for TWS or the Gateway. See the [module](self) documentation.
+pub struct Client {
+    server_version: i32,
+    client_id: i32,
+    connection_time: Option<OffsetDateTime>,
+    managed_accounts: String,
+    next_request_id: AtomicI32,
+    order_id: Arc<AtomicI32>,
+    request_timeout: Mutex<Duration>,
+    connection: Connection,
+}
+
+impl Client {
+    /// Connects to TWS or the Gateway at address, and starts the API with client_id.
+    ///
+    /// # Arguments
+    /// * `address`   - address of server. e.g. 127.0.0.1:4002
+    /// * `client_id` - id of client. e.g. 100
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::async_client::Client;
+    ///
+    /// # async fn run() {
+    /// let client = Client::connect("127.0.0.1:4002", 100).await.expect("connection failed");
+    ///
+    /// println!("server_version: {}", client.server_version());
+    /// println!("managed_accounts: {}", client.managed_accounts());
+    /// println!("next_order_id: {}", client.next_order_id());
+    /// # }
+    /// ```
+    pub async fn connect(address: &str, client_id: i32) -> Result<Client, Error> {
+        let (reader, mut writer) = TcpStream::connect(address).await?.into_split();
+        let mut reader = BufReader::new(reader);
+
+        // handshake
+        writer.write_all(encode_handshake().as_bytes()).await?;
+        let mut ack = match read_message(&mut reader).await {
+            Ok(ack) => ack,
+            Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Err(Error::ConnectionFailed(format!(
+                    "the server may be rejecting connections from this host: {err}"
+                )));
+            }
+            Err(err) => return Err(err),
+        };
+        let server_version = ack.next_int()?;
+        let (connection_time, _) = parse_connection_time(ack.next_string()?.as_str());
+
+        write_message(&mut writer, &RequestMessage::start_api(server_version, client_id, "")).await?;
+
+        let (order_id, managed_accounts) = match tokio::time::timeout(DEFAULT_STARTUP_TIMEOUT, receive_account_info(&mut reader)).await {
+            Ok(account_info) => account_info?,
+            Err(_) => return Err(startup_timed_out(DEFAULT_STARTUP_TIMEOUT)),
+        };
+
+        let order_id = Arc::new(AtomicI32::new(order_id));
+        let connection = Connection::start(reader, writer, Arc::clone(&order_id));
+
+        Ok(Client {
+            server_version,
+            client_id,
+            connection_time,
+            managed_accounts,
+            next_request_id: AtomicI32::new(9000),
+            order_id,
+            request_timeout: Mutex::new(DEFAULT_REQUEST_TIMEOUT),
+            connection,
+        })
+    }
+
+    /// Returns the server version negotiated when connecting.
+    pub fn server_version(&self) -> i32 {
+        self.server_version
+    }
+
+    /// The time of the server when the client connected
+    pub fn connection_time(&self) -> Option<OffsetDateTime> {
+        self.connection_time
+    }
+
+    /// Returns the managed accounts.
+    pub fn managed_accounts(&self) -> String {
+        self.managed_accounts.to_owned()
+    }
+
+    /// Returns the next request ID. Each ID is returned once.
+    pub fn next_request_id(&self) -> i32 {
+        self.next_request_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Returns and increments the order ID. Each ID is returned once.
+    ///
+    /// Starts at the next valid order id received on connection, and is raised to the id of each next valid id message received afterwards.
+    pub fn next_order_id(&self) -> i32 {
+        self.order_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Sets how long requests wait for each response. Subscriptions wait indefinitely.
+    pub fn set_request_timeout(&self, request_timeout: Duration) {
+        *self.request_timeout.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = request_timeout;
+    }
+
+    /// Returns how long requests wait for each response.
+    pub fn request_timeout(&self) -> Duration {
+        *self.request_timeout.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Sends a request, returning the stream of its responses. Responses are routed by the request id, see [Client::next_request_id].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::async_client::Client;
+    /// use ibapi::messages::RequestMessage;
+    ///
+    /// # async fn run() {
+    /// let client = Client::connect("127.0.0.1:4002", 100).await.expect("connection failed");
+    ///
+    /// let request_id = client.next_request_id();
+    /// let message = RequestMessage::matching_symbols(request_id, "TSLA").expect("encoding failed");
+    ///
+    /// let mut responses = client.send_request(request_id, message).expect("request failed");
+    /// println!("response: {:?}", responses.next_response().await);
+    /// # }
+    /// ```
+    pub fn send_request(&self, request_id: i32, message: RequestMessage) -> Result<ResponseStream, Error> {
+        self.connection.request(request_id, &message, Some(self.request_timeout()))
+    }
+
+    /// Sends a message without waiting for responses, e.g. to cancel a subscription.
+    pub fn send_message(&self, message: RequestMessage) -> Result<(), Error> {
+        self.connection.send(&message)
+    }
+
+    /// Requests the current server time.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::async_client::Client;
+    ///
+    /// # async fn run() {
+    /// let client = Client::connect("127.0.0.1:4002", 100).await.expect("connection failed");
+    ///
+    /// let server_time = client.server_time().await.expect("request failed");
+    /// println!("server time: {server_time}");
+    /// # }
+    /// ```
+    pub async fn server_time(&self) -> Result<OffsetDateTime, Error> {
+        let message = RequestMessage::current_time(self.server_version);
+
+        let mut response = self.connection.request_current_time(&message, self.request_timeout()).await?;
+        decode_current_time(&mut response)
+    }
+
+    /// Requests contract information. See [crate::Client::contract_details].
+    ///
+    /// # Arguments
+    /// * `contract` - The [Contract] used as sample to query the available contracts. Typically, it will contain the [Contract]'s symbol, currency, security_type, and exchange.
+    pub async fn contract_details(&self, contract: &Contract) -> Result<Vec<ContractDetails>, Error> {
+        contracts::verify_contract(self.server_version, contract)?;
+
+        let request_id = self.next_request_id();
+        let message = contracts::encoders::request_contract_data(self.server_version, request_id, contract)?;
+
+        let mut responses = self.send_request(request_id, message)?;
+
+        let mut contract_details = Vec::default();
+        while let Some(mut message) = responses.next_response().await? {
+            match message.message_type() {
+                IncomingMessages::ContractData => contract_details.push(contracts::decoders::contract_details(self.server_version, &mut message)?),
+                IncomingMessages::ContractDataEnd => return Ok(contract_details),
+                _ => error!("unexpected message: {message:?}"),
+            }
+        }
+
+        // the stream ended before the end marker, the contract details are incomplete
+        Err(Error::Disconnected)
+    }
+
+    /// Requests stock symbols matching pattern. See [crate::Client::matching_symbols].
+    ///
+    /// # Arguments
+    /// * `pattern` - Either start of ticker symbol or (for larger strings) company name.
+    pub async fn matching_symbols(&self, pattern: &str) -> Result<Vec<ContractDescription>, Error> {
+        check_server_version(
+            self.server_version,
+            server_versions::REQ_MATCHING_SYMBOLS,
+            "It does not support mathing symbols requests.",
+        )?;
+
+        let request_id = self.next_request_id();
+        let message = contracts::encoders::request_matching_symbols(request_id, pattern)?;
+
+        let mut responses = self.send_request(request_id, message)?;
+
+        match responses.next_response().await? {
+            Some(mut message) if message.message_type() == IncomingMessages::SymbolSamples => {
+                contracts::decoders::contract_descriptions(self.server_version, &mut message)
+            }
+            Some(message) => {
+                info!("unexpected message: {message:?}");
+                Err(Error::UnexpectedResponse(message.message_type()))
+            }
+            None => Err(Error::Disconnected),
+        }
+    }
+
+    /// Requests realtime bars, as a stream cancelled once dropped. See [crate::Client::realtime_bars].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::async_client::Client;
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::market_data::realtime::{BarSize, WhatToShow};
+    ///
+    /// # async fn run() {
+    /// let client = Client::connect("127.0.0.1:4002", 100).await.expect("connection failed");
+    ///
+    /// let contract = Contract::stock("TSLA");
+    /// let mut bars = client.realtime_bars(&contract, BarSize::Sec5, WhatToShow::Trades, false).await.expect("request failed");
+    ///
+    /// while let Some(bar) = bars.next().await {
+    ///     println!("bar: {:?}", bar.expect("subscription failed"));
+    /// }
+    /// # }
+    /// ```
+    #[cfg(feature = "market_data")]
+    pub async fn realtime_bars(
+        &self,
+        contract: &Contract,
+        bar_size: BarSize,
+        what_to_show: WhatToShow,
+        use_rth: bool,
+    ) -> Result<Subscription<Bar>, Error> {
+        check_server_version(
+            self.server_version,
+            server_versions::REAL_TIME_BARS,
+            "It does not support real time bars.",
+        )?;
+
+        if !contract.trading_class.is_empty() || contract.contract_id > 0 {
+            check_server_version(
+                self.server_version,
+                server_versions::TRADING_CLASS,
+                "It does not support ConId nor TradingClass parameters in reqRealTimeBars.",
+            )?;
+        }
+
+        let request_id = self.next_request_id();
+        let message = realtime::encoders::encode_request_realtime_bars(
+            self.server_version,
+            request_id,
+            contract,
+            &bar_size,
+            &what_to_show,
+            use_rth,
+            Vec::default(),
+        )?;
+
+        let mut responses = self.connection.request(request_id, &message, None)?;
+        responses.set_cancel_message(realtime::encoders::cancel_realtime_bars(request_id)?);
+
+        Ok(Subscription::new(responses, self.server_version, decode_realtime_bar))
+    }
+}
+
+impl Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("server_version", &self.server_version)
+            .field("server_time", &self.connection_time)
+            .field("client_id", &self.client_id)
+            .finish()
+    }
+}
+
+// Reads the next order id and managed accounts sent by the server after the api is started.
+async fn receive_account_info(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>) -> Result<(i32, String), Error> {
+    let mut order_id = None;
+    let mut managed_accounts = None;
+
+    while order_id.is_none() || managed_accounts.is_none() {
+        let mut message = read_message(reader).await?;
+
+        match message.message_type() {
+            IncomingMessages::NextValidId => {
+                message.skip(); // message type
+                message.skip(); // message version
+
+                order_id = Some(message.next_int()?);
+            }
+            IncomingMessages::ManagedAccounts => {
+                message.skip(); // message type
+                message.skip(); // message version
+
+                managed_accounts = Some(message.next_string()?);
+            }
+            IncomingMessages::Error => {
+                error!("message: {message:?}")
+            }
+            _ => info!("message: {message:?}"),
+        }
+    }
+
+    Ok((order_id.unwrap_or_default(), managed_accounts.unwrap_or_default()))
+}
+
+#[cfg(feature = "market_data")]
+fn decode_realtime_bar(_server_version: i32, message: &mut ResponseMessage) -> Result<Bar, Error> {
+    match message.message_type() {
+        IncomingMessages::RealTimeBars => realtime::decoders::decode_realtime_bar(message),
+        message_type => Err(Error::UnexpectedResponse(message_type)),
+    }
+}
@@ -0,0 +1,220 @@
+use std::future::Future;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use time::macros::datetime;
+
+use super::*;
+use crate::messages::ResponseMessage;
+use crate::stubs::fake_gateway;
+
+// Runs future on a runtime of the test thread, as the tests use blocking fake gateways.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[test]
+fn connect_and_request_server_time() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["176|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // current time
+        &["49|1|1678740829|"],
+    ]);
+
+    block_on(async {
+        let client = Client::connect(&gateway.address, 7).await.unwrap();
+
+        assert_eq!(client.server_version(), 176);
+        assert_eq!(client.managed_accounts(), "DU1234567");
+        assert_eq!(client.next_order_id(), 90);
+        assert_eq!(client.server_time().await.unwrap(), datetime!(2023-03-13 20:53:49 UTC));
+    });
+
+    let requests = gateway.join();
+    assert_eq!(requests[1], "71|2|7||", "start api");
+    assert_eq!(requests[2], "49|1|", "current time");
+}
+
+#[test]
+fn responses_routed_by_request_id() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["176|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // matching symbols for TSLA, answered after AAPL
+        &[],
+        // matching symbols for AAPL
+        &[
+            "79|9001|1|265598|AAPL|STK|NASDAQ|USD|0|Apple Inc|e1|",
+            "79|9000|1|76792991|TSLA|STK|NASDAQ|USD|0|Tesla Inc|e1|",
+        ],
+    ]);
+
+    block_on(async {
+        let client = Client::connect(&gateway.address, 7).await.unwrap();
+
+        let mut tesla = client
+            .send_request(9000, RequestMessage::matching_symbols(9000, "TSLA").unwrap())
+            .unwrap();
+        let mut apple = client
+            .send_request(9001, RequestMessage::matching_symbols(9001, "AAPL").unwrap())
+            .unwrap();
+
+        let decode = |message: Option<ResponseMessage>| contracts::decoders::contract_descriptions(176, &mut message.unwrap()).unwrap();
+        let apple = decode(apple.next_response().await.unwrap());
+        let tesla = decode(tesla.next_response().await.unwrap());
+
+        assert_eq!(tesla[0].contract.symbol, "TSLA");
+        assert_eq!(apple[0].contract.symbol, "AAPL");
+    });
+}
+
+#[test]
+fn tws_errors_fail_requests() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["176|20230405 22:20:39 PST|"],
+        // start api, then a data farm warning
+        &["9|1|90|", "15|1|DU1234567|", "4|2|-1|2104|Market data farm connection is OK:usfarm|"],
+        // contract details
+        &["4|2|9000|200|No security definition has been found for the request|"],
+    ]);
+
+    block_on(async {
+        let client = Client::connect(&gateway.address, 7).await.unwrap();
+
+        let result = client.contract_details(&Contract::stock("XYZ")).await;
+        assert!(
+            matches!(result, Err(Error::TwsError { code: 200, .. })),
+            "expected no security definition error, got {result:?}"
+        );
+    });
+}
+
+#[test]
+fn requests_time_out() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["176|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+    ]);
+
+    block_on(async {
+        let client = Client::connect(&gateway.address, 7).await.unwrap();
+        client.set_request_timeout(Duration::from_millis(50));
+
+        assert!(matches!(client.matching_symbols("TSLA").await, Err(Error::Timeout)));
+        assert!(matches!(client.server_time().await, Err(Error::Timeout)));
+    });
+}
+
+#[test]
+#[cfg(feature = "market_data")]
+fn realtime_bars_cancelled_once_dropped() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["176|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // realtime bars
+        &[
+            "50|3|9000|1678323335|4028.75|4029.00|4028.25|4028.50|2|4026.75|1|",
+            "50|3|9000|1678323340|4028.50|4028.75|4028.25|4028.75|3|4028.60|2|",
+        ],
+    ]);
+
+    block_on(async {
+        let client = Client::connect(&gateway.address, 7).await.unwrap();
+
+        let mut bars = client
+            .realtime_bars(&Contract::stock("AAPL"), BarSize::Sec5, WhatToShow::Trades, false)
+            .await
+            .unwrap();
+
+        assert_eq!(bars.next().await.unwrap().unwrap().close, 4028.50);
+        assert_eq!(bars.next().await.unwrap().unwrap().close, 4028.75);
+        drop(bars);
+
+        // the cancel message is written by the writer task
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    });
+
+    let requests = gateway.join();
+    assert_eq!(requests.last().unwrap(), "51|1|9000|", "cancel realtime bars");
+}
+
+#[test]
+fn stale_current_time_answers_are_discarded() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["176|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // current time, not answered in time
+        &[],
+        // matching symbols, with the late current time answer
+        &["49|1|1000|", "79|9000|1|76792991|TSLA|STK|NASDAQ|USD|0|Tesla Inc|e1|"],
+        // current time
+        &["49|1|1678740829|"],
+    ]);
+
+    block_on(async {
+        let client = Client::connect(&gateway.address, 7).await.unwrap();
+        client.set_request_timeout(Duration::from_millis(50));
+
+        assert!(matches!(client.server_time().await, Err(Error::Timeout)));
+        assert_eq!(client.matching_symbols("TSLA").await.unwrap()[0].contract.symbol, "TSLA");
+
+        client.set_request_timeout(Duration::from_secs(5));
+        assert_eq!(client.server_time().await.unwrap(), datetime!(2023-03-13 20:53:49 UTC));
+    });
+}
+
+#[test]
+fn contract_details_fail_once_disconnected() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let mut prefix = [0_u8; 4];
+        stream.read_exact(&mut prefix).unwrap();
+        assert_eq!(&prefix, b"API\0", "handshake prefix");
+
+        // handshake, start api, then the connection drops after the contract details request
+        for responses in [&["176|20230405 22:20:39 PST|"][..], &["9|1|90|", "15|1|DU1234567|"], &[]] {
+            let size = stream.read_u32::<BigEndian>().unwrap();
+            let mut request = vec![0_u8; size as usize];
+            stream.read_exact(&mut request).unwrap();
+
+            for response in responses {
+                let response = response.replace('|', "\0");
+                stream.write_u32::<BigEndian>(response.len() as u32).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        }
+    });
+
+    block_on(async {
+        let client = Client::connect(&address, 7).await.unwrap();
+
+        let result = client.contract_details(&Contract::stock("TSLA")).await;
+        assert!(matches!(result, Err(Error::Disconnected)), "expected disconnected error, got {result:?}");
+    });
+
+    server.join().unwrap();
+}
@@ -0,0 +1,348 @@
+//! The connection of the async client.
+//!
+//! A reader task decodes messages and routes them by request id to the channel of the request, like the reader thread of
+//! the synchronous transport. Messages are written by a writer task, so streams can send their cancel message once dropped.
+
+use std::collections::HashMap;
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant, Sleep};
+
+use crate::client::transport::{write_frame, MAX_MESSAGE_LEN};
+use crate::messages::{IncomingMessages, RequestMessage, ResponseMessage};
+use crate::Error;
+
+// Senders of the responses of pending requests, by request id.
+type Requests = Mutex<HashMap<i32, UnboundedSender<ResponseMessage>>>;
+
+#[derive(Debug)]
+pub(super) struct Connection {
+    writer: UnboundedSender<Vec<u8>>,
+    requests: Arc<Requests>,
+    current_time: tokio::sync::Mutex<UnboundedReceiver<ResponseMessage>>,
+    reader: JoinHandle<()>,
+}
+
+impl Connection {
+    // Starts the reader and writer tasks of a connection whose API was started. Next valid ids received raise next_order_id.
+    pub(super) fn start(reader: BufReader<OwnedReadHalf>, writer: OwnedWriteHalf, next_order_id: Arc<AtomicI32>) -> Connection {
+        let requests = Arc::new(Requests::default());
+        let (current_time_in, current_time) = mpsc::unbounded_channel();
+        let (packets, packets_out) = mpsc::unbounded_channel();
+
+        let reader = tokio::spawn(read_messages(reader, Arc::clone(&requests), current_time_in, next_order_id));
+        tokio::spawn(write_packets(writer, packets_out));
+
+        Connection {
+            writer: packets,
+            requests,
+            current_time: tokio::sync::Mutex::new(current_time),
+            reader,
+        }
+    }
+
+    // Sends a message without waiting for responses.
+    pub(super) fn send(&self, message: &RequestMessage) -> Result<(), Error> {
+        send(&self.writer, message)
+    }
+
+    // Sends a request, routing its responses to the stream returned. Without a timeout, the stream waits for responses indefinitely.
+    pub(super) fn request(&self, request_id: i32, message: &RequestMessage, timeout: Option<Duration>) -> Result<ResponseStream, Error> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.requests.lock().unwrap().insert(request_id, sender);
+
+        let responses = ResponseStream {
+            messages: receiver,
+            request_id,
+            requests: Arc::clone(&self.requests),
+            writer: self.writer.clone(),
+            cancel_message: None,
+            timeout,
+            deadline: timeout.map(|timeout| Box::pin(time::sleep(timeout))),
+        };
+
+        // a failed request is released as its stream is dropped
+        self.send(message)?;
+
+        Ok(responses)
+    }
+
+    // Sends a request answered by a current time message, and waits up to timeout for the answer.
+    pub(super) async fn request_current_time(&self, message: &RequestMessage, timeout: Duration) -> Result<ResponseMessage, Error> {
+        // one request at a time, as answers don't carry a request id
+        let mut current_time = self.current_time.lock().await;
+
+        // discards answers to earlier requests that timed out
+        while current_time.try_recv().is_ok() {}
+
+        self.send(message)?;
+
+        match time::timeout(timeout, current_time.recv()).await {
+            Ok(Some(message)) => Ok(message),
+            Ok(None) => Err(Error::Disconnected),
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+}
+
+impl Drop for Connection {
+    // Stops reading. The writer task ends once the streams holding it are dropped.
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Responses to a request sent with [Client::send_request](super::Client::send_request).
+///
+/// The stream ends once the connection is closed. Error messages from TWS about the request are returned as [Error::TwsError],
+/// and [Error::Timeout] is returned when no response arrives within the request timeout. Warnings, see [Error::is_warning], are logged and skipped.
+#[derive(Debug)]
+pub struct ResponseStream {
+    messages: UnboundedReceiver<ResponseMessage>,
+    request_id: i32,
+    requests: Arc<Requests>,
+    writer: UnboundedSender<Vec<u8>>,
+    cancel_message: Option<RequestMessage>,
+    timeout: Option<Duration>,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl ResponseStream {
+    /// Returns the next message. Returns None once the connection is closed.
+    pub async fn next_response(&mut self) -> Result<Option<ResponseMessage>, Error> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await.transpose()
+    }
+
+    /// Returns the id of the request.
+    pub fn request_id(&self) -> i32 {
+        self.request_id
+    }
+
+    // Sends message once the stream is dropped, ending the subscription with the server.
+    #[cfg(feature = "market_data")]
+    pub(super) fn set_cancel_message(&mut self, message: RequestMessage) {
+        self.cancel_message = Some(message);
+    }
+
+    fn restart_deadline(&mut self) {
+        if let (Some(timeout), Some(deadline)) = (self.timeout, &mut self.deadline) {
+            deadline.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+}
+
+impl Stream for ResponseStream {
+    type Item = Result<ResponseMessage, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.messages.poll_recv(cx) {
+                Poll::Ready(Some(message)) => {
+                    self.restart_deadline();
+
+                    if message.message_type() != IncomingMessages::Error {
+                        return Poll::Ready(Some(Ok(message)));
+                    }
+
+                    let error = Error::from_tws_message(&message);
+                    if !error.is_warning() {
+                        return Poll::Ready(Some(Err(error)));
+                    }
+                    warn!("request {}: {error}", self.request_id);
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => {
+                    let timed_out = self.deadline.as_mut().is_some_and(|deadline| deadline.as_mut().poll(cx).is_ready());
+                    if timed_out {
+                        self.restart_deadline();
+                        return Poll::Ready(Some(Err(Error::Timeout)));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ResponseStream {
+    // Releases the request, cancelling it with the server when it is a subscription.
+    fn drop(&mut self) {
+        self.requests.lock().unwrap().remove(&self.request_id);
+
+        if let Some(message) = self.cancel_message.take() {
+            if let Err(err) = send(&self.writer, &message) {
+                warn!("error cancelling request {}: {err}", self.request_id);
+            }
+        }
+    }
+}
+
+/// A subscription decoding the responses to a request, e.g. [Client::realtime_bars](super::Client::realtime_bars).
+///
+/// The subscription is cancelled with the server once dropped.
+pub struct Subscription<T> {
+    responses: ResponseStream,
+    server_version: i32,
+    decode: fn(i32, &mut ResponseMessage) -> Result<T, Error>,
+}
+
+impl<T> Subscription<T> {
+    #[cfg(feature = "market_data")]
+    pub(super) fn new(responses: ResponseStream, server_version: i32, decode: fn(i32, &mut ResponseMessage) -> Result<T, Error>) -> Subscription<T> {
+        Subscription {
+            responses,
+            server_version,
+            decode,
+        }
+    }
+
+    /// Returns the next item. Returns None once the connection is closed.
+    pub async fn next(&mut self) -> Option<Result<T, Error>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Returns the id of the request.
+    pub fn request_id(&self) -> i32 {
+        self.responses.request_id()
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = Result<T, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let server_version = self.server_version;
+        let decode = self.decode;
+
+        Pin::new(&mut self.responses)
+            .poll_next(cx)
+            .map(|item| item.map(|response| response.and_then(|mut message| decode(server_version, &mut message))))
+    }
+}
+
+impl<T> std::fmt::Debug for Subscription<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription").field("responses", &self.responses).finish()
+    }
+}
+
+// Reads a length prefixed message.
+pub(super) async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<ResponseMessage, Error> {
+    let message_size = reader.read_u32().await? as usize;
+    // the connection can't be resynchronized
+    if message_size > MAX_MESSAGE_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("message length {message_size} exceeds the maximum of {MAX_MESSAGE_LEN}"),
+        )
+        .into());
+    }
+
+    let mut data = vec![0_u8; message_size];
+    reader.read_exact(&mut data).await?;
+
+    let raw_string = String::from_utf8(data)?;
+    debug!("<- {:?}", raw_string);
+
+    Ok(ResponseMessage::from_string(raw_string))
+}
+
+// Writes a message before the writer task is started, e.g. to start the API.
+pub(super) async fn write_message(writer: &mut OwnedWriteHalf, message: &RequestMessage) -> Result<(), Error> {
+    let mut packet = Vec::new();
+    write_frame(&mut packet, message)?;
+
+    writer.write_all(&packet).await?;
+    Ok(())
+}
+
+fn send(writer: &UnboundedSender<Vec<u8>>, message: &RequestMessage) -> Result<(), Error> {
+    let mut packet = Vec::new();
+    write_frame(&mut packet, message)?;
+
+    // the writer task ends once writing fails
+    writer.send(packet).map_err(|_| Error::Disconnected)
+}
+
+async fn read_messages(
+    mut reader: BufReader<OwnedReadHalf>,
+    requests: Arc<Requests>,
+    current_time: UnboundedSender<ResponseMessage>,
+    next_order_id: Arc<AtomicI32>,
+) {
+    loop {
+        let message = match read_message(&mut reader).await {
+            Ok(message) => message,
+            Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                info!("connection closed by server");
+                break;
+            }
+            Err(err) => {
+                error!("error reading message: {err}");
+                break;
+            }
+        };
+
+        dispatch_message(message, &requests, &current_time, &next_order_id);
+    }
+
+    // ends the streams of pending requests
+    requests.lock().unwrap().clear();
+}
+
+fn dispatch_message(message: ResponseMessage, requests: &Requests, current_time: &UnboundedSender<ResponseMessage>, next_order_id: &AtomicI32) {
+    match message.message_type() {
+        IncomingMessages::NextValidId => {
+            if let Ok(order_id) = message.peek_int(2) {
+                next_order_id.fetch_max(order_id, Ordering::Relaxed);
+            }
+        }
+        IncomingMessages::CurrentTime | IncomingMessages::CurrentTimeInMillis => {
+            current_time.send(message).ok();
+        }
+        IncomingMessages::ManagedAccounts => info!("managed accounts: {message:?}"),
+        _ => route_response(requests, message),
+    }
+}
+
+// Sends message to the request it answers. Messages of released requests are dropped.
+fn route_response(requests: &Requests, message: ResponseMessage) {
+    let sender = message
+        .request_id()
+        .and_then(|request_id| requests.lock().unwrap().get(&request_id).cloned());
+
+    match sender {
+        Some(sender) => {
+            sender.send(message).ok();
+        }
+        None if message.message_type() == IncomingMessages::Error => {
+            let error = Error::from_tws_message(&message);
+            if error.is_warning() {
+                warn!("{error}");
+            } else {
+                error!("{error}");
+            }
+        }
+        None => info!("no request for message: {message:?}"),
+    }
+}
+
+async fn write_packets(mut writer: OwnedWriteHalf, mut packets: UnboundedReceiver<Vec<u8>>) {
+    while let Some(packet) = packets.recv().await {
+        if let Err(err) = writer.write_all(&packet).await {
+            error!("error writing message: {err}");
+            break;
+        }
+    }
+}
@@ -1,35 +1,75 @@
-use std::cell::RefCell;
+#[cfg(feature = "accounts")]
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use byteorder::{BigEndian, WriteBytesExt};
-use log::{debug, error, info};
+use crossbeam::channel::Receiver;
+use log::{debug, error, info, warn};
 use time::macros::format_description;
 use time::OffsetDateTime;
-use time_tz::{timezones, OffsetResult, PrimitiveDateTimeExt, Tz};
+use time_tz::{timezones, OffsetDateTimeExt, OffsetResult, PrimitiveDateTimeExt, Tz};
 
-use crate::accounts::{FamilyCode, Position};
-use crate::client::transport::{GlobalResponseIterator, MessageBus, ResponseIterator, TcpMessageBus};
+#[cfg(feature = "accounts")]
+use crate::accounts::{self, FaDataType, FamilyCode, NetPosition, Position};
+#[cfg(feature = "tls")]
+use crate::client::transport::TlsConfig;
+use crate::client::transport::{trace, ConnectOptions, TcpMessageBus, WireCapture, DEFAULT_MAX_MESSAGES_PER_SECOND, DEFAULT_REQUEST_TIMEOUT};
 use crate::contracts::Contract;
-use crate::errors::Error;
+use crate::errors::{Error, ErrorKind};
+#[cfg(feature = "market_data")]
 use crate::market_data::historical;
-use crate::market_data::realtime::{self, Bar, BarSize, WhatToShow};
-use crate::messages::RequestMessage;
-use crate::messages::{IncomingMessages, OutgoingMessages};
-use crate::orders::{Order, OrderDataResult, OrderNotification};
-use crate::{accounts, contracts, orders, server_versions};
-
+#[cfg(feature = "market_data")]
+use crate::market_data::realtime::{self, Bar, BarSize, MarketDataType, MarketSnapshot, WhatToShow};
+use crate::messages::IncomingMessages;
+use crate::messages::{RequestMessage, ResponseMessage};
+#[cfg(feature = "orders")]
+use crate::orders::{self, Order, OrderDataResult, OrderNotification};
+use crate::{contracts, server_versions};
+
+mod clock;
 pub(crate) mod transport;
 
+pub use clock::{Clock, SystemClock, VirtualClock};
+pub use transport::{
+    CancellationToken, GlobalResponseIterator, MessageBus, MetricsObserver, RecordingMessageBus, ReplayMessageBus, ResponseIterator, Signal,
+};
+
 // Client
 
 const MIN_SERVER_VERSION: i32 = 100;
-const MAX_SERVER_VERSION: i32 = server_versions::HISTORICAL_SCHEDULE;
+pub(crate) const MAX_SERVER_VERSION: i32 = server_versions::HISTORICAL_SCHEDULE;
 
 /// TWS API Client. Manages the connection to TWS or Gateway.
 /// Tracks some global information such as server version and server time.
 /// Supports generation of order ids
+///
+/// A [Client] is `Send` and `Sync`. Share it with an [Arc] to submit requests concurrently from multiple threads.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// use ibapi::Client;
+///
+/// let client = Arc::new(Client::connect("127.0.0.1:4002", 100).expect("connection failed"));
+///
+/// let worker = {
+///     let client = Arc::clone(&client);
+///     thread::spawn(move || client.server_time())
+/// };
+///
+/// println!("server time: {:?}", client.server_time());
+/// println!("server time: {:?}", worker.join().unwrap());
+/// ```
 pub struct Client {
     /// IB server version
     pub(crate) server_version: i32,
@@ -39,10 +79,710 @@ pub struct Client {
     pub(crate) time_zone: Option<&'static Tz>,
 
     managed_accounts: String,
+    optional_capabilities: String,
     client_id: i32, // ID of client.
-    pub(crate) message_bus: RefCell<Box<dyn MessageBus>>,
+    message_bus: Mutex<Box<dyn MessageBus>>,
     next_request_id: AtomicI32, // Next available request_id.
-    order_id: AtomicI32,        // Next available order_id. Starts with value returned on connection.
+    order_id: Arc<AtomicI32>,   // Next available order_id. Starts with value returned on connection.
+    request_timeout: Mutex<Duration>,
+    current_time: Mutex<()>, // held while requesting the current time, as answers carry no request id
+    pub(crate) clock: Arc<dyn Clock>,
+}
+
+/// Snapshot of the connection to TWS or Gateway. See [Client::connection_info].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionInfo {
+    /// Server version negotiated during the handshake.
+    pub server_version: i32,
+    /// Time of the server when the client connected. None if the server time could not be parsed.
+    pub server_time: Option<OffsetDateTime>,
+    /// ID of the client.
+    pub client_id: i32,
+    /// Accounts managed by the logged in user.
+    pub managed_accounts: Vec<String>,
+    /// Next order ID that will be returned by [Client::next_order_id].
+    pub next_valid_order_id: i32,
+}
+
+// How long to wait for the next valid order id and managed accounts after starting the api.
+pub(crate) const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+thread_local! {
+    // Timeout of requests sent from this thread within Client::with_request_timeout.
+    static SCOPED_REQUEST_TIMEOUT: std::cell::Cell<Option<Duration>> = const { std::cell::Cell::new(None) };
+    // Token joined by requests sent from this thread within Client::with_cancellation.
+    static SCOPED_CANCELLATION: std::cell::RefCell<Option<CancellationToken>> = const { std::cell::RefCell::new(None) };
+}
+
+// Sets the request timeout of the thread, restoring the previous one when dropped, also if the scope panics.
+struct RequestTimeoutScope {
+    previous: Option<Duration>,
+}
+
+impl RequestTimeoutScope {
+    fn enter(request_timeout: Duration) -> Self {
+        RequestTimeoutScope {
+            previous: SCOPED_REQUEST_TIMEOUT.replace(Some(request_timeout)),
+        }
+    }
+}
+
+impl Drop for RequestTimeoutScope {
+    fn drop(&mut self) {
+        SCOPED_REQUEST_TIMEOUT.set(self.previous);
+    }
+}
+
+// Sets the cancellation token of the thread, restoring the previous one when dropped, also if the scope panics.
+struct CancellationScope {
+    previous: Option<CancellationToken>,
+}
+
+impl CancellationScope {
+    fn enter(token: &CancellationToken) -> Self {
+        CancellationScope {
+            previous: SCOPED_CANCELLATION.replace(Some(token.clone())),
+        }
+    }
+}
+
+impl Drop for CancellationScope {
+    fn drop(&mut self) {
+        SCOPED_CANCELLATION.set(self.previous.take());
+    }
+}
+
+/// Configures the connection to TWS or Gateway. See [Client::builder].
+///
+/// For long running processes, combine a [heartbeat](ClientBuilder::heartbeat) to detect dead connections,
+/// a [reconnect policy](ClientBuilder::reconnect_policy) and [connection events](Client::connection_events)
+/// to be notified as the connection is lost and re-established.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientBuilder {
+    host: String,
+    port: u16,
+    failover: Vec<String>,
+    client_id: i32,
+    optional_capabilities: String,
+    connect_timeout: Option<Duration>,
+    startup_timeout: Duration,
+    request_timeout: Duration,
+    retry_policy: Option<RetryPolicy>,
+    heartbeat: Option<(Duration, Duration)>,
+    reconnect_policy: RetryPolicy,
+    maintenance_window: Option<MaintenanceWindow>,
+    proxy: Option<Socks5Proxy>,
+    socket_options: SocketOptions,
+    max_messages_per_second: Option<u32>,
+    subscription_buffer: Option<SubscriptionBuffer>,
+    wire_capture: Option<PathBuf>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+}
+
+impl Default for ClientBuilder {
+    // Defaults to the IB Gateway paper trading port on the local host.
+    fn default() -> Self {
+        ClientBuilder {
+            host: "127.0.0.1".into(),
+            port: 4002,
+            failover: Vec::new(),
+            client_id: 100,
+            optional_capabilities: "".into(),
+            connect_timeout: None,
+            startup_timeout: DEFAULT_STARTUP_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            retry_policy: None,
+            heartbeat: None,
+            reconnect_policy: RetryPolicy::unlimited(),
+            maintenance_window: None,
+            proxy: None,
+            socket_options: SocketOptions::default(),
+            max_messages_per_second: Some(DEFAULT_MAX_MESSAGES_PER_SECOND),
+            subscription_buffer: None,
+            wire_capture: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Sets the host running TWS or Gateway. Defaults to 127.0.0.1.
+    ///
+    /// Host names are resolved to all their IPv4 and IPv6 addresses, and each is tried until one connects.
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// Sets the API port. Defaults to 4002, the Gateway paper trading port.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Adds a backup TWS or Gateway at `address`, e.g. backup.example.com:4001, used when the servers before it cannot be reached.
+    /// Repeat to add backups in priority order, after the primary server at [host](Self::host) and [port](Self::port).
+    ///
+    /// Connecting and reconnecting try the primary server first, then each backup in turn. Client state survives the switch:
+    /// subscriptions are replayed, and order ids continue from the client's next order id rather than the backup's.
+    /// Switching to another server publishes [ConnectionEvent::FailedOver].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::builder()
+    ///     .host("gateway-1.example.com")
+    ///     .port(4001)
+    ///     .failover("gateway-2.example.com:4001")
+    ///     .connect()
+    ///     .expect("connection failed");
+    /// ```
+    pub fn failover(mut self, address: &str) -> Self {
+        self.failover.push(address.into());
+        self
+    }
+
+    /// Sets the client id. Each client connected to the same TWS or Gateway needs a distinct id. Defaults to 100.
+    pub fn client_id(mut self, client_id: i32) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    /// Sets the optional capabilities requested from the server. See [Client::connect_with].
+    pub fn optional_capabilities(mut self, optional_capabilities: &str) -> Self {
+        self.optional_capabilities = optional_capabilities.into();
+        self
+    }
+
+    /// Sets how long to wait for the connection to be established. Waits for the operating system's timeout by default.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Sets how long to wait for the next valid order id and managed accounts once connected. Defaults to 10 seconds.
+    /// Connecting fails with [Error::Timeout] when they don't arrive in time.
+    pub fn startup_timeout(mut self, startup_timeout: Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+
+    /// Sets how long to wait for each response to a request, e.g. contract details or historical data. Defaults to 10 seconds.
+    ///
+    /// Requests without a response within the timeout fail with [Error::Timeout]. See [Client::set_request_timeout].
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Retries connecting, with backoff, while the server cannot be reached. e.g. while the gateway restarts. Connects once by default.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Sets how a dropped connection is re-established. By default reconnection is retried until it succeeds, see [RetryPolicy::unlimited].
+    ///
+    /// Once the connection drops, [ConnectionEvent::Disconnected] and [ConnectionEvent::Reconnecting] are published before the first attempt,
+    /// and [ConnectionEvent::Connected] once an attempt succeeds. When attempts are exhausted the client is shut down, releasing waiting
+    /// subscriptions, and [ConnectionEvent::ReconnectFailed] is published.
+    pub fn reconnect_policy(mut self, reconnect_policy: RetryPolicy) -> Self {
+        self.reconnect_policy = reconnect_policy;
+        self
+    }
+
+    /// Connects through a SOCKS5 proxy, e.g. when the gateway is only reachable from another network. Reconnections use the proxy too.
+    ///
+    /// The host is resolved by the proxy, so it may be a name only known on the gateway's network.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::client::Socks5Proxy;
+    /// use ibapi::Client;
+    ///
+    /// let proxy = Socks5Proxy::new("bastion.example.com:1080").with_credentials("trader", "secret");
+    ///
+    /// let client = Client::builder()
+    ///     .host("gateway.internal")
+    ///     .socks5_proxy(proxy)
+    ///     .connect()
+    ///     .expect("connection failed");
+    /// ```
+    pub fn socks5_proxy(mut self, proxy: Socks5Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Connects over TLS, for gateways configured to require SSL on the API port. Requires the `tls` feature.
+    ///
+    /// The server's certificate is verified against the roots of `config`, for the host connected to. Gateways usually present
+    /// a self-signed certificate, which must then be among the roots. Reconnections and failover addresses use TLS too.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::rustls::pki_types::pem::PemObject;
+    /// use ibapi::rustls::pki_types::CertificateDer;
+    /// use ibapi::rustls::{ClientConfig, RootCertStore};
+    /// use ibapi::Client;
+    ///
+    /// let mut roots = RootCertStore::empty();
+    /// roots.add(CertificateDer::from_pem_file("gateway.pem").expect("invalid certificate")).expect("invalid certificate");
+    /// let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    ///
+    /// let client = Client::builder().host("gateway.local").tls(config).connect().expect("connection failed");
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: rustls::ClientConfig) -> Self {
+        self.tls = Some(TlsConfig::new(config));
+        self
+    }
+
+    /// Limits the rate of messages sent to the server. Bursts of requests wait rather than exceed the limit.
+    ///
+    /// The server disconnects clients sending more than 50 messages per second, the default limit. None disables the limit.
+    pub fn max_messages_per_second(mut self, max_messages_per_second: Option<u32>) -> Self {
+        self.max_messages_per_second = max_messages_per_second;
+        self
+    }
+
+    /// Bounds the messages buffered for each streaming subscription, e.g. realtime bars and tick by tick data.
+    /// Unbounded by default. See [Client::set_subscription_buffer].
+    pub fn subscription_buffer(mut self, subscription_buffer: SubscriptionBuffer) -> Self {
+        self.subscription_buffer = Some(subscription_buffer);
+        self
+    }
+
+    /// Records every message exchanged with the server to the file at `path`, replacing an existing file.
+    /// Attach the capture to bug reports about messages that fail to decode.
+    ///
+    /// Each message is a line holding the time, the direction, `->` sent or `<-` received, and the message as sent on the wire, hex encoded.
+    /// Captures include account numbers and positions, so review them before sharing.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::builder().wire_capture("session.capture").connect().expect("connection failed");
+    /// ```
+    pub fn wire_capture(mut self, path: impl AsRef<Path>) -> Self {
+        self.wire_capture = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets socket level options applied to the connection, and to re-established connections.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use ibapi::client::SocketOptions;
+    /// use ibapi::Client;
+    ///
+    /// let socket_options = SocketOptions {
+    ///     keepalive: Some(Duration::from_secs(60)),
+    ///     keepalive_interval: Some(Duration::from_secs(10)),
+    ///     ..SocketOptions::default()
+    /// };
+    ///
+    /// let client = Client::builder().socket_options(socket_options).connect().expect("connection failed");
+    /// ```
+    pub fn socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.socket_options = socket_options;
+        self
+    }
+
+    /// Declares the daily restart of TWS or Gateway.
+    ///
+    /// During the window requests fail immediately with [Error::MaintenanceWindow], heartbeats are suspended,
+    /// and a dropped connection is re-established once the window ends.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use ibapi::client::MaintenanceWindow;
+    /// use ibapi::Client;
+    /// use time::macros::time;
+    /// use time_tz::timezones;
+    ///
+    /// let restart = MaintenanceWindow::new(time!(23:45), Duration::from_secs(15 * 60), timezones::db::america::NEW_YORK);
+    ///
+    /// let client = Client::builder().maintenance_window(restart).connect().expect("connection failed");
+    /// ```
+    pub fn maintenance_window(mut self, maintenance_window: MaintenanceWindow) -> Self {
+        self.maintenance_window = Some(maintenance_window);
+        self
+    }
+
+    /// Requests the server time every `interval` to detect dead connections. Disabled by default.
+    ///
+    /// If nothing is received from the server within `deadline` of a heartbeat, the connection is flagged as unhealthy,
+    /// see [Client::is_healthy], and re-established.
+    pub fn heartbeat(mut self, interval: Duration, deadline: Duration) -> Self {
+        self.heartbeat = Some((interval, deadline));
+        self
+    }
+
+    /// Returns the address of the server. e.g. `127.0.0.1:4002`, or `[::1]:4002` for IPv6 hosts.
+    pub fn address(&self) -> String {
+        if self.host.contains(':') && !self.host.starts_with('[') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Establishes the connection to TWS or Gateway.
+    pub fn connect(&self) -> Result<Client, Error> {
+        let mut message_bus = self.open_message_bus()?;
+        message_bus.set_reconnect_policy(self.reconnect_policy.clone());
+        message_bus.set_maintenance_window(self.maintenance_window.clone());
+        message_bus.set_rate_limit(self.max_messages_per_second);
+        message_bus.set_request_timeout(self.request_timeout);
+        message_bus.set_subscription_buffer(self.subscription_buffer.clone());
+
+        let message_bus = Mutex::new(Box::new(message_bus) as Box<dyn MessageBus>);
+        let client = Client::do_connect(self.client_id, &self.optional_capabilities, self.startup_timeout, message_bus)?;
+        client.set_request_timeout(self.request_timeout);
+
+        if let Some((interval, deadline)) = self.heartbeat {
+            let message = RequestMessage::current_time(client.server_version);
+            client.message_bus().start_heartbeat(&message, interval, deadline)?;
+        }
+
+        Ok(client)
+    }
+
+    // Opens the connection, retrying failed attempts according to the retry policy.
+    fn open_message_bus(&self) -> Result<TcpMessageBus, Error> {
+        let address = self.address();
+        let max_attempts = self.retry_policy.as_ref().map_or(1, |policy| policy.max_attempts.max(1));
+
+        let capture = match &self.wire_capture {
+            Some(path) => Some(Arc::new(WireCapture::create(path)?)),
+            None => None,
+        };
+
+        let mut attempt = 1;
+        loop {
+            let connect_options = ConnectOptions {
+                connect_timeout: self.connect_timeout,
+                proxy: self.proxy.clone(),
+                socket_options: self.socket_options.clone(),
+                capture: capture.clone(),
+                failover: self.failover.clone(),
+                #[cfg(feature = "tls")]
+                tls: self.tls.clone(),
+            };
+
+            match TcpMessageBus::connect_with_options(&address, connect_options) {
+                Ok(message_bus) => return Ok(message_bus),
+                Err(Error::Io(err)) if attempt < max_attempts => {
+                    let policy = self.retry_policy.as_ref().expect("retry policy");
+                    let delay = policy.delay(attempt);
+                    warn!("connection attempt {attempt} to {address} failed: {err}. retrying in {delay:?}");
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Controls how connection attempts are retried. See [ClientBuilder::retry_policy] and [ClientBuilder::reconnect_policy].
+///
+/// The delay between attempts starts at `initial_backoff` and doubles after each failure, up to `max_backoff`.
+/// Up to a `jitter` fraction of each delay is randomly removed, so clients that lost their connection together don't reconnect in lockstep.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of connection attempts, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between attempts.
+    pub max_backoff: Duration,
+    /// Fraction of each delay, between 0 and 1, that is randomized. Values outside the range are clamped, and non-finite values mean no jitter.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Creates a policy making up to max_attempts attempts, waiting 1 second before the first retry and at most 30 seconds between attempts,
+    /// with 10% jitter.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            jitter: 0.1,
+        }
+    }
+
+    /// Creates a policy that retries until connected. Used to re-establish dropped connections by default.
+    pub fn unlimited() -> Self {
+        RetryPolicy::new(u32::MAX)
+    }
+
+    /// Returns the delay after the given failed attempt, starting from 1, before jitter is applied.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    // Returns the backoff with up to the jitter fraction randomly removed.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        // clamp passes NaN through, which mul_f64 rejects
+        let jitter = if self.jitter.is_finite() { self.jitter.clamp(0.0, 1.0) } else { 0.0 };
+        let jitter = jitter * random_fraction();
+        self.backoff(attempt).mul_f64(1.0 - jitter)
+    }
+}
+
+// Returns a pseudo random number in [0, 1), seeded from the randomly keyed standard hasher.
+fn random_fraction() -> f64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let hasher = RandomState::new().build_hasher();
+    (hasher.finish() >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// Socket level options for the connection to TWS or Gateway. See [ClientBuilder::socket_options].
+///
+/// Buffer sizes and keepalive are supported on Linux, Android, FreeBSD and macOS. Elsewhere setting them fails the connection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SocketOptions {
+    /// Sends messages immediately rather than coalescing small writes (TCP_NODELAY). Enabled by default for low latency order submission.
+    pub nodelay: bool,
+    /// Size of the socket receive buffer (SO_RCVBUF). Defaults to the operating system's setting.
+    pub receive_buffer_size: Option<usize>,
+    /// Size of the socket send buffer (SO_SNDBUF). Defaults to the operating system's setting.
+    pub send_buffer_size: Option<usize>,
+    /// Enables TCP keepalive, probing the connection after it's idle this long. Disabled by default.
+    pub keepalive: Option<Duration>,
+    /// Time between keepalive probes. Defaults to the operating system's setting.
+    pub keepalive_interval: Option<Duration>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            nodelay: true,
+            receive_buffer_size: None,
+            send_buffer_size: None,
+            keepalive: None,
+            keepalive_interval: None,
+        }
+    }
+}
+
+/// Bounds the messages buffered for a streaming subscription that its consumer has not yet read.
+/// See [ClientBuilder::subscription_buffer] and [Client::set_subscription_buffer].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubscriptionBuffer {
+    /// Number of messages buffered before the overflow policy applies.
+    pub capacity: usize,
+    /// What happens to messages arriving while the buffer is full.
+    pub overflow: OverflowPolicy,
+}
+
+impl SubscriptionBuffer {
+    /// Creates a buffer holding up to `capacity` messages.
+    ///
+    /// # Arguments
+    /// * `capacity` - number of messages buffered. At least one message is buffered.
+    /// * `overflow` - what happens to messages arriving while the buffer is full.
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
+        SubscriptionBuffer {
+            capacity: capacity.max(1),
+            overflow,
+        }
+    }
+}
+
+/// Handling of messages for a subscription whose buffer is full. See [SubscriptionBuffer].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Waits for the consumer to catch up. Messages for every other request are held up in the meantime.
+    Block,
+    /// Discards the oldest buffered message to make room, so the consumer sees the most recent data.
+    DropOldest,
+    /// Discards the arriving message.
+    DropNewest,
+    /// Ends the subscription. The consumer receives the buffered messages, then the subscription ends with an error logged.
+    Error,
+}
+
+/// SOCKS5 proxy to connect through. See [ClientBuilder::socks5_proxy].
+#[derive(Clone, PartialEq)]
+pub struct Socks5Proxy {
+    address: String,
+    credentials: Option<(String, String)>,
+}
+
+impl Socks5Proxy {
+    /// Creates a proxy that doesn't require authentication.
+    ///
+    /// # Arguments
+    /// * `address` - address of the proxy. e.g. 127.0.0.1:1080
+    pub fn new(address: &str) -> Self {
+        Socks5Proxy {
+            address: address.into(),
+            credentials: None,
+        }
+    }
+
+    /// Authenticates to the proxy with username and password.
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+}
+
+// Keeps the password out of logs.
+impl Debug for Socks5Proxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Socks5Proxy")
+            .field("address", &self.address)
+            .field("username", &self.credentials.as_ref().map(|(username, _)| username))
+            .finish()
+    }
+}
+
+/// Daily period during which TWS or Gateway restarts. See [ClientBuilder::maintenance_window].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaintenanceWindow {
+    start: time::Time,
+    duration: Duration,
+    time_zone: &'static Tz,
+}
+
+impl MaintenanceWindow {
+    /// Creates a window starting daily at `start`, in `time_zone`, and lasting `duration`.
+    ///
+    /// # Arguments
+    /// * `start`     - time of day the restart begins. e.g. 23:45
+    /// * `duration`  - how long TWS or Gateway is unavailable.
+    /// * `time_zone` - time zone of `start`. e.g. America/New_York
+    pub fn new(start: time::Time, duration: Duration, time_zone: &'static Tz) -> Self {
+        MaintenanceWindow { start, duration, time_zone }
+    }
+
+    /// Returns true if `at` falls within the window.
+    pub fn contains(&self, at: OffsetDateTime) -> bool {
+        self.remaining(at).is_some()
+    }
+
+    /// Returns how long until the window ends if `at` falls within it.
+    pub fn remaining(&self, at: OffsetDateTime) -> Option<Duration> {
+        const DAY: i64 = 24 * 60 * 60 * 1_000_000_000;
+
+        let time_of_day = |time: time::Time| (time - time::Time::MIDNIGHT).whole_nanoseconds() as i64;
+
+        let local = at.to_timezone(self.time_zone).time();
+        // time since the window last started, wrapping around midnight
+        let elapsed = (time_of_day(local) - time_of_day(self.start)).rem_euclid(DAY);
+        let elapsed = Duration::from_nanos(elapsed as u64);
+
+        self.duration.checked_sub(elapsed).filter(|remaining| !remaining.is_zero())
+    }
+}
+
+/// Changes in the state of the connection to TWS or Gateway, and of TWS's connections to IB servers. See [Client::connection_events].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// The connection to TWS or Gateway was re-established.
+    Connected,
+    /// The connection to TWS or Gateway was lost, or shut down.
+    Disconnected,
+    /// Attempting to re-establish the connection to TWS or Gateway.
+    Reconnecting,
+    /// Re-establishing the connection failed after the attempts allowed by [ClientBuilder::reconnect_policy]. The client is shut down,
+    /// as by [Client::shutdown].
+    ReconnectFailed,
+    /// The connection was re-established to another server than before, at address. Follows [Connected](Self::Connected).
+    /// See [ClientBuilder::failover].
+    FailedOver { address: String },
+    /// Code 1100. Connectivity between TWS and IB servers was lost.
+    ConnectivityLost,
+    /// Code 1101. Connectivity was restored but market data was lost. Market data subscriptions must be requested again.
+    ConnectivityRestoredDataLost,
+    /// Code 1102. Connectivity was restored and market data was maintained.
+    ConnectivityRestored,
+    /// A data farm connection was broken, e.g. code 2103. Market data from the farm is stale.
+    FarmDisconnected { code: i32, message: String },
+    /// A data farm connection is OK, e.g. code 2104.
+    FarmConnected { code: i32, message: String },
+}
+
+impl ConnectionEvent {
+    // Maps status codes received in error messages to events.
+    pub(crate) fn from_error_code(code: i32, message: &str) -> Option<ConnectionEvent> {
+        match code {
+            1100 => Some(ConnectionEvent::ConnectivityLost),
+            1101 => Some(ConnectionEvent::ConnectivityRestoredDataLost),
+            1102 => Some(ConnectionEvent::ConnectivityRestored),
+            // market data, historical data and security definition farms
+            2103 | 2105 | 2157 => Some(ConnectionEvent::FarmDisconnected {
+                code,
+                message: message.into(),
+            }),
+            2104 | 2106 | 2158 => Some(ConnectionEvent::FarmConnected {
+                code,
+                message: message.into(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// An error, warning or notification received from TWS. See [Client::error_events].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorEvent {
+    /// Id of the request or order the message is about, or -1 for system messages, e.g. connectivity notifications.
+    pub request_id: i32,
+    /// Error code, see [ErrorKind::from_code].
+    pub code: i32,
+    pub message: String,
+    /// Details of rejected orders, as JSON. Empty for other messages.
+    pub advanced_order_reject_json: String,
+}
+
+impl ErrorEvent {
+    /// Returns the category of the error code.
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::from_code(self.code)
+    }
+
+    /// Returns true for warnings and notices that don't fail the request. See [Error::is_warning].
+    pub fn is_warning(&self) -> bool {
+        self.kind().is_warning()
+    }
+}
+
+impl std::fmt::Display for ErrorEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[{}] request {}: {}", self.code, self.request_id, self.message)
+    }
+}
+
+/// How fields that fail to parse are handled when decoding messages from the server. See [Client::set_parse_mode].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Unparseable fields fail the whole message.
+    #[default]
+    Strict,
+    /// Unparseable numeric fields are logged and replaced with a default, 0 or None.
+    Lenient,
 }
 
 impl Client {
@@ -50,6 +790,12 @@ impl Client {
     ///
     /// Connects to server using the given connection string
     ///
+    /// If the connection drops it is re-established, with backoff, and active market data subscriptions are requested again.
+    ///
+    /// Outgoing messages are limited to 50 per second, the server's limit. See [ClientBuilder::max_messages_per_second].
+    ///
+    /// Fails if the server does not send the next valid order id and managed accounts within 10 seconds. See [ClientBuilder::startup_timeout].
+    ///
     /// # Arguments
     /// * `address`   - address of server. e.g. 127.0.0.1:4002
     /// * `client_id` - id of client. e.g. 100
@@ -67,40 +813,214 @@ impl Client {
     /// println!("next_order_id: {}", client.next_order_id());
     /// ```
     pub fn connect(address: &str, client_id: i32) -> Result<Client, Error> {
-        let message_bus = RefCell::new(Box::new(TcpMessageBus::connect(address)?));
-        Client::do_connect(client_id, message_bus)
+        Client::connect_with(address, client_id, "")
+    }
+
+    /// Establishes connection to TWS or Gateway, requesting optional capabilities.
+    ///
+    /// Optional capabilities are sent to the server when the API is started. Some institutional features are enabled this way.
+    /// Capabilities are only sent when the server supports them.
+    ///
+    /// # Arguments
+    /// * `address`               - address of server. e.g. 127.0.0.1:4002
+    /// * `client_id`             - id of client. e.g. 100
+    /// * `optional_capabilities` - capabilities to request from server. Empty if not applicable.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect_with("127.0.0.1:4002", 100, "+PACEAPI").expect("connection failed");
+    ///
+    /// println!("optional_capabilities: {}", client.optional_capabilities());
+    /// ```
+    pub fn connect_with(address: &str, client_id: i32, optional_capabilities: &str) -> Result<Client, Error> {
+        let message_bus = Mutex::new(Box::new(TcpMessageBus::connect(address)?) as Box<dyn MessageBus>);
+        Client::do_connect(client_id, optional_capabilities, DEFAULT_STARTUP_TIMEOUT, message_bus)
+    }
+
+    /// Replays a session recorded with [ClientBuilder::wire_capture] or [RecordingMessageBus], without TWS or Gateway.
+    ///
+    /// The returned client receives the messages of the recorded session from a [ReplayMessageBus]. Each message is released once the client
+    /// has sent the requests that preceded it in the recording, so making the same requests in the same order reproduces the session.
+    /// Requests that differ from the recording are logged as warnings. Useful for regression tests and offline demos.
+    ///
+    /// Messages are played back as fast as the client consumes them. The client's [clock](Client::now) is a [VirtualClock]
+    /// moved to the recorded time of each message played back, so time read from the client follows the recorded session.
+    /// Request timeouts still run on the system clock.
+    ///
+    /// # Arguments
+    /// * `path` - capture written by [ClientBuilder::wire_capture] or [RecordingMessageBus].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::replay("session.capture").expect("replay failed");
+    ///
+    /// println!("server time: {:?}", client.server_time());
+    /// ```
+    pub fn replay(path: impl AsRef<Path>) -> Result<Client, Error> {
+        let captured = transport::read_capture(path.as_ref())?;
+        let (client_id, optional_capabilities) = transport::replay::recorded_client(&captured).unwrap_or((100, "".into()));
+
+        let message_bus = ReplayMessageBus::new(captured);
+        let clock = message_bus.clock();
+
+        let message_bus = Mutex::new(Box::new(message_bus) as Box<dyn MessageBus>);
+        let mut client = Client::do_connect(client_id, &optional_capabilities, DEFAULT_STARTUP_TIMEOUT, message_bus)?;
+        client.clock = Arc::new(clock);
+
+        Ok(client)
+    }
+
+    /// Establishes connection to the Client Portal gateway, an alternative to TWS or Gateway. Requires the `client_portal` feature.
+    ///
+    /// Requests are translated to the gateway's REST and WebSocket endpoints, so the same code runs against either backend.
+    /// Supported are [server_time](Client::server_time), [positions](Client::positions), [next_valid_order_id](Client::next_valid_order_id)
+    /// and market data of contracts with a contract id, e.g. [market_snapshot](Client::market_snapshot). Other requests fail.
+    /// Order ids are allocated locally, as the gateway doesn't use them.
+    ///
+    /// The gateway is connected to over TLS, and its certificate verified with the Mozilla root certificates. The gateway ships
+    /// with a self-signed certificate, so either configure it with a certificate issued for its host, or add its certificate
+    /// to the roots of [connect_client_portal_with_tls](Client::connect_client_portal_with_tls). Addresses like
+    /// `http://localhost:5000` connect in the clear, to gateways configured with `listenSsl: false`.
+    ///
+    /// The gateway's session must be authenticated by logging in through the gateway's web page.
+    ///
+    /// # Arguments
+    /// * `address` - address of the gateway. e.g. localhost:5000
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect_client_portal("localhost:5000").expect("connection failed");
+    ///
+    /// println!("managed_accounts: {}", client.managed_accounts());
+    /// println!("server time: {:?}", client.server_time());
+    /// ```
+    #[cfg(feature = "client_portal")]
+    pub fn connect_client_portal(address: &str) -> Result<Client, Error> {
+        let message_bus = Mutex::new(Box::new(transport::ClientPortalBus::connect(address, None)?) as Box<dyn MessageBus>);
+        Client::do_connect(0, "", DEFAULT_STARTUP_TIMEOUT, message_bus)
     }
 
-    fn do_connect(client_id: i32, message_bus: RefCell<Box<dyn MessageBus>>) -> Result<Client, Error> {
+    /// Establishes connection to the Client Portal gateway over TLS, verifying the gateway's certificate with the roots of `config`.
+    /// See [connect_client_portal](Client::connect_client_portal). Requires the `client_portal` feature.
+    ///
+    /// # Arguments
+    /// * `address` - address of the gateway. e.g. localhost:5000
+    /// * `config`  - TLS configuration, e.g. with the gateway's self-signed certificate among its roots.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::rustls::pki_types::pem::PemObject;
+    /// use ibapi::rustls::pki_types::CertificateDer;
+    /// use ibapi::rustls::{ClientConfig, RootCertStore};
+    /// use ibapi::Client;
+    ///
+    /// let mut roots = RootCertStore::empty();
+    /// roots.add(CertificateDer::from_pem_file("gateway.pem").expect("invalid certificate")).expect("invalid certificate");
+    /// let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    ///
+    /// let client = Client::connect_client_portal_with_tls("localhost:5000", config).expect("connection failed");
+    /// ```
+    #[cfg(feature = "client_portal")]
+    pub fn connect_client_portal_with_tls(address: &str, config: rustls::ClientConfig) -> Result<Client, Error> {
+        let message_bus = Mutex::new(Box::new(transport::ClientPortalBus::connect(address, Some(config))?) as Box<dyn MessageBus>);
+        Client::do_connect(0, "", DEFAULT_STARTUP_TIMEOUT, message_bus)
+    }
+
+    /// Establishes connection over a custom [MessageBus], e.g. an in-memory bus for tests or a bridge to an existing connection manager.
+    ///
+    /// The bus is driven as described by [MessageBus]: the handshake and start of the API are exchanged before this returns.
+    ///
+    /// # Arguments
+    /// * `message_bus` - transport to TWS.
+    /// * `client_id`   - id of client. e.g. 100
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::client::MessageBus;
+    /// use ibapi::Client;
+    ///
+    /// fn connect(message_bus: Box<dyn MessageBus>) {
+    ///     let client = Client::with_message_bus(message_bus, 100).expect("connection failed");
+    ///
+    ///     println!("server_version: {}", client.server_version());
+    /// }
+    /// ```
+    pub fn with_message_bus(message_bus: Box<dyn MessageBus>, client_id: i32) -> Result<Client, Error> {
+        Client::do_connect(client_id, "", DEFAULT_STARTUP_TIMEOUT, Mutex::new(message_bus))
+    }
+
+    /// Returns a [ClientBuilder] to configure the connection, e.g. to connect multiple clients with distinct client ids.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use ibapi::client::RetryPolicy;
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::builder()
+    ///     .host("127.0.0.1")
+    ///     .port(4002)
+    ///     .client_id(101)
+    ///     .connect_timeout(Duration::from_secs(5))
+    ///     .retry_policy(RetryPolicy::new(5))
+    ///     .connect()
+    ///     .expect("connection failed");
+    ///
+    /// println!("server_version: {}", client.server_version());
+    /// ```
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    fn do_connect(
+        client_id: i32,
+        optional_capabilities: &str,
+        startup_timeout: Duration,
+        message_bus: Mutex<Box<dyn MessageBus>>,
+    ) -> Result<Client, Error> {
         let mut client = Client {
             server_version: 0,
             connection_time: None,
             time_zone: None,
             managed_accounts: String::from(""),
+            optional_capabilities: String::from(optional_capabilities),
             message_bus,
             client_id,
             next_request_id: AtomicI32::new(9000),
-            order_id: AtomicI32::new(-1),
+            order_id: Arc::new(AtomicI32::new(-1)),
+            request_timeout: Mutex::new(DEFAULT_REQUEST_TIMEOUT),
+            current_time: Mutex::new(()),
+            clock: Arc::new(SystemClock),
         };
 
         client.handshake()?;
         client.start_api()?;
-        client.receive_account_info()?;
+        client.receive_account_info(startup_timeout)?;
 
-        client.message_bus.borrow_mut().process_messages(client.server_version)?;
+        client.message_bus().track_next_order_id(Arc::clone(&client.order_id));
+        client.message_bus().process_messages(client.server_version)?;
 
         Ok(client)
     }
 
     // sends server handshake
     fn handshake(&mut self) -> Result<(), Error> {
-        let prefix = "API\0";
-        let version = format!("v{MIN_SERVER_VERSION}..{MAX_SERVER_VERSION}");
-
-        let packet = prefix.to_owned() + &encode_packet(&version);
-        self.message_bus.borrow_mut().write(&packet)?;
+        self.message_bus().write(&encode_handshake())?;
 
-        let ack = self.message_bus.borrow_mut().read_message();
+        let ack = self.message_bus().read_message();
 
         match ack {
             Ok(mut response_message) => {
@@ -110,7 +1030,9 @@ impl Client {
                 (self.connection_time, self.time_zone) = parse_connection_time(time.as_str());
             }
             Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
-                return Err(Error::Simple(format!("The server may be rejecting connections from this host: {err}")));
+                return Err(Error::ConnectionFailed(format!(
+                    "the server may be rejecting connections from this host: {err}"
+                )));
             }
             Err(err) => {
                 return Err(err);
@@ -121,32 +1043,39 @@ impl Client {
 
     // asks server to start processing messages
     fn start_api(&mut self) -> Result<(), Error> {
-        const VERSION: i32 = 2;
+        let prelude = RequestMessage::start_api(self.server_version, self.client_id, &self.optional_capabilities);
 
-        let prelude = &mut RequestMessage::default();
-
-        prelude.push_field(&OutgoingMessages::StartApi);
-        prelude.push_field(&VERSION);
-        prelude.push_field(&self.client_id);
-
-        if self.server_version > server_versions::OPTIONAL_CAPABILITIES {
-            prelude.push_field(&"");
-        }
-
-        self.message_bus.borrow_mut().write_message(prelude)?;
+        self.message_bus().start_api(&prelude)?;
 
         Ok(())
     }
 
-    // Fetches next order id and managed accounts.
-    fn receive_account_info(&mut self) -> Result<(), Error> {
+    // Waits for the next order id and managed accounts sent by the server after the api is started.
+    fn receive_account_info(&mut self, startup_timeout: Duration) -> Result<(), Error> {
+        let result = self.wait_for_account_info(startup_timeout);
+        self.message_bus().set_read_timeout(None)?;
+        result
+    }
+
+    fn wait_for_account_info(&mut self, startup_timeout: Duration) -> Result<(), Error> {
         let mut saw_next_order_id: bool = false;
         let mut saw_managed_accounts: bool = false;
 
-        let mut attempts = 0;
-        const MAX_ATTEMPTS: i32 = 100;
-        loop {
-            let mut message = self.message_bus.borrow_mut().read_message()?;
+        let deadline = Instant::now() + startup_timeout;
+        while !(saw_next_order_id && saw_managed_accounts) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(startup_timed_out(startup_timeout));
+            }
+
+            self.message_bus().set_read_timeout(Some(remaining))?;
+            let mut message = match self.message_bus().read_message() {
+                Ok(message) => message,
+                Err(Error::Io(err)) if matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    return Err(startup_timed_out(startup_timeout));
+                }
+                Err(err) => return Err(err),
+            };
 
             match message.message_type() {
                 IncomingMessages::NextValidId => {
@@ -170,55 +1099,524 @@ impl Client {
                 }
                 _ => info!("message: {message:?}"),
             }
-
-            attempts += 1;
-            if (saw_next_order_id && saw_managed_accounts) || attempts > MAX_ATTEMPTS {
-                break;
-            }
         }
 
         Ok(())
     }
 
-    /// Returns the next request ID.
-    pub fn next_request_id(&self) -> i32 {
-        self.next_request_id.fetch_add(1, Ordering::Relaxed)
+    /// Returns the next request ID. Safe to call from several threads, each ID is returned once.
+    pub fn next_request_id(&self) -> i32 {
+        self.next_request_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Returns and increments the order ID. Safe to call from several threads, each ID is returned once.
+    ///
+    /// Starts at the next valid order id received on connection, and is raised to the id of each next valid id message
+    /// received afterwards, e.g. those answering [Client::next_valid_order_id].
+    pub fn next_order_id(&self) -> i32 {
+        self.order_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Sets the current value of order ID.
+    #[cfg(feature = "orders")]
+    pub(crate) fn set_next_order_id(&self, order_id: i32) {
+        self.order_id.store(order_id, Ordering::Relaxed)
+    }
+
+    pub fn server_version(&self) -> i32 {
+        self.server_version
+    }
+
+    /// The time of the server when the client connected
+    pub fn connection_time(&self) -> Option<OffsetDateTime> {
+        self.connection_time
+    }
+
+    /// Returns the current time of the client's [Clock]: the system time when connected to TWS or Gateway, and the time
+    /// of the last message played back when replaying a session, see [Client::replay]. Code reading the time from the
+    /// client, e.g. to find bar boundaries, behaves the same live and in replays.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::replay("session.capture").expect("replay failed");
+    ///
+    /// println!("session time: {}", client.now());
+    /// ```
+    pub fn now(&self) -> OffsetDateTime {
+        self.clock.now()
+    }
+
+    /// Returns the managed accounts.
+    pub fn managed_accounts(&self) -> String {
+        self.managed_accounts.to_owned()
+    }
+
+    /// Returns the optional capabilities requested when the API was started.
+    pub fn optional_capabilities(&self) -> &str {
+        &self.optional_capabilities
+    }
+
+    /// Returns a snapshot of the connection: negotiated server version, client id, managed accounts and next order id.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let info = client.connection_info();
+    /// println!("connected to server version {} as client {}", info.server_version, info.client_id);
+    /// println!("managed accounts: {:?}", info.managed_accounts);
+    /// ```
+    pub fn connection_info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            server_version: self.server_version,
+            server_time: self.connection_time,
+            client_id: self.client_id,
+            managed_accounts: self
+                .managed_accounts
+                .split(',')
+                .map(|account| account.trim())
+                .filter(|account| !account.is_empty())
+                .map(String::from)
+                .collect(),
+            next_valid_order_id: self.order_id.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sets how fields that fail to parse are handled for messages received from now on.
+    ///
+    /// [ParseMode::Strict], the default, fails the message. [ParseMode::Lenient] logs a warning and substitutes
+    /// 0 or None for unparseable numeric fields, trading precision for uptime.
+    ///
+    /// # Arguments
+    /// * `parse_mode` - how unparseable fields are handled.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::client::ParseMode;
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// client.set_parse_mode(ParseMode::Lenient);
+    /// ```
+    pub fn set_parse_mode(&self, parse_mode: ParseMode) {
+        self.message_bus().set_parse_mode(parse_mode)
+    }
+
+    /// Bounds the messages buffered for streaming subscriptions started after this call, e.g. realtime bars and tick by tick data.
+    /// None buffers without bound. Subscriptions already started keep their buffer.
+    ///
+    /// # Arguments
+    /// * `subscription_buffer` - capacity and overflow policy for new subscriptions.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::client::{OverflowPolicy, SubscriptionBuffer};
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// // only the latest ticks matter to a slow consumer
+    /// client.set_subscription_buffer(Some(SubscriptionBuffer::new(100, OverflowPolicy::DropOldest)));
+    /// let ticks = client.tick_by_tick_midpoint(&Contract::stock("AAPL"), 0, false).expect("request failed");
+    ///
+    /// client.set_subscription_buffer(None);
+    /// ```
+    pub fn set_subscription_buffer(&self, subscription_buffer: Option<SubscriptionBuffer>) {
+        self.message_bus().set_subscription_buffer(subscription_buffer)
+    }
+
+    /// Reports messages sent and received, decode errors and the round trip time of requests and orders to observer, e.g. to monitor a production connection.
+    /// None stops reporting.
+    ///
+    /// # Arguments
+    /// * `observer` - receives the measurements, from the threads sending, receiving and decoding messages.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// use ibapi::client::MetricsObserver;
+    /// use ibapi::messages::IncomingMessages;
+    /// use ibapi::Client;
+    ///
+    /// #[derive(Default)]
+    /// struct Counters {
+    ///     received: AtomicUsize,
+    ///     decode_errors: AtomicUsize,
+    /// }
+    ///
+    /// impl MetricsObserver for Counters {
+    ///     fn message_received(&self, _message_type: IncomingMessages, _bytes: usize) {
+    ///         self.received.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    ///
+    ///     fn decode_error(&self, _message_type: IncomingMessages) {
+    ///         self.decode_errors.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// }
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let counters = Arc::new(Counters::default());
+    /// client.set_metrics_observer(Some(counters.clone()));
+    /// ```
+    pub fn set_metrics_observer(&self, observer: Option<Arc<dyn MetricsObserver>>) {
+        self.message_bus().set_metrics_observer(observer)
+    }
+
+    /// Sets how long requests sent from now on wait for each response. Requests without a response within the timeout fail with [Error::Timeout].
+    ///
+    /// # Arguments
+    /// * `request_timeout` - how long to wait for each response.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// client.set_request_timeout(Duration::from_secs(30));
+    /// ```
+    pub fn set_request_timeout(&self, request_timeout: Duration) {
+        *self.request_timeout.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = request_timeout;
+    }
+
+    /// Returns how long requests sent from the calling thread wait for each response.
+    pub fn request_timeout(&self) -> Duration {
+        SCOPED_REQUEST_TIMEOUT
+            .get()
+            .unwrap_or_else(|| *self.request_timeout.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    /// Runs `f`, with requests it sends from the calling thread waiting up to `request_timeout` for each response.
+    /// Other threads keep the client's timeout. See [Client::set_request_timeout].
+    ///
+    /// # Arguments
+    /// * `request_timeout` - how long requests sent by `f` wait for each response.
+    /// * `f` - sends the requests.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::market_data::historical::{BarSize, ToDuration, WhatToShow};
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// // a year of minute bars takes a while to arrive
+    /// let bars = client.with_request_timeout(Duration::from_secs(120), |client| {
+    ///     client.historical_data_ending_now(&Contract::stock("AAPL"), 1.years(), BarSize::Min, WhatToShow::Trades, true)
+    /// });
+    /// ```
+    pub fn with_request_timeout<'a, T>(&'a self, request_timeout: Duration, f: impl FnOnce(&'a Client) -> T) -> T {
+        let _scope = RequestTimeoutScope::enter(request_timeout);
+        f(self)
+    }
+
+    /// Runs `f`, with requests and subscriptions it starts from the calling thread joining `token`.
+    /// Cancelling the token, from any thread, ends them and sends the matching cancel messages to TWS. See [CancellationToken].
+    ///
+    /// Orders are not cancelled by the token. See [Client::cancel_order].
+    ///
+    /// # Arguments
+    /// * `token` - cancels the requests started by `f`.
+    /// * `f` - starts the requests.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// use ibapi::client::CancellationToken;
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::market_data::realtime::{BarSize, WhatToShow};
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let token = CancellationToken::new();
+    /// let bars = client
+    ///     .with_cancellation(&token, |client| client.realtime_bars(&Contract::stock("AAPL"), BarSize::Sec5, WhatToShow::Trades, false))
+    ///     .expect("request failed");
+    ///
+    /// let canceller = token.clone();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_secs(60));
+    ///     canceller.cancel();
+    /// });
+    ///
+    /// // ends once cancelled
+    /// for bar in bars {
+    ///     println!("bar: {bar:?}");
+    /// }
+    /// ```
+    pub fn with_cancellation<'a, T>(&'a self, token: &CancellationToken, f: impl FnOnce(&'a Client) -> T) -> T {
+        let _scope = CancellationScope::enter(token);
+        f(self)
+    }
+
+    /// Flushes messages written to the connection, so they are on the wire before returning.
+    ///
+    /// Useful for latency sensitive work, e.g. starting a timer only once an order has been sent.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::orders::{order_builder, Action};
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("AAPL");
+    /// let order = order_builder::market_order(Action::Buy, 100.0);
+    ///
+    /// let notifications = client.place_order(client.next_order_id(), &contract, &order).expect("request failed");
+    /// client.flush().expect("flush failed");
+    /// ```
+    pub fn flush(&self) -> Result<(), Error> {
+        self.message_bus().flush()
+    }
+
+    /// Shuts down the connection for a controlled exit.
+    ///
+    /// Stops processing messages from the server and closes the connection. Iterators waiting on responses end,
+    /// so no thread is left blocked, and subsequent requests fail with [Error::Shutdown].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// client.shutdown().expect("shutdown failed");
+    /// ```
+    pub fn shutdown(&self) -> Result<(), Error> {
+        self.message_bus().shutdown()
+    }
+
+    /// Subscribes to connection lifecycle events, e.g. to detect when market data is stale because a data farm disconnected.
+    ///
+    /// Only events occurring after subscribing are received. The channel disconnects when the client is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::client::ConnectionEvent;
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let events = client.connection_events();
+    /// std::thread::spawn(move || {
+    ///     for event in events {
+    ///         if let ConnectionEvent::FarmDisconnected { message, .. } = event {
+    ///             println!("market data may be stale: {message}");
+    ///         }
+    ///     }
+    /// });
+    /// ```
+    pub fn connection_events(&self) -> Receiver<ConnectionEvent> {
+        self.message_bus().connection_events()
     }
 
-    /// Returns and increments the order ID.
-    pub fn next_order_id(&self) -> i32 {
-        self.order_id.fetch_add(1, Ordering::Relaxed)
+    /// Subscribes to every error, warning and notification received from TWS, whether about a request or the whole system,
+    /// e.g. to log and alert on them from a monitoring thread.
+    ///
+    /// Errors about a request are also returned by the request. Only messages received after subscribing are received.
+    /// The channel disconnects when the client is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let errors = client.error_events();
+    /// std::thread::spawn(move || {
+    ///     for error in errors {
+    ///         if !error.is_warning() {
+    ///             eprintln!("TWS error: {error}");
+    ///         }
+    ///     }
+    /// });
+    /// ```
+    pub fn error_events(&self) -> Receiver<ErrorEvent> {
+        self.message_bus().error_events()
     }
 
-    /// Sets the current value of order ID.
-    pub(crate) fn set_next_order_id(&self, order_id: i32) {
-        self.order_id.store(order_id, Ordering::Relaxed)
+    /// Returns false when the server stopped answering heartbeats, until the connection is re-established.
+    ///
+    /// Always true unless heartbeats are enabled with [ClientBuilder::heartbeat].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::builder()
+    ///     .heartbeat(Duration::from_secs(30), Duration::from_secs(10))
+    ///     .connect()
+    ///     .expect("connection failed");
+    ///
+    /// if !client.is_healthy() {
+    ///     println!("connection is not responding");
+    /// }
+    /// ```
+    pub fn is_healthy(&self) -> bool {
+        self.message_bus().is_healthy()
     }
 
-    pub fn server_version(&self) -> i32 {
-        self.server_version
+    /// Disconnects cleanly from TWS or Gateway.
+    ///
+    /// Flushes pending writes, then shuts down as [Client::shutdown] does and waits for the threads processing messages to stop.
+    /// Outstanding iterators end and subsequent requests fail with [Error::Shutdown].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// client.disconnect().expect("disconnect failed");
+    /// ```
+    pub fn disconnect(&self) -> Result<(), Error> {
+        self.message_bus().disconnect()
     }
 
-    /// The time of the server when the client connected
-    pub fn connection_time(&self) -> Option<OffsetDateTime> {
-        self.connection_time.clone()
+    /// Requests the server's current time, with second precision.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let server_time = client.server_time().expect("request failed");
+    /// println!("server time: {server_time}");
+    /// ```
+    pub fn server_time(&self) -> Result<OffsetDateTime, Error> {
+        let message = RequestMessage::current_time(self.server_version);
+        self.request_current_time(message, decode_current_time)
     }
 
-    /// Returns the managed accounts.
-    pub fn managed_accounts(&self) -> String {
-        self.managed_accounts.to_owned()
+    /// Requests the server's current time, with millisecond precision.
+    ///
+    /// Servers that don't support millisecond precision fall back to [Client::server_time].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let server_time = client.server_time_millis().expect("request failed");
+    /// println!("server time: {server_time}");
+    /// ```
+    pub fn server_time_millis(&self) -> Result<OffsetDateTime, Error> {
+        if self.server_version < server_versions::CURRENT_TIME_IN_MILLIS {
+            return self.server_time();
+        }
+
+        let message = RequestMessage::current_time_millis(self.server_version);
+        self.request_current_time(message, decode_current_time_in_millis)
     }
+}
 
-    // === Accounts ===
+// === Accounts ===
 
+#[cfg(feature = "accounts")]
+impl Client {
     /// Get current [Position]s for all accessible accounts.
     #[allow(clippy::needless_lifetimes)]
     pub fn positions<'a>(&'a self) -> core::result::Result<impl Iterator<Item = Position> + 'a, Error> {
         accounts::positions(self)
     }
 
-    // === Contracts ===
+    /// Get current positions netted across all accessible accounts, keyed by contract id.
+    ///
+    /// Consumes the initial [Position] snapshot. Quantities are summed and the average cost is weighted by the size held in each account.
+    /// The average cost is None when some accounts are long and others short, see [NetPosition::average_cost].
+    /// Returns [Error::Timeout] if the end of the snapshot never arrives.
+    ///
+    /// # Arguments
+    /// * `include_flat` - include contracts whose positions net to zero.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let positions = client.net_positions(false).expect("request failed");
+    /// for (contract_id, position) in &positions {
+    ///     println!("{contract_id}: {} @ {:?}", position.position, position.average_cost);
+    /// }
+    /// ```
+    pub fn net_positions(&self, include_flat: bool) -> Result<HashMap<i32, NetPosition>, Error> {
+        accounts::net_positions(self, include_flat)
+    }
+
+    /// Replaces the Financial Advisor's configuration for the given data type.
+    ///
+    /// Blocks until the server acknowledges the change and returns the status text reported by the server.
+    ///
+    /// # Arguments
+    /// * `fa_data_type` - type of configuration being replaced. e.g. [FaDataType::Groups]
+    /// * `xml`          - XML document describing the new configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::accounts::FaDataType;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><ListOfGroups></ListOfGroups>";
+    /// let status = client.replace_fa(FaDataType::Groups, xml).expect("request failed");
+    /// println!("status: {status}");
+    /// ```
+    pub fn replace_fa(&self, fa_data_type: FaDataType, xml: &str) -> Result<String, Error> {
+        accounts::replace_fa(self, fa_data_type, xml)
+    }
+
+    /// Get current [FamilyCode]s for all accessible accounts.
+    pub fn family_codes(&self) -> Result<Vec<FamilyCode>, Error> {
+        accounts::family_codes(self)
+    }
+}
+
+// === Contracts ===
 
+impl Client {
     /// Requests contract information.
     ///
     /// Provides all the contracts matching the contract provided. It can also be used to retrieve complete options and futures chains. Though it is now (in API version > 9.72.12) advised to use reqSecDefOptParams for that purpose.
@@ -244,9 +1642,35 @@ impl Client {
         Ok(contracts::contract_details(self, contract)?.into_iter())
     }
 
-    /// Get current [FamilyCode]s for all accessible accounts.
-    pub fn family_codes(&self) -> Result<Vec<FamilyCode>, Error> {
-        accounts::family_codes(self)
+    /// Requests contract information for many contracts at once.
+    ///
+    /// All requests are sent up front and the results for each request are collected independently.
+    /// This is much faster than calling [Self::contract_details()] for each contract in turn.
+    ///
+    /// Returns the [contracts::ContractDetails] for each contract, keyed by the contract's index in `contracts`.
+    /// Each contract has its own result, so one failed request, e.g. for an unknown symbol, doesn't fail the others.
+    ///
+    /// # Arguments
+    /// * `contracts` - The [Contract]s used as samples to query the available contracts.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contracts = vec![Contract::stock("TSLA"), Contract::stock("AAPL"), Contract::stock("MSFT")];
+    /// for (i, result) in client.contract_details_batch(&contracts) {
+    ///     match result {
+    ///         Ok(contract_details) => println!("{}: {:?}", contracts[i].symbol, contract_details),
+    ///         Err(err) => println!("{}: {err}", contracts[i].symbol),
+    ///     }
+    /// }
+    /// ```
+    pub fn contract_details_batch(&self, contracts: &[Contract]) -> Vec<(usize, Result<Vec<contracts::ContractDetails>, Error>)> {
+        contracts::contract_details_batch(self, contracts)
     }
 
     /// Requests details about a given market rule
@@ -258,6 +1682,31 @@ impl Client {
         contracts::market_rule(self, market_rule_id)
     }
 
+    /// Rounds a price to the [Contract]'s minimum price increment.
+    ///
+    /// Resolves the market rule for the contract's exchange and rounds using the price increment applicable at the given price.
+    /// Prices that do not conform to the minimum increment are rejected by TWS.
+    ///
+    /// # Arguments
+    /// * `contract` - [Contract] the price is for.
+    /// * `price`    - price to round.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("TSLA");
+    /// let price = client.round_to_tick(&contract, 187.123).expect("request failed");
+    /// println!("price: {price}");
+    /// ```
+    pub fn round_to_tick(&self, contract: &Contract, price: f64) -> Result<f64, Error> {
+        contracts::round_to_tick(self, contract, price)
+    }
+
     /// Requests matching stock symbols.
     ///
     /// # Arguments
@@ -278,9 +1727,12 @@ impl Client {
     pub fn matching_symbols(&self, pattern: &str) -> Result<impl Iterator<Item = contracts::ContractDescription>, Error> {
         Ok(contracts::matching_symbols(self, pattern)?.into_iter())
     }
+}
 
-    // === Orders ===
+// === Orders ===
 
+#[cfg(feature = "orders")]
+impl Client {
     /// Requests all *current* open orders in associated accounts at the current moment.
     /// Open orders are returned once; this function does not initiate a subscription.
     ///
@@ -347,7 +1799,7 @@ impl Client {
     /// Requests completed [Order]s.
     ///
     /// # Arguments
-    /// * `api_only` - request only orders placed by the API.
+    /// * `api_only` - request only orders placed by the API. Set to false to also include orders placed manually in TWS, e.g. for end of day reconciliation.
     ///
     /// # Examples
     ///
@@ -411,7 +1863,9 @@ impl Client {
         orders::global_cancel(self)
     }
 
-    /// Cancels all open [Order]s.
+    /// Requests the next valid order id from the server (reqIds), and continues [Client::next_order_id] from it.
+    ///
+    /// Useful when several clients place orders on the same account, as order ids must increase across all of them.
     ///
     /// # Examples
     ///
@@ -460,35 +1914,181 @@ impl Client {
     ///
     /// ```no_run
     /// use ibapi::Client;
-    /// use ibapi::contracts::Contract;
-    /// use ibapi::orders::{order_builder, Action, OrderNotification};
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::orders::{order_builder, Action, OrderNotification};
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("MSFT");
+    /// let order = order_builder::market_order(Action::Buy, 100.0);
+    /// let order_id = client.next_order_id();
+    ///
+    /// let notifications = client.place_order(order_id, &contract, &order).expect("request failed");
+    ///
+    /// for notification in notifications {
+    ///     match notification {
+    ///         OrderNotification::OrderStatus(order_status) => {
+    ///             println!("order status: {order_status:?}")
+    ///         }
+    ///         OrderNotification::OpenOrder(open_order) => println!("open order: {open_order:?}"),
+    ///         OrderNotification::ExecutionData(execution) => println!("execution: {execution:?}"),
+    ///         OrderNotification::CommissionReport(report) => println!("commission report: {report:?}"),
+    ///         OrderNotification::Message(message) => println!("message: {message:?}"),
+    ///    }
+    /// }
+    /// ```
+    pub fn place_order(&self, order_id: i32, contract: &Contract, order: &Order) -> Result<impl Iterator<Item = OrderNotification>, Error> {
+        orders::place_order(self, order_id, contract, order)
+    }
+
+    /// Submits an [Order], resubmitting it once with rounded prices if it is rejected for not conforming to the minimum price increment.
+    ///
+    /// When the server rejects the order with error code 110, the limit and auxiliary prices are rounded to the nearest valid increment
+    /// for the [Contract] (see [Client::round_to_tick]) and the order is resubmitted with the same order id. A second rejection is returned
+    /// as an [OrderNotification::Message].
+    ///
+    /// # Arguments
+    /// * `order_id` - ID for [Order]. Get next valid ID using [Client::next_order_id].
+    /// * `contract` - [Contract] to submit order for.
+    /// * `order` - [Order] to submit.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::orders::{order_builder, Action};
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("MSFT");
+    /// let order = order_builder::limit_order(Action::Buy, 100.0, 330.123);
+    /// let order_id = client.next_order_id();
+    ///
+    /// let notifications = client.place_order_auto_round(order_id, &contract, &order).expect("request failed");
+    ///
+    /// for notification in notifications {
+    ///     println!("{notification:?}");
+    /// }
+    /// ```
+    pub fn place_order_auto_round<'a>(
+        &'a self,
+        order_id: i32,
+        contract: &Contract,
+        order: &Order,
+    ) -> Result<impl Iterator<Item = OrderNotification> + 'a, Error> {
+        orders::place_order_auto_round(self, order_id, contract, order)
+    }
+
+    /// Submits a basket of [Order]s in a single write.
+    ///
+    /// All but the last [Order] are submitted with `transmit` set to false, so that the basket is released when the last [Order] is transmitted.
+    /// Link the orders with `parent_id` to have them transmitted together, e.g. legs of a pairs trade.
+    ///
+    /// Returns an iterator of order notifications for each [Order], in the order submitted.
+    ///
+    /// # Arguments
+    /// * `orders` - order id, [Contract] and [Order] of each order in the basket. Get next valid ID using [Client::next_order_id].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::orders::{order_builder, Action};
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let parent_id = client.next_order_id();
+    /// let mut short = order_builder::market_order(Action::Sell, 100.0);
+    /// short.parent_id = parent_id;
+    ///
+    /// let basket = vec![
+    ///     (parent_id, Contract::stock("KO"), order_builder::market_order(Action::Buy, 100.0)),
+    ///     (client.next_order_id(), Contract::stock("PEP"), short),
+    /// ];
+    ///
+    /// let notifications = client.place_basket(&basket).expect("request failed");
+    ///
+    /// for (i, order_notifications) in notifications.into_iter().enumerate() {
+    ///     for notification in order_notifications {
+    ///         println!("order {}: {notification:?}", basket[i].0);
+    ///     }
+    /// }
+    /// ```
+    pub fn place_basket(&self, orders: &[(i32, Contract, Order)]) -> Result<Vec<impl Iterator<Item = OrderNotification>>, Error> {
+        orders::place_basket(self, orders)
+    }
+
+    /// Encodes the request [Client::place_order] would send, without sending it.
+    ///
+    /// The order is validated the same way as when it is placed. [RequestMessage::encode] returns the message as sent.
+    ///
+    /// # Arguments
+    /// * `order_id` - ID for [Order].
+    /// * `contract` - [Contract] to submit order for.
+    /// * `order` - [Order] to submit.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::orders::{order_builder, Action};
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("MSFT");
+    /// let order = order_builder::limit_order(Action::Buy, 100.0, 250.0);
+    ///
+    /// let message = client.encode_place_order(client.next_order_id(), &contract, &order).expect("encoding failed");
+    /// println!("{}", message.encode().replace('\0', "|"));
+    /// ```
+    pub fn encode_place_order(&self, order_id: i32, contract: &Contract, order: &Order) -> Result<RequestMessage, Error> {
+        orders::encode_place_order(self, order_id, contract, order)
+    }
+
+    /// Encodes the request [Client::cancel_order] would send, without sending it.
+    ///
+    /// # Arguments
+    /// * `order_id` - ID of [Order] to cancel.
+    /// * `manual_order_cancel_time` - can't find documentation. leave blank.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
     ///
     /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
     ///
-    /// let contract = Contract::stock("MSFT");
-    /// let order = order_builder::market_order(Action::Buy, 100.0);
-    /// let order_id = client.next_order_id();
+    /// let message = client.encode_cancel_order(41, "").expect("encoding failed");
+    /// println!("{}", message.encode().replace('\0', "|"));
+    /// ```
+    pub fn encode_cancel_order(&self, order_id: i32, manual_order_cancel_time: &str) -> Result<RequestMessage, Error> {
+        orders::encode_cancel_order(self, order_id, manual_order_cancel_time)
+    }
+
+    /// Encodes the request [Client::global_cancel] would send, without sending it.
     ///
-    /// let notifications = client.place_order(order_id, &contract, &order).expect("request failed");
+    /// # Examples
     ///
-    /// for notification in notifications {
-    ///     match notification {
-    ///         OrderNotification::OrderStatus(order_status) => {
-    ///             println!("order status: {order_status:?}")
-    ///         }
-    ///         OrderNotification::OpenOrder(open_order) => println!("open order: {open_order:?}"),
-    ///         OrderNotification::ExecutionData(execution) => println!("execution: {execution:?}"),
-    ///         OrderNotification::CommissionReport(report) => println!("commission report: {report:?}"),
-    ///         OrderNotification::Message(message) => println!("message: {message:?}"),
-    ///    }
-    /// }
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let message = client.encode_global_cancel().expect("encoding failed");
+    /// println!("{}", message.encode().replace('\0', "|"));
     /// ```
-    pub fn place_order(&self, order_id: i32, contract: &Contract, order: &Order) -> Result<impl Iterator<Item = OrderNotification>, Error> {
-        orders::place_order(self, order_id, contract, order)
+    pub fn encode_global_cancel(&self) -> Result<RequestMessage, Error> {
+        orders::encode_global_cancel(self)
     }
+}
 
-    // === Historical Market Data ===
+// === Historical Market Data ===
 
+#[cfg(feature = "market_data")]
+impl Client {
     /// Returns the timestamp of earliest available historical data for a contract and data type.
     /// ```no_run
     /// use ibapi::Client;
@@ -595,6 +2195,48 @@ impl Client {
         historical::historical_data(self, contract, None, duration, bar_size, Some(what_to_show), use_rth)
     }
 
+    /// Requests interval of historical data ending now for [Contract] and keeps the most recent bar up to date.
+    ///
+    /// The iterator returns the initial historical bars followed by updates to the bar currently forming.
+    /// Updates continue until the iterator is dropped.
+    ///
+    /// # Arguments
+    /// * `contract`     - [Contract] to retrieve [historical::Bar]s for.
+    /// * `duration`     - duration of interval to retrieve [historical::Bar]s for.
+    /// * `bar_size`     - [historical::BarSize] to return.
+    /// * `what_to_show` - requested bar type: [historical::WhatToShow].
+    /// * `use_rth`      - use regular trading hours.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::Client;
+    /// use ibapi::market_data::historical::{BarSize, ToDuration, WhatToShow};
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("TSLA");
+    ///
+    /// let bars = client
+    ///     .historical_data_streaming(&contract, 1.days(), BarSize::Min5, WhatToShow::Trades, true)
+    ///     .expect("historical data request failed");
+    ///
+    /// for bar in bars {
+    ///     println!("{bar:?}");
+    /// }
+    /// ```
+    pub fn historical_data_streaming<'a>(
+        &'a self,
+        contract: &Contract,
+        duration: historical::Duration,
+        bar_size: historical::BarSize,
+        what_to_show: historical::WhatToShow,
+        use_rth: bool,
+    ) -> Result<impl Iterator<Item = historical::Bar> + 'a, Error> {
+        historical::historical_data_streaming(self, contract, duration, bar_size, Some(what_to_show), use_rth)
+    }
+
     /// Requests [historical::HistoricalSchedule] for an interval of given duration
     /// ending at specified date.
     ///
@@ -787,8 +2429,145 @@ impl Client {
     ) -> Result<impl Iterator<Item = historical::TickLast>, Error> {
         historical::historical_ticks_trade(self, contract, start, end, number_of_ticks, use_rth)
     }
+}
+
+// === Realtime Market Data ===
+
+#[cfg(feature = "market_data")]
+impl Client {
+    /// Subscribes to the market data of a contract.
+    ///
+    /// Returns the price, size, string and generic ticks as they are received. The subscription is cancelled when the iterator is dropped.
+    /// A snapshot subscription ends with [TickEvent::SnapshotEnd](realtime::TickEvent::SnapshotEnd), after which the server releases it.
+    /// The first event is usually [TickEvent::RequestParameters](realtime::TickEvent::RequestParameters), reporting the minimum tick, BBO exchange and snapshot permissions of the subscription.
+    ///
+    /// # Arguments
+    /// * `contract`      - The [Contract] to request market data for.
+    /// * `generic_ticks` - the [GenericTick](realtime::GenericTick)s to receive in addition to the default ticks.
+    /// * `snapshot`      - request a one time snapshot instead of a streaming subscription.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::market_data::realtime::{GenericTick, TickEvent};
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("AAPL");
+    /// let ticks = client.market_data(&contract, &[GenericTick::RTVolume], false).expect("request failed");
+    ///
+    /// for tick in ticks.take(100) {
+    ///     match tick {
+    ///         TickEvent::Price { tick_type, price, attributes } => println!("{tick_type:?}: {price:?} {attributes:?}"),
+    ///         TickEvent::Size { tick_type, size } => println!("{tick_type:?}: {size}"),
+    ///         tick => println!("{tick:?}"),
+    ///     }
+    /// }
+    /// ```
+    pub fn market_data<'a>(
+        &'a self,
+        contract: &Contract,
+        generic_ticks: &[realtime::GenericTick],
+        snapshot: bool,
+    ) -> Result<impl Iterator<Item = realtime::TickEvent> + 'a, Error> {
+        realtime::market_data(self, contract, generic_ticks, snapshot)
+    }
+
+    /// Subscribes to the order book of a contract.
+    ///
+    /// The first updates insert the rows of the book, later updates insert, update or delete rows as the book changes. The subscription is cancelled when the iterator is dropped.
+    /// Level II data requires a market depth subscription for the exchange.
+    ///
+    /// If the server resets the book, [MarketDepthUpdate::Reset](realtime::MarketDepthUpdate::Reset) is received and the book is rebuilt by the updates that follow.
+    /// [OrderBook](realtime::OrderBook) maintains the book from the updates.
+    ///
+    /// A smart depth book aggregates the books of all exchanges offering market depth for the contract, and reports the exchange
+    /// of each row as its market maker. See [Client::market_depth_exchanges] to label the exchanges.
+    ///
+    /// # Arguments
+    /// * `contract`       - The [Contract] to request the order book for.
+    /// * `number_of_rows` - number of rows on each side of the book.
+    /// * `is_smart_depth` - request the aggregated book of all exchanges.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::market_data::realtime::MarketDepthUpdate;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("AAPL");
+    /// let updates = client.market_depth(&contract, 5, false).expect("request failed");
+    ///
+    /// for update in updates.take(100) {
+    ///     match update {
+    ///         MarketDepthUpdate::MarketDepth(depth) => println!("{depth:?}"),
+    ///         MarketDepthUpdate::MarketDepthL2(depth) => println!("{}: {depth:?}", depth.market_maker),
+    ///         MarketDepthUpdate::Reset => println!("book reset"),
+    ///     }
+    /// }
+    /// ```
+    pub fn market_depth<'a>(
+        &'a self,
+        contract: &Contract,
+        number_of_rows: i32,
+        is_smart_depth: bool,
+    ) -> Result<impl Iterator<Item = realtime::MarketDepthUpdate> + 'a, Error> {
+        realtime::market_depth(self, contract, number_of_rows, is_smart_depth)
+    }
+
+    /// Requests the exchanges offering market depth, with the aggregated group each belongs to in smart depth books.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let exchanges = client.market_depth_exchanges().expect("request failed");
+    ///
+    /// for exchange in &exchanges {
+    ///     println!("{} {:?}: {} {:?}", exchange.exchange, exchange.security_type, exchange.service_data_type, exchange.aggregated_group);
+    /// }
+    /// ```
+    pub fn market_depth_exchanges(&self) -> Result<Vec<realtime::DepthMarketDataDescription>, Error> {
+        realtime::market_depth_exchanges(self)
+    }
 
-    // === Realtime Market Data ===
+    /// Requests a one time snapshot of the contract's market data.
+    ///
+    /// Returns once the server signals the end of the snapshot. Forex pairs are quoted on bid and ask only, so [MarketSnapshot::price] falls back to the midpoint.
+    ///
+    /// A regulatory snapshot returns the consolidated NBBO for US stocks without a market data subscription.
+    /// **Each regulatory snapshot is billed (currently USD 0.01 per request)**, so it is only requested when `regulatory` is true.
+    /// Billed snapshots are marked by [MarketSnapshot::billed].
+    /// Requesting a regulatory snapshot fails, without sending the request, if the server does not support it.
+    ///
+    /// # Arguments
+    /// * `contract`   - The [Contract] to request market data for.
+    /// * `regulatory` - request a cost-bearing regulatory snapshot instead of a regular snapshot.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::forex("EUR", "USD");
+    /// let snapshot = client.market_snapshot(&contract, false).expect("request failed");
+    ///
+    /// println!("bid: {:?}, ask: {:?}, price: {:?} at {:?}", snapshot.bid, snapshot.ask, snapshot.price(), snapshot.timestamp);
+    /// ```
+    pub fn market_snapshot(&self, contract: &Contract, regulatory: bool) -> Result<MarketSnapshot, Error> {
+        realtime::market_snapshot(self, contract, regulatory)
+    }
 
     /// Requests realtime bars.
     ///
@@ -823,12 +2602,58 @@ impl Client {
         realtime::realtime_bars(self, contract, &bar_size, &what_to_show, use_rth, Vec::default())
     }
 
+    /// Switches the type of market data returned by subsequent market data requests.
+    ///
+    /// The server confirms the type in use on each subscription with a [TickEvent::MarketDataType](realtime::TickEvent::MarketDataType).
+    /// With [MarketDataType::Delayed], accounts without a live market data subscription receive delayed ticks, reported as the Delayed tick types.
+    ///
+    /// # Arguments
+    /// * `market_data_type` - the [MarketDataType] to request.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    /// use ibapi::market_data::realtime::MarketDataType;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// client.set_market_data_type(MarketDataType::Delayed).expect("request failed");
+    ///
+    /// let contract = Contract::stock("AAPL");
+    /// let snapshot = client.market_snapshot(&contract, false).expect("request failed");
+    ///
+    /// println!("delayed price: {:?}", snapshot.price());
+    /// ```
+    pub fn set_market_data_type(&self, market_data_type: MarketDataType) -> Result<(), Error> {
+        realtime::set_market_data_type(self, market_data_type)
+    }
+
     /// Requests tick by tick AllLast ticks.
     ///
+    /// AllLast ticks include trades reported outside the consolidated tape, such as combos, odd lots and derivatives. The subscription is cancelled when the iterator is dropped.
+    ///
     /// # Arguments
     /// * `contract` - The [Contract] used as sample to query the available contracts. Typically, it will contain the [Contract]'s symbol, currency, security_type, and exchange.
     /// * `number_of_ticks` - number of ticks.
     /// * `ignore_size` - ignore size flag.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("AAPL");
+    /// let ticks = client.tick_by_tick_all_last(&contract, 0, false).expect("request failed");
+    ///
+    /// for trade in ticks.take(10) {
+    ///     println!("{} {} @ {} on {} [{}]", trade.time, trade.size, trade.price, trade.exchange, trade.special_conditions);
+    /// }
+    /// ```
     pub fn tick_by_tick_all_last<'a>(
         &'a self,
         contract: &Contract,
@@ -840,10 +2665,28 @@ impl Client {
 
     /// Requests tick by tick BidAsk ticks.
     ///
+    /// The subscription is cancelled when the iterator is dropped.
+    ///
     /// # Arguments
     /// * `contract` - The [Contract] used as sample to query the available contracts. Typically, it will contain the [Contract]'s symbol, currency, security_type, and exchange.
     /// * `number_of_ticks` - number of ticks.
     /// * `ignore_size` - ignore size flag.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("AAPL");
+    /// let ticks = client.tick_by_tick_bid_ask(&contract, 0, false).expect("request failed");
+    ///
+    /// for bid_ask in ticks.take(10) {
+    ///     println!("{} {}x{} / {}x{}", bid_ask.time, bid_ask.bid_size, bid_ask.bid_price, bid_ask.ask_price, bid_ask.ask_size);
+    /// }
+    /// ```
     pub fn tick_by_tick_bid_ask<'a>(
         &'a self,
         contract: &Contract,
@@ -855,10 +2698,28 @@ impl Client {
 
     /// Requests tick by tick Last ticks.
     ///
+    /// Last ticks only include trades reported on the consolidated tape. The subscription is cancelled when the iterator is dropped.
+    ///
     /// # Arguments
     /// * `contract` - The [Contract] used as sample to query the available contracts. Typically, it will contain the [Contract]'s symbol, currency, security_type, and exchange.
     /// * `number_of_ticks` - number of ticks.
     /// * `ignore_size` - ignore size flag.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("AAPL");
+    /// let ticks = client.tick_by_tick_last(&contract, 0, false).expect("request failed");
+    ///
+    /// for trade in ticks.take(10) {
+    ///     println!("{} {} @ {}", trade.time, trade.size, trade.price);
+    /// }
+    /// ```
     pub fn tick_by_tick_last<'a>(
         &'a self,
         contract: &Contract,
@@ -870,10 +2731,28 @@ impl Client {
 
     /// Requests tick by tick MidPoint ticks.
     ///
+    /// The subscription is cancelled when the iterator is dropped.
+    ///
     /// # Arguments
     /// * `contract` - The [Contract] used as sample to query the available contracts. Typically, it will contain the [Contract]'s symbol, currency, security_type, and exchange.
     /// * `number_of_ticks` - number of ticks.
     /// * `ignore_size` - ignore size flag.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ibapi::Client;
+    /// use ibapi::contracts::Contract;
+    ///
+    /// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+    ///
+    /// let contract = Contract::stock("AAPL");
+    /// let ticks = client.tick_by_tick_midpoint(&contract, 0, false).expect("request failed");
+    ///
+    /// for mid_point in ticks.take(10) {
+    ///     println!("{} {}", mid_point.time, mid_point.mid_point);
+    /// }
+    /// ```
     pub fn tick_by_tick_midpoint<'a>(
         &'a self,
         contract: &Contract,
@@ -882,79 +2761,190 @@ impl Client {
     ) -> Result<impl Iterator<Item = realtime::MidPoint> + 'a, Error> {
         realtime::tick_by_tick_midpoint(self, contract, number_of_ticks, ignore_size)
     }
+}
+
+// == Internal Use ==
 
-    // == Internal Use ==
+impl Client {
+    // Locks the message bus for the duration of a call. Iterators returned by the bus don't hold the lock,
+    // so requests from other threads proceed while responses are consumed.
+    pub(crate) fn message_bus(&self) -> MutexGuard<'_, Box<dyn MessageBus>> {
+        self.message_bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
 
     #[cfg(test)]
-    pub(crate) fn stubbed(message_bus: RefCell<Box<dyn MessageBus>>, server_version: i32) -> Client {
+    pub(crate) fn stubbed(message_bus: Mutex<Box<dyn MessageBus>>, server_version: i32) -> Client {
         Client {
-            server_version: server_version,
+            server_version,
             connection_time: None,
             time_zone: None,
             managed_accounts: String::from(""),
+            optional_capabilities: String::from(""),
             message_bus,
             client_id: 100,
             next_request_id: AtomicI32::new(9000),
-            order_id: AtomicI32::new(-1),
+            order_id: Arc::new(AtomicI32::new(-1)),
+            request_timeout: Mutex::new(DEFAULT_REQUEST_TIMEOUT),
+            current_time: Mutex::new(()),
+            clock: Arc::new(SystemClock),
         }
     }
 
+    #[cfg(any(feature = "market_data", feature = "orders"))]
     pub(crate) fn send_message(&self, packet: RequestMessage) -> Result<(), Error> {
-        self.message_bus.borrow_mut().write_message(&packet)
+        self.message_bus().write_message(&packet)
     }
 
     pub(crate) fn send_request(&self, request_id: i32, message: RequestMessage) -> Result<ResponseIterator, Error> {
         debug!("send_message({:?}, {:?})", request_id, message);
-        self.message_bus.borrow_mut().send_generic_message(request_id, &message)
+        let mut responses = trace::request_span(request_id, &message, || self.message_bus().send_generic_message(request_id, &message))?;
+        responses.set_timeout(Some(self.request_timeout()));
+        join_scoped_cancellation(&mut responses);
+        Ok(responses)
     }
 
+    #[cfg(feature = "market_data")]
     pub(crate) fn send_durable_request(&self, request_id: i32, message: RequestMessage) -> Result<ResponseIterator, Error> {
         debug!("send_durable_request({:?}, {:?})", request_id, message);
-        self.message_bus.borrow_mut().send_durable_message(request_id, &message)
+        let mut responses = trace::request_span(request_id, &message, || self.message_bus().send_durable_message(request_id, &message))?;
+        join_scoped_cancellation(&mut responses);
+        Ok(responses)
     }
 
+    #[cfg(feature = "orders")]
     pub(crate) fn send_order(&self, order_id: i32, message: RequestMessage) -> Result<ResponseIterator, Error> {
         debug!("send_order({:?}, {:?})", order_id, message);
-        self.message_bus.borrow_mut().send_order_message(order_id, &message)
+        let mut responses = trace::order_span(order_id, &message, || self.message_bus().send_order_message(order_id, &message))?;
+        responses.set_timeout(Some(self.request_timeout()));
+        Ok(responses)
+    }
+
+    #[cfg(feature = "orders")]
+    pub(crate) fn send_orders(&self, orders: &[(i32, RequestMessage)]) -> Result<Vec<ResponseIterator>, Error> {
+        debug!("send_orders({:?})", orders);
+        let mut responses = trace::order_spans(orders, || self.message_bus().send_order_messages(orders))?;
+        for responses in &mut responses {
+            responses.set_timeout(Some(self.request_timeout()));
+        }
+        Ok(responses)
     }
 
     /// Sends request for the next valid order id.
+    #[cfg(feature = "orders")]
     pub(crate) fn request_next_order_id(&self, message: RequestMessage) -> Result<GlobalResponseIterator, Error> {
-        self.message_bus.borrow_mut().request_next_order_id(&message)
+        self.message_bus().request_next_order_id(&message)
     }
 
     /// Sends request for open orders.
+    #[cfg(feature = "orders")]
     pub(crate) fn request_order_data(&self, message: RequestMessage) -> Result<GlobalResponseIterator, Error> {
-        self.message_bus.borrow_mut().request_open_orders(&message)
+        self.message_bus().request_open_orders(&message)
     }
 
     /// Sends request for market rule.
     pub(crate) fn request_market_rule(&self, message: RequestMessage) -> Result<GlobalResponseIterator, Error> {
-        self.message_bus.borrow_mut().request_market_rule(&message)
+        self.message_bus().request_market_rule(&message)
     }
 
     /// Sends request for positions.
+    #[cfg(feature = "accounts")]
     pub(crate) fn request_positions(&self, message: RequestMessage) -> Result<GlobalResponseIterator, Error> {
-        self.message_bus.borrow_mut().request_positions(&message)
+        self.message_bus().request_positions(&message)
     }
 
     /// Sends request for family codes.
+    #[cfg(feature = "accounts")]
     pub(crate) fn request_family_codes(&self, message: RequestMessage) -> Result<GlobalResponseIterator, Error> {
-        self.message_bus.borrow_mut().request_family_codes(&message)
+        self.message_bus().request_family_codes(&message)
     }
 
-    pub(crate) fn check_server_version(&self, version: i32, message: &str) -> Result<(), Error> {
-        if version <= self.server_version {
-            Ok(())
-        } else {
-            Err(Error::ServerVersion(version, self.server_version, message.into()))
+    /// Sends request for the exchanges offering market depth.
+    #[cfg(feature = "market_data")]
+    pub(crate) fn request_market_depth_exchanges(&self, message: RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.message_bus().request_market_depth_exchanges(&message)
+    }
+
+    /// Sends request for the server's current time.
+    // Sends a request answered by a current time message, and decodes the answer. One request is sent at a time, as answers
+    // carry no request id.
+    fn request_current_time(
+        &self,
+        message: RequestMessage,
+        decode: fn(&mut ResponseMessage) -> Result<OffsetDateTime, Error>,
+    ) -> Result<OffsetDateTime, Error> {
+        let _current_time = self.current_time.lock().unwrap();
+
+        let mut responses = self.message_bus().request_current_time(&message)?;
+
+        match responses.next() {
+            Some(mut message) => decode(&mut message),
+            None => Err(Error::Timeout),
         }
     }
+
+    pub(crate) fn check_server_version(&self, version: i32, message: &str) -> Result<(), Error> {
+        check_server_version(self.server_version, version, message)
+    }
 }
 
 impl Drop for Client {
+    // Stops the message processing threads, which would otherwise outlive the client.
     fn drop(&mut self) {
-        info!("dropping basic client")
+        info!("dropping basic client");
+
+        if let Err(err) = self.message_bus().shutdown() {
+            error!("error shutting down client: {err}");
+        }
+    }
+}
+
+/// Cloneable handle to a [Client] that can be shared between threads.
+///
+/// The handle dereferences to the [Client], an `Arc<Client>` under the hood. Threads send requests concurrently,
+/// and iterators returned by the [Client] borrow the handle they were requested through.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::thread;
+///
+/// use ibapi::client::ClientHandle;
+/// use ibapi::Client;
+///
+/// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+/// let handle = ClientHandle::new(client);
+///
+/// let worker = {
+///     let handle = handle.clone();
+///     thread::spawn(move || handle.server_time())
+/// };
+///
+/// println!("server time: {:?}", handle.server_time());
+/// println!("server time: {:?}", worker.join().unwrap());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientHandle {
+    client: Arc<Client>,
+}
+
+impl ClientHandle {
+    /// Creates a handle that takes ownership of the [Client].
+    pub fn new(client: Client) -> ClientHandle {
+        ClientHandle { client: Arc::new(client) }
+    }
+}
+
+impl Deref for ClientHandle {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl From<Client> for ClientHandle {
+    fn from(client: Client) -> ClientHandle {
+        ClientHandle::new(client)
     }
 }
 
@@ -969,7 +2959,7 @@ impl Debug for Client {
 }
 
 // Parses following format: 20230405 22:20:39 PST
-fn parse_connection_time(connection_time: &str) -> (Option<OffsetDateTime>, Option<&'static Tz>) {
+pub(crate) fn parse_connection_time(connection_time: &str) -> (Option<OffsetDateTime>, Option<&'static Tz>) {
     let parts: Vec<&str> = connection_time.split(' ').collect();
 
     let zones = timezones::find_by_name(parts[2]);
@@ -993,11 +2983,67 @@ fn parse_connection_time(connection_time: &str) -> (Option<OffsetDateTime>, Opti
         },
         Err(err) => {
             error!("could not parse connection time from {date_str}: {err}");
-            return (None, Some(timezone));
+            (None, Some(timezone))
         }
     }
 }
 
+pub(crate) fn decode_current_time(message: &mut ResponseMessage) -> Result<OffsetDateTime, Error> {
+    if message.message_type() != IncomingMessages::CurrentTime {
+        return Err(Error::UnexpectedResponse(message.message_type()));
+    }
+
+    message.skip(); // message type
+    message.skip(); // message version
+
+    message.next_date_time()
+}
+
+fn decode_current_time_in_millis(message: &mut ResponseMessage) -> Result<OffsetDateTime, Error> {
+    if message.message_type() != IncomingMessages::CurrentTimeInMillis {
+        return Err(Error::UnexpectedResponse(message.message_type()));
+    }
+
+    message.skip(); // message type
+
+    let millis = message.next_long()?;
+    match OffsetDateTime::from_unix_timestamp_nanos(millis as i128 * 1_000_000) {
+        Ok(time) => Ok(time),
+        Err(err) => Err(Error::InvalidResponse(format!("invalid server time {millis}: {err}"))),
+    }
+}
+
+// Fails unless the server version is at least version, the version introducing the feature described by message.
+pub(crate) fn check_server_version(server_version: i32, version: i32, message: &str) -> Result<(), Error> {
+    if version <= server_version {
+        Ok(())
+    } else {
+        Err(Error::ServerVersion(version, server_version, message.into()))
+    }
+}
+
+// Joins responses to the token of the enclosing Client::with_cancellation, if any.
+fn join_scoped_cancellation(responses: &mut ResponseIterator) {
+    SCOPED_CANCELLATION.with_borrow(|token| {
+        if let Some(token) = token {
+            responses.set_cancellation(token);
+        }
+    });
+}
+
+pub(crate) fn startup_timed_out(startup_timeout: Duration) -> Error {
+    error!("timed out after {startup_timeout:?} waiting for next valid order id and managed accounts");
+    Error::Timeout
+}
+
+// Encodes the handshake announcing the range of server versions supported.
+pub(crate) fn encode_handshake() -> String {
+    let prefix = "API\0";
+    let version = format!("v{MIN_SERVER_VERSION}..{MAX_SERVER_VERSION}");
+
+    prefix.to_owned() + &encode_packet(&version)
+}
+
 fn encode_packet(message: &str) -> String {
     let data = message.as_bytes();
 
@@ -0,0 +1,97 @@
+//! Sources of the current time. Live clients read the system clock, while replayed sessions and simulations advance a
+//! [VirtualClock], so the time read from the client with [Client::now](crate::Client::now), and the fills and `GTD` expirations
+//! of the simulator, follow the replayed data rather than the wall clock.
+//!
+//! The connection itself still runs on the system clock: request and startup timeouts, rate limiting, heartbeats and the
+//! maintenance window are unaffected by a [VirtualClock].
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use time::{Duration, OffsetDateTime};
+
+/// Source of the current time of a [Client](crate::Client). See [Client::now](crate::Client::now).
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> OffsetDateTime;
+}
+
+/// The system clock, used by clients connected to TWS or Gateway.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+/// A clock only moving when told to, e.g. to the time of each message of a replayed session. Clones share the same time.
+///
+/// Time never moves backwards: setting an earlier time leaves the clock unchanged.
+///
+/// ```
+/// use ibapi::client::{Clock, VirtualClock};
+/// use time::macros::datetime;
+/// use time::Duration;
+///
+/// let clock = VirtualClock::new(datetime!(2023-04-15 16:30:00 UTC));
+/// clock.advance(Duration::seconds(5));
+///
+/// assert_eq!(clock.now(), datetime!(2023-04-15 16:30:05 UTC));
+/// ```
+#[derive(Clone, Debug)]
+pub struct VirtualClock {
+    now: Arc<Mutex<OffsetDateTime>>,
+}
+
+impl VirtualClock {
+    /// Creates a clock reading `start` until moved.
+    pub fn new(start: OffsetDateTime) -> VirtualClock {
+        VirtualClock {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    /// Moves the clock to `time`, if later than the current time.
+    pub fn set(&self, time: OffsetDateTime) {
+        let mut now = self.now.lock().unwrap();
+        if time > *now {
+            *now = time;
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration.abs();
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> OffsetDateTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn virtual_clock() {
+        let clock = VirtualClock::new(datetime!(2023-04-15 16:30:00 UTC));
+        let shared = clock.clone();
+
+        clock.set(datetime!(2023-04-15 16:31:00 UTC));
+        assert_eq!(shared.now(), datetime!(2023-04-15 16:31:00 UTC));
+
+        clock.set(datetime!(2023-04-15 16:29:00 UTC));
+        assert_eq!(shared.now(), datetime!(2023-04-15 16:31:00 UTC), "time never moves backwards");
+
+        shared.advance(Duration::minutes(-1));
+        assert_eq!(clock.now(), datetime!(2023-04-15 16:32:00 UTC));
+    }
+}
@@ -1,8 +1,14 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+
 use time::macros::datetime;
 use time_tz::{timezones, OffsetResult, PrimitiveDateTimeExt};
 
 use super::*;
 
+use crate::client::transport::TcpMessageBus;
+use crate::stubs::{fake_gateway, MessageBusStub, RequestMessages};
+
 #[test]
 fn test_parse_connection_time() {
     let example = "20230405 22:20:39 PST";
@@ -13,3 +19,850 @@ fn test_parse_connection_time() {
         assert_eq!(connection_time, Some(other));
     }
 }
+
+#[test]
+fn start_api_with_optional_capabilities() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let mut client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+    client.optional_capabilities = "+PACEAPI".to_owned();
+
+    let results = client.start_api();
+    assert!(results.is_ok(), "failed to start api: {:?}", results.err());
+
+    let request_messages = client.message_bus().request_messages();
+
+    assert_eq!(request_messages[0].encode_simple(), "71|2|100|+PACEAPI|");
+    assert_eq!(client.optional_capabilities(), "+PACEAPI");
+}
+
+#[test]
+fn start_api_without_optional_capabilities_support() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let mut client = Client::stubbed(message_bus, server_versions::OPTIONAL_CAPABILITIES);
+    client.optional_capabilities = "+PACEAPI".to_owned();
+
+    let results = client.start_api();
+    assert!(results.is_ok(), "failed to start api: {:?}", results.err());
+
+    let request_messages = client.message_bus().request_messages();
+
+    assert_eq!(request_messages[0].encode_simple(), "71|2|100|");
+}
+
+#[test]
+fn test_decode_current_time_in_millis() {
+    let mut message = ResponseMessage::from("109\x001678890000123\x00");
+
+    let server_time = decode_current_time_in_millis(&mut message).expect("failed to decode current time in millis");
+
+    assert_eq!(server_time, datetime!(2023-03-15 14:20:00.123 UTC));
+    assert_eq!(server_time.millisecond(), 123);
+}
+
+#[test]
+fn current_time_decoders_check_message_type() {
+    let mut message = ResponseMessage::from("49\x001\x001678890000\x00");
+    let result = decode_current_time_in_millis(&mut message);
+    assert!(
+        matches!(result, Err(Error::UnexpectedResponse(IncomingMessages::CurrentTime))),
+        "{result:?}"
+    );
+
+    let mut message = ResponseMessage::from("109\x001678890000123\x00");
+    let result = decode_current_time(&mut message);
+    assert!(
+        matches!(result, Err(Error::UnexpectedResponse(IncomingMessages::CurrentTimeInMillis))),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn server_time_millis_falls_back_on_older_servers() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["49|1|1678890000|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let server_time = client.server_time_millis().expect("failed to request server time");
+
+    assert_eq!(server_time, datetime!(2023-03-15 14:20:00 UTC));
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0].encode_simple(), "49|1|");
+}
+
+#[test]
+fn server_time_millis() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["109|1678890000123|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::CURRENT_TIME_IN_MILLIS);
+
+    let server_time = client.server_time_millis().expect("failed to request server time");
+
+    assert_eq!(server_time, datetime!(2023-03-15 14:20:00.123 UTC));
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0].encode_simple(), "105|");
+}
+
+#[test]
+fn connection_info() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "173|20230405 22:20:39 PST|".to_owned(),
+            "9|1|90|".to_owned(),
+            "15|1|DU1234567,DU7654321,|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::do_connect(100, "", DEFAULT_STARTUP_TIMEOUT, message_bus).expect("connection failed");
+
+    let info = client.connection_info();
+
+    let la = timezones::db::america::LOS_ANGELES;
+    let connection_time = datetime!(2023-04-05 22:20:39).assume_timezone(la).unwrap();
+
+    assert_eq!(info.server_version, 173, "info.server_version");
+    assert_eq!(info.server_time, Some(connection_time), "info.server_time");
+    assert_eq!(info.client_id, 100, "info.client_id");
+    assert_eq!(info.managed_accounts, vec!["DU1234567", "DU7654321"], "info.managed_accounts");
+    assert_eq!(info.next_valid_order_id, 90, "info.next_valid_order_id");
+
+    // snapshot does not consume order ids
+    assert_eq!(client.connection_info().next_valid_order_id, 90, "info.next_valid_order_id");
+    assert_eq!(client.next_order_id(), 90, "client.next_order_id()");
+    assert_eq!(client.connection_info().next_valid_order_id, 91, "info.next_valid_order_id");
+}
+
+#[test]
+fn connect_sequence() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "173|20230405 22:20:39 PST|".to_owned(),
+            "9|1|90|".to_owned(),
+            "15|1|DU1234567|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::do_connect(100, "+PACEAPI", DEFAULT_STARTUP_TIMEOUT, message_bus).expect("connection failed");
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages.len(), 2, "messages written during connect");
+
+    // handshake: API prefix followed by the length prefixed range of supported server versions
+    assert_eq!(request_messages[0][0], "API\0\0\0\0\x09v100..165", "handshake");
+    // start api: message type, version, client id and optional capabilities
+    assert_eq!(request_messages[1].encode(), "71\x002\x00100\x00+PACEAPI\x00", "start api");
+}
+
+#[test]
+fn encode_start_api_optional_capabilities() {
+    let message = RequestMessage::start_api(server_versions::OPTIONAL_CAPABILITIES + 1, 100, "+PACEAPI");
+    assert_eq!(message.encode_simple(), "71|2|100|+PACEAPI|");
+
+    let message = RequestMessage::start_api(server_versions::OPTIONAL_CAPABILITIES, 100, "+PACEAPI");
+    assert_eq!(message.encode_simple(), "71|2|100|");
+}
+
+#[test]
+fn client_is_send() {
+    fn assert_send<T: Send>() {}
+
+    assert_send::<Client>();
+    assert_send::<ClientHandle>();
+}
+
+#[test]
+fn client_is_sync() {
+    fn assert_sync<T: Sync>() {}
+
+    assert_sync::<Client>();
+}
+
+#[test]
+fn client_shared_between_threads() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["49|1|1678890000|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Arc::new(Client::stubbed(message_bus, server_versions::SIZE_RULES));
+
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let client = Arc::clone(&client);
+            std::thread::spawn(move || (client.next_request_id(), client.server_time()))
+        })
+        .collect();
+
+    let mut request_ids = vec![];
+    for worker in workers {
+        let (request_id, server_time) = worker.join().expect("worker panicked");
+        assert_eq!(server_time.expect("server time request failed"), datetime!(2023-03-15 14:20:00 UTC));
+        request_ids.push(request_id);
+    }
+
+    request_ids.sort();
+    assert_eq!(request_ids, vec![9000, 9001], "request ids are unique across threads");
+    assert_eq!(client.message_bus().request_messages().len(), 2, "request_messages.len()");
+}
+
+#[test]
+fn client_handle_shared_between_threads() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["49|1|1678890000|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let handle = ClientHandle::new(Client::stubbed(message_bus, server_versions::SIZE_RULES));
+
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let handle = handle.clone();
+            std::thread::spawn(move || handle.server_time())
+        })
+        .collect();
+
+    for worker in workers {
+        let server_time = worker.join().expect("worker panicked").expect("server time request failed");
+        assert_eq!(server_time, datetime!(2023-03-15 14:20:00 UTC), "server_time");
+    }
+
+    let request_messages = handle.message_bus().request_messages();
+    assert_eq!(request_messages.len(), 2, "request_messages.len()");
+    assert_eq!(request_messages[0].encode_simple(), "49|1|", "request_messages[0]");
+    assert_eq!(request_messages[1].encode_simple(), "49|1|", "request_messages[1]");
+}
+
+#[test]
+fn client_builder() {
+    let builder = Client::builder();
+    assert_eq!(builder, ClientBuilder::default());
+    assert_eq!(builder.address(), "127.0.0.1:4002", "default address");
+
+    let builder = builder
+        .host("gateway.local")
+        .port(7497)
+        .client_id(7)
+        .optional_capabilities("+PACEAPI")
+        .connect_timeout(std::time::Duration::from_secs(5));
+
+    assert_eq!(builder.address(), "gateway.local:7497", "address");
+    assert_eq!(Client::builder().host("::1").address(), "[::1]:4002", "ipv6 address");
+    assert_eq!(Client::builder().host("[::1]").address(), "[::1]:4002", "bracketed ipv6 address");
+    assert_eq!(builder.client_id, 7, "client_id");
+    assert_eq!(builder.optional_capabilities, "+PACEAPI", "optional_capabilities");
+    assert_eq!(builder.connect_timeout, Some(std::time::Duration::from_secs(5)), "connect_timeout");
+}
+
+#[test]
+fn connect_fails_when_account_info_never_arrives() {
+    // acknowledges the handshake, then stays silent until the client gives up
+    let gateway = fake_gateway(&[&["173|20230405 22:20:39 PST|"]]);
+
+    let started = Instant::now();
+    let result = Client::builder()
+        .port(gateway.port())
+        .startup_timeout(Duration::from_millis(200))
+        .connect();
+
+    assert!(matches!(result, Err(Error::Timeout)), "expected startup timeout, got {:?}", result.err());
+    assert!(started.elapsed() < Duration::from_secs(5), "connect should fail promptly");
+
+    gateway.join();
+}
+
+#[test]
+fn retry_policy_backoff() {
+    let policy = RetryPolicy::new(10);
+
+    assert_eq!(policy.backoff(1), std::time::Duration::from_secs(1));
+    assert_eq!(policy.backoff(2), std::time::Duration::from_secs(2));
+    assert_eq!(policy.backoff(5), std::time::Duration::from_secs(16));
+    assert_eq!(policy.backoff(6), std::time::Duration::from_secs(30), "capped at max_backoff");
+    assert_eq!(policy.backoff(40), std::time::Duration::from_secs(30), "capped at max_backoff");
+}
+
+#[test]
+fn retry_policy_jitter() {
+    let policy = RetryPolicy {
+        jitter: 0.5,
+        ..RetryPolicy::new(10)
+    };
+
+    let delays: Vec<_> = (0..20).map(|_| policy.delay(3)).collect();
+    for delay in &delays {
+        assert!(
+            *delay > std::time::Duration::from_secs(2) && *delay <= std::time::Duration::from_secs(4),
+            "delay {delay:?} outside jitter range"
+        );
+    }
+    assert!(delays.iter().any(|delay| *delay != delays[0]), "delays should vary");
+
+    let policy = RetryPolicy { jitter: 0.0, ..policy };
+    assert_eq!(policy.delay(3), std::time::Duration::from_secs(4), "no jitter");
+
+    for jitter in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+        let policy = RetryPolicy { jitter, ..policy.clone() };
+        assert_eq!(policy.delay(3), std::time::Duration::from_secs(4), "jitter {jitter} ignored");
+    }
+}
+
+#[test]
+fn connect_retries_unreachable_server() {
+    // reserve a port, then close it so connections are refused
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: std::time::Duration::from_millis(50),
+        max_backoff: std::time::Duration::from_secs(1),
+        jitter: 0.0,
+    };
+
+    let started = std::time::Instant::now();
+    let result = Client::builder().port(port).retry_policy(policy).connect();
+
+    assert!(matches!(result, Err(Error::Io(_))), "expected connection error, got {:?}", result.err());
+    // waits 50ms then 100ms between the three attempts
+    assert!(
+        started.elapsed() >= std::time::Duration::from_millis(150),
+        "elapsed: {:?}",
+        started.elapsed()
+    );
+}
+
+#[test]
+fn maintenance_window() {
+    use std::time::Duration;
+    use time::macros::time;
+
+    let new_york = timezones::db::america::NEW_YORK;
+    let window = MaintenanceWindow::new(time!(23:45), Duration::from_secs(15 * 60), new_york);
+
+    // 23:50 EDT
+    assert_eq!(window.remaining(datetime!(2023-03-15 03:50 UTC)), Some(Duration::from_secs(10 * 60)));
+    // 23:30 EDT
+    assert_eq!(window.remaining(datetime!(2023-03-15 03:30 UTC)), None);
+    // midnight EDT, as the window ends
+    assert!(!window.contains(datetime!(2023-03-15 04:00 UTC)));
+
+    // windows spanning midnight
+    let window = MaintenanceWindow::new(time!(23:55), Duration::from_secs(15 * 60), new_york);
+    assert_eq!(window.remaining(datetime!(2023-03-15 04:05 UTC)), Some(Duration::from_secs(5 * 60)));
+    assert_eq!(window.remaining(datetime!(2023-03-15 03:56 UTC)), Some(Duration::from_secs(14 * 60)));
+    assert!(!window.contains(datetime!(2023-03-15 04:15 UTC)));
+}
+
+#[test]
+fn replay_recorded_session() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["173|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // current time
+        &["49|1|1678740829|"],
+    ]);
+
+    let path = std::env::temp_dir().join(format!("ibapi-replay-{}.capture", std::process::id()));
+
+    let client = Client::builder().port(gateway.port()).client_id(7).wire_capture(&path).connect().unwrap();
+    let server_time = client.server_time().unwrap();
+    client.disconnect().unwrap();
+    gateway.join();
+
+    let replayed = Client::replay(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(replayed.server_version(), 173);
+    assert_eq!(replayed.client_id, 7, "client id from recording");
+    assert_eq!(replayed.managed_accounts(), "DU1234567");
+    assert_eq!(replayed.next_order_id(), 90);
+    assert_eq!(replayed.server_time().unwrap(), server_time);
+}
+
+#[test]
+fn replay_clock_follows_recorded_session() {
+    // capture line of a message, with its length prefix
+    let line = |time: &str, direction: &str, prefix: &str, message: &str| {
+        let mut bytes = prefix.as_bytes().to_vec();
+        bytes.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(message.as_bytes());
+        let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+        format!("{time} {direction} {hex}\n")
+    };
+    let capture = [
+        line("2023-04-15T16:30:00Z", "->", "API\0", "v100..165"),
+        line("2023-04-15T16:30:00.1Z", "<-", "", "165\x0020230415 16:30:00 UTC\x00"),
+        line("2023-04-15T16:30:00.2Z", "->", "", "71\x002\x00100\x00\x00"),
+        line("2023-04-15T16:30:00.3Z", "<-", "", "9\x001\x0090\x00"),
+        line("2023-04-15T16:30:00.3Z", "<-", "", "15\x001\x00DU1234567\x00"),
+        line("2023-04-15T16:31:00Z", "->", "", "49\x001\x00"),
+        line("2023-04-15T16:31:05Z", "<-", "", "49\x001\x001681576265\x00"),
+    ]
+    .concat();
+
+    let path = std::env::temp_dir().join(format!("ibapi-replay-clock-{}.capture", std::process::id()));
+    std::fs::write(&path, capture).unwrap();
+
+    let replayed = Client::replay(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert!(replayed.now() < datetime!(2023-04-15 16:31:05 UTC), "responses not played back yet");
+    replayed.server_time().unwrap();
+    assert_eq!(replayed.now(), datetime!(2023-04-15 16:31:05 UTC));
+}
+
+#[test]
+fn replay_session_recorded_over_message_bus() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["176|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // matching symbols
+        &["79|9000|1|76792991|TSLA|STK|NASDAQ|USD|0|Tesla Inc|e1|"],
+        // current time
+        &["49|1|1678740829|"],
+    ]);
+
+    let path = std::env::temp_dir().join(format!("ibapi-recording-{}.capture", std::process::id()));
+
+    let message_bus = RecordingMessageBus::new(Box::new(TcpMessageBus::connect(&gateway.address).unwrap()), &path).unwrap();
+    let client = Client::with_message_bus(Box::new(message_bus), 7).unwrap();
+    let symbols: Vec<String> = client
+        .matching_symbols("TSLA")
+        .unwrap()
+        .map(|description| description.contract.symbol)
+        .collect();
+    let server_time = client.server_time().unwrap();
+    client.disconnect().unwrap();
+    gateway.join();
+
+    let replayed = Client::replay(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(replayed.server_version(), 176);
+    assert_eq!(replayed.client_id, 7, "client id from recording");
+    assert_eq!(replayed.next_order_id(), 90);
+    let replayed_symbols: Vec<String> = replayed
+        .matching_symbols("TSLA")
+        .unwrap()
+        .map(|description| description.contract.symbol)
+        .collect();
+    assert_eq!(replayed_symbols, symbols);
+    assert_eq!(replayed.server_time().unwrap(), server_time);
+
+    // requests past the end of the recording receive no responses
+    assert_eq!(replayed.matching_symbols("AAPL").unwrap().count(), 0);
+}
+
+#[test]
+#[cfg(feature = "orders")]
+fn next_order_id_follows_next_valid_ids() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["173|20230405 22:20:39 PST|"],
+        // start api, then a next valid id sent unrequested
+        &["9|1|90|", "15|1|DU1234567|", "9|1|120|"],
+        // request ids
+        &["9|1|130|"],
+    ]);
+
+    let client = Client::builder().port(gateway.port()).connect().unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while client.connection_info().next_valid_order_id != 120 {
+        assert!(Instant::now() < deadline, "next order id not raised by next valid id sent unrequested");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert_eq!(
+        client.next_valid_order_id().unwrap(),
+        130,
+        "skips next valid id received before the request"
+    );
+    assert_eq!(client.next_order_id(), 130);
+    assert_eq!(client.next_order_id(), 131);
+
+    client.disconnect().unwrap();
+    gateway.join();
+}
+
+#[test]
+fn unknown_messages_are_skipped() {
+    let gateway = fake_gateway(&[
+        // handshake
+        &["173|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // current time, after messages of unknown types
+        &["250|1|9000|some|new|fields|", "251|", "49|1|1678740829|"],
+    ]);
+
+    let client = Client::builder().port(gateway.port()).connect().unwrap();
+    let server_time = client.server_time().unwrap();
+    assert_eq!(server_time.unix_timestamp(), 1678740829, "messages after unknown ids still arrive");
+    assert!(client.is_healthy());
+
+    client.disconnect().unwrap();
+    gateway.join();
+}
+
+#[test]
+fn failover() {
+    let backup = fake_gateway(&[
+        // handshake
+        &["173|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+    ]);
+
+    // the primary host can't be resolved
+    let client = Client::builder().host("no host").failover(&backup.address).connect().unwrap();
+    assert_eq!(client.managed_accounts(), "DU1234567");
+    assert_eq!(client.next_order_id(), 90);
+
+    client.disconnect().unwrap();
+    backup.join();
+}
+
+#[test]
+fn request_timeout() {
+    use crate::contracts::Contract;
+
+    // contract details are never answered
+    let gateway = fake_gateway(&[
+        // handshake
+        &["173|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+    ]);
+
+    let client = Client::builder()
+        .port(gateway.port())
+        .request_timeout(Duration::from_millis(100))
+        .connect()
+        .unwrap();
+    assert_eq!(client.request_timeout(), Duration::from_millis(100));
+
+    let started = Instant::now();
+    let result = client.contract_details(&Contract::stock("AAPL")).err();
+    assert!(matches!(result, Some(Error::Timeout)), "{result:?}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let scoped = client.with_request_timeout(Duration::from_millis(300), |client| {
+        let started = Instant::now();
+        let result = client.contract_details(&Contract::stock("AAPL")).err();
+        assert!(matches!(result, Some(Error::Timeout)), "{result:?}");
+        (client.request_timeout(), started.elapsed())
+    });
+    assert_eq!(scoped.0, Duration::from_millis(300));
+    assert!(scoped.1 >= Duration::from_millis(300));
+    assert_eq!(client.request_timeout(), Duration::from_millis(100), "timeout restored after scope");
+
+    client.set_request_timeout(Duration::from_secs(1));
+    assert_eq!(client.request_timeout(), Duration::from_secs(1));
+
+    client.disconnect().unwrap();
+    gateway.join();
+}
+
+#[test]
+fn request_timeout_restored_when_scope_panics() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        client.with_request_timeout(Duration::from_secs(120), |_| panic!("request failed"))
+    }));
+    assert!(result.is_err(), "expected the scope to panic");
+
+    assert_eq!(client.request_timeout(), DEFAULT_REQUEST_TIMEOUT, "timeout restored after panic");
+}
+
+#[test]
+fn cancellation_scope_restored_when_scope_panics() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+    let token = CancellationToken::new();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        client.with_cancellation(&token, |_| panic!("request failed"))
+    }));
+    assert!(result.is_err(), "expected the scope to panic");
+
+    assert!(SCOPED_CANCELLATION.with_borrow(Option::is_none), "token released after panic");
+}
+
+#[test]
+#[cfg(feature = "market_data")]
+fn subscriptions_dropped_after_shutdown() {
+    use crate::contracts::Contract;
+
+    let gateway = fake_gateway(&[
+        // handshake
+        &["173|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // realtime bars
+        &["50|3|9000|1678323335|4028.75|4029.00|4028.25|4028.50|2|4026.75|1|"],
+        // market data
+        &[],
+    ]);
+
+    let client = Client::builder().port(gateway.port()).connect().unwrap();
+
+    let mut bars = client
+        .realtime_bars(&Contract::stock("AAPL"), BarSize::Sec5, WhatToShow::Trades, false)
+        .unwrap();
+    assert!(bars.next().is_some(), "bar received before shutting down");
+    let ticks = client.market_data(&Contract::stock("AAPL"), &[], false).unwrap();
+
+    client.shutdown().unwrap();
+
+    // cancelling with the server fails, which is logged rather than panicking
+    drop(bars);
+    drop(ticks);
+
+    gateway.join();
+}
+
+#[test]
+#[cfg(feature = "market_data")]
+fn cancellation_token() {
+    use crate::contracts::Contract;
+
+    let gateway = fake_gateway(&[
+        // handshake
+        &["173|20230405 22:20:39 PST|"],
+        // start api
+        &["9|1|90|", "15|1|DU1234567|"],
+        // realtime bars
+        &["50|3|9000|1678323335|4028.75|4029.00|4028.25|4028.50|2|4026.75|1|"],
+    ]);
+
+    let client = Client::builder().port(gateway.port()).connect().unwrap();
+
+    let token = CancellationToken::new();
+    let mut bars = client
+        .with_cancellation(&token, |client| {
+            client.realtime_bars(&Contract::stock("AAPL"), BarSize::Sec5, WhatToShow::Trades, false)
+        })
+        .unwrap();
+    assert!(bars.next().is_some(), "bar received before cancelling");
+
+    token.cancel();
+    assert!(bars.next().is_none(), "subscription ends once cancelled");
+    drop(bars);
+
+    // a request waiting for its response is woken
+    let token = CancellationToken::new();
+    let canceller = token.clone();
+    let started = Instant::now();
+    let cancelling = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        canceller.cancel();
+    });
+    let result = client
+        .with_request_timeout(Duration::from_secs(30), |client| {
+            client.with_cancellation(&token, |client| {
+                client.head_timestamp(&Contract::stock("AAPL"), historical::WhatToShow::Trades, true)
+            })
+        })
+        .err();
+    cancelling.join().unwrap();
+    assert!(matches!(result, Some(Error::Cancelled)), "{result:?}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // requests sent with a cancelled token are cancelled right away
+    let result = client
+        .with_cancellation(&token, |client| {
+            client.head_timestamp(&Contract::stock("AAPL"), historical::WhatToShow::Trades, true)
+        })
+        .err();
+    assert!(matches!(result, Some(Error::Cancelled)), "{result:?}");
+
+    // cancel messages are queued by the bus's signal thread, so may follow later requests
+    let mut cancels = vec!["51|1|9000|", "90|9001|", "90|9002|"];
+    while !cancels.is_empty() {
+        let request = gateway.requests.recv_timeout(Duration::from_secs(5)).expect("cancel messages not sent");
+        cancels.retain(|cancel| *cancel != request);
+    }
+    client.disconnect().unwrap();
+
+    // after the handshake and start api
+    let requests = gateway.join().split_off(2);
+    let count = |request: &str| requests.iter().filter(|sent| *sent == request).count();
+    assert_eq!(requests.len(), 6, "requests sent: {requests:?}");
+    assert_eq!(count("51|1|9000|"), 1, "realtime bars cancelled once: {requests:?}");
+    assert_eq!(count("90|9001|"), 1, "head timestamp cancelled: {requests:?}");
+    assert_eq!(count("90|9002|"), 1, "head timestamp cancelled: {requests:?}");
+}
+
+#[cfg(all(feature = "client_portal", feature = "accounts", feature = "market_data"))]
+#[test]
+fn client_portal_session() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+    use tungstenite::handshake::derive_accept_key;
+    use tungstenite::protocol::Role;
+    use tungstenite::{Message, WebSocket};
+
+    fn serve(stream: TcpStream, config: Arc<ServerConfig>) {
+        let mut reader = BufReader::new(StreamOwned::new(ServerConnection::new(config).unwrap(), stream));
+
+        // request line and headers, none when the client refused the certificate
+        let mut head = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            if line.trim().is_empty() {
+                break;
+            }
+            head.push(line.trim_end().to_owned());
+        }
+        let header = |name: &str| {
+            head.iter()
+                .find_map(|line| line.split_once(':').filter(|(header, _)| header.eq_ignore_ascii_case(name)))
+                .map(|(_, value)| value.trim().to_owned())
+        };
+
+        let path = head[0].split(' ').nth(1).unwrap().to_owned();
+        if path == "/v1/api/ws" {
+            assert_eq!(header("cookie").as_deref(), Some("api=abc123"));
+            let accept = derive_accept_key(header("sec-websocket-key").unwrap().as_bytes());
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+            )
+            .unwrap();
+
+            let buffered = reader.buffer().to_vec();
+            let mut websocket = WebSocket::from_partially_read(reader.into_inner(), buffered, Role::Server, None);
+            while let Ok(message) = websocket.read() {
+                if message.to_text().unwrap_or_default().starts_with("smd+265598+") {
+                    let update = r#"{"topic":"smd+265598","conid":265598,"84":"168.41","86":"168.43","31":"C168.42","88":"1,300"}"#;
+                    websocket.send(Message::text(update)).unwrap();
+                }
+            }
+            return;
+        }
+
+        let body = match path.as_str() {
+            "/v1/api/iserver/auth/status" => r#"{"authenticated":true,"connected":true}"#,
+            "/v1/api/iserver/accounts" => r#"{"accounts":["DU1234567"],"selectedAccount":"DU1234567"}"#,
+            "/v1/api/portfolio/accounts" => r#"[{"id":"DU1234567"}]"#,
+            "/v1/api/tickle" => r#"{"session":"abc123","iserver":{"authStatus":{"authenticated":true}}}"#,
+            "/v1/api/portfolio/DU1234567/positions/0" => {
+                r#"[{"acctId":"DU1234567","conid":265598,"contractDesc":"AAPL","position":100.0,"avgCost":150.25,"assetClass":"STK","ticker":"AAPL","currency":"USD","strike":0,"multiplier":null}]"#
+            }
+            _ => "[]",
+        };
+        let stream = reader.get_mut();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nDate: Tue, 14 Mar 2023 20:53:49 GMT\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        stream.conn.send_close_notify();
+        stream.flush().unwrap();
+    }
+
+    let fixture = |name: &str| format!("{}/fixtures/tls/{name}", env!("CARGO_MANIFEST_DIR"));
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let certificates = CertificateDer::pem_file_iter(fixture("server.pem"))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let key = PrivateKeyDer::from_pem_file(fixture("server-key.pem")).unwrap();
+    let server_config = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .unwrap();
+    let server_config = Arc::new(server_config);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let config = Arc::clone(&server_config);
+            std::thread::spawn(move || serve(stream, config));
+        }
+    });
+
+    // the gateway's certificate isn't issued by a Mozilla root
+    let result = Client::connect_client_portal(&address);
+    assert!(
+        matches!(result, Err(Error::ConnectionFailed(_))),
+        "expected certificate error, got {result:?}"
+    );
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_file(fixture("ca.pem")).unwrap()).unwrap();
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let client = Client::connect_client_portal_with_tls(&address, config).unwrap();
+
+    assert_eq!(client.server_version(), MAX_SERVER_VERSION);
+    assert_eq!(client.managed_accounts(), "DU1234567");
+    assert_eq!(client.server_time().unwrap(), datetime!(2023-03-14 20:53:49 UTC));
+
+    let positions: Vec<Position> = client.positions().unwrap().collect();
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].account, "DU1234567");
+    assert_eq!(positions[0].contract.contract_id, 265598);
+    assert_eq!(positions[0].contract.symbol, "AAPL");
+    assert_eq!(positions[0].position, 100.0);
+    assert_eq!(positions[0].average_cost, 150.25);
+
+    let mut contract = Contract::stock("AAPL");
+    contract.contract_id = 265598;
+    let snapshot = client.market_snapshot(&contract, false).unwrap();
+    assert_eq!(snapshot.bid, Some(168.41));
+    assert_eq!(snapshot.ask, Some(168.43));
+    assert_eq!(snapshot.last, Some(168.42));
+    assert_eq!(snapshot.bid_size, Some(1300.0));
+
+    assert!(client.family_codes().is_err(), "requests without client portal equivalent fail");
+
+    client.disconnect().unwrap();
+}
@@ -1,61 +1,293 @@
-use std::collections::HashMap;
-use std::io::{prelude::*, Cursor};
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::io::{prelude::*, Cursor, ErrorKind};
 use std::iter::Iterator;
-use std::net::TcpStream;
-use std::sync::{Arc, RwLock};
+use std::net::{self, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use crossbeam::channel::{self, Receiver, Sender};
-use log::{debug, error, info};
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use log::{debug, error, info, warn};
+use time::OffsetDateTime;
 
+use crate::client::{
+    ConnectionEvent, ErrorEvent, MaintenanceWindow, OverflowPolicy, ParseMode, RetryPolicy, SocketOptions, Socks5Proxy, SubscriptionBuffer,
+};
 use crate::messages::IncomingMessages;
-use crate::messages::{RequestMessage, ResponseMessage};
-use crate::{server_versions, Error};
+use crate::messages::{OutgoingMessages, RequestMessage, ResponseMessage};
+use crate::{server_versions, Error, ToField};
+pub(crate) use buffer_pool::BufferPool;
+pub use cancellation::CancellationToken;
+use cancellation::Registration;
+use capture::Direction;
+pub(crate) use capture::{read_capture, WireCapture};
+#[cfg(feature = "client_portal")]
+pub(crate) use client_portal::ClientPortalBus;
+use connection::Connection;
+#[cfg(feature = "tls")]
+pub(crate) use connection::TlsConfig;
+use metrics::Metrics;
+pub use metrics::MetricsObserver;
+use rate_limiter::RateLimiter;
 use recorder::MessageRecorder;
-
+pub use replay::{RecordingMessageBus, ReplayMessageBus};
+use writer::{FrameSender, MessageWriter};
+
+mod buffer_pool;
+mod cancellation;
+mod capture;
+#[cfg(feature = "client_portal")]
+mod client_portal;
+mod connection;
+mod metrics;
+mod rate_limiter;
 mod recorder;
-
-pub(crate) trait MessageBus {
+pub(crate) mod replay;
+mod socket;
+mod socks5;
+pub(crate) mod trace;
+mod writer;
+
+/// Transport between the [Client](crate::Client) and TWS. Implement it to run the client over a custom transport,
+/// e.g. an in-memory bus for tests or a bridge to an existing connection manager, and connect with [Client::with_message_bus](crate::Client::with_message_bus).
+///
+/// The client drives the bus as follows:
+///
+/// 1. [write](MessageBus::write) sends the handshake. [read_message](MessageBus::read_message) returns the reply:
+///    the server version and connection time, e.g. `"173\020230405 22:20:39 PST\0"`.
+/// 2. [start_api](MessageBus::start_api) starts the API. [read_message](MessageBus::read_message) returns the
+///    next valid order id ([NextValidId](crate::messages::IncomingMessages::NextValidId)) and managed accounts
+///    ([ManagedAccounts](crate::messages::IncomingMessages::ManagedAccounts)), under the timeout set with [set_read_timeout](MessageBus::set_read_timeout).
+/// 3. [process_messages](MessageBus::process_messages) starts routing messages received to the iterators returned for requests.
+///    Messages are routed by [request id](crate::messages::ResponseMessage::request_id), [order id](crate::messages::ResponseMessage::order_id)
+///    or, for messages without either, to the iterator returned by the `request_*` method for their type.
+///
+/// Requests and responses are [RequestMessage]s and [ResponseMessage]s, the fields of the messages exchanged with TWS.
+/// [ResponseIterator]s send a [Signal] when dropped, after which responses to the request or order may be discarded.
+///
+/// Only the methods sending and receiving messages must be implemented. The connection management hooks do nothing by default.
+pub trait MessageBus: Send + Any {
+    /// Blocks until the next message is received. Only used until [process_messages](MessageBus::process_messages) is called.
     fn read_message(&mut self) -> Result<ResponseMessage, Error>;
 
+    /// Sends a message without expecting responses, e.g. a cancellation.
     fn write_message(&mut self, packet: &RequestMessage) -> Result<(), Error>;
 
+    /// Sends a request and returns its responses, which stop once none arrives for a while.
     fn send_generic_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error>;
+    /// Sends a subscription and returns its responses, until cancelled. Subscriptions should be renewed after reconnecting.
     fn send_durable_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error>;
+    /// Sends an order related request and returns the messages about the order.
     fn send_order_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error>;
-    fn request_next_order_id(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error>;
-    fn request_open_orders(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error>;
-    fn request_market_rule(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error>;
-    fn request_positions(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error>;
-    fn request_family_codes(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error>;
+    /// Sends several orders together, without other messages interleaved, and returns the messages about each.
+    /// By default each order is sent with [send_order_message](MessageBus::send_order_message).
+    fn send_order_messages(&mut self, orders: &[(i32, RequestMessage)]) -> Result<Vec<ResponseIterator>, Error> {
+        orders
+            .iter()
+            .map(|(order_id, message)| self.send_order_message(*order_id, message))
+            .collect()
+    }
+
+    /// Requests the next valid order id. Responses are [NextValidId](crate::messages::IncomingMessages::NextValidId) messages.
+    ///
+    /// The `request_*` methods fail with [Error::NotImplemented](crate::Error::NotImplemented) unless implemented.
+    fn request_next_order_id(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+    /// Requests open orders. Responses are open order, order status and open order end messages.
+    fn request_open_orders(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+    /// Requests a market rule. Responses are [MarketRule](crate::messages::IncomingMessages::MarketRule) messages.
+    fn request_market_rule(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+    /// Requests positions. Responses are [Position](crate::messages::IncomingMessages::Position) messages, then a position end message.
+    fn request_positions(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+    /// Requests family codes. Responses are [FamilyCodes](crate::messages::IncomingMessages::FamilyCodes) messages.
+    fn request_family_codes(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+    /// Requests the exchanges offering market depth. Responses are [MktDepthExchanges](crate::messages::IncomingMessages::MktDepthExchanges) messages.
+    fn request_market_depth_exchanges(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
+    /// Requests the server's time. Responses are current time messages, in seconds or milliseconds as requested.
+    fn request_current_time(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(Error::NotImplemented)
+    }
 
+    /// Sends raw data. Only used for the handshake, which is `API\0` followed by the length prefixed range of supported server versions.
     fn write(&mut self, packet: &str) -> Result<(), Error>;
 
-    fn process_messages(&mut self, server_version: i32) -> Result<(), Error>;
+    /// Asks the server to start processing messages. The request is repeated when the connection is re-established.
+    /// By default the message is sent with [write_message](MessageBus::write_message).
+    fn start_api(&mut self, message: &RequestMessage) -> Result<(), Error> {
+        self.write_message(message)
+    }
+
+    // The methods below are hooks for the client's connection management. They do nothing by default.
 
-    fn request_messages(&self) -> Vec<RequestMessage> {
-        vec![]
+    /// Blocks until messages sent so far were written.
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Starts routing received messages to the iterators returned for requests.
+    fn process_messages(&mut self, _server_version: i32) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Sets how messages routed afterwards handle unparseable fields.
+    fn set_parse_mode(&mut self, _parse_mode: ParseMode) {}
+
+    /// Bounds the buffer of durable subscriptions started afterwards. None buffers without bound.
+    fn set_subscription_buffer(&mut self, _subscription_buffer: Option<SubscriptionBuffer>) {}
+
+    /// Reports traffic with the server to observer from now on. None stops reporting.
+    fn set_metrics_observer(&mut self, _observer: Option<Arc<dyn MetricsObserver>>) {}
+
+    /// Raises next_order_id to the order id of each [NextValidId](crate::messages::IncomingMessages::NextValidId) message
+    /// routed from now on, including those the server sends unrequested, e.g. after reconnecting.
+    fn track_next_order_id(&mut self, _next_order_id: Arc<AtomicI32>) {}
+
+    /// Bounds how long read_message blocks. None blocks until a message arrives.
+    /// On timeout, read_message fails with an [io error](crate::Error::Io) of kind `TimedOut` or `WouldBlock`.
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Stops processing messages and releases every response iterator, so consumers blocked waiting on responses return.
+    /// Requests afterwards fail with [Error::Shutdown](crate::Error::Shutdown).
+    fn shutdown(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Periodically sends message and marks the connection unhealthy when nothing is received within deadline.
+    fn start_heartbeat(&mut self, _message: &RequestMessage, _interval: Duration, _deadline: Duration) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Subscribes to connection lifecycle events.
+    fn connection_events(&mut self) -> Receiver<ConnectionEvent> {
+        channel::never()
+    }
+
+    /// Subscribes to every error, warning and notification received from the server.
+    fn error_events(&mut self) -> Receiver<ErrorEvent> {
+        channel::never()
+    }
+
+    /// False once a heartbeat went unanswered, until a message is received again.
+    fn is_healthy(&self) -> bool {
+        true
+    }
+
+    /// Flushes pending writes, shuts down and waits for the message processing threads to stop.
+    fn disconnect(&mut self) -> Result<(), Error> {
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 pub struct TcpMessageBus {
-    reader: Arc<TcpStream>,
-    writer: Box<TcpStream>,
+    address: String,
+    // the primary or failover address currently connected to
+    connected_address: String,
+    connect_options: ConnectOptions,
+    reconnect_policy: RetryPolicy,
+    maintenance_window: Option<MaintenanceWindow>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    request_timeout: Duration,
+    subscription_buffer: Option<SubscriptionBuffer>,
+    stream: Arc<RwLock<Arc<Connection>>>,
+    writer: MessageWriter,
+    start_api: Option<RequestMessage>,
     handles: Vec<JoinHandle<i32>>,
     requests: Arc<SenderHash<i32, ResponseMessage>>,
     orders: Arc<SenderHash<i32, ResponseMessage>>,
+    subscriptions: Arc<Subscriptions>,
     recorder: MessageRecorder,
+    buffers: Arc<BufferPool>,
     globals: Arc<GlobalChannels>,
     signals_send: Sender<Signal>,
     signals_recv: Receiver<Signal>,
+    parse_mode: Arc<RwLock<ParseMode>>,
+    shutdown: Arc<AtomicBool>,
+    heartbeat: Arc<Heartbeat>,
+    metrics: Arc<Metrics>,
+}
+
+// Tracks liveness of the connection. Any message from the server counts as a response to a heartbeat.
+#[derive(Debug)]
+struct Heartbeat {
+    last_received: Mutex<Instant>,
+    // senders of the current time requests not yet answered, oldest first
+    current_time_requests: Mutex<VecDeque<CurrentTimeRequester>>,
+    healthy: AtomicBool,
+}
+
+// Sender of a current time request. Answers carry no request id, but arrive in the order the requests were sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CurrentTimeRequester {
+    Heartbeat,
+    Client,
+}
+
+impl Heartbeat {
+    fn new() -> Self {
+        Heartbeat {
+            last_received: Mutex::new(Instant::now()),
+            current_time_requests: Mutex::new(VecDeque::new()),
+            healthy: AtomicBool::new(true),
+        }
+    }
+
+    // Sends a current time request with send, recording its sender once sent. Answers received meanwhile wait, so they are
+    // matched in the order the requests were written.
+    fn send_current_time(&self, requester: CurrentTimeRequester, send: impl FnOnce() -> Result<(), Error>) -> Result<(), Error> {
+        let mut requests = self.current_time_requests.lock().unwrap();
+        send()?;
+        requests.push_back(requester);
+        Ok(())
+    }
+
+    // Forgets requests sent on a lost connection, which won't be answered.
+    fn reconnected(&self) {
+        self.current_time_requests.lock().unwrap().clear();
+    }
+
+    // Records a message from the server. Returns true if the message answers a heartbeat and should not be dispatched.
+    fn received(&self, message: &ResponseMessage) -> bool {
+        *self.last_received.lock().unwrap() = Instant::now();
+        self.healthy.store(true, Ordering::Relaxed);
+
+        if message.message_type() != IncomingMessages::CurrentTime {
+            return false;
+        }
+        self.current_time_requests.lock().unwrap().pop_front() == Some(CurrentTimeRequester::Heartbeat)
+    }
+
+    fn received_since(&self, instant: Instant) -> bool {
+        *self.last_received.lock().unwrap() >= instant
+    }
 }
 
+/// Sent by a [ResponseIterator] when dropped, so the [MessageBus] can release the request or order.
 pub enum Signal {
+    /// Responses to the request id are no longer consumed.
     Request(i32),
+    /// Messages about the order id are no longer consumed.
     Order(i32),
+    /// The request was cancelled with a [CancellationToken]. Responses are no longer consumed, and the message, e.g. cancelMktData, is sent to the server.
+    Cancel(i32, RequestMessage),
+    /// Stops the thread releasing requests and orders.
+    Stop,
 }
 
 #[derive(Debug)]
@@ -70,6 +302,45 @@ struct GlobalChannels {
     recv_positions: Arc<Receiver<ResponseMessage>>,
     send_family_codes: Arc<Sender<ResponseMessage>>,
     recv_family_codes: Arc<Receiver<ResponseMessage>>,
+    send_market_depth_exchanges: Arc<Sender<ResponseMessage>>,
+    recv_market_depth_exchanges: Arc<Receiver<ResponseMessage>>,
+    send_current_time: Arc<Sender<ResponseMessage>>,
+    recv_current_time: Arc<Receiver<ResponseMessage>>,
+    connection_events: Events<ConnectionEvent>,
+    error_events: Events<ErrorEvent>,
+    // next order id of the client, raised by next valid id messages
+    next_order_id: RwLock<Option<Arc<AtomicI32>>>,
+}
+
+// Broadcasts events to every subscriber.
+#[derive(Debug)]
+struct Events<T> {
+    subscribers: Mutex<Vec<Sender<T>>>,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Events {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T: Clone + std::fmt::Debug> Events<T> {
+    fn subscribe(&self) -> Receiver<T> {
+        let (sender, receiver) = channel::unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    // Sends event to subscribers, forgetting those that dropped their receiver.
+    fn publish(&self, event: T) {
+        trace::event(&event);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
 }
 
 impl GlobalChannels {
@@ -79,6 +350,8 @@ impl GlobalChannels {
         let (send_market_rule, recv_market_rule) = channel::unbounded();
         let (send_positions, recv_positions) = channel::unbounded();
         let (send_family_codes, recv_family_codes) = channel::unbounded();
+        let (send_market_depth_exchanges, recv_market_depth_exchanges) = channel::unbounded();
+        let (send_current_time, recv_current_time) = channel::unbounded();
 
         GlobalChannels {
             order_ids_in: Arc::new(order_ids_in),
@@ -91,36 +364,129 @@ impl GlobalChannels {
             recv_positions: Arc::new(recv_positions),
             send_family_codes: Arc::new(send_family_codes),
             recv_family_codes: Arc::new(recv_family_codes),
+            send_market_depth_exchanges: Arc::new(send_market_depth_exchanges),
+            recv_market_depth_exchanges: Arc::new(recv_market_depth_exchanges),
+            send_current_time: Arc::new(send_current_time),
+            recv_current_time: Arc::new(recv_current_time),
+            connection_events: Events::default(),
+            error_events: Events::default(),
+            next_order_id: RwLock::new(None),
         }
     }
 }
 
+// How connections to the server are opened.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ConnectOptions {
+    // gives up connecting after timeout when given
+    pub connect_timeout: Option<Duration>,
+    // tunnels the connection through proxy when given
+    pub proxy: Option<Socks5Proxy>,
+    pub socket_options: SocketOptions,
+    // records bytes exchanged with the server when given
+    pub capture: Option<Arc<WireCapture>>,
+    // backup servers, tried in order when the address cannot be reached
+    pub failover: Vec<String>,
+    // encrypts connections when given
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfig>,
+}
+
 impl TcpMessageBus {
     // establishes TCP connection to server
     pub fn connect(connection_string: &str) -> Result<TcpMessageBus, Error> {
-        let stream = TcpStream::connect(connection_string)?;
+        TcpMessageBus::connect_with_options(connection_string, ConnectOptions::default())
+    }
+
+    // establishes TCP connection to server, as configured by options
+    pub fn connect_with_options(connection_string: &str, connect_options: ConnectOptions) -> Result<TcpMessageBus, Error> {
+        let (connected_address, stream) = connect_first(connection_string, &connect_options, open_stream)?;
 
-        let reader = Arc::new(stream.try_clone()?);
-        let writer = Box::new(stream);
         let requests = Arc::new(SenderHash::new());
         let orders = Arc::new(SenderHash::new());
 
         let (signals_send, signals_recv) = channel::unbounded();
 
+        let stream = Arc::new(RwLock::new(Arc::new(stream)));
+        let writer = MessageWriter::spawn(Arc::clone(&stream), connect_options.capture.clone());
+
         Ok(TcpMessageBus {
-            reader,
+            address: connection_string.to_owned(),
+            connected_address,
+            connect_options,
+            reconnect_policy: RetryPolicy::unlimited(),
+            maintenance_window: None,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            rate_limiter: Some(Arc::new(RateLimiter::new(DEFAULT_MAX_MESSAGES_PER_SECOND))),
+            subscription_buffer: None,
+            stream,
             writer,
+            start_api: None,
             handles: Vec::default(),
             requests,
             orders,
+            subscriptions: Arc::new(Subscriptions::new()),
             recorder: MessageRecorder::new(),
+            buffers: Arc::new(BufferPool::new()),
             globals: Arc::new(GlobalChannels::new()),
             signals_send,
             signals_recv,
+            parse_mode: Arc::new(RwLock::new(ParseMode::default())),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            heartbeat: Arc::new(Heartbeat::new()),
+            metrics: Arc::new(Metrics::default()),
         })
     }
 
-    fn add_request(&mut self, request_id: i32, sender: Sender<ResponseMessage>) -> Result<(), Error> {
+    // Sets how a dropped connection is re-established. Takes effect when messages start being processed.
+    pub(crate) fn set_reconnect_policy(&mut self, reconnect_policy: RetryPolicy) {
+        self.reconnect_policy = reconnect_policy;
+    }
+
+    // Declares the daily restart, during which requests are refused and reconnection is deferred.
+    pub(crate) fn set_maintenance_window(&mut self, maintenance_window: Option<MaintenanceWindow>) {
+        self.maintenance_window = maintenance_window;
+    }
+
+    // Sets how long requests and orders wait for each response.
+    pub(crate) fn set_request_timeout(&mut self, request_timeout: Duration) {
+        self.request_timeout = request_timeout;
+    }
+
+    // Limits outgoing messages to max_messages_per_second. None disables the limit.
+    pub(crate) fn set_rate_limit(&mut self, max_messages_per_second: Option<u32>) {
+        self.rate_limiter = max_messages_per_second.map(|limit| Arc::new(RateLimiter::new(limit)));
+    }
+
+    // Returns the current connection to the server.
+    fn stream(&self) -> Arc<Connection> {
+        Arc::clone(&self.stream.read().unwrap())
+    }
+
+    // Responses of requests sent on this bus, which fail with Error::Shutdown once it is shut down.
+    fn response_iterator(
+        &self,
+        messages: Receiver<ResponseMessage>,
+        request_id: Option<i32>,
+        order_id: Option<i32>,
+        timeout: Option<Duration>,
+    ) -> ResponseIterator {
+        let mut responses = ResponseIterator::new(messages, self.signals_send.clone(), request_id, order_id, timeout);
+        responses.shutdown = Some(Arc::clone(&self.shutdown));
+        responses
+    }
+
+    fn ensure_running(&self) -> Result<(), Error> {
+        if self.shutdown.load(Ordering::Relaxed) {
+            Err(Error::Shutdown)
+        } else if in_maintenance_window(self.maintenance_window.as_ref()).is_some() {
+            Err(Error::MaintenanceWindow)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn add_request(&mut self, request_id: i32, sender: impl Into<ResponseSender<ResponseMessage>>) -> Result<(), Error> {
         self.requests.insert(request_id, sender);
         Ok(())
     }
@@ -129,55 +495,403 @@ impl TcpMessageBus {
         self.orders.insert(order_id, sender);
         Ok(())
     }
+
+    // Queues message for writing, once the rate limit allows.
+    fn send(&mut self, message: &RequestMessage) -> Result<(), Error> {
+        let writer = self.writer.sender();
+        self.send_with(message, |packet| writer.send(vec![packet]))
+    }
+
+    // Frames message and hands it to send once the rate limit allows.
+    fn send_with(&mut self, message: &RequestMessage, send: impl FnOnce(Vec<u8>) -> Result<(), Error>) -> Result<(), Error> {
+        self.ensure_running()?;
+
+        let mut packet = Vec::new();
+        write_frame(&mut packet, message)?;
+        self.metrics.sent(message, &packet);
+
+        throttle(self.rate_limiter.as_deref());
+        send(packet)?;
+
+        self.recorder.record_request(message);
+
+        Ok(())
+    }
+}
+
+// How long to wait for each response to a request, unless configured.
+pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Default limit on messages sent to the server. The server disconnects clients exceeding 50 messages per second.
+pub(crate) const DEFAULT_MAX_MESSAGES_PER_SECOND: u32 = 50;
+
+// Waits until another message may be sent, when rate limited.
+fn throttle(rate_limiter: Option<&RateLimiter>) {
+    if let Some(rate_limiter) = rate_limiter {
+        rate_limiter.acquire();
+    }
+}
+
+// Re-sends every active subscription with its original request id, in a single batch, e.g. after the connection was re-established.
+// Subscriptions whose response channel was already released are dropped from the registry.
+fn replay_subscriptions(
+    writer: &FrameSender,
+    subscriptions: &Subscriptions,
+    requests: &SenderHash<i32, ResponseMessage>,
+    rate_limiter: Option<&RateLimiter>,
+) -> Result<usize, Error> {
+    let mut frames = Vec::new();
+
+    for (request_id, message) in subscriptions.active() {
+        if !requests.contains(&request_id) {
+            subscriptions.remove(&request_id);
+            continue;
+        }
+
+        debug!("replaying subscription for request_id {request_id}");
+        let mut packet = Vec::new();
+        write_frame(&mut packet, &message)?;
+        throttle(rate_limiter);
+        frames.push(packet);
+    }
+
+    let replayed = frames.len();
+    if replayed > 0 {
+        writer.send(frames)?;
+    }
+
+    Ok(replayed)
+}
+
+// Re-establishes a dropped connection, retrying with backoff until connected, shut down or out of attempts.
+// Each attempt tries the address, then the failover addresses. Returns the address connected to with the connection.
+fn reconnect(
+    address: &str,
+    connect_options: &ConnectOptions,
+    start_api: Option<&RequestMessage>,
+    server_version: i32,
+    policy: &RetryPolicy,
+    maintenance_window: Option<&MaintenanceWindow>,
+    shutdown: &AtomicBool,
+) -> Option<(String, Connection)> {
+    let _reconnecting = trace::reconnecting(address);
+    let mut attempt = 1;
+
+    loop {
+        if shutdown.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        // the server is expected to be down until the restart completes
+        if let Some(remaining) = in_maintenance_window(maintenance_window) {
+            info!("in maintenance window, reconnecting to {address} in {remaining:?}");
+            wait_unless_shutdown(remaining, shutdown);
+            continue;
+        }
+
+        let establish = |address: &str, connect_options: &ConnectOptions| establish_connection(address, connect_options, start_api, server_version);
+        match connect_first(address, connect_options, establish) {
+            Ok(connection) => return Some(connection),
+            Err(err) if attempt < policy.max_attempts => {
+                let delay = policy.delay(attempt);
+                warn!("reconnecting to {address} failed, retrying in {delay:?}: {err}");
+                wait_unless_shutdown(delay, shutdown);
+                attempt += 1;
+            }
+            Err(err) => {
+                error!("reconnecting to {address} failed after {attempt} attempts, giving up: {err}");
+                return None;
+            }
+        }
+    }
+}
+
+// Returns the time left in the maintenance window, if currently within it.
+fn in_maintenance_window(maintenance_window: Option<&MaintenanceWindow>) -> Option<Duration> {
+    maintenance_window.and_then(|window| window.remaining(OffsetDateTime::now_utc()))
+}
+
+// Sleeps for duration, waking early when shut down. Returns false once shut down.
+fn wait_unless_shutdown(duration: Duration, shutdown: &AtomicBool) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if shutdown.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::park_timeout(deadline - now);
+    }
+}
+
+// Connects to the address, or failing that to each failover address in turn. Returns the address connected to with the connection,
+// or the error connecting to the last address.
+fn connect_first<T, F>(address: &str, connect_options: &ConnectOptions, connect: F) -> Result<(String, T), Error>
+where
+    F: Fn(&str, &ConnectOptions) -> Result<T, Error>,
+{
+    let mut result = connect(address, connect_options);
+
+    let mut candidate = address;
+    for failover in &connect_options.failover {
+        match result {
+            Ok(_) => break,
+            Err(err) => {
+                warn!("connecting to {candidate} failed, failing over to {failover}: {err}");
+                candidate = failover;
+                result = connect(candidate, connect_options);
+            }
+        }
+    }
+
+    if result.is_ok() && candidate != address {
+        info!("connected to failover address {candidate}");
+    }
+    result.map(|stream| (candidate.to_owned(), stream))
+}
+
+// Opens a connection to the server, through the proxy when configured, and applies socket options. Completes the TLS handshake when configured.
+fn open_stream(address: &str, connect_options: &ConnectOptions) -> Result<Connection, Error> {
+    let stream = match &connect_options.proxy {
+        Some(proxy) => connect_proxied(address, proxy, connect_options.connect_timeout)?,
+        None => connect_direct(address, connect_options.connect_timeout)?,
+    };
+
+    socket::configure(&stream, &connect_options.socket_options)?;
+
+    #[cfg(feature = "tls")]
+    if let Some(tls) = &connect_options.tls {
+        return Ok(Connection::tls(stream, address, tls.config(), connect_options.connect_timeout)?);
+    }
+
+    Ok(Connection::plain(stream))
+}
+
+fn connect_proxied(address: &str, proxy: &Socks5Proxy, connect_timeout: Option<Duration>) -> Result<TcpStream, Error> {
+    let mut stream = connect_direct(&proxy.address, connect_timeout)?;
+
+    // the timeout also bounds the proxy negotiation
+    stream.set_read_timeout(connect_timeout)?;
+    socks5::handshake(&mut stream, proxy, address)?;
+    stream.set_read_timeout(None)?;
+
+    debug!("connected to {address} through proxy {}", proxy.address);
+    Ok(stream)
+}
+
+// Opens a connection to any address the host resolves to, waiting at most connect_timeout for each when given.
+fn connect_direct(address: &str, connect_timeout: Option<Duration>) -> Result<TcpStream, Error> {
+    let socket_addresses = interleave_families(address.to_socket_addrs()?.collect());
+
+    if socket_addresses.is_empty() {
+        return Err(Error::ConnectionFailed(format!("could not resolve address: {address}")));
+    }
+
+    Ok(connect_any(socket_addresses, connect_timeout)?)
+}
+
+// Delay before racing the next address while earlier attempts are still pending, as recommended by RFC 8305.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// Connects to the first address to accept. Attempts are started in order, each after the previous one fails or
+// CONNECTION_ATTEMPT_DELAY elapses, so an unreachable address doesn't hold up the others.
+fn connect_any(socket_addresses: Vec<SocketAddr>, connect_timeout: Option<Duration>) -> std::io::Result<TcpStream> {
+    let connect = move |socket_address: &SocketAddr| match connect_timeout {
+        Some(connect_timeout) => TcpStream::connect_timeout(socket_address, connect_timeout),
+        None => TcpStream::connect(socket_address),
+    };
+
+    if let [socket_address] = socket_addresses.as_slice() {
+        return connect(socket_address);
+    }
+
+    let (sender, receiver) = channel::unbounded();
+    let mut remaining = socket_addresses.into_iter();
+    let mut pending = 0;
+    let mut last_error = None;
+
+    loop {
+        if let Some(socket_address) = remaining.next() {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                debug!("connecting to {socket_address}");
+                // the losing attempts' connections are dropped
+                sender.send(connect(&socket_address)).ok();
+            });
+            pending += 1;
+        } else if pending == 0 {
+            break;
+        }
+
+        let result = if remaining.len() > 0 {
+            receiver.recv_timeout(CONNECTION_ATTEMPT_DELAY).ok()
+        } else {
+            receiver.recv().ok()
+        };
+
+        match result {
+            Some(Ok(stream)) => return Ok(stream),
+            Some(Err(err)) => {
+                pending -= 1;
+                last_error = Some(err);
+            }
+            None => {}
+        }
+    }
+
+    Err(last_error.expect("connection error"))
+}
+
+// Alternates between IPv6 and IPv4 addresses, starting with the family resolved first.
+fn interleave_families(socket_addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = socket_addresses.first() else {
+        return socket_addresses;
+    };
+
+    let preferred_ipv6 = first.is_ipv6();
+    let (preferred, others): (Vec<_>, Vec<_>) = socket_addresses.into_iter().partition(|address| address.is_ipv6() == preferred_ipv6);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + others.len());
+    let mut preferred = preferred.into_iter();
+    let mut others = others.into_iter();
+    loop {
+        match (preferred.next(), others.next()) {
+            (None, None) => return interleaved,
+            (address, other) => {
+                interleaved.extend(address);
+                interleaved.extend(other);
+            }
+        }
+    }
+}
+
+// Connects and repeats the handshake and start api sequence.
+fn establish_connection(
+    address: &str,
+    connect_options: &ConnectOptions,
+    start_api: Option<&RequestMessage>,
+    expected_server_version: i32,
+) -> Result<Connection, Error> {
+    let stream = open_stream(address, connect_options)?;
+    let capture = connect_options.capture.as_deref();
+
+    let handshake = super::encode_handshake();
+    capture_sent(capture, handshake.as_bytes());
+    (&stream).write_all(handshake.as_bytes())?;
+
+    let mut ack = read_packet(&mut &stream)?;
+    capture_received(capture, &ack);
+    let server_version = ack.next_int()?;
+    info!("reconnected to {address}, server version {server_version}");
+    // e.g. a failover server running another release. requests are still encoded for the version negotiated when connecting
+    if server_version != expected_server_version {
+        warn!("server version {server_version} of {address} differs from version {expected_server_version} negotiated when connecting");
+    }
+
+    if let Some(message) = start_api {
+        let mut packet = Vec::new();
+        write_frame(&mut packet, message)?;
+        capture_sent(capture, &packet);
+        (&stream).write_all(&packet)?;
+    }
+
+    Ok(stream)
 }
 
 const UNSPECIFIED_REQUEST_ID: i32 = -1;
 
 impl MessageBus for TcpMessageBus {
     fn read_message(&mut self) -> Result<ResponseMessage, Error> {
-        read_packet(&self.reader)
+        let message = read_packet(&mut self.stream().as_ref())?;
+        capture_received(self.connect_options.capture.as_deref(), &message);
+        Ok(message)
     }
 
     fn send_generic_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error> {
         let (sender, receiver) = channel::unbounded();
 
         self.add_request(request_id, sender)?;
-        self.write_message(packet)?;
+        trace::request(request_id, packet);
+        self.metrics.request_sent(request_id, packet);
+        self.send(packet)?;
 
-        Ok(ResponseIterator::new(
-            receiver,
-            self.signals_send.clone(),
-            Some(request_id),
-            None,
-            Some(Duration::from_secs(10)),
-        ))
+        Ok(self.response_iterator(receiver, Some(request_id), None, Some(self.request_timeout)))
     }
 
     fn send_durable_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error> {
-        let (sender, receiver) = channel::unbounded();
+        let (sender, receiver) = match &self.subscription_buffer {
+            Some(buffer) => {
+                let (sender, receiver) = channel::bounded(buffer.capacity);
+                (ResponseSender::bounded(sender, buffer.overflow, &receiver), receiver)
+            }
+            None => {
+                let (sender, receiver) = channel::unbounded();
+                (sender.into(), receiver)
+            }
+        };
 
         self.add_request(request_id, sender)?;
-        self.write_message(packet)?;
+        self.subscriptions.insert(request_id, packet.clone());
+        trace::request(request_id, packet);
+        self.metrics.request_sent(request_id, packet);
+        self.send(packet)?;
 
-        Ok(ResponseIterator::new(receiver, self.signals_send.clone(), Some(request_id), None, None))
+        Ok(self.response_iterator(receiver, Some(request_id), None, None))
     }
 
     fn send_order_message(&mut self, order_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
         let (sender, receiver) = channel::unbounded();
 
         self.add_order(order_id, sender)?;
-        self.write_message(message)?;
+        trace::order(order_id, message);
+        self.metrics.order_sent(order_id, message);
+        self.send(message)?;
+
+        Ok(self.response_iterator(receiver, None, Some(order_id), Some(self.request_timeout)))
+    }
+
+    // Queues all order messages as one batch, written together so that no other message is interleaved between them.
+    fn send_order_messages(&mut self, orders: &[(i32, RequestMessage)]) -> Result<Vec<ResponseIterator>, Error> {
+        self.ensure_running()?;
 
-        Ok(ResponseIterator::new(
-            receiver,
-            self.signals_send.clone(),
-            None,
-            Some(order_id),
-            Some(Duration::from_secs(10)),
-        ))
+        let mut frames = Vec::with_capacity(orders.len());
+        let mut responses = Vec::with_capacity(orders.len());
+
+        for (order_id, message) in orders {
+            let (sender, receiver) = channel::unbounded();
+
+            self.add_order(*order_id, sender)?;
+            trace::order(*order_id, message);
+            self.metrics.order_sent(*order_id, message);
+
+            let mut packet = Vec::new();
+            write_frame(&mut packet, message)?;
+            self.metrics.sent(message, &packet);
+            frames.push(packet);
+
+            responses.push(self.response_iterator(receiver, None, Some(*order_id), Some(self.request_timeout)));
+        }
+
+        // the orders are sent together, once the rate limit allows them all
+        for _ in orders {
+            throttle(self.rate_limiter.as_deref());
+        }
+        self.writer.sender().send(frames)?;
+
+        for (_, message) in orders {
+            self.recorder.record_request(message);
+        }
+
+        Ok(responses)
     }
 
     fn request_next_order_id(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        // next valid ids received unrequested are not responses to this request
+        while self.globals.order_ids_out.try_recv().is_ok() {}
+
         self.write_message(message)?;
         Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.order_ids_out)))
     }
@@ -202,73 +916,346 @@ impl MessageBus for TcpMessageBus {
         Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_family_codes)))
     }
 
+    fn request_market_depth_exchanges(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.write_message(message)?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_market_depth_exchanges)))
+    }
+
+    fn request_current_time(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        trace::message(message);
+
+        // answers to earlier requests that timed out
+        while self.globals.recv_current_time.try_recv().is_ok() {}
+
+        // answers in milliseconds are never taken for heartbeats
+        if message.fields().first() == Some(&OutgoingMessages::RequestCurrentTime.to_field()) {
+            let heartbeat = Arc::clone(&self.heartbeat);
+            let writer = self.writer.sender();
+            self.send_with(message, |packet| {
+                heartbeat.send_current_time(CurrentTimeRequester::Client, || writer.send(vec![packet]))
+            })?;
+        } else {
+            self.send(message)?;
+        }
+
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_current_time)))
+    }
+
     fn write_message(&mut self, message: &RequestMessage) -> Result<(), Error> {
-        let data = message.encode();
-        debug!("-> {data:?}");
+        trace::message(message);
+        self.send(message)
+    }
 
-        let data = data.as_bytes();
+    fn write(&mut self, data: &str) -> Result<(), Error> {
+        debug!("{data:?} ->");
+        capture_sent(self.connect_options.capture.as_deref(), data.as_bytes());
+        self.stream().as_ref().write_all(data.as_bytes())?;
+        Ok(())
+    }
 
-        let mut packet = Vec::with_capacity(data.len() + 4);
+    fn start_api(&mut self, message: &RequestMessage) -> Result<(), Error> {
+        self.write_message(message)?;
+        self.start_api = Some(message.clone());
+        Ok(())
+    }
 
-        packet.write_u32::<BigEndian>(data.len() as u32)?;
-        packet.write_all(data)?;
+    // Waits until queued messages were written to the connection.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()
+    }
 
-        self.writer.write_all(&packet)?;
+    fn set_parse_mode(&mut self, parse_mode: ParseMode) {
+        *self.parse_mode.write().unwrap() = parse_mode;
+    }
 
-        self.recorder.record_request(message);
+    fn set_subscription_buffer(&mut self, subscription_buffer: Option<SubscriptionBuffer>) {
+        self.subscription_buffer = subscription_buffer;
+    }
 
+    fn set_metrics_observer(&mut self, observer: Option<Arc<dyn MetricsObserver>>) {
+        self.metrics.set_observer(observer);
+    }
+
+    fn track_next_order_id(&mut self, next_order_id: Arc<AtomicI32>) {
+        *self.globals.next_order_id.write().unwrap() = Some(next_order_id);
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.stream().set_read_timeout(timeout)?;
         Ok(())
     }
 
-    fn write(&mut self, data: &str) -> Result<(), Error> {
-        debug!("{data:?} ->");
-        self.writer.write_all(data.as_bytes())?;
+    // Stops the message reader and releases every response channel, so consumers blocked waiting on responses return.
+    fn shutdown(&mut self) -> Result<(), Error> {
+        if self.shutdown.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        self.requests.clear();
+        self.orders.clear();
+        self.subscriptions.clear();
+        self.signals_send.send(Signal::Stop).unwrap();
+
+        // messages already queued are still sent
+        if let Err(err) = self.writer.flush() {
+            warn!("error writing queued messages: {err}");
+        }
+        self.writer.stop();
+
+        self.globals.connection_events.publish(ConnectionEvent::Disconnected);
+
+        // wakes the heartbeat
+        for handle in &self.handles {
+            handle.thread().unpark();
+        }
+
+        // unblocks the reader thread
+        if let Err(err) = self.stream().shutdown(net::Shutdown::Both) {
+            if err.kind() != ErrorKind::NotConnected {
+                return Err(err.into());
+            }
+        }
+
+        info!("message bus shut down");
+        Ok(())
+    }
+
+    fn start_heartbeat(&mut self, message: &RequestMessage, interval: Duration, deadline: Duration) -> Result<(), Error> {
+        self.ensure_running()?;
+
+        let mut packet = Vec::new();
+        write_frame(&mut packet, message)?;
+
+        let stream = Arc::clone(&self.stream);
+        let writer = self.writer.sender();
+        let heartbeat = Arc::clone(&self.heartbeat);
+        let shutdown = Arc::clone(&self.shutdown);
+        let maintenance_window = self.maintenance_window.clone();
+        let rate_limiter = self.rate_limiter.clone();
+
+        let handle = thread::spawn(move || {
+            while wait_unless_shutdown(interval, &shutdown) {
+                if in_maintenance_window(maintenance_window.as_ref()).is_some() {
+                    continue;
+                }
+                let connection = Arc::clone(&stream.read().unwrap());
+
+                let sent = Instant::now();
+                throttle(rate_limiter.as_deref());
+                if let Err(err) = heartbeat.send_current_time(CurrentTimeRequester::Heartbeat, || writer.send(vec![packet.clone()])) {
+                    warn!("error sending heartbeat: {err}");
+                }
+
+                if !wait_unless_shutdown(deadline, &shutdown) {
+                    break;
+                }
+
+                if !heartbeat.received_since(sent) {
+                    warn!("no response to heartbeat within {deadline:?}, dropping connection");
+                    heartbeat.healthy.store(false, Ordering::Relaxed);
+
+                    // the message reader reconnects
+                    connection.shutdown(net::Shutdown::Both).ok();
+                }
+            }
+            debug!("heartbeat stopped");
+            0
+        });
+
+        self.handles.push(handle);
+
         Ok(())
     }
 
+    fn connection_events(&mut self) -> Receiver<ConnectionEvent> {
+        self.globals.connection_events.subscribe()
+    }
+
+    fn error_events(&mut self) -> Receiver<ErrorEvent> {
+        self.globals.error_events.subscribe()
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.heartbeat.healthy.load(Ordering::Relaxed)
+    }
+
+    fn disconnect(&mut self) -> Result<(), Error> {
+        if let Err(err) = self.flush() {
+            warn!("error flushing connection: {err}");
+        }
+
+        self.shutdown()?;
+
+        for handle in self.handles.drain(..) {
+            if handle.join().is_err() {
+                error!("message processing thread panicked");
+            }
+        }
+        self.writer.join();
+
+        info!("message bus disconnected");
+        Ok(())
+    }
+
+    // Dispatches messages from the server. When the connection drops it is re-established and active subscriptions are replayed.
+    // Order notifications resume on the new connection, as the server reports open orders for the client id.
     fn process_messages(&mut self, server_version: i32) -> Result<(), Error> {
-        let reader = Arc::clone(&self.reader);
+        let address = self.address.clone();
+        let mut connected_address = self.connected_address.clone();
+        let connect_options = self.connect_options.clone();
+        let reconnect_policy = self.reconnect_policy.clone();
+        let maintenance_window = self.maintenance_window.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let stream = Arc::clone(&self.stream);
+        let writer = self.writer.sender();
+        let start_api = self.start_api.clone();
+        let subscriptions = Arc::clone(&self.subscriptions);
         let requests = Arc::clone(&self.requests);
         let recorder = self.recorder.clone();
         let orders = Arc::clone(&self.orders);
         let globals = Arc::clone(&self.globals);
         let executions = SenderHash::<String, ResponseMessage>::new();
+        let parse_mode = Arc::clone(&self.parse_mode);
+        let shutdown = Arc::clone(&self.shutdown);
+        let heartbeat = Arc::clone(&self.heartbeat);
+        let buffers = Arc::clone(&self.buffers);
+        let capture = self.connect_options.capture.clone();
+        let metrics = Arc::clone(&self.metrics);
+        let signals = self.signals_send.clone();
+
+        let handle = thread::spawn(move || {
+            let mut _connection = trace::connection(&connected_address);
+            loop {
+                let reader = Arc::clone(&stream.read().unwrap());
+
+                match read_pooled_packet(&mut reader.as_ref(), &buffers) {
+                    Ok(mut message) => {
+                        message.parse_mode = *parse_mode.read().unwrap();
+                        message.metrics = metrics.observer();
+                        metrics.received(&message);
+                        recorder.record_response(&message);
+                        capture_received(capture.as_deref(), &message);
+                        if heartbeat.received(&message) {
+                            continue;
+                        }
+                        dispatch_message(message, server_version, &requests, &orders, &globals, &executions);
+                    }
+                    Err(err) => {
+                        if shutdown.load(Ordering::Relaxed) {
+                            debug!("message reader stopped");
+                            return 0;
+                        }
 
-        let handle = thread::spawn(move || loop {
-            match read_packet(&reader) {
-                Ok(message) => {
-                    recorder.record_response(&message);
-                    dispatch_message(message, server_version, &requests, &orders, &globals, &executions);
-                }
-                Err(err) => {
-                    error!("error reading packet: {:?}", err);
-                    continue;
-                }
-            };
+                        if let Error::Io(err) = err {
+                            error!("connection to {connected_address} lost: {err}");
+                            globals.connection_events.publish(ConnectionEvent::Disconnected);
+                            globals.connection_events.publish(ConnectionEvent::Reconnecting);
+
+                            let Some((reconnected_address, connection)) = reconnect(
+                                &address,
+                                &connect_options,
+                                start_api.as_ref(),
+                                server_version,
+                                &reconnect_policy,
+                                maintenance_window.as_ref(),
+                                &shutdown,
+                            ) else {
+                                // gave up, so nothing more will arrive. Releases consumers blocked on responses, as shutdown does.
+                                if !shutdown.swap(true, Ordering::Relaxed) {
+                                    requests.clear();
+                                    orders.clear();
+                                    subscriptions.clear();
+                                    signals.send(Signal::Stop).ok();
+                                    writer.stop();
+                                    globals.connection_events.publish(ConnectionEvent::ReconnectFailed);
+                                }
+                                debug!("message reader stopped");
+                                return 0;
+                            };
+                            let connection = Arc::new(connection);
+                            *stream.write().unwrap() = Arc::clone(&connection);
+                            heartbeat.reconnected();
+
+                            // shut down while reconnecting
+                            if shutdown.load(Ordering::Relaxed) {
+                                connection.shutdown(net::Shutdown::Both).ok();
+                                return 0;
+                            }
+                            _connection = trace::connection(&reconnected_address);
+                            globals.connection_events.publish(ConnectionEvent::Connected);
+                            if reconnected_address != connected_address {
+                                globals.connection_events.publish(ConnectionEvent::FailedOver {
+                                    address: reconnected_address.clone(),
+                                });
+                                connected_address = reconnected_address;
+                            }
+
+                            match replay_subscriptions(&writer, &subscriptions, &requests, rate_limiter.as_deref()) {
+                                Ok(replayed) => info!("replayed {replayed} subscriptions"),
+                                Err(err) => error!("error replaying subscriptions: {err}"),
+                            }
+                            continue;
+                        }
+
+                        error!("error reading packet: {:?}", err);
+                        continue;
+                    }
+                };
 
-            // FIXME - does read block?
-            // thread::sleep(Duration::from_secs(1));
+                // FIXME - does read block?
+                // thread::sleep(Duration::from_secs(1));
+            }
         });
 
         self.handles.push(handle);
 
         let requests = Arc::clone(&self.requests);
         let orders = Arc::clone(&self.orders);
+        let subscriptions = Arc::clone(&self.subscriptions);
         let signal_recv = self.signals_recv.clone();
+        let writer = self.writer.sender();
+        let rate_limiter = self.rate_limiter.clone();
+        let metrics = Arc::clone(&self.metrics);
 
-        let handle = thread::spawn(move || loop {
+        let handle = thread::spawn(move || {
             for signal in &signal_recv {
                 match signal {
                     Signal::Request(request_id) => {
                         requests.remove(&request_id);
+                        subscriptions.remove(&request_id);
                         debug!("released request_id {}, requests.len()={}", request_id, requests.len());
+                        trace::released("request_id", request_id);
+                        metrics.request_released(request_id);
+                    }
+                    Signal::Cancel(request_id, message) => {
+                        requests.remove(&request_id);
+                        subscriptions.remove(&request_id);
+                        debug!("cancelled request_id {}, requests.len()={}", request_id, requests.len());
+                        trace::released("request_id", request_id);
+                        trace::message(&message);
+                        metrics.request_released(request_id);
+
+                        let mut packet = Vec::new();
+                        let sent = write_frame(&mut packet, &message).and_then(|_| {
+                            metrics.sent(&message, &packet);
+                            throttle(rate_limiter.as_deref());
+                            writer.send(vec![packet])
+                        });
+                        if let Err(err) = sent {
+                            warn!("error sending cancellation of request_id {request_id}: {err}");
+                        }
                     }
                     Signal::Order(order_id) => {
                         orders.remove(&order_id);
                         debug!("released order_id {}, orders.len()={}", order_id, requests.len());
+                        trace::released("order_id", order_id);
+                        metrics.order_released(order_id);
                     }
+                    Signal::Stop => break,
                 }
             }
+            debug!("signal processor stopped");
+            0
         });
 
         self.handles.push(handle);
@@ -285,17 +1272,26 @@ fn dispatch_message(
     globals: &Arc<GlobalChannels>,
     executions: &SenderHash<String, ResponseMessage>,
 ) {
-    match message.message_type() {
-        IncomingMessages::Error => {
-            let request_id = message.peek_int(2).unwrap_or(-1);
+    trace::response(&message);
 
-            if request_id == UNSPECIFIED_REQUEST_ID {
-                error_event(server_version, message).unwrap();
-            } else {
+    match message.message_type() {
+        IncomingMessages::Error => match decode_error_event(server_version, message.clone()) {
+            Ok(event) if event.request_id == UNSPECIFIED_REQUEST_ID => {
+                if let Some(connection_event) = ConnectionEvent::from_error_code(event.code, &event.message) {
+                    globals.connection_events.publish(connection_event);
+                }
+                globals.error_events.publish(event);
+            }
+            Ok(event) => {
+                globals.error_events.publish(event);
                 process_response(requests, orders, message);
             }
-        }
+            Err(err) => error!("error decoding error message {message:?}: {err}"),
+        },
         IncomingMessages::NextValidId => {
+            if let (Some(next_order_id), Ok(order_id)) = (globals.next_order_id.read().unwrap().as_ref(), message.peek_int(2)) {
+                next_order_id.fetch_max(order_id, Ordering::Relaxed);
+            }
             globals.order_ids_in.send(message).unwrap();
         }
         IncomingMessages::MarketRule => {
@@ -307,6 +1303,12 @@ fn dispatch_message(
         IncomingMessages::FamilyCodes => {
             globals.send_family_codes.send(message).unwrap();
         }
+        IncomingMessages::MktDepthExchanges => {
+            globals.send_market_depth_exchanges.send(message).unwrap();
+        }
+        IncomingMessages::CurrentTime | IncomingMessages::CurrentTimeInMillis => {
+            globals.send_current_time.send(message).unwrap();
+        }
 
         IncomingMessages::ManagedAccounts => process_managed_accounts(server_version, message),
         IncomingMessages::OrderStatus
@@ -317,35 +1319,112 @@ fn dispatch_message(
         | IncomingMessages::ExecutionData
         | IncomingMessages::ExecutionDataEnd
         | IncomingMessages::CommissionsReport => process_orders(message, requests, orders, executions, globals),
+        IncomingMessages::Unknown(message_id) => warn!("skipping unsupported message id {message_id}: {message:?}"),
         _ => process_response(requests, orders, message),
     };
 }
 
-fn read_packet(mut reader: &TcpStream) -> Result<ResponseMessage, Error> {
+// Largest message accepted by the server, and from it. A longer length prefix means the stream is out of sync.
+pub(crate) const MAX_MESSAGE_LEN: usize = 0xFF_FFFF;
+
+// Appends the length prefixed encoding of message to packet.
+pub(crate) fn write_frame(packet: &mut Vec<u8>, message: &RequestMessage) -> Result<(), Error> {
+    let data = message.encode();
+    debug!("-> {data:?}");
+
+    let data = data.as_bytes();
+    if data.len() > MAX_MESSAGE_LEN {
+        return Err(Error::InvalidArgument(format!(
+            "message of {} bytes exceeds the maximum of {MAX_MESSAGE_LEN}",
+            data.len()
+        )));
+    }
+
+    packet.reserve(data.len() + 4);
+    packet.write_u32::<BigEndian>(data.len() as u32)?;
+    packet.write_all(data)?;
+
+    Ok(())
+}
+
+fn capture_sent(capture: Option<&WireCapture>, bytes: &[u8]) {
+    if let Some(capture) = capture {
+        capture.record(Direction::Sent, bytes);
+    }
+}
+
+fn capture_received(capture: Option<&WireCapture>, message: &ResponseMessage) {
+    if let Some(capture) = capture {
+        capture.record_received(message.raw());
+    }
+}
+
+// Reads a length prefixed message. Messages split across TCP segments are reassembled.
+fn read_packet<R: Read>(reader: &mut R) -> Result<ResponseMessage, Error> {
     let message_size = read_header(reader)?;
-    let mut data = vec![0_u8; message_size];
+    let data = vec![0_u8; message_size];
+
+    read_message_data(reader, data)
+}
+
+// Reads a message into a buffer from buffers. The buffer is returned to the pool once the message is dropped.
+fn read_pooled_packet<R: Read>(reader: &mut R, buffers: &Arc<BufferPool>) -> Result<ResponseMessage, Error> {
+    let message_size = read_header(reader)?;
+    let data = buffers.take(message_size);
+
+    Ok(read_message_data(reader, data)?.recycle_into(buffers))
+}
 
-    reader.read_exact(&mut data)?;
+fn read_message_data<R: Read>(reader: &mut R, mut data: Vec<u8>) -> Result<ResponseMessage, Error> {
+    read_frame(reader, &mut data, true)?;
 
     let raw_string = String::from_utf8(data)?;
     debug!("<- {:?}", raw_string);
 
-    let packet = ResponseMessage::from(&raw_string);
+    let packet = ResponseMessage::from_string(raw_string);
 
     Ok(packet)
 }
 
-fn read_header(mut reader: &TcpStream) -> Result<usize, Error> {
+fn read_header<R: Read>(reader: &mut R) -> Result<usize, Error> {
     let buffer = &mut [0_u8; 4];
-    reader.read_exact(buffer)?;
+    read_frame(reader, buffer, false)?;
 
     let mut reader = Cursor::new(buffer);
-    let count = reader.read_u32::<BigEndian>()?;
+    let count = reader.read_u32::<BigEndian>()? as usize;
+
+    // the connection can't be resynchronized, so it is dropped and re-established
+    if count > MAX_MESSAGE_LEN {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("message length {count} exceeds the maximum of {MAX_MESSAGE_LEN}"),
+        )
+        .into());
+    }
+
+    Ok(count)
+}
+
+// Fills buffer from reader. A read timeout is only reported before the first byte of a message,
+// as giving up part way through would lose the message boundary.
+fn read_frame<R: Read>(reader: &mut R, buffer: &mut [u8], in_message: bool) -> std::io::Result<()> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => return Err(std::io::Error::new(ErrorKind::UnexpectedEof, "connection closed")),
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) && (in_message || filled > 0) => {}
+            Err(err) => return Err(err),
+        }
+    }
 
-    Ok(count as usize)
+    Ok(())
 }
 
-fn error_event(server_version: i32, mut packet: ResponseMessage) -> Result<(), Error> {
+// Decodes an error message, logging it by severity.
+fn decode_error_event(server_version: i32, mut packet: ResponseMessage) -> Result<ErrorEvent, Error> {
     packet.skip(); // message_id
 
     let version = packet.next_int()?;
@@ -353,25 +1432,35 @@ fn error_event(server_version: i32, mut packet: ResponseMessage) -> Result<(), E
     if version < 2 {
         let message = packet.next_string()?;
         error!("version 2 error: {}", message);
-        Ok(())
-    } else {
-        let request_id = packet.next_int()?;
-        let error_code = packet.next_int()?;
-        let error_message = packet.next_string()?;
+        return Ok(ErrorEvent {
+            request_id: UNSPECIFIED_REQUEST_ID,
+            code: -1,
+            message,
+            advanced_order_reject_json: String::new(),
+        });
+    }
 
-        // if 322 forward to market_rule_id
+    let request_id = packet.next_int()?;
+    let code = packet.next_int()?;
+    let message = packet.next_string()?;
 
-        let mut advanced_order_reject_json: String = "".to_string();
-        if server_version >= server_versions::ADVANCED_ORDER_REJECT {
-            advanced_order_reject_json = packet.next_string()?;
-        }
-        debug!(
-            "request_id: {}, error_code: {}, error_message: {}, advanced_order_reject_json: {}",
-            request_id, error_code, error_message, advanced_order_reject_json
-        );
-        println!("[{error_code}] {error_message}");
-        Ok(())
+    let mut advanced_order_reject_json = String::new();
+    if server_version >= server_versions::ADVANCED_ORDER_REJECT {
+        advanced_order_reject_json = packet.next_string()?;
     }
+
+    let event = ErrorEvent {
+        request_id,
+        code,
+        message,
+        advanced_order_reject_json,
+    };
+    if event.is_warning() {
+        info!("{event}");
+    } else {
+        warn!("{event}");
+    }
+    Ok(event)
 }
 
 fn process_managed_accounts(_server_version: i32, mut packet: ResponseMessage) {
@@ -482,7 +1571,7 @@ fn process_orders(
 
 #[derive(Debug)]
 struct SenderHash<K, V> {
-    data: RwLock<HashMap<K, Sender<V>>>,
+    data: RwLock<HashMap<K, ResponseSender<V>>>,
 }
 
 impl<K: std::hash::Hash + Eq + std::fmt::Debug, V: std::fmt::Debug> SenderHash<K, V> {
@@ -493,29 +1582,40 @@ impl<K: std::hash::Hash + Eq + std::fmt::Debug, V: std::fmt::Debug> SenderHash<K
     }
 
     pub fn send(&self, id: &K, message: V) -> Result<(), Error> {
-        let senders = self.data.read().unwrap();
-        debug!("senders: {senders:?}");
-        if let Some(sender) = senders.get(id) {
-            if let Err(err) = sender.send(message) {
-                error!("error sending: {id:?}, {err}")
+        // sends without holding the lock, as a full buffer may block
+        let sender = {
+            let senders = self.data.read().unwrap();
+            debug!("senders: {senders:?}");
+            senders.get(id).cloned()
+        };
+
+        let Some(sender) = sender else {
+            error!("no recipient found for: {id:?}, {message:?}");
+            return Ok(());
+        };
+
+        match sender.send(message) {
+            Ok(()) => {}
+            Err(SendError::Overflow) => {
+                error!("subscription {id:?} overflowed its buffer, ending it");
+                self.remove(id);
             }
-        } else {
-            error!("no recipient found for: {id:?}, {message:?}")
+            Err(SendError::Disconnected) => error!("error sending: {id:?}, receiver disconnected"),
         }
         Ok(())
     }
 
-    pub fn copy_sender(&self, id: K) -> Option<Sender<V>> {
+    pub fn copy_sender(&self, id: K) -> Option<ResponseSender<V>> {
         let senders = self.data.read().unwrap();
         senders.get(&id).cloned()
     }
 
-    pub fn insert(&self, id: K, message: Sender<V>) -> Option<Sender<V>> {
+    pub fn insert(&self, id: K, sender: impl Into<ResponseSender<V>>) -> Option<ResponseSender<V>> {
         let mut senders = self.data.write().unwrap();
-        senders.insert(id, message)
+        senders.insert(id, sender.into())
     }
 
-    pub fn remove(&self, id: &K) -> Option<Sender<V>> {
+    pub fn remove(&self, id: &K) -> Option<ResponseSender<V>> {
         let mut senders = self.data.write().unwrap();
         senders.remove(id)
     }
@@ -529,19 +1629,139 @@ impl<K: std::hash::Hash + Eq + std::fmt::Debug, V: std::fmt::Debug> SenderHash<K
         let senders = self.data.read().unwrap();
         senders.len()
     }
+
+    // Drops all senders, disconnecting their receivers.
+    pub fn clear(&self) {
+        let mut senders = self.data.write().unwrap();
+        senders.clear()
+    }
+}
+
+// Delivers responses to a request. Bounded senders apply an overflow policy once the buffer is full.
+#[derive(Debug)]
+struct ResponseSender<V> {
+    sender: Sender<V>,
+    overflow: OverflowPolicy,
+    // receiving end, for discarding the oldest message
+    oldest: Option<Receiver<V>>,
+}
+
+#[derive(Debug, PartialEq)]
+enum SendError {
+    Overflow,
+    Disconnected,
+}
+
+impl<V> ResponseSender<V> {
+    fn bounded(sender: Sender<V>, overflow: OverflowPolicy, receiver: &Receiver<V>) -> Self {
+        // only drop oldest needs the receiver. Holding it otherwise would keep the channel open after the consumer left.
+        let oldest = (overflow == OverflowPolicy::DropOldest).then(|| receiver.clone());
+        ResponseSender { sender, overflow, oldest }
+    }
+
+    fn send(&self, message: V) -> Result<(), SendError> {
+        let mut message = match self.overflow {
+            OverflowPolicy::Block => return self.sender.send(message).map_err(|_| SendError::Disconnected),
+            _ => message,
+        };
+
+        loop {
+            match self.sender.try_send(message) {
+                Ok(()) => return Ok(()),
+                Err(channel::TrySendError::Disconnected(_)) => return Err(SendError::Disconnected),
+                Err(channel::TrySendError::Full(rejected)) => match (self.overflow, &self.oldest) {
+                    (OverflowPolicy::DropOldest, Some(oldest)) => {
+                        // the consumer may have made room meanwhile
+                        oldest.try_recv().ok();
+                        message = rejected;
+                    }
+                    (OverflowPolicy::Error, _) => return Err(SendError::Overflow),
+                    _ => {
+                        debug!("buffer full, dropping message");
+                        return Ok(());
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<V> Clone for ResponseSender<V> {
+    fn clone(&self) -> Self {
+        ResponseSender {
+            sender: self.sender.clone(),
+            overflow: self.overflow,
+            oldest: self.oldest.clone(),
+        }
+    }
 }
 
+// Unbounded senders never overflow.
+impl<V> From<Sender<V>> for ResponseSender<V> {
+    fn from(sender: Sender<V>) -> Self {
+        ResponseSender {
+            sender,
+            overflow: OverflowPolicy::Block,
+            oldest: None,
+        }
+    }
+}
+
+// Tracks the requests behind durable subscriptions so they can be re-sent with their original request ids.
 #[derive(Debug)]
-pub(crate) struct ResponseIterator {
+struct Subscriptions {
+    data: RwLock<HashMap<i32, RequestMessage>>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self {
+            data: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn insert(&self, request_id: i32, message: RequestMessage) {
+        let mut subscriptions = self.data.write().unwrap();
+        subscriptions.insert(request_id, message);
+    }
+
+    pub fn remove(&self, request_id: &i32) -> Option<RequestMessage> {
+        let mut subscriptions = self.data.write().unwrap();
+        subscriptions.remove(request_id)
+    }
+
+    // Returns active subscriptions ordered by request id, so they are replayed in the order they were made.
+    pub fn active(&self) -> Vec<(i32, RequestMessage)> {
+        let subscriptions = self.data.read().unwrap();
+        let mut active: Vec<(i32, RequestMessage)> = subscriptions.iter().map(|(id, message)| (*id, message.clone())).collect();
+        active.sort_by_key(|(id, _)| *id);
+        active
+    }
+
+    pub fn clear(&self) {
+        let mut subscriptions = self.data.write().unwrap();
+        subscriptions.clear()
+    }
+}
+
+/// Responses to a request, or messages about an order, as returned by a [MessageBus].
+#[derive(Debug)]
+pub struct ResponseIterator {
     messages: Receiver<ResponseMessage>, // for client to receive incoming messages
     signals: Sender<Signal>,             // for client to signal termination
     request_id: Option<i32>,             // initiating request_id
     order_id: Option<i32>,               // initiating order_id
     timeout: Option<Duration>,           // How long to wait for next message
+    cancellation: Option<Arc<Registration>>,
+    shutdown: Option<Arc<AtomicBool>>, // set once the message bus is shut down
+    #[cfg(feature = "tracing")]
+    span: tracing::Span, // span of the request or order, closed once released
 }
 
 impl ResponseIterator {
-    pub(crate) fn new(
+    /// Creates an iterator over the messages received on messages. Once dropped, it sends the request or order id given on signals.
+    /// Without a timeout, iteration ends once the sender of messages is dropped. With one, also once no message arrives within it.
+    pub fn new(
         messages: Receiver<ResponseMessage>,
         signals: Sender<Signal>,
         request_id: Option<i32>,
@@ -554,18 +1774,121 @@ impl ResponseIterator {
             request_id,
             order_id,
             timeout,
+            cancellation: None,
+            shutdown: None,
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::none(),
+        }
+    }
+
+    /// Returns the next message. Fails with [Error::Timeout] when none arrives within the timeout. Returns None once the responses end.
+    /// Fails with [Error::Cancelled] once the request is cancelled with a [CancellationToken], and with [Error::Shutdown] once the client
+    /// is shut down.
+    ///
+    /// Error messages from TWS about the request fail with [Error::TwsError]. Warnings, see [Error::is_warning], are logged and skipped.
+    pub fn next_response(&mut self) -> Result<Option<ResponseMessage>, Error> {
+        loop {
+            let Some(message) = self.receive()? else {
+                return Ok(None);
+            };
+            if message.message_type() != IncomingMessages::Error {
+                return Ok(Some(message));
+            }
+
+            let error = Error::from_tws_message(&message);
+            if !error.is_warning() {
+                return Err(error);
+            }
+            warn!("request {}: {error}", self.request_id.unwrap_or(-1));
+        }
+    }
+
+    // Returns the next message of a subscription, ending it on errors. The error is logged, as iterators of subscriptions can't return it.
+    #[cfg(feature = "market_data")]
+    pub(crate) fn next_or_end(&mut self) -> Option<ResponseMessage> {
+        match self.next_response() {
+            Ok(message) => message,
+            Err(Error::Cancelled | Error::Shutdown) => None,
+            Err(err) => {
+                error!("request {} ended: {err}", self.request_id.unwrap_or(-1));
+                None
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Result<Option<ResponseMessage>, Error> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        let received = match self.timeout {
+            Some(timeout) => self.messages.recv_timeout(timeout),
+            None => self.messages.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(message) => Ok(Some(message)),
+            // shutting down releases the request, disconnecting the channel
+            Err(_) if self.is_shut_down() => Err(Error::Shutdown),
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            // cancelling releases the request, disconnecting the channel
+            Err(RecvTimeoutError::Disconnected) if self.is_cancelled() => Err(Error::Cancelled),
+            Err(RecvTimeoutError::Disconnected) => Ok(None),
+        }
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shutdown.as_ref().is_some_and(|shutdown| shutdown.load(Ordering::Relaxed))
+    }
+
+    /// Returns true once the request is cancelled with a [CancellationToken].
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().is_some_and(|registration| registration.is_cancelled())
+    }
+
+    // Joins the request to token. Requests without a request id, e.g. orders, can't be cancelled.
+    pub(crate) fn set_cancellation(&mut self, token: &CancellationToken) {
+        if let Some(request_id) = self.request_id {
+            self.cancellation = Some(token.register(request_id, self.signals.clone()));
+        }
+    }
+
+    // Sets the message sent to the server if the request is cancelled, e.g. cancelMktData.
+    #[cfg(feature = "market_data")]
+    pub(crate) fn set_cancel_message(&self, message: RequestMessage) {
+        if let Some(registration) = &self.cancellation {
+            registration.set_cancel_message(message);
+        }
+    }
+
+    // Sets how long to wait for each message, replacing the timeout chosen by the message bus.
+    pub(crate) fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the next message if one is already queued, without blocking. Returns None when no message is queued.
+    /// Allows polling many subscriptions from a single thread, e.g. in an event loop.
+    ///
+    /// Fails with [Error::Disconnected] once no more messages will arrive, and with [Error::Cancelled] once the request is cancelled.
+    pub fn try_next(&self) -> Result<Option<ResponseMessage>, Error> {
+        match self.messages.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) if self.is_cancelled() => Err(Error::Cancelled),
+            Err(TryRecvError::Disconnected) => Err(Error::Disconnected),
         }
     }
 }
 
 impl Drop for ResponseIterator {
     fn drop(&mut self) {
+        // the bus may no longer be listening, e.g. after shutdown
         if let Some(request_id) = self.request_id {
-            self.signals.send(Signal::Request(request_id)).unwrap();
+            self.signals.send(Signal::Request(request_id)).ok();
         }
 
         if let Some(order_id) = self.order_id {
-            self.signals.send(Signal::Order(order_id)).unwrap();
+            self.signals.send(Signal::Order(order_id)).ok();
         }
     }
 }
@@ -573,6 +1896,10 @@ impl Drop for ResponseIterator {
 impl Iterator for ResponseIterator {
     type Item = ResponseMessage;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.is_cancelled() {
+            return None;
+        }
+
         if let Some(timeout) = self.timeout {
             match self.messages.recv_timeout(timeout) {
                 Ok(message) => Some(message),
@@ -593,12 +1920,15 @@ impl Iterator for ResponseIterator {
     }
 }
 
+/// Responses to requests without a request id, e.g. positions, as returned by a [MessageBus].
+/// Iteration ends once no message arrives within 5 seconds.
 #[derive(Debug)]
-pub(crate) struct GlobalResponseIterator {
+pub struct GlobalResponseIterator {
     messages: Arc<Receiver<ResponseMessage>>,
 }
 
 impl GlobalResponseIterator {
+    /// Creates an iterator over the messages received on messages. The receiver is shared by every request of the same kind.
     pub fn new(messages: Arc<Receiver<ResponseMessage>>) -> Self {
         Self { messages }
     }
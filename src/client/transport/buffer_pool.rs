@@ -0,0 +1,87 @@
+//! Reuses receive buffers, so the message reader doesn't allocate for every message.
+
+use std::fmt;
+use std::sync::Mutex;
+
+// Buffers kept for reuse. Enough for the messages a busy consumer typically has queued.
+const MAX_POOLED_BUFFERS: usize = 64;
+// Larger buffers, e.g. for historical data, are released rather than kept.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+// Leaves out the buffer contents.
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool").finish_non_exhaustive()
+    }
+}
+
+impl BufferPool {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns a zeroed buffer of size bytes, reusing a released buffer when one is available.
+    pub(crate) fn take(&self, size: usize) -> Vec<u8> {
+        let mut buffer = self
+            .buffers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop()
+            .unwrap_or_default();
+        buffer.clear();
+        buffer.resize(size, 0);
+        buffer
+    }
+
+    // Keeps buffer for reuse, unless the pool is full or the buffer is oversized.
+    pub(crate) fn release(&self, buffer: Vec<u8>) {
+        if buffer.capacity() == 0 || buffer.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+
+        let mut buffers = self.buffers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if buffers.len() < MAX_POOLED_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_released_buffers() {
+        let pool = BufferPool::new();
+
+        let buffer = pool.take(100);
+        assert_eq!(buffer, vec![0; 100]);
+        let address = buffer.as_ptr();
+
+        pool.release(buffer);
+        assert_eq!(pool.len(), 1);
+
+        let buffer = pool.take(50);
+        assert_eq!(buffer.as_ptr(), address, "buffer should be reused");
+        assert_eq!(buffer, vec![0; 50]);
+        assert_eq!(pool.len(), 0);
+
+        pool.release(vec![0; MAX_POOLED_CAPACITY + 1]);
+        assert_eq!(pool.len(), 0, "oversized buffers are released");
+
+        for _ in 0..MAX_POOLED_BUFFERS + 1 {
+            pool.release(vec![0; 10]);
+        }
+        assert_eq!(pool.len(), MAX_POOLED_BUFFERS);
+    }
+}
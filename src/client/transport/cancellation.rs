@@ -0,0 +1,175 @@
+//! Cancellation of requests and subscriptions from any thread.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use crossbeam::channel::Sender;
+
+use super::Signal;
+use crate::messages::RequestMessage;
+
+/// Cancels requests and subscriptions, from any thread. Requests join the token when sent within [Client::with_cancellation](crate::Client::with_cancellation).
+///
+/// Cancelling ends the iterators of the requests, releases their request ids and sends the matching cancel message to TWS,
+/// e.g. cancelMktData or cancelHistoricalData. Requests waiting for a response fail with [Error::Cancelled](crate::Error::Cancelled).
+/// Requests sent with a token that is already cancelled are cancelled right away.
+///
+/// Clones share the same state, so a clone can be handed to the thread deciding when to cancel.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    state: Arc<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    cancelled: AtomicBool,
+    // requests still consumed, released with their iterators
+    requests: Mutex<Vec<Weak<Registration>>>,
+}
+
+// A request joined to a token, shared with the iterator of its responses.
+#[derive(Debug)]
+pub(crate) struct Registration {
+    request_id: i32,
+    signals: Sender<Signal>,
+    cancel: Mutex<Cancel>,
+}
+
+#[derive(Debug, Default)]
+struct Cancel {
+    // sent to the server on cancellation, if the request has one
+    message: Option<RequestMessage>,
+    cancelled: bool,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every request joined to the token. Later calls have no effect.
+    pub fn cancel(&self) {
+        if self.state.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let requests = std::mem::take(&mut *lock(&self.state.requests));
+        for registration in requests.iter().filter_map(Weak::upgrade) {
+            registration.cancel();
+        }
+    }
+
+    /// Returns true once the token is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Joins the request to the token, cancelling it right away if the token is already cancelled.
+    pub(crate) fn register(&self, request_id: i32, signals: Sender<Signal>) -> Arc<Registration> {
+        let registration = Arc::new(Registration {
+            request_id,
+            signals,
+            cancel: Mutex::new(Cancel::default()),
+        });
+
+        let mut requests = lock(&self.state.requests);
+        if self.is_cancelled() {
+            drop(requests);
+            registration.cancel();
+        } else {
+            requests.retain(|request| request.strong_count() > 0);
+            requests.push(Arc::downgrade(&registration));
+        }
+
+        registration
+    }
+}
+
+impl Registration {
+    // Sets the message sent to the server when the request is cancelled. Sent right away if it already was.
+    #[cfg(feature = "market_data")]
+    pub(crate) fn set_cancel_message(&self, message: RequestMessage) {
+        let mut cancel = lock(&self.cancel);
+        if cancel.cancelled {
+            self.signals.send(Signal::Cancel(self.request_id, message)).ok();
+        } else {
+            cancel.message = Some(message);
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        lock(&self.cancel).cancelled
+    }
+
+    fn cancel(&self) {
+        let mut cancel = lock(&self.cancel);
+        if cancel.cancelled {
+            return;
+        }
+        cancel.cancelled = true;
+
+        // the bus may no longer be listening, e.g. after shutdown
+        let signal = match cancel.message.take() {
+            Some(message) => Signal::Cancel(self.request_id, message),
+            None => Signal::Request(self.request_id),
+        };
+        self.signals.send(signal).ok();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam::channel;
+
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "market_data")]
+    fn cancel_registered_requests() {
+        let (signals, received) = channel::unbounded();
+        let token = CancellationToken::new();
+
+        let first = token.register(9000, signals.clone());
+        first.set_cancel_message(RequestMessage::new());
+        let second = token.register(9001, signals.clone());
+        assert!(!token.is_cancelled());
+        assert!(received.try_recv().is_err(), "nothing signalled before cancelling");
+
+        token.clone().cancel();
+        token.cancel();
+        assert!(token.is_cancelled());
+        assert!(first.is_cancelled() && second.is_cancelled());
+
+        assert!(matches!(received.try_recv(), Ok(Signal::Cancel(9000, _))));
+        assert!(matches!(received.try_recv(), Ok(Signal::Request(9001))));
+        assert!(received.try_recv().is_err(), "requests are cancelled once");
+
+        // joining a cancelled token cancels right away
+        let late = token.register(9002, signals);
+        assert!(late.is_cancelled());
+        assert!(matches!(received.try_recv(), Ok(Signal::Request(9002))));
+        late.set_cancel_message(RequestMessage::new());
+        assert!(matches!(received.try_recv(), Ok(Signal::Cancel(9002, _))));
+    }
+
+    #[test]
+    fn release_dropped_requests() {
+        let (signals, received) = channel::unbounded();
+        let token = CancellationToken::new();
+
+        for request_id in 0..10 {
+            drop(token.register(request_id, signals.clone()));
+        }
+        let _kept = token.register(10, signals);
+        assert_eq!(lock(&token.state.requests).len(), 1, "dropped requests are released");
+
+        token.cancel();
+        assert!(matches!(received.try_recv(), Ok(Signal::Request(10))));
+        assert!(received.try_recv().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Captures the raw bytes exchanged with the server, for bug reports and replay.
+//!
+//! Each message sent or received, and the handshake, is a line: `<timestamp> <direction> <bytes>`.
+//! The timestamp is RFC 3339 in UTC, the direction `->` for bytes sent and `<-` for bytes received,
+//! and bytes are hex encoded, including the 4 byte length prefix of each message.
+
+use std::fmt::{self, Write as _};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::error;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    fn symbol(self) -> &'static str {
+        match self {
+            Direction::Sent => "->",
+            Direction::Received => "<-",
+        }
+    }
+
+    fn from_symbol(symbol: &str) -> Option<Direction> {
+        match symbol {
+            "->" => Some(Direction::Sent),
+            "<-" => Some(Direction::Received),
+            _ => None,
+        }
+    }
+}
+
+// A line of a capture.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Captured {
+    pub time: OffsetDateTime,
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+}
+
+// Reads the capture at path, as written by WireCapture.
+pub(crate) fn read_capture(path: &Path) -> Result<Vec<Captured>, Error> {
+    let capture = fs::read_to_string(path)?;
+
+    capture
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| parse_line(line).ok_or_else(|| Error::Parse(i + 1, line.into(), "invalid capture line".into())))
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<Captured> {
+    let mut parts = line.split(' ');
+
+    let time = OffsetDateTime::parse(parts.next()?, &Rfc3339).ok()?;
+    let direction = Direction::from_symbol(parts.next()?)?;
+    let hex = parts.next()?;
+
+    if parts.next().is_some() || hex.len() % 2 != 0 {
+        return None;
+    }
+
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some(Captured { time, direction, bytes })
+}
+
+pub(crate) struct WireCapture {
+    file: Mutex<File>,
+}
+
+// Leaves out the file handle.
+impl fmt::Debug for WireCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WireCapture").finish_non_exhaustive()
+    }
+}
+
+impl WireCapture {
+    // Creates the capture file at path, replacing an existing file.
+    pub(crate) fn create(path: &Path) -> io::Result<WireCapture> {
+        Ok(WireCapture {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    pub(crate) fn record(&self, direction: Direction, bytes: &[u8]) {
+        let line = format_line(OffsetDateTime::now_utc(), direction, bytes);
+
+        // lines are written whole, so a capture cut short by a crash is still readable
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(err) = file.write_all(line.as_bytes()) {
+            error!("error writing wire capture: {err}");
+        }
+    }
+
+    // Records a message received, restoring its length prefix.
+    pub(crate) fn record_received(&self, message: &str) {
+        self.record(Direction::Received, &frame(message));
+    }
+
+    // Records an encoded message sent, adding its length prefix.
+    pub(crate) fn record_sent(&self, message: &str) {
+        self.record(Direction::Sent, &frame(message));
+    }
+}
+
+// Returns message as sent on the wire, with its length prefix.
+pub(crate) fn frame(message: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(message.len() + 4);
+    bytes.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(message.as_bytes());
+    bytes
+}
+
+fn format_line(time: OffsetDateTime, direction: Direction, bytes: &[u8]) -> String {
+    let timestamp = time.format(&Rfc3339).unwrap_or_default();
+
+    let mut line = String::with_capacity(timestamp.len() + 4 + bytes.len() * 2 + 1);
+    line.push_str(&timestamp);
+    line.push(' ');
+    line.push_str(direction.symbol());
+    line.push(' ');
+    for byte in bytes {
+        write!(line, "{byte:02x}").unwrap();
+    }
+    line.push('\n');
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn parse_captured_lines() {
+        let time = datetime!(2023-03-14 13:30:05.25 UTC);
+
+        let line = format_line(time, Direction::Received, &[0, 0, 0, 2, b'9', 0]);
+        assert_eq!(
+            parse_line(line.trim_end()),
+            Some(Captured {
+                time,
+                direction: Direction::Received,
+                bytes: vec![0, 0, 0, 2, b'9', 0]
+            })
+        );
+
+        assert_eq!(parse_line("2023-03-14T13:30:05.25Z => 00"), None, "direction");
+        assert_eq!(parse_line("2023-03-14T13:30:05.25Z -> 0g"), None, "hex");
+        assert_eq!(parse_line("2023-03-14T13:30:05.25Z -> 000"), None, "odd length");
+        assert_eq!(parse_line("13:30:05 -> 00"), None, "timestamp");
+    }
+
+    #[test]
+    fn format_captured_lines() {
+        let time = datetime!(2023-03-14 13:30:05.25 UTC);
+
+        assert_eq!(format_line(time, Direction::Sent, b"API\0"), "2023-03-14T13:30:05.25Z -> 41504900\n");
+        assert_eq!(
+            format_line(time, Direction::Received, &[0, 0, 0, 2, b'9', 0]),
+            "2023-03-14T13:30:05.25Z <- 000000023900\n"
+        );
+    }
+}
@@ -0,0 +1,953 @@
+//! Runs the client against the Client Portal gateway, instead of TWS or IB Gateway.
+//!
+//! Requests are translated to the gateway's REST and WebSocket endpoints, and its responses to the messages TWS would have
+//! sent, so the decoders and message routing are shared by both backends. Requests without a Client Portal equivalent fail.
+//!
+//! The gateway is connected to over TLS, unless its address is an `http://` url, and the session must be authenticated
+//! by logging in through the gateway's web page.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{self, Receiver, Sender};
+use log::{debug, error, info, warn};
+use serde_json::{json, Value};
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::{
+    dispatch_message, wait_unless_shutdown, GlobalChannels, GlobalResponseIterator, MessageBus, ResponseIterator, ResponseSender, SenderHash, Signal,
+};
+use crate::client::{ConnectionEvent, ErrorEvent, ParseMode, SubscriptionBuffer};
+use crate::messages::{OutgoingMessages, RequestMessage, ResponseMessage};
+use crate::Error;
+use http::Gateway;
+use websocket::{WebSocket, WebSocketWriter};
+
+mod http;
+mod websocket;
+
+// The gateway closes sessions idle for a few minutes.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+// Like TWS, snapshots end after 11 seconds with whatever arrived.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(11);
+// Positions are returned in pages of up to 100.
+const MAX_POSITION_PAGES: usize = 100;
+
+// Market data fields requested from the gateway, with the tick type each is reported as.
+const PRICE_FIELDS: &[(&str, i32)] = &[("84", 1), ("86", 2), ("31", 4), ("70", 6), ("71", 7), ("7741", 9), ("7295", 14)];
+const SIZE_FIELDS: &[(&str, i32)] = &[("88", 0), ("85", 3), ("7059", 5), ("87", 8)];
+
+const BID: i32 = 1;
+const ASK: i32 = 2;
+
+#[derive(Debug)]
+pub(crate) struct ClientPortalBus {
+    gateway: Gateway,
+    server_version: i32,
+    accounts: Vec<String>,
+    next_order_id: AtomicI32,
+    // messages translated from the gateway's responses, as if received from TWS
+    incoming_send: Sender<ResponseMessage>,
+    incoming_recv: Receiver<ResponseMessage>,
+    read_timeout: Option<Duration>,
+    websocket: Option<Arc<WebSocketWriter>>,
+    market_data: Arc<MarketData>,
+    requests: Arc<SenderHash<i32, ResponseMessage>>,
+    orders: Arc<SenderHash<i32, ResponseMessage>>,
+    globals: Arc<GlobalChannels>,
+    signals_send: Sender<Signal>,
+    signals_recv: Receiver<Signal>,
+    // disconnected to stop dispatching messages
+    stop: Option<Sender<()>>,
+    subscription_buffer: Option<SubscriptionBuffer>,
+    parse_mode: Arc<RwLock<ParseMode>>,
+    shutdown: Arc<AtomicBool>,
+    healthy: Arc<AtomicBool>,
+    handles: Vec<JoinHandle<i32>>,
+}
+
+impl ClientPortalBus {
+    // Connects to the gateway at address, e.g. localhost:5000, over TLS with config, or the Mozilla root certificates when None.
+    // Fails unless the gateway's session is authenticated.
+    pub(crate) fn connect(address: &str, config: Option<rustls::ClientConfig>) -> Result<ClientPortalBus, Error> {
+        let gateway = Gateway::new(address, config)?;
+
+        let status = gateway.get("/v1/api/iserver/auth/status")?;
+        if status.get("authenticated").and_then(Value::as_bool) != Some(true) {
+            return Err(Error::ConnectionFailed(format!(
+                "client portal session is not authenticated. log in at {} first",
+                gateway.url()
+            )));
+        }
+
+        let (incoming_send, incoming_recv) = channel::unbounded();
+        let (signals_send, signals_recv) = channel::unbounded();
+
+        Ok(ClientPortalBus {
+            gateway,
+            server_version: 0,
+            accounts: Vec::new(),
+            next_order_id: AtomicI32::new(1),
+            incoming_send,
+            incoming_recv,
+            read_timeout: None,
+            websocket: None,
+            market_data: Arc::new(MarketData::default()),
+            requests: Arc::new(SenderHash::new()),
+            orders: Arc::new(SenderHash::new()),
+            globals: Arc::new(GlobalChannels::new()),
+            signals_send,
+            signals_recv,
+            stop: None,
+            subscription_buffer: None,
+            parse_mode: Arc::new(RwLock::new(ParseMode::default())),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            healthy: Arc::new(AtomicBool::new(true)),
+            handles: Vec::new(),
+        })
+    }
+
+    fn ensure_running(&self) -> Result<(), Error> {
+        if self.shutdown.load(Ordering::Relaxed) {
+            Err(Error::Shutdown)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn receive(&self, fields: &[&str]) {
+        self.incoming_send.send(response(fields)).ok();
+    }
+
+    fn response_channel(&self, durable: bool) -> (ResponseSender<ResponseMessage>, Receiver<ResponseMessage>) {
+        match &self.subscription_buffer {
+            Some(buffer) if durable => {
+                let (sender, receiver) = channel::bounded(buffer.capacity);
+                (ResponseSender::bounded(sender, buffer.overflow, &receiver), receiver)
+            }
+            _ => {
+                let (sender, receiver) = channel::unbounded();
+                (sender.into(), receiver)
+            }
+        }
+    }
+
+    fn send_request(&mut self, request_id: i32, message: &RequestMessage, durable: bool) -> Result<ResponseIterator, Error> {
+        self.ensure_running()?;
+
+        if message_type(message) != Some(OutgoingMessages::RequestMarketData as i32) {
+            return Err(unsupported(message));
+        }
+
+        let (sender, receiver) = self.response_channel(durable);
+        self.requests.insert(request_id, sender);
+        self.request_market_data(request_id, message)?;
+
+        let timeout = if durable { None } else { Some(Duration::from_secs(10)) };
+        Ok(ResponseIterator::new(
+            receiver,
+            self.signals_send.clone(),
+            Some(request_id),
+            None,
+            timeout,
+        ))
+    }
+
+    // Subscribes to market data of the contract in message, a RequestMarketData request.
+    fn request_market_data(&mut self, request_id: i32, message: &RequestMessage) -> Result<(), Error> {
+        let request = MarketDataRequest::parse(message)?;
+
+        if request.contract_id == 0 {
+            self.receive(&[
+                "4",
+                "2",
+                &request_id.to_string(),
+                "200",
+                "No security definition has been found for the request. Client Portal requires the contract id.",
+            ]);
+            return Ok(());
+        }
+
+        let Some(websocket) = &self.websocket else {
+            return Err(Error::InvalidArgument("market data is streamed once messages are processed".into()));
+        };
+
+        if self.market_data.subscribe(request_id, request.contract_id, request.snapshot) {
+            websocket.send_text(&subscribe_market_data(request.contract_id))?;
+        }
+
+        Ok(())
+    }
+
+    fn cancel_market_data(&self, request_id: i32) {
+        cancel_market_data(&self.market_data, self.websocket.as_deref(), request_id);
+    }
+
+    // Reads positions of every account, a page at a time.
+    fn receive_positions(&self) -> Result<(), Error> {
+        for account in &self.accounts {
+            for page in 0..MAX_POSITION_PAGES {
+                let positions = self.gateway.get(&format!("/v1/api/portfolio/{account}/positions/{page}"))?;
+                let positions = positions.as_array().map(Vec::as_slice).unwrap_or_default();
+                if positions.is_empty() {
+                    break;
+                }
+
+                for position in positions {
+                    let field = |name: &str| position.get(name).and_then(text).unwrap_or_default();
+                    let number = |name: &str| position.get(name).and_then(text).unwrap_or_else(|| "0".into());
+
+                    self.receive(&[
+                        "61",
+                        "3",
+                        account,
+                        &field("conid"),
+                        &field("ticker"),
+                        &field("assetClass"),
+                        &field("expiry"),
+                        &number("strike"),
+                        &field("putOrCall"),
+                        &field("multiplier"),
+                        &field("listingExchange"),
+                        &field("currency"),
+                        &field("contractDesc"),
+                        "",
+                        &number("position"),
+                        &number("avgCost"),
+                    ]);
+                }
+            }
+        }
+
+        self.receive(&["62", "1"]);
+        Ok(())
+    }
+
+    // Opens the streaming connection, authenticated with the session of the gateway.
+    fn open_websocket(&mut self) -> Result<WebSocket, Error> {
+        let tickle = self.gateway.post("/v1/api/tickle")?.body;
+        let session = tickle
+            .get("session")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::ConnectionFailed("client portal did not return a session".into()))?;
+
+        let websocket = WebSocket::connect(&self.gateway, "/v1/api/ws", &format!("api={session}"))?;
+        let writer = websocket.writer();
+        writer.send_text(&json!({ "session": session }).to_string())?;
+
+        self.websocket = Some(Arc::new(writer));
+        Ok(websocket)
+    }
+}
+
+impl MessageBus for ClientPortalBus {
+    fn read_message(&mut self) -> Result<ResponseMessage, Error> {
+        let message = match self.read_timeout {
+            Some(timeout) => self.incoming_recv.recv_timeout(timeout).map_err(|err| {
+                if err.is_timeout() {
+                    Error::Io(std::io::Error::new(std::io::ErrorKind::TimedOut, "no message from client portal"))
+                } else {
+                    Error::Shutdown
+                }
+            })?,
+            None => self.incoming_recv.recv().map_err(|_| Error::Shutdown)?,
+        };
+
+        Ok(message)
+    }
+
+    fn write_message(&mut self, message: &RequestMessage) -> Result<(), Error> {
+        self.ensure_running()?;
+
+        match message_type(message) {
+            Some(message_id) if message_id == OutgoingMessages::CancelMarketData as i32 => {
+                let request_id = message[2].parse()?;
+                self.cancel_market_data(request_id);
+                Ok(())
+            }
+            _ => Err(unsupported(message)),
+        }
+    }
+
+    fn send_generic_message(&mut self, request_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.send_request(request_id, message, false)
+    }
+
+    fn send_durable_message(&mut self, request_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.send_request(request_id, message, true)
+    }
+
+    fn send_order_message(&mut self, _order_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        Err(unsupported(message))
+    }
+
+    // Order ids are only used by the client, so are allocated locally.
+    fn request_next_order_id(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.ensure_running()?;
+
+        let order_id = self.next_order_id.fetch_add(1, Ordering::Relaxed);
+        self.receive(&["9", "1", &order_id.to_string()]);
+
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.order_ids_out)))
+    }
+
+    fn request_open_orders(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(unsupported(message))
+    }
+
+    fn request_market_rule(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(unsupported(message))
+    }
+
+    fn request_positions(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.ensure_running()?;
+        self.receive_positions()?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_positions)))
+    }
+
+    fn request_family_codes(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(unsupported(message))
+    }
+
+    fn request_market_depth_exchanges(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Err(unsupported(message))
+    }
+
+    // Reports the time of the gateway's response.
+    fn request_current_time(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.ensure_running()?;
+
+        let response = self.gateway.post("/v1/api/tickle")?;
+        let time = match response.date.as_deref().and_then(parse_http_date) {
+            Some(time) => time,
+            None => {
+                debug!("client portal response has no date, using local time");
+                OffsetDateTime::now_utc()
+            }
+        };
+
+        if message_type(message) == Some(OutgoingMessages::RequestCurrentTimeInMillis as i32) {
+            let millis = time.unix_timestamp_nanos() / 1_000_000;
+            self.receive(&["109", &millis.to_string()]);
+        } else {
+            self.receive(&["49", "1", &time.unix_timestamp().to_string()]);
+        }
+
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_current_time)))
+    }
+
+    // Answers the handshake with the highest server version the client supports, as the messages are translated for it.
+    fn write(&mut self, packet: &str) -> Result<(), Error> {
+        let Some(versions) = packet.strip_prefix("API\0").and_then(|framed| framed.get(4..)) else {
+            return Err(Error::InvalidArgument(format!("unexpected data for client portal: {packet:?}")));
+        };
+
+        let max_version = versions.rsplit("..").next().unwrap_or_default();
+        self.server_version = max_version.parse()?;
+
+        let connection_time = OffsetDateTime::now_utc().format(format_description!("[year][month][day] [hour]:[minute]:[second] UTC"));
+        let connection_time = connection_time.map_err(|err| Error::ConnectionFailed(err.to_string()))?;
+        self.receive(&[&self.server_version.to_string(), &connection_time]);
+
+        Ok(())
+    }
+
+    // Reads the accounts of the session, which also makes the gateway ready for portfolio requests.
+    fn start_api(&mut self, _message: &RequestMessage) -> Result<(), Error> {
+        let accounts = self.gateway.get("/v1/api/iserver/accounts")?;
+        self.accounts = accounts
+            .get("accounts")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(text)
+            .collect();
+        self.gateway.get("/v1/api/portfolio/accounts")?;
+
+        let order_id = self.next_order_id.fetch_add(1, Ordering::Relaxed);
+        self.receive(&["9", "1", &order_id.to_string()]);
+        self.receive(&["15", "1", &self.accounts.join(",")]);
+
+        Ok(())
+    }
+
+    // Streams market data from the gateway, and dispatches translated messages to the requests they answer.
+    fn process_messages(&mut self, server_version: i32) -> Result<(), Error> {
+        let mut websocket = self.open_websocket()?;
+
+        let writer = self.websocket.clone().expect("websocket");
+        let market_data = Arc::clone(&self.market_data);
+        let incoming = self.incoming_send.clone();
+        let shutdown = Arc::clone(&self.shutdown);
+        let globals = Arc::clone(&self.globals);
+
+        let handle = thread::spawn(move || {
+            loop {
+                match websocket.read_text() {
+                    Ok(Some(text)) => {
+                        for message in receive_market_data(&market_data, &writer, &text) {
+                            incoming.send(message).ok();
+                        }
+                    }
+                    Ok(None) | Err(_) if shutdown.load(Ordering::Relaxed) => break,
+                    Ok(None) => {
+                        error!("client portal closed the websocket");
+                        globals.connection_events.publish(ConnectionEvent::Disconnected);
+                        break;
+                    }
+                    Err(err) => {
+                        error!("error reading from client portal: {err}");
+                        globals.connection_events.publish(ConnectionEvent::Disconnected);
+                        break;
+                    }
+                }
+            }
+            debug!("client portal reader stopped");
+            0
+        });
+        self.handles.push(handle);
+
+        // keeps the websocket alive and ends snapshots that timed out
+        let writer = self.websocket.clone().expect("websocket");
+        let market_data = Arc::clone(&self.market_data);
+        let incoming = self.incoming_send.clone();
+        let shutdown = Arc::clone(&self.shutdown);
+
+        let handle = thread::spawn(move || {
+            let mut keepalive = Instant::now();
+            while wait_unless_shutdown(Duration::from_secs(1), &shutdown) {
+                if keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+                    if let Err(err) = writer.send_text("tic") {
+                        warn!("error sending keepalive: {err}");
+                    }
+                    keepalive = Instant::now();
+                }
+
+                for message in expire_snapshots(&market_data, &writer, Instant::now()) {
+                    incoming.send(message).ok();
+                }
+            }
+            debug!("client portal keepalive stopped");
+            0
+        });
+        self.handles.push(handle);
+
+        let (stop_send, stop_recv) = channel::bounded::<()>(0);
+        self.stop = Some(stop_send);
+
+        let incoming = self.incoming_recv.clone();
+        let requests = Arc::clone(&self.requests);
+        let orders = Arc::clone(&self.orders);
+        let globals = Arc::clone(&self.globals);
+        let parse_mode = Arc::clone(&self.parse_mode);
+        let executions = SenderHash::<String, ResponseMessage>::new();
+
+        let handle = thread::spawn(move || {
+            loop {
+                channel::select! {
+                    recv(incoming) -> message => match message {
+                        Ok(mut message) => {
+                            message.parse_mode = *parse_mode.read().unwrap();
+                            dispatch_message(message, server_version, &requests, &orders, &globals, &executions);
+                        }
+                        Err(_) => break,
+                    },
+                    recv(stop_recv) -> _ => break,
+                }
+            }
+            debug!("client portal dispatcher stopped");
+            0
+        });
+        self.handles.push(handle);
+
+        let requests = Arc::clone(&self.requests);
+        let orders = Arc::clone(&self.orders);
+        let market_data = Arc::clone(&self.market_data);
+        let writer = self.websocket.clone();
+        let signal_recv = self.signals_recv.clone();
+
+        let handle = thread::spawn(move || {
+            for signal in &signal_recv {
+                match signal {
+                    // the gateway is unsubscribed rather than sent TWS cancel messages
+                    Signal::Request(request_id) | Signal::Cancel(request_id, _) => {
+                        requests.remove(&request_id);
+                        cancel_market_data(&market_data, writer.as_deref(), request_id);
+                        debug!("released request_id {}, requests.len()={}", request_id, requests.len());
+                    }
+                    Signal::Order(order_id) => {
+                        orders.remove(&order_id);
+                    }
+                    Signal::Stop => break,
+                }
+            }
+            debug!("signal processor stopped");
+            0
+        });
+        self.handles.push(handle);
+
+        Ok(())
+    }
+
+    fn set_parse_mode(&mut self, parse_mode: ParseMode) {
+        *self.parse_mode.write().unwrap() = parse_mode;
+    }
+
+    fn set_subscription_buffer(&mut self, subscription_buffer: Option<SubscriptionBuffer>) {
+        self.subscription_buffer = subscription_buffer;
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        if self.shutdown.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        self.requests.clear();
+        self.orders.clear();
+        self.signals_send.send(Signal::Stop).unwrap();
+        self.stop.take();
+
+        if let Some(websocket) = &self.websocket {
+            websocket.close();
+        }
+
+        self.globals.connection_events.publish(ConnectionEvent::Disconnected);
+
+        // wakes the keepalive and heartbeat
+        for handle in &self.handles {
+            handle.thread().unpark();
+        }
+
+        info!("client portal bus shut down");
+        Ok(())
+    }
+
+    // Tickles the gateway, which keeps the session alive. The connection is unhealthy while the session isn't authenticated.
+    fn start_heartbeat(&mut self, _message: &RequestMessage, interval: Duration, _deadline: Duration) -> Result<(), Error> {
+        self.ensure_running()?;
+
+        let gateway = self.gateway.clone();
+        let healthy = Arc::clone(&self.healthy);
+        let shutdown = Arc::clone(&self.shutdown);
+
+        let handle = thread::spawn(move || {
+            while wait_unless_shutdown(interval, &shutdown) {
+                let authenticated = gateway
+                    .post("/v1/api/tickle")
+                    .map(|tickle| tickle.body.get("iserver").and_then(authenticated).unwrap_or(false));
+
+                match authenticated {
+                    Ok(true) => healthy.store(true, Ordering::Relaxed),
+                    Ok(false) => {
+                        warn!("client portal session is no longer authenticated");
+                        healthy.store(false, Ordering::Relaxed);
+                    }
+                    Err(err) => {
+                        warn!("no response to heartbeat: {err}");
+                        healthy.store(false, Ordering::Relaxed);
+                    }
+                }
+            }
+            debug!("heartbeat stopped");
+            0
+        });
+        self.handles.push(handle);
+
+        Ok(())
+    }
+
+    fn connection_events(&mut self) -> Receiver<ConnectionEvent> {
+        self.globals.connection_events.subscribe()
+    }
+
+    fn error_events(&mut self) -> Receiver<ErrorEvent> {
+        self.globals.error_events.subscribe()
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    fn disconnect(&mut self) -> Result<(), Error> {
+        self.shutdown()?;
+
+        for handle in self.handles.drain(..) {
+            if handle.join().is_err() {
+                error!("message processing thread panicked");
+            }
+        }
+
+        info!("client portal bus disconnected");
+        Ok(())
+    }
+}
+
+fn authenticated(iserver: &Value) -> Option<bool> {
+    iserver.get("authStatus")?.get("authenticated")?.as_bool()
+}
+
+// Returns strings, and numbers formatted without a trailing .0. The gateway sends many numbers as strings, and vice versa.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(number) => match number.as_f64() {
+            Some(value) if value.fract() == 0.0 && value.abs() < 1e15 => Some(format!("{}", value as i64)),
+            _ => Some(number.to_string()),
+        },
+        _ => None,
+    }
+}
+
+fn message_type(message: &RequestMessage) -> Option<i32> {
+    message.encode().split('\0').next()?.parse().ok()
+}
+
+fn unsupported(message: &RequestMessage) -> Error {
+    let message_id = message_type(message).map_or_else(|| "?".into(), |id| id.to_string());
+    Error::InvalidArgument(format!("request with message id {message_id} is not supported by the client portal"))
+}
+
+// Encodes fields as a message from TWS.
+fn response(fields: &[&str]) -> ResponseMessage {
+    let mut data = fields.join("\0");
+    data.push('\0');
+    ResponseMessage::from_string(data)
+}
+
+// Parses dates like Wed, 21 Oct 2015 07:28:00 GMT.
+fn parse_http_date(date: &str) -> Option<OffsetDateTime> {
+    let format = format_description!("[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT");
+    PrimitiveDateTime::parse(date, format).ok().map(PrimitiveDateTime::assume_utc)
+}
+
+// The parts of a RequestMarketData request the gateway needs.
+#[derive(Debug, PartialEq)]
+struct MarketDataRequest {
+    contract_id: i64,
+    snapshot: bool,
+}
+
+impl MarketDataRequest {
+    fn parse(message: &RequestMessage) -> Result<MarketDataRequest, Error> {
+        let fields = message.encode();
+        let fields: Vec<&str> = fields.split('\0').collect();
+
+        // message id, version, request id, then the contract
+        let contract_id = fields.get(3).copied().unwrap_or_default().parse().unwrap_or(0);
+        if fields.get(5) == Some(&"BAG") {
+            return Err(Error::InvalidArgument("combo market data is not supported by the client portal".into()));
+        }
+
+        // after the contract are the delta neutral contract, generic ticks and the snapshot flag
+        let mut i = 15;
+        if fields.get(i) == Some(&"1") {
+            i += 3;
+        }
+        let snapshot = fields.get(i + 2) == Some(&"1");
+
+        Ok(MarketDataRequest { contract_id, snapshot })
+    }
+}
+
+fn subscribe_market_data(contract_id: i64) -> String {
+    let fields: Vec<&str> = PRICE_FIELDS.iter().chain(SIZE_FIELDS).map(|(field, _)| *field).collect();
+    format!("smd+{contract_id}+{}", json!({ "fields": fields }))
+}
+
+fn unsubscribe_market_data(writer: Option<&WebSocketWriter>, contract_id: i64) {
+    if let Some(writer) = writer {
+        if let Err(err) = writer.send_text(&format!("umd+{contract_id}+{{}}")) {
+            warn!("error unsubscribing market data for {contract_id}: {err}");
+        }
+    }
+}
+
+fn cancel_market_data(market_data: &MarketData, writer: Option<&WebSocketWriter>, request_id: i32) {
+    if let Some(contract_id) = market_data.unsubscribe(request_id) {
+        unsubscribe_market_data(writer, contract_id);
+    }
+}
+
+// Translates a message from the websocket to ticks for each request subscribed to its contract.
+fn receive_market_data(market_data: &MarketData, writer: &WebSocketWriter, text: &str) -> Vec<ResponseMessage> {
+    let update: Value = match serde_json::from_str(text) {
+        Ok(update) => update,
+        Err(err) => {
+            warn!("error parsing message {text:?}: {err}");
+            return Vec::new();
+        }
+    };
+
+    let Some(contract_id) = market_data_topic(&update) else {
+        debug!("ws <- {text:?}");
+        return Vec::new();
+    };
+
+    let (messages, unsubscribed) = market_data.update(contract_id, &update);
+    for contract_id in unsubscribed {
+        unsubscribe_market_data(Some(writer), contract_id);
+    }
+
+    messages
+}
+
+// Returns the contract of market data messages, whose topic is smd+<contract id>.
+fn market_data_topic(message: &Value) -> Option<i64> {
+    message.get("topic")?.as_str()?.strip_prefix("smd+")?.parse().ok()
+}
+
+fn expire_snapshots(market_data: &MarketData, writer: &WebSocketWriter, now: Instant) -> Vec<ResponseMessage> {
+    let (messages, unsubscribed) = market_data.expire(now);
+    for contract_id in unsubscribed {
+        unsubscribe_market_data(Some(writer), contract_id);
+    }
+    messages
+}
+
+// Market data requests by request id. The gateway streams each contract once, however many requests subscribe to it.
+#[derive(Debug, Default)]
+struct MarketData {
+    subscriptions: Mutex<HashMap<i32, Subscription>>,
+}
+
+#[derive(Debug)]
+struct Subscription {
+    contract_id: i64,
+    // when the request is a snapshot, when it ends
+    snapshot: Option<Instant>,
+    // tick types of the snapshot received so far
+    received: Vec<i32>,
+}
+
+impl MarketData {
+    // Returns true if the contract wasn't streamed yet.
+    fn subscribe(&self, request_id: i32, contract_id: i64, snapshot: bool) -> bool {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+
+        let streamed = subscriptions.values().any(|subscription| subscription.contract_id == contract_id);
+        subscriptions.insert(
+            request_id,
+            Subscription {
+                contract_id,
+                snapshot: snapshot.then(|| Instant::now() + SNAPSHOT_TIMEOUT),
+                received: Vec::new(),
+            },
+        );
+
+        !streamed
+    }
+
+    // Returns the contract when no other request is subscribed to it.
+    fn unsubscribe(&self, request_id: i32) -> Option<i64> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let subscription = subscriptions.remove(&request_id)?;
+        remaining(&subscriptions, subscription.contract_id)
+    }
+
+    // Translates an update of the contract to ticks. Returns the messages, and contracts no longer subscribed to.
+    // Snapshots end once both bid and ask were received.
+    fn update(&self, contract_id: i64, update: &Value) -> (Vec<ResponseMessage>, Vec<i64>) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+
+        let mut messages = Vec::new();
+        let mut completed = Vec::new();
+
+        for (request_id, subscription) in subscriptions
+            .iter_mut()
+            .filter(|(_, subscription)| subscription.contract_id == contract_id)
+        {
+            let request_id = request_id.to_string();
+
+            if let Some(err) = update.get("error").and_then(text) {
+                messages.push(response(&["4", "2", &request_id, "354", &err]));
+                continue;
+            }
+
+            for (field, tick_type) in PRICE_FIELDS {
+                if let Some(price) = update.get(field).and_then(text).and_then(|price| parse_price(&price)) {
+                    messages.push(response(&["1", "6", &request_id, &tick_type.to_string(), &price.to_string(), "0", "0"]));
+                    subscription.received.push(*tick_type);
+                }
+            }
+            for (field, tick_type) in SIZE_FIELDS {
+                if let Some(size) = update.get(field).and_then(text).and_then(|size| parse_size(&size)) {
+                    messages.push(response(&["2", "6", &request_id, &tick_type.to_string(), &size.to_string()]));
+                }
+            }
+
+            if subscription.snapshot.is_some() && subscription.received.contains(&BID) && subscription.received.contains(&ASK) {
+                messages.push(response(&["57", "1", &request_id]));
+                completed.push(request_id.parse().unwrap());
+            }
+        }
+
+        let unsubscribed = self.remove(&mut subscriptions, completed);
+        (messages, unsubscribed)
+    }
+
+    // Ends snapshots that didn't complete in time.
+    fn expire(&self, now: Instant) -> (Vec<ResponseMessage>, Vec<i64>) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+
+        let expired: Vec<i32> = subscriptions
+            .iter()
+            .filter(|(_, subscription)| subscription.snapshot.is_some_and(|deadline| deadline <= now))
+            .map(|(request_id, _)| *request_id)
+            .collect();
+        let messages = expired.iter().map(|request_id| response(&["57", "1", &request_id.to_string()])).collect();
+
+        let unsubscribed = self.remove(&mut subscriptions, expired);
+        (messages, unsubscribed)
+    }
+
+    fn remove(&self, subscriptions: &mut HashMap<i32, Subscription>, request_ids: Vec<i32>) -> Vec<i64> {
+        let mut unsubscribed = Vec::new();
+        for request_id in request_ids {
+            if let Some(subscription) = subscriptions.remove(&request_id) {
+                unsubscribed.extend(remaining(subscriptions, subscription.contract_id));
+            }
+        }
+        unsubscribed
+    }
+}
+
+// Returns contract_id when no subscription remains for it.
+fn remaining(subscriptions: &HashMap<i32, Subscription>, contract_id: i64) -> Option<i64> {
+    if subscriptions.values().any(|subscription| subscription.contract_id == contract_id) {
+        None
+    } else {
+        Some(contract_id)
+    }
+}
+
+// Prices are prefixed with C when the market is closed and with H when trading is halted.
+fn parse_price(price: &str) -> Option<f64> {
+    price.trim_start_matches(['C', 'H']).replace(',', "").parse().ok()
+}
+
+// Sizes are formatted for display, e.g. 1,300 or 23.9M.
+fn parse_size(size: &str) -> Option<f64> {
+    let size = size.replace(',', "");
+    let (number, multiplier) = match size.chars().last()? {
+        'K' => (&size[..size.len() - 1], 1e3),
+        'M' => (&size[..size.len() - 1], 1e6),
+        'B' => (&size[..size.len() - 1], 1e9),
+        _ => (&size[..], 1.0),
+    };
+    number.parse::<f64>().ok().map(|number| number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "market_data")]
+    fn parse_market_data_requests() {
+        use crate::contracts::Contract;
+        use crate::market_data::realtime::encoders::encode_request_market_data;
+        use crate::server_versions;
+
+        let mut contract = Contract::stock("AAPL");
+        contract.contract_id = 265598;
+
+        let message = encode_request_market_data(server_versions::SIZE_RULES, 9000, &contract, "", true, false).unwrap();
+        assert_eq!(
+            MarketDataRequest::parse(&message).unwrap(),
+            MarketDataRequest {
+                contract_id: 265598,
+                snapshot: true
+            }
+        );
+
+        let message = encode_request_market_data(server_versions::SIZE_RULES, 9000, &contract, "233", false, false).unwrap();
+        assert!(!MarketDataRequest::parse(&message).unwrap().snapshot);
+    }
+
+    #[test]
+    fn translate_market_data() {
+        let market_data = MarketData::default();
+        assert!(market_data.subscribe(9000, 265598, true));
+        assert!(!market_data.subscribe(9001, 265598, false), "contract is already streamed");
+
+        let update: Value =
+            serde_json::from_str(r#"{"topic":"smd+265598","conid":265598,"31":"C168.42","84":"168.41","88":"1,300","87":"23.9M"}"#).unwrap();
+        let (messages, unsubscribed) = market_data.update(265598, &update);
+        assert!(unsubscribed.is_empty());
+
+        let mut encoded: Vec<String> = messages.iter().map(|message| message.raw().replace('\0', "|")).collect();
+        encoded.sort();
+        assert_eq!(
+            encoded,
+            vec![
+                "1|6|9000|1|168.41|0|0|",
+                "1|6|9000|4|168.42|0|0|",
+                "1|6|9001|1|168.41|0|0|",
+                "1|6|9001|4|168.42|0|0|",
+                "2|6|9000|0|1300|",
+                "2|6|9000|8|23900000|",
+                "2|6|9001|0|1300|",
+                "2|6|9001|8|23900000|",
+            ]
+        );
+
+        // the snapshot ends with the ask
+        let update: Value = serde_json::from_str(r#"{"topic":"smd+265598","86":"168.43"}"#).unwrap();
+        let (messages, unsubscribed) = market_data.update(265598, &update);
+        assert!(messages.iter().any(|message| message.raw().replace('\0', "|") == "57|1|9000|"));
+        assert!(unsubscribed.is_empty(), "streamed for 9001");
+
+        assert_eq!(market_data.unsubscribe(9001), Some(265598));
+        assert_eq!(market_data.unsubscribe(9001), None);
+    }
+
+    #[test]
+    fn expire_snapshots() {
+        let market_data = MarketData::default();
+        market_data.subscribe(9000, 265598, true);
+
+        let (messages, _) = market_data.expire(Instant::now());
+        assert!(messages.is_empty());
+
+        let (messages, unsubscribed) = market_data.expire(Instant::now() + SNAPSHOT_TIMEOUT);
+        assert_eq!(messages[0].raw().replace('\0', "|"), "57|1|9000|");
+        assert_eq!(unsubscribed, vec![265598]);
+    }
+
+    #[test]
+    fn parse_formatted_values() {
+        assert_eq!(parse_price("168.42"), Some(168.42));
+        assert_eq!(parse_price("C168.42"), Some(168.42));
+        assert_eq!(parse_price("H1,168.42"), Some(1168.42));
+        assert_eq!(parse_price(""), None);
+
+        assert_eq!(parse_size("1,300"), Some(1300.0));
+        assert_eq!(parse_size("1.5K"), Some(1500.0));
+        assert_eq!(parse_size("23.9M"), Some(23_900_000.0));
+        assert_eq!(parse_size("x"), None);
+
+        assert_eq!(market_data_topic(&json!({"topic": "smd+265598", "31": "1"})), Some(265598));
+        assert_eq!(market_data_topic(&json!({"topic": "system"})), None);
+
+        assert_eq!(text(&json!(265598)), Some("265598".into()));
+        assert_eq!(text(&json!(-1.5e2)), Some("-150".into()));
+        assert_eq!(text(&json!(150.25)), Some("150.25".into()));
+        assert_eq!(text(&json!("AAPL")), Some("AAPL".into()));
+        assert_eq!(text(&json!(null)), None);
+
+        assert_eq!(parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT"), Some(datetime!(2015-10-21 07:28:00 UTC)));
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! The Client Portal gateway's REST endpoints, over TLS unless the gateway's address is an `http://` url.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+use rustls::{ClientConfig, RootCertStore};
+use serde_json::Value;
+
+use crate::Error;
+
+// The gateway answers within a few seconds, or not at all.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub(crate) struct Gateway {
+    agent: ureq::Agent,
+    // host and port, e.g. localhost:5000
+    address: String,
+    tls: Option<Arc<ClientConfig>>,
+}
+
+pub(crate) struct Response {
+    // the value of the Date header
+    pub date: Option<String>,
+    pub body: Value,
+}
+
+impl Gateway {
+    // Addresses the gateway at address, e.g. localhost:5000 or https://localhost:5000, over TLS with config, or with the
+    // Mozilla root certificates when None. Addresses like http://localhost:5000 are served in the clear.
+    pub(crate) fn new(address: &str, config: Option<ClientConfig>) -> Result<Gateway, Error> {
+        let address = address.trim_end_matches('/');
+
+        let (address, tls) = match address.strip_prefix("http://") {
+            Some(_) if config.is_some() => {
+                return Err(Error::InvalidArgument(format!("{address} is served in the clear, not over TLS")));
+            }
+            Some(address) => (address, None),
+            None => {
+                let config = match config {
+                    Some(config) => config,
+                    None => default_config()?,
+                };
+                (address.strip_prefix("https://").unwrap_or(address), Some(Arc::new(config)))
+            }
+        };
+
+        let mut agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).user_agent("ibapi");
+        if let Some(config) = &tls {
+            agent = agent.tls_config(Arc::clone(config));
+        }
+
+        Ok(Gateway {
+            agent: agent.build(),
+            address: address.to_owned(),
+            tls,
+        })
+    }
+
+    pub(crate) fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub(crate) fn tls(&self) -> Option<Arc<ClientConfig>> {
+        self.tls.clone()
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        REQUEST_TIMEOUT
+    }
+
+    // The url of the gateway's web page, e.g. https://localhost:5000.
+    pub(crate) fn url(&self) -> String {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        format!("{scheme}://{}", self.address)
+    }
+
+    pub(crate) fn websocket_url(&self, path: &str) -> String {
+        let scheme = if self.tls.is_some() { "wss" } else { "ws" };
+        format!("{scheme}://{}{path}", self.address)
+    }
+
+    pub(crate) fn get(&self, path: &str) -> Result<Value, Error> {
+        Ok(self.request("GET", path)?.body)
+    }
+
+    pub(crate) fn post(&self, path: &str) -> Result<Response, Error> {
+        self.request("POST", path)
+    }
+
+    // Sends a request without a body, failing on unsuccessful responses.
+    fn request(&self, method: &str, path: &str) -> Result<Response, Error> {
+        debug!("{method} {path} ->");
+
+        let request = self.agent.request(method, &format!("{}{path}", self.url()));
+        // the gateway wants a content length for posts
+        let result = if method == "POST" { request.send_bytes(&[]) } else { request.call() };
+        let response = match result {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                return Err(Error::ConnectionFailed(format!("client portal responded {status}: {}", body.trim())));
+            }
+            Err(ureq::Error::Transport(err)) => return Err(Error::ConnectionFailed(format!("client portal: {err}"))),
+        };
+        debug!("<- {method} {path} {}", response.status());
+
+        let date = response.header("date").map(str::to_owned);
+        let body = response.into_string()?;
+        let body = serde_json::from_str(&body).map_err(|err| Error::InvalidResponse(format!("invalid json from client portal: {err}")))?;
+
+        Ok(Response { date, body })
+    }
+}
+
+// Verifies the gateway's certificate with the Mozilla root certificates.
+fn default_config() -> Result<ClientConfig, Error> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| Error::ConnectionFailed(err.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gateway_urls() {
+        let gateway = Gateway::new("localhost:5000", None).unwrap();
+        assert_eq!(gateway.address(), "localhost:5000");
+        assert_eq!(gateway.url(), "https://localhost:5000");
+        assert_eq!(gateway.websocket_url("/v1/api/ws"), "wss://localhost:5000/v1/api/ws");
+
+        let gateway = Gateway::new("https://localhost:5000/", None).unwrap();
+        assert_eq!(gateway.address(), "localhost:5000");
+        assert!(gateway.tls().is_some());
+
+        let gateway = Gateway::new("http://127.0.0.1:5000", None).unwrap();
+        assert_eq!(gateway.address(), "127.0.0.1:5000");
+        assert_eq!(gateway.url(), "http://127.0.0.1:5000");
+        assert_eq!(gateway.websocket_url("/v1/api/ws"), "ws://127.0.0.1:5000/v1/api/ws");
+        assert!(gateway.tls().is_none());
+
+        let config = default_config().unwrap();
+        assert!(
+            Gateway::new("http://127.0.0.1:5000", Some(config)).is_err(),
+            "tls configuration for plain http"
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! The Client Portal gateway's streaming endpoint, a WebSocket read by a single thread.
+//!
+//! Messages are sent from other threads by queueing them for the reader, which sends them between reads.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crossbeam::channel::{self, Receiver, Sender};
+use log::debug;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::http::HeaderValue;
+use tungstenite::Message;
+
+use super::http::Gateway;
+use crate::client::transport::connection::Connection;
+use crate::Error;
+
+// How long a read waits before sending queued messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub(crate) struct WebSocket {
+    socket: tungstenite::WebSocket<Stream>,
+    outgoing: Receiver<Message>,
+    writer: Sender<Message>,
+}
+
+// Queues messages for the reader of a WebSocket, from other threads.
+#[derive(Clone, Debug)]
+pub(crate) struct WebSocketWriter {
+    outgoing: Sender<Message>,
+}
+
+impl WebSocket {
+    // Opens the WebSocket at path on the gateway, sending cookie with the upgrade request.
+    pub(crate) fn connect(gateway: &Gateway, path: &str, cookie: &str) -> Result<WebSocket, Error> {
+        let socket = TcpStream::connect(gateway.address())?;
+        let connection = match gateway.tls() {
+            Some(config) => Connection::tls(socket, gateway.address(), config, Some(gateway.timeout()))?,
+            None => Connection::plain(socket),
+        };
+        connection.set_read_timeout(Some(gateway.timeout()))?;
+
+        let mut request = gateway.websocket_url(path).into_client_request().map_err(websocket_error)?;
+        let cookie = HeaderValue::from_str(cookie).map_err(|err| Error::InvalidArgument(err.to_string()))?;
+        request.headers_mut().insert("Cookie", cookie);
+
+        let (socket, _) = tungstenite::client(request, Stream(connection))
+            .map_err(|err| Error::ConnectionFailed(format!("client portal refused websocket upgrade: {err}")))?;
+        socket.get_ref().0.set_read_timeout(Some(POLL_INTERVAL))?;
+        debug!("websocket {path} opened");
+
+        let (writer, outgoing) = channel::unbounded();
+        Ok(WebSocket { socket, outgoing, writer })
+    }
+
+    pub(crate) fn writer(&self) -> WebSocketWriter {
+        WebSocketWriter {
+            outgoing: self.writer.clone(),
+        }
+    }
+
+    // Returns the next text or binary message, sending queued messages meanwhile. Returns None once the connection is closed.
+    pub(crate) fn read_text(&mut self) -> Result<Option<String>, Error> {
+        loop {
+            while let Ok(message) = self.outgoing.try_recv() {
+                if let Message::Close(frame) = message {
+                    self.socket.close(frame).ok();
+                    self.socket.flush().ok();
+                    return Ok(None);
+                }
+                self.socket.send(message).map_err(websocket_error)?;
+            }
+
+            match self.socket.read() {
+                Ok(Message::Text(text)) => return Ok(Some(text)),
+                Ok(Message::Binary(data)) => return Ok(Some(String::from_utf8_lossy(&data).into_owned())),
+                // pings are answered by tungstenite
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err)) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => return Ok(None),
+                Err(err) => return Err(websocket_error(err)),
+            }
+        }
+    }
+}
+
+impl WebSocketWriter {
+    pub(crate) fn send_text(&self, text: &str) -> Result<(), Error> {
+        debug!("ws -> {text:?}");
+        self.outgoing.send(Message::Text(text.to_owned())).map_err(|_| Error::Shutdown)
+    }
+
+    // Closes the connection once the reader sends what was queued before, which then stops reading.
+    pub(crate) fn close(&self) {
+        self.outgoing.send(Message::Close(None)).ok();
+    }
+}
+
+fn websocket_error(err: tungstenite::Error) -> Error {
+    match err {
+        tungstenite::Error::Io(err) => Error::Io(err),
+        err => Error::ConnectionFailed(format!("client portal websocket: {err}")),
+    }
+}
+
+// The connection owned by the WebSocket.
+#[derive(Debug)]
+struct Stream(Connection);
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.0).read(buf)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.0).flush()
+    }
+}
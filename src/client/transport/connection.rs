@@ -0,0 +1,206 @@
+//! The connection to the server, in the clear or over TLS.
+//!
+//! The connection is read by the message reader while the writer thread writes to it, so both go through shared references.
+//! Over TLS the session is locked to decrypt or encrypt, but not while waiting for the server, so reads don't block writes.
+
+use std::io::{self, IoSlice, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub(crate) struct Connection {
+    socket: TcpStream,
+    #[cfg(feature = "tls")]
+    tls: Option<tls::Session>,
+}
+
+impl Connection {
+    // A connection exchanging messages in the clear.
+    pub(crate) fn plain(socket: TcpStream) -> Connection {
+        Connection {
+            socket,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    pub(crate) fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.socket.shutdown(how)
+    }
+}
+
+impl Read for &Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(feature = "tls")]
+        if let Some(session) = &self.tls {
+            return session.read(&self.socket, buf);
+        }
+
+        (&self.socket).read(buf)
+    }
+}
+
+impl Write for &Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(feature = "tls")]
+        if let Some(session) = &self.tls {
+            return session.write(&self.socket, buf);
+        }
+
+        (&self.socket).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        #[cfg(feature = "tls")]
+        if let Some(session) = &self.tls {
+            return session.write_vectored(&self.socket, bufs);
+        }
+
+        (&self.socket).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.socket).flush()
+    }
+}
+
+#[cfg(feature = "tls")]
+pub(crate) use tls::TlsConfig;
+
+#[cfg(feature = "tls")]
+mod tls {
+    use std::io::{self, ErrorKind, IoSlice, Read, Write};
+    use std::net::TcpStream;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use rustls::pki_types::ServerName;
+    use rustls::{ClientConfig, ClientConnection};
+
+    use super::Connection;
+
+    // Bytes read from the socket at a time. Small enough that the plaintext of a read never exceeds what the session buffers.
+    const READ_SIZE: usize = 4096;
+
+    // TLS configuration of connections. Configurations are equal when they're the same configuration.
+    #[derive(Clone, Debug)]
+    pub(crate) struct TlsConfig(Arc<ClientConfig>);
+
+    impl TlsConfig {
+        pub(crate) fn new(config: ClientConfig) -> TlsConfig {
+            TlsConfig(Arc::new(config))
+        }
+
+        pub(crate) fn config(&self) -> Arc<ClientConfig> {
+            Arc::clone(&self.0)
+        }
+    }
+
+    impl PartialEq for TlsConfig {
+        fn eq(&self, other: &Self) -> bool {
+            Arc::ptr_eq(&self.0, &other.0)
+        }
+    }
+
+    #[derive(Debug)]
+    pub(super) struct Session {
+        connection: Mutex<ClientConnection>,
+    }
+
+    impl Connection {
+        // Completes a TLS handshake with the server at address over socket, waiting at most handshake_timeout when given.
+        pub(crate) fn tls(
+            mut socket: TcpStream,
+            address: &str,
+            config: Arc<ClientConfig>,
+            handshake_timeout: Option<Duration>,
+        ) -> io::Result<Connection> {
+            let server_name = ServerName::try_from(host(address).to_owned()).map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+            let mut connection = ClientConnection::new(config, server_name).map_err(io::Error::other)?;
+
+            socket.set_read_timeout(handshake_timeout)?;
+            while connection.is_handshaking() {
+                connection.complete_io(&mut socket)?;
+            }
+            socket.set_read_timeout(None)?;
+
+            Ok(Connection {
+                socket,
+                tls: Some(Session {
+                    connection: Mutex::new(connection),
+                }),
+            })
+        }
+    }
+
+    impl Session {
+        pub(super) fn read(&self, mut socket: &TcpStream, buf: &mut [u8]) -> io::Result<usize> {
+            let mut received = [0_u8; READ_SIZE];
+
+            loop {
+                match self.connection.lock().unwrap().reader().read(buf) {
+                    // no plaintext is buffered until more records arrive
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                    result => return result,
+                }
+
+                // waits for records without holding the session, so messages can be written meanwhile
+                let len = socket.read(&mut received)?;
+                if len == 0 {
+                    return Err(io::Error::new(ErrorKind::UnexpectedEof, "connection closed without TLS close_notify"));
+                }
+
+                let mut connection = self.connection.lock().unwrap();
+                let mut records = &received[..len];
+                while !records.is_empty() {
+                    connection.read_tls(&mut records)?;
+                    connection
+                        .process_new_packets()
+                        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+                }
+                // e.g. alerts or key updates answering the server
+                while connection.wants_write() {
+                    connection.write_tls(&mut socket)?;
+                }
+            }
+        }
+
+        pub(super) fn write(&self, socket: &TcpStream, buf: &[u8]) -> io::Result<usize> {
+            self.write_vectored(socket, &[IoSlice::new(buf)])
+        }
+
+        // Encrypts bufs and sends the records before returning, so messages aren't held back.
+        pub(super) fn write_vectored(&self, mut socket: &TcpStream, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+            let mut connection = self.connection.lock().unwrap();
+
+            let len = connection.writer().write_vectored(bufs)?;
+            while connection.wants_write() {
+                connection.write_tls(&mut socket)?;
+            }
+
+            Ok(len)
+        }
+    }
+
+    // Returns the host of address, e.g. `gateway.local` of `gateway.local:4002`, without the brackets of IPv6 addresses.
+    fn host(address: &str) -> &str {
+        let host = address.rsplit_once(':').map_or(address, |(host, _port)| host);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn hosts() {
+            assert_eq!(host("gateway.local:4002"), "gateway.local");
+            assert_eq!(host("127.0.0.1:4002"), "127.0.0.1");
+            assert_eq!(host("[::1]:4002"), "::1");
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Measurements of the traffic with the server, reported to a [MetricsObserver].
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::messages::{IncomingMessages, RequestMessage, ResponseMessage};
+
+/// Receives measurements of the connection to TWS, e.g. to export them to a monitoring system. See [Client::set_metrics_observer](crate::Client::set_metrics_observer).
+///
+/// Methods are called from the threads sending, receiving and decoding messages, so they should return quickly,
+/// e.g. by incrementing counters. Every method does nothing by default.
+pub trait MetricsObserver: Send + Sync {
+    /// Called for each message sent, with its message id, e.g. 1 for market data requests, and its size on the wire.
+    fn message_sent(&self, _message_id: i32, _bytes: usize) {}
+
+    /// Called for each message received, with its type and size on the wire.
+    fn message_received(&self, _message_type: IncomingMessages, _bytes: usize) {}
+
+    /// Called when a field of a received message fails to parse, including fields substituted in [lenient](crate::client::ParseMode::Lenient) mode.
+    fn decode_error(&self, _message_type: IncomingMessages) {}
+
+    /// Called with the time between sending a request or order, identified by its message id, and receiving its first response.
+    fn round_trip(&self, _message_id: i32, _latency: Duration) {}
+}
+
+// Reports traffic to the observer, once one is set.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    observer: RwLock<Option<Arc<dyn MetricsObserver>>>,
+    // message id and send time of requests and orders, until their first response
+    requests: Mutex<HashMap<i32, (i32, Instant)>>,
+    orders: Mutex<HashMap<i32, (i32, Instant)>>,
+}
+
+impl Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").field("observed", &self.observer().is_some()).finish()
+    }
+}
+
+impl Metrics {
+    pub fn set_observer(&self, observer: Option<Arc<dyn MetricsObserver>>) {
+        if observer.is_none() {
+            self.requests.lock().unwrap().clear();
+            self.orders.lock().unwrap().clear();
+        }
+        *self.observer.write().unwrap() = observer;
+    }
+
+    pub fn observer(&self) -> Option<Arc<dyn MetricsObserver>> {
+        self.observer.read().unwrap().clone()
+    }
+
+    // Reports a message written, frame being its length prefixed encoding.
+    pub fn sent(&self, message: &RequestMessage, frame: &[u8]) {
+        if let Some(observer) = self.observer() {
+            observer.message_sent(message_id(message), frame.len());
+        }
+    }
+
+    // Starts timing the round trip of a request.
+    pub fn request_sent(&self, request_id: i32, message: &RequestMessage) {
+        if self.observer.read().unwrap().is_some() {
+            self.requests.lock().unwrap().insert(request_id, (message_id(message), Instant::now()));
+        }
+    }
+
+    // Starts timing the round trip of an order.
+    pub fn order_sent(&self, order_id: i32, message: &RequestMessage) {
+        if self.observer.read().unwrap().is_some() {
+            self.orders.lock().unwrap().insert(order_id, (message_id(message), Instant::now()));
+        }
+    }
+
+    // Reports a message received, and the round trip of the request or order it is the first response to.
+    pub fn received(&self, message: &ResponseMessage) {
+        let Some(observer) = self.observer() else {
+            return;
+        };
+
+        // length prefix and data
+        observer.message_received(message.message_type(), message.raw().len() + 4);
+
+        let sent = match (message.request_id(), message.order_id()) {
+            (Some(request_id), _) => self.requests.lock().unwrap().remove(&request_id),
+            (None, Some(order_id)) => self.orders.lock().unwrap().remove(&order_id),
+            (None, None) => None,
+        };
+        if let Some((message_id, sent_at)) = sent {
+            observer.round_trip(message_id, sent_at.elapsed());
+        }
+    }
+
+    // Stops timing requests released before their first response.
+    pub fn request_released(&self, request_id: i32) {
+        self.requests.lock().unwrap().remove(&request_id);
+    }
+
+    pub fn order_released(&self, order_id: i32) {
+        self.orders.lock().unwrap().remove(&order_id);
+    }
+}
+
+fn message_id(message: &RequestMessage) -> i32 {
+    message.fields().first().and_then(|field| field.parse().ok()).unwrap_or(-1)
+}
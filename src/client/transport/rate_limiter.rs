@@ -0,0 +1,94 @@
+//! Throttles outgoing messages to stay within the server's message rate limit.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Token bucket allowing short bursts. The burst size plus the tokens refilled in a second never exceed the limit,
+// so no one second window exceeds it.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    capacity: f64,
+    // tokens refilled per second
+    rate: f64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    // Limits messages to max_messages_per_second, allowing bursts of a fifth of the limit.
+    pub(crate) fn new(max_messages_per_second: u32) -> Self {
+        let limit = max_messages_per_second.max(2) as f64;
+        let capacity = (limit / 5.0).floor().max(1.0);
+
+        RateLimiter {
+            capacity,
+            rate: limit - capacity,
+            state: Mutex::new(Bucket {
+                tokens: capacity,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    // Blocks until a message may be sent.
+    pub(crate) fn acquire(&self) {
+        while let Some(wait) = self.try_acquire(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+
+    // Takes a token at now, if one is left. Otherwise returns how long to wait for the next one.
+    fn try_acquire(&self, now: Instant) -> Option<Duration> {
+        let mut bucket = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let refilled = now.saturating_duration_since(bucket.refilled_at).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + refilled).min(self.capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return None;
+        }
+
+        Some(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_after_burst() {
+        // bursts of 100, then 400 messages per second
+        let limiter = RateLimiter::new(500);
+        let mut now = limiter.state.lock().unwrap().refilled_at;
+
+        for _ in 0..100 {
+            assert_eq!(limiter.try_acquire(now), None, "burst should not be throttled");
+        }
+        assert_eq!(
+            limiter.try_acquire(now),
+            Some(Duration::from_secs_f64(1.0 / 400.0)),
+            "wait for next token"
+        );
+
+        for _ in 0..400 {
+            now += Duration::from_millis(3);
+            assert_eq!(limiter.try_acquire(now), None, "token refilled");
+        }
+
+        // idle time refills no more than a burst
+        now += Duration::from_secs(10);
+        for _ in 0..100 {
+            assert_eq!(limiter.try_acquire(now), None, "burst after idle");
+        }
+        assert!(limiter.try_acquire(now).is_some(), "throttled after burst");
+    }
+}
@@ -8,7 +8,7 @@ fn env_var_enables_recorder() {
 
     env::set_var(&key, &dir);
 
-    let recorder = MessageRecorder::new();
+    let _recorder = MessageRecorder::new();
 
     // TODO - refactor
     // assert_eq!(true, recorder.enabled);
@@ -19,9 +19,9 @@ fn env_var_enables_recorder() {
 fn recorder_is_disabled() {
     let key = String::from("IBAPI_RECORDING_DIR");
 
-    env::set_var(&key, &"");
+    env::set_var(&key, "");
 
-    let recorder = MessageRecorder::new();
+    let _recorder = MessageRecorder::new();
 
     // assert_eq!(false, recorder.enabled);
     // assert_eq!("", &recorder.recording_dir);
@@ -33,7 +33,7 @@ fn recorder_generates_output_file() {
 
     let recorder = MessageRecorder {
         enabled: true,
-        recording_dir: recording_dir,
+        recording_dir,
     };
 
     assert_eq!(format!("{}/0001-request.msg", recorder.recording_dir), recorder.request_file(1));
@@ -0,0 +1,564 @@
+//! Records sessions over any [MessageBus] and plays them back without a server.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::atomic::AtomicI32;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam::channel::{self, Receiver, Sender};
+use log::{debug, info, warn};
+use time::OffsetDateTime;
+
+use super::capture::{frame, read_capture, Captured, Direction, WireCapture};
+use super::{
+    dispatch_message, GlobalChannels, GlobalResponseIterator, MessageBus, MetricsObserver, ResponseIterator, SenderHash, Signal,
+    DEFAULT_REQUEST_TIMEOUT,
+};
+use crate::client::{ConnectionEvent, ErrorEvent, ParseMode, SubscriptionBuffer, VirtualClock};
+use crate::messages::{OutgoingMessages, RequestMessage, ResponseMessage};
+use crate::Error;
+
+const HANDSHAKE_PREFIX: &[u8] = b"API\0";
+
+/// Records the messages exchanged over another [MessageBus], for playback with [ReplayMessageBus].
+///
+/// The recording is in the format written by [ClientBuilder::wire_capture](crate::client::ClientBuilder::wire_capture). Responses are recorded
+/// as the wrapped bus delivers them. Recordings include account numbers and positions, so review them before sharing.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ibapi::client::{MessageBus, RecordingMessageBus};
+/// use ibapi::Client;
+///
+/// fn connect(message_bus: Box<dyn MessageBus>) {
+///     let message_bus = RecordingMessageBus::new(message_bus, "session.capture").expect("error creating recording");
+///     let client = Client::with_message_bus(Box::new(message_bus), 100).expect("connection failed");
+///
+///     println!("server time: {:?}", client.server_time());
+/// }
+/// ```
+pub struct RecordingMessageBus {
+    inner: Box<dyn MessageBus>,
+    capture: Arc<WireCapture>,
+    // responses to requests without a request id, by kind of request, with the receiver of the wrapped bus they are taken from
+    globals: HashMap<&'static str, (Receiver<ResponseMessage>, Arc<Receiver<ResponseMessage>>)>,
+}
+
+// Leaves out the wrapped bus, which needn't implement Debug.
+impl fmt::Debug for RecordingMessageBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingMessageBus").finish_non_exhaustive()
+    }
+}
+
+impl RecordingMessageBus {
+    /// Wraps `message_bus`, recording to the file at `path`. An existing file is replaced.
+    pub fn new(message_bus: Box<dyn MessageBus>, path: impl AsRef<Path>) -> Result<RecordingMessageBus, Error> {
+        Ok(RecordingMessageBus {
+            inner: message_bus,
+            capture: Arc::new(WireCapture::create(path.as_ref())?),
+            globals: HashMap::new(),
+        })
+    }
+
+    fn record_sent(&self, message: &RequestMessage) {
+        self.capture.record_sent(&message.encode());
+    }
+
+    // Returns an iterator over responses, recording them as they are passed on. The returned iterator releases the request or order.
+    fn record_responses(&self, mut responses: ResponseIterator) -> ResponseIterator {
+        let (sender, receiver) = channel::unbounded();
+        forward(Arc::clone(&self.capture), responses.messages.clone(), sender);
+
+        ResponseIterator::new(
+            receiver,
+            responses.signals.clone(),
+            responses.request_id.take(),
+            responses.order_id.take(),
+            responses.timeout,
+        )
+    }
+
+    // Returns an iterator over responses to requests of kind, recording them as they are passed on.
+    // Responses are taken from the wrapped bus once per receiver it returns, as receivers of global responses are shared.
+    fn record_global(&mut self, kind: &'static str, responses: GlobalResponseIterator) -> GlobalResponseIterator {
+        if let Some((source, recorded)) = self.globals.get(kind) {
+            if source.same_channel(&responses.messages) {
+                return GlobalResponseIterator::new(Arc::clone(recorded));
+            }
+        }
+
+        let (sender, receiver) = channel::unbounded();
+        let source = (*responses.messages).clone();
+        forward(Arc::clone(&self.capture), source.clone(), sender);
+
+        let recorded = Arc::new(receiver);
+        self.globals.insert(kind, (source, Arc::clone(&recorded)));
+        GlobalResponseIterator::new(recorded)
+    }
+}
+
+// Records messages from responses and passes them on to recorded, until either side is dropped.
+fn forward(capture: Arc<WireCapture>, responses: Receiver<ResponseMessage>, recorded: Sender<ResponseMessage>) {
+    thread::spawn(move || {
+        for message in responses {
+            capture.record_received(message.raw());
+            if recorded.send(message).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+impl MessageBus for RecordingMessageBus {
+    fn read_message(&mut self) -> Result<ResponseMessage, Error> {
+        let message = self.inner.read_message()?;
+        self.capture.record_received(message.raw());
+        Ok(message)
+    }
+
+    fn write_message(&mut self, packet: &RequestMessage) -> Result<(), Error> {
+        self.record_sent(packet);
+        self.inner.write_message(packet)
+    }
+
+    fn send_generic_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.record_sent(packet);
+        let responses = self.inner.send_generic_message(request_id, packet)?;
+        Ok(self.record_responses(responses))
+    }
+
+    fn send_durable_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.record_sent(packet);
+        let responses = self.inner.send_durable_message(request_id, packet)?;
+        Ok(self.record_responses(responses))
+    }
+
+    fn send_order_message(&mut self, order_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.record_sent(packet);
+        let responses = self.inner.send_order_message(order_id, packet)?;
+        Ok(self.record_responses(responses))
+    }
+
+    fn send_order_messages(&mut self, orders: &[(i32, RequestMessage)]) -> Result<Vec<ResponseIterator>, Error> {
+        for (_, message) in orders {
+            self.record_sent(message);
+        }
+        let responses = self.inner.send_order_messages(orders)?;
+        Ok(responses.into_iter().map(|responses| self.record_responses(responses)).collect())
+    }
+
+    fn request_next_order_id(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.record_sent(message);
+        let responses = self.inner.request_next_order_id(message)?;
+        Ok(self.record_global("next_order_id", responses))
+    }
+
+    fn request_open_orders(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.record_sent(message);
+        let responses = self.inner.request_open_orders(message)?;
+        Ok(self.record_global("open_orders", responses))
+    }
+
+    fn request_market_rule(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.record_sent(message);
+        let responses = self.inner.request_market_rule(message)?;
+        Ok(self.record_global("market_rule", responses))
+    }
+
+    fn request_positions(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.record_sent(message);
+        let responses = self.inner.request_positions(message)?;
+        Ok(self.record_global("positions", responses))
+    }
+
+    fn request_family_codes(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.record_sent(message);
+        let responses = self.inner.request_family_codes(message)?;
+        Ok(self.record_global("family_codes", responses))
+    }
+
+    fn request_market_depth_exchanges(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.record_sent(message);
+        let responses = self.inner.request_market_depth_exchanges(message)?;
+        Ok(self.record_global("market_depth_exchanges", responses))
+    }
+
+    fn request_current_time(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.record_sent(message);
+        let responses = self.inner.request_current_time(message)?;
+        Ok(self.record_global("current_time", responses))
+    }
+
+    fn write(&mut self, packet: &str) -> Result<(), Error> {
+        self.capture.record(Direction::Sent, packet.as_bytes());
+        self.inner.write(packet)
+    }
+
+    fn start_api(&mut self, message: &RequestMessage) -> Result<(), Error> {
+        self.record_sent(message);
+        self.inner.start_api(message)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+
+    fn process_messages(&mut self, server_version: i32) -> Result<(), Error> {
+        self.inner.process_messages(server_version)
+    }
+
+    fn set_parse_mode(&mut self, parse_mode: ParseMode) {
+        self.inner.set_parse_mode(parse_mode)
+    }
+
+    fn set_subscription_buffer(&mut self, subscription_buffer: Option<SubscriptionBuffer>) {
+        self.inner.set_subscription_buffer(subscription_buffer)
+    }
+
+    fn set_metrics_observer(&mut self, observer: Option<Arc<dyn MetricsObserver>>) {
+        self.inner.set_metrics_observer(observer)
+    }
+
+    fn track_next_order_id(&mut self, next_order_id: Arc<AtomicI32>) {
+        self.inner.track_next_order_id(next_order_id)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.inner.shutdown()
+    }
+
+    fn start_heartbeat(&mut self, message: &RequestMessage, interval: Duration, deadline: Duration) -> Result<(), Error> {
+        self.inner.start_heartbeat(message, interval, deadline)
+    }
+
+    fn connection_events(&mut self) -> Receiver<ConnectionEvent> {
+        self.inner.connection_events()
+    }
+
+    fn error_events(&mut self) -> Receiver<ErrorEvent> {
+        self.inner.error_events()
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.inner.is_healthy()
+    }
+
+    fn disconnect(&mut self) -> Result<(), Error> {
+        self.inner.disconnect()
+    }
+}
+
+/// Plays back a session recorded with [RecordingMessageBus] or [ClientBuilder::wire_capture](crate::client::ClientBuilder::wire_capture),
+/// without a server. See [Client::replay](crate::Client::replay).
+///
+/// Each message the session received is released once the messages the session sent before it were sent, so making the same requests
+/// in the same order reproduces the session. Messages that differ from the recording are logged as warnings. The replay ends with
+/// the recording, or where the session reconnected. Requests then end without responses.
+///
+/// The [clock](ReplayMessageBus::clock) is moved to the recorded time of each message as it's released.
+#[derive(Debug)]
+pub struct ReplayMessageBus {
+    // entries of the recording not yet played back
+    captured: VecDeque<Captured>,
+    clock: VirtualClock,
+    handshakes: usize,
+    // messages released before messages are processed, returned by read_message
+    received: VecDeque<ResponseMessage>,
+    // server version messages are processed for, once processing starts
+    server_version: Option<i32>,
+    parse_mode: ParseMode,
+    requests: Arc<SenderHash<i32, ResponseMessage>>,
+    orders: Arc<SenderHash<i32, ResponseMessage>>,
+    executions: SenderHash<String, ResponseMessage>,
+    globals: Arc<GlobalChannels>,
+    signals_send: Sender<Signal>,
+    signals_recv: Receiver<Signal>,
+}
+
+impl ReplayMessageBus {
+    /// Opens the recording at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<ReplayMessageBus, Error> {
+        Ok(ReplayMessageBus::new(read_capture(path.as_ref())?))
+    }
+
+    pub(crate) fn new(captured: Vec<Captured>) -> ReplayMessageBus {
+        let clock = VirtualClock::new(captured.first().map_or(OffsetDateTime::UNIX_EPOCH, |entry| entry.time));
+        let (signals_send, signals_recv) = channel::unbounded();
+
+        let mut message_bus = ReplayMessageBus {
+            captured: captured.into(),
+            clock,
+            handshakes: 0,
+            received: VecDeque::new(),
+            server_version: None,
+            parse_mode: ParseMode::default(),
+            requests: Arc::new(SenderHash::new()),
+            orders: Arc::new(SenderHash::new()),
+            executions: SenderHash::new(),
+            globals: Arc::new(GlobalChannels::new()),
+            signals_send,
+            signals_recv,
+        };
+        message_bus.release();
+        message_bus
+    }
+
+    /// Returns the clock following the recorded time of the messages played back.
+    pub fn clock(&self) -> VirtualClock {
+        self.clock.clone()
+    }
+
+    // Checks that sent is the next message sent in the recording, then releases the messages received after it.
+    fn play(&mut self, sent: &[u8]) {
+        match self.captured.pop_front() {
+            Some(entry) if entry.bytes.starts_with(HANDSHAKE_PREFIX) && self.handshakes > 0 => {
+                // the session reconnected. the client being replayed to won't.
+                info!("stopping replay at reconnection");
+                self.captured.clear();
+            }
+            Some(entry) => {
+                if entry.bytes.starts_with(HANDSHAKE_PREFIX) {
+                    self.handshakes += 1;
+                }
+
+                if entry.bytes == sent {
+                    debug!("replay: client sent the recorded message");
+                } else {
+                    warn!(
+                        "replay diverged from recording. expected {:?}, client sent {:?}",
+                        printable(&entry.bytes),
+                        printable(sent)
+                    );
+                }
+            }
+            None => debug!("replay finished, client sent {:?}", printable(sent)),
+        }
+
+        self.release();
+    }
+
+    // Releases the messages received up to the next message sent. Once the recording is played back, requests end.
+    fn release(&mut self) {
+        while let Some(entry) = self.captured.pop_front() {
+            if entry.direction == Direction::Sent {
+                self.captured.push_front(entry);
+                return;
+            }
+
+            // the time is the message's before the client can read it
+            self.clock.set(entry.time);
+
+            let Some(data) = entry.bytes.get(4..).and_then(|data| String::from_utf8(data.to_vec()).ok()) else {
+                warn!("skipping invalid message in recording: {:?}", printable(&entry.bytes));
+                continue;
+            };
+            let mut message = ResponseMessage::from_string(data);
+            message.parse_mode = self.parse_mode;
+
+            match self.server_version {
+                Some(server_version) => dispatch_message(message, server_version, &self.requests, &self.orders, &self.globals, &self.executions),
+                None => self.received.push_back(message),
+            }
+        }
+
+        if self.server_version.is_some() {
+            debug!("replay finished, ending requests");
+            self.requests.clear();
+            self.orders.clear();
+        }
+    }
+
+    // Forgets requests and orders whose responses are no longer consumed. Cancellations are played back as messages sent.
+    fn receive_signals(&mut self) {
+        while let Ok(signal) = self.signals_recv.try_recv() {
+            match signal {
+                Signal::Request(request_id) => {
+                    self.requests.remove(&request_id);
+                }
+                Signal::Order(order_id) => {
+                    self.orders.remove(&order_id);
+                }
+                Signal::Cancel(request_id, message) => {
+                    self.requests.remove(&request_id);
+                    self.play(&frame(&message.encode()));
+                }
+                Signal::Stop => {}
+            }
+        }
+    }
+
+    fn send(&mut self, message: &RequestMessage) {
+        self.play(&frame(&message.encode()));
+    }
+}
+
+fn printable(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).replace('\0', "|")
+}
+
+impl MessageBus for ReplayMessageBus {
+    fn read_message(&mut self) -> Result<ResponseMessage, Error> {
+        self.received
+            .pop_front()
+            .ok_or_else(|| Error::Io(io::Error::new(ErrorKind::UnexpectedEof, "end of recording")))
+    }
+
+    fn write_message(&mut self, packet: &RequestMessage) -> Result<(), Error> {
+        self.receive_signals();
+        self.send(packet);
+        Ok(())
+    }
+
+    fn send_generic_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.receive_signals();
+
+        let (sender, receiver) = channel::unbounded();
+        self.requests.insert(request_id, sender);
+        self.send(packet);
+
+        Ok(ResponseIterator::new(
+            receiver,
+            self.signals_send.clone(),
+            Some(request_id),
+            None,
+            Some(DEFAULT_REQUEST_TIMEOUT),
+        ))
+    }
+
+    fn send_durable_message(&mut self, request_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.receive_signals();
+
+        let (sender, receiver) = channel::unbounded();
+        self.requests.insert(request_id, sender);
+        self.send(packet);
+
+        Ok(ResponseIterator::new(receiver, self.signals_send.clone(), Some(request_id), None, None))
+    }
+
+    fn send_order_message(&mut self, order_id: i32, packet: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.receive_signals();
+
+        let (sender, receiver) = channel::unbounded();
+        self.orders.insert(order_id, sender);
+        self.send(packet);
+
+        Ok(ResponseIterator::new(
+            receiver,
+            self.signals_send.clone(),
+            None,
+            Some(order_id),
+            Some(DEFAULT_REQUEST_TIMEOUT),
+        ))
+    }
+
+    fn request_next_order_id(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        // next valid ids received unrequested are not responses to this request
+        while self.globals.order_ids_out.try_recv().is_ok() {}
+
+        self.write_message(message)?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.order_ids_out)))
+    }
+
+    fn request_open_orders(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.write_message(message)?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.open_orders_out)))
+    }
+
+    fn request_market_rule(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.write_message(message)?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_market_rule)))
+    }
+
+    fn request_positions(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.write_message(message)?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_positions)))
+    }
+
+    fn request_family_codes(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.write_message(message)?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_family_codes)))
+    }
+
+    fn request_market_depth_exchanges(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.write_message(message)?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_market_depth_exchanges)))
+    }
+
+    fn request_current_time(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.write_message(message)?;
+        Ok(GlobalResponseIterator::new(Arc::clone(&self.globals.recv_current_time)))
+    }
+
+    fn write(&mut self, packet: &str) -> Result<(), Error> {
+        self.play(packet.as_bytes());
+        Ok(())
+    }
+
+    // Dispatches messages released so far that weren't read, then each message as it's released.
+    fn process_messages(&mut self, server_version: i32) -> Result<(), Error> {
+        self.server_version = Some(server_version);
+
+        while let Some(message) = self.received.pop_front() {
+            dispatch_message(message, server_version, &self.requests, &self.orders, &self.globals, &self.executions);
+        }
+        self.release();
+
+        Ok(())
+    }
+
+    fn set_parse_mode(&mut self, parse_mode: ParseMode) {
+        self.parse_mode = parse_mode;
+    }
+
+    fn track_next_order_id(&mut self, next_order_id: Arc<AtomicI32>) {
+        *self.globals.next_order_id.write().unwrap() = Some(next_order_id);
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.captured.clear();
+        self.requests.clear();
+        self.orders.clear();
+        self.globals.connection_events.publish(ConnectionEvent::Disconnected);
+        Ok(())
+    }
+
+    fn connection_events(&mut self) -> Receiver<ConnectionEvent> {
+        self.globals.connection_events.subscribe()
+    }
+
+    fn error_events(&mut self) -> Receiver<ErrorEvent> {
+        self.globals.error_events.subscribe()
+    }
+
+    fn disconnect(&mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+}
+
+// Returns the client id and optional capabilities the session started the api with.
+pub(crate) fn recorded_client(captured: &[Captured]) -> Option<(i32, String)> {
+    let start_api = (OutgoingMessages::StartApi as i32).to_string();
+
+    captured
+        .iter()
+        .filter(|entry| entry.direction == Direction::Sent && entry.bytes.len() > 4)
+        .find_map(|entry| {
+            let message = std::str::from_utf8(&entry.bytes[4..]).ok()?;
+            match message.split('\0').collect::<Vec<&str>>().as_slice() {
+                [message_id, _version, client_id, rest @ ..] if *message_id == start_api => {
+                    Some((client_id.parse().ok()?, rest.first().unwrap_or(&"").to_string()))
+                }
+                _ => None,
+            }
+        })
+}
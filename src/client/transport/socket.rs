@@ -0,0 +1,156 @@
+//! Applies socket level options to connections.
+
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::client::SocketOptions;
+
+// Applies options to a newly opened connection.
+pub(super) fn configure(stream: &TcpStream, options: &SocketOptions) -> io::Result<()> {
+    stream.set_nodelay(options.nodelay)?;
+
+    if let Some(size) = options.receive_buffer_size {
+        sys::set_option(stream, sys::SOL_SOCKET, sys::SO_RCVBUF, to_int(size)?)?;
+    }
+
+    if let Some(size) = options.send_buffer_size {
+        sys::set_option(stream, sys::SOL_SOCKET, sys::SO_SNDBUF, to_int(size)?)?;
+    }
+
+    if let Some(idle) = options.keepalive {
+        sys::set_option(stream, sys::SOL_SOCKET, sys::SO_KEEPALIVE, 1)?;
+        sys::set_option(stream, sys::IPPROTO_TCP, sys::TCP_KEEPIDLE, seconds(idle)?)?;
+
+        if let Some(interval) = options.keepalive_interval {
+            sys::set_option(stream, sys::IPPROTO_TCP, sys::TCP_KEEPINTVL, seconds(interval)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn to_int(value: usize) -> io::Result<i32> {
+    i32::try_from(value).map_err(|_| io::Error::new(ErrorKind::InvalidInput, format!("socket option value too large: {value}")))
+}
+
+// Keepalive timers have a resolution of one second.
+fn seconds(duration: Duration) -> io::Result<i32> {
+    let seconds = duration.as_secs().max(1);
+    i32::try_from(seconds).map_err(|_| io::Error::new(ErrorKind::InvalidInput, format!("keepalive duration too large: {duration:?}")))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "macos", target_os = "ios"))]
+mod sys {
+    use std::io;
+    use std::mem::size_of;
+    use std::net::TcpStream;
+    use std::os::unix::io::AsRawFd;
+
+    pub use libc::{IPPROTO_TCP, SOL_SOCKET, SO_KEEPALIVE, SO_RCVBUF, SO_SNDBUF, TCP_KEEPINTVL};
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub use libc::TCP_KEEPALIVE as TCP_KEEPIDLE;
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    pub use libc::TCP_KEEPIDLE;
+
+    pub fn set_option(stream: &TcpStream, level: i32, name: i32, value: i32) -> io::Result<()> {
+        // SAFETY: the descriptor is owned by stream and value outlives the call.
+        let result = unsafe {
+            libc::setsockopt(
+                stream.as_raw_fd(),
+                level,
+                name,
+                &value as *const i32 as *const libc::c_void,
+                size_of::<i32>() as libc::socklen_t,
+            )
+        };
+
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(test)]
+    pub fn get_option(stream: &TcpStream, level: i32, name: i32) -> io::Result<i32> {
+        let mut value: i32 = 0;
+        let mut len = size_of::<i32>() as libc::socklen_t;
+
+        // SAFETY: the descriptor is owned by stream, and value and len outlive the call.
+        let result = unsafe { libc::getsockopt(stream.as_raw_fd(), level, name, &mut value as *mut i32 as *mut libc::c_void, &mut len) };
+
+        if result == 0 {
+            Ok(value)
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+// Only TCP_NODELAY is supported on other platforms.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "macos", target_os = "ios")))]
+mod sys {
+    use std::io::{self, ErrorKind};
+    use std::net::TcpStream;
+
+    pub const SOL_SOCKET: i32 = 0;
+    pub const IPPROTO_TCP: i32 = 0;
+    pub const SO_RCVBUF: i32 = 0;
+    pub const SO_SNDBUF: i32 = 0;
+    pub const SO_KEEPALIVE: i32 = 0;
+    pub const TCP_KEEPIDLE: i32 = 0;
+    pub const TCP_KEEPINTVL: i32 = 0;
+
+    pub fn set_option(_stream: &TcpStream, _level: i32, _name: i32, _value: i32) -> io::Result<()> {
+        Err(io::Error::new(ErrorKind::Unsupported, "socket option not supported on this platform"))
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn configure_socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let options = SocketOptions {
+            receive_buffer_size: Some(256 * 1024),
+            send_buffer_size: Some(128 * 1024),
+            keepalive: Some(Duration::from_secs(60)),
+            keepalive_interval: Some(Duration::from_secs(10)),
+            ..SocketOptions::default()
+        };
+        configure(&stream, &options).unwrap();
+
+        assert!(stream.nodelay().unwrap(), "nodelay enabled by default");
+        // linux doubles buffer sizes to allow for bookkeeping overhead
+        assert!(
+            sys::get_option(&stream, sys::SOL_SOCKET, sys::SO_RCVBUF).unwrap() >= 256 * 1024,
+            "receive buffer"
+        );
+        assert!(
+            sys::get_option(&stream, sys::SOL_SOCKET, sys::SO_SNDBUF).unwrap() >= 128 * 1024,
+            "send buffer"
+        );
+        assert_eq!(sys::get_option(&stream, sys::SOL_SOCKET, sys::SO_KEEPALIVE).unwrap(), 1, "keepalive");
+        assert_eq!(
+            sys::get_option(&stream, sys::IPPROTO_TCP, sys::TCP_KEEPIDLE).unwrap(),
+            60,
+            "keepalive idle"
+        );
+        assert_eq!(
+            sys::get_option(&stream, sys::IPPROTO_TCP, sys::TCP_KEEPINTVL).unwrap(),
+            10,
+            "keepalive interval"
+        );
+
+        configure(&stream, &SocketOptions { nodelay: false, ..options }).unwrap();
+        assert!(!stream.nodelay().unwrap(), "nodelay disabled");
+    }
+}
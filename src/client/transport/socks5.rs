@@ -0,0 +1,161 @@
+//! Tunnels connections through a SOCKS5 proxy (RFC 1928), with optional username/password authentication (RFC 1929).
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::IpAddr;
+
+use crate::client::Socks5Proxy;
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+const USERNAME_PASSWORD_VERSION: u8 = 1;
+const CONNECT: u8 = 0x01;
+const SUCCEEDED: u8 = 0x00;
+const IPV4: u8 = 0x01;
+const DOMAIN: u8 = 0x03;
+const IPV6: u8 = 0x04;
+
+// Asks the proxy, connected on stream, to connect to target. e.g. gateway.local:4002
+pub(super) fn handshake<S: Read + Write>(stream: &mut S, proxy: &Socks5Proxy, target: &str) -> io::Result<()> {
+    let request = encode_connect_request(target)?;
+
+    if proxy.credentials.is_some() {
+        stream.write_all(&[VERSION, 2, NO_AUTHENTICATION, USERNAME_PASSWORD])?;
+    } else {
+        stream.write_all(&[VERSION, 1, NO_AUTHENTICATION])?;
+    }
+
+    let mut method = [0_u8; 2];
+    stream.read_exact(&mut method)?;
+
+    match (method[1], &proxy.credentials) {
+        (NO_AUTHENTICATION, _) => {}
+        (USERNAME_PASSWORD, Some((username, password))) => authenticate(stream, username, password)?,
+        (NO_ACCEPTABLE_METHODS, _) => return Err(proxy_error("proxy rejected the offered authentication methods")),
+        (method, _) => return Err(proxy_error(&format!("proxy selected unsupported authentication method {method}"))),
+    }
+
+    stream.write_all(&request)?;
+
+    // version, reply, reserved and bound address type
+    let mut reply = [0_u8; 4];
+    stream.read_exact(&mut reply)?;
+
+    if reply[1] != SUCCEEDED {
+        return Err(io::Error::new(
+            ErrorKind::ConnectionRefused,
+            format!("proxy could not connect to {target}: {}", reply_message(reply[1])),
+        ));
+    }
+
+    // skips the bound address and port
+    let address_len = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN => {
+            let mut len = [0_u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        address_type => return Err(proxy_error(&format!("proxy replied with unknown address type {address_type}"))),
+    };
+    stream.read_exact(&mut vec![0_u8; address_len + 2])?;
+
+    Ok(())
+}
+
+fn authenticate<S: Read + Write>(stream: &mut S, username: &str, password: &str) -> io::Result<()> {
+    if username.len() > 255 || password.len() > 255 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "proxy username and password are limited to 255 bytes",
+        ));
+    }
+
+    let mut request = vec![USERNAME_PASSWORD_VERSION, username.len() as u8];
+    request.extend_from_slice(username.as_bytes());
+    request.push(password.len() as u8);
+    request.extend_from_slice(password.as_bytes());
+    stream.write_all(&request)?;
+
+    let mut status = [0_u8; 2];
+    stream.read_exact(&mut status)?;
+
+    if status[1] != SUCCEEDED {
+        return Err(io::Error::new(ErrorKind::PermissionDenied, "proxy authentication failed"));
+    }
+
+    Ok(())
+}
+
+fn encode_connect_request(target: &str) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(ErrorKind::InvalidInput, format!("invalid address: {target}"));
+
+    let (host, port) = target.rsplit_once(':').ok_or_else(invalid)?;
+    let port: u16 = port.parse().map_err(|_| invalid())?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let mut request = vec![VERSION, CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        // host names are resolved by the proxy
+        Err(_) => {
+            if host.is_empty() || host.len() > 255 {
+                return Err(invalid());
+            }
+            request.push(DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+
+    Ok(request)
+}
+
+fn reply_message(reply: u8) -> &'static str {
+    match reply {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+fn proxy_error(message: &str) -> io::Error {
+    io::Error::other(message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_connect_requests() {
+        assert_eq!(
+            encode_connect_request("gateway:4002").unwrap(),
+            [&[5, 1, 0, 3, 7][..], b"gateway", &[0x0f, 0xa2]].concat()
+        );
+        assert_eq!(encode_connect_request("10.0.0.1:4002").unwrap(), [5, 1, 0, 1, 10, 0, 0, 1, 0x0f, 0xa2]);
+        assert_eq!(
+            encode_connect_request("[::1]:4002").unwrap(),
+            [&[5, 1, 0, 4][..], &[0; 15], &[1, 0x0f, 0xa2]].concat()
+        );
+
+        assert!(encode_connect_request("gateway").is_err(), "missing port");
+        assert!(encode_connect_request(":4002").is_err(), "missing host");
+    }
+}
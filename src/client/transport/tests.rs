@@ -1 +1,1068 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
 
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use super::*;
+use crate::messages::OutgoingMessages;
+use crate::stubs::fake_gateway;
+
+fn market_data_request(request_id: i32, symbol: &str) -> RequestMessage {
+    let mut message = RequestMessage::new();
+    message.push_field(&OutgoingMessages::RequestMarketData);
+    message.push_field(&11);
+    message.push_field(&request_id);
+    message.push_field(&symbol);
+    message
+}
+
+#[test]
+fn replay_subscriptions_after_reconnect() {
+    let gateway = fake_gateway(&[]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let _aapl = message_bus.send_durable_message(9000, &market_data_request(9000, "AAPL")).unwrap();
+    let tsla = message_bus.send_durable_message(9001, &market_data_request(9001, "TSLA")).unwrap();
+    let _msft = message_bus.send_durable_message(9002, &market_data_request(9002, "MSFT")).unwrap();
+
+    for _ in 0..3 {
+        gateway.requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    // released subscriptions are not replayed
+    drop(tsla);
+    while message_bus.requests.contains(&9001) {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // simulates the connection being re-established
+    let replayed = replay_subscriptions(
+        &message_bus.writer.sender(),
+        &message_bus.subscriptions,
+        &message_bus.requests,
+        message_bus.rate_limiter.as_deref(),
+    )
+    .unwrap();
+    assert_eq!(replayed, 2, "replayed subscriptions");
+
+    assert_eq!(gateway.requests.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|9000|AAPL|");
+    assert_eq!(gateway.requests.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|9002|MSFT|");
+    assert!(gateway.requests.recv_timeout(Duration::from_millis(100)).is_err(), "unexpected request");
+
+    assert!(!gateway.is_finished(), "server closed connection");
+}
+
+#[test]
+fn order_messages_are_routed_by_order_id() {
+    let order_status = |order_id: i32| format!("3|{order_id}|PreSubmitted|0|100|0|{order_id}|0|0|100||0||");
+    let statuses = [order_status(102), order_status(101), order_status(100)];
+
+    // acknowledges orders in reverse order of submission
+    let gateway = fake_gateway(&[&[], &[], &[&statuses[0], &statuses[1], &statuses[2]]]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let orders = vec![
+        (100, market_data_request(100, "KO")),
+        (101, market_data_request(101, "PEP")),
+        (102, market_data_request(102, "SPY")),
+    ];
+
+    let responses = message_bus.send_order_messages(&orders).unwrap();
+
+    assert_eq!(gateway.requests.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|100|KO|");
+    assert_eq!(gateway.requests.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|101|PEP|");
+    assert_eq!(gateway.requests.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|102|SPY|");
+
+    for ((order_id, _), mut messages) in orders.iter().zip(responses) {
+        let message = messages.next().expect("missing order status");
+        assert_eq!(message.message_type(), IncomingMessages::OrderStatus, "message.message_type()");
+        assert_eq!(message.peek_int(1).unwrap(), *order_id, "message.order_id");
+    }
+
+    message_bus.disconnect().unwrap();
+    gateway.join();
+}
+
+#[test]
+fn orders_wait_for_the_request_timeout() {
+    // accepts the orders without answering
+    let gateway = fake_gateway(&[]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.set_request_timeout(Duration::from_millis(50));
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let mut responses = message_bus.send_order_messages(&[(100, market_data_request(100, "KO"))]).unwrap();
+
+    let result = responses[0].next_response();
+    assert!(matches!(result, Err(Error::Timeout)), "{result:?}");
+
+    message_bus.disconnect().unwrap();
+    gateway.join();
+}
+
+#[test]
+fn flush_writes_message_to_connection() {
+    let gateway = fake_gateway(&[]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+
+    message_bus.write_message(&market_data_request(9000, "AAPL")).unwrap();
+    message_bus.flush().unwrap();
+
+    assert_eq!(gateway.requests.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|9000|AAPL|");
+}
+
+fn connect_timeout(seconds: u64) -> ConnectOptions {
+    ConnectOptions {
+        connect_timeout: Some(Duration::from_secs(seconds)),
+        ..ConnectOptions::default()
+    }
+}
+
+#[test]
+fn connect_with_timeout() {
+    let gateway = fake_gateway(&[]);
+
+    let mut message_bus = TcpMessageBus::connect_with_options(&gateway.address, connect_timeout(1)).unwrap();
+
+    message_bus.write_message(&market_data_request(9000, "AAPL")).unwrap();
+    message_bus.flush().unwrap();
+
+    assert_eq!(gateway.requests.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|9000|AAPL|");
+
+    assert!(
+        TcpMessageBus::connect_with_options("no port", connect_timeout(1)).is_err(),
+        "expected error for invalid address"
+    );
+}
+
+#[test]
+fn try_next_does_not_block() {
+    let (sender, receiver) = channel::unbounded();
+    let (signals_send, _signals_recv) = channel::unbounded();
+
+    let responses = ResponseIterator::new(receiver, signals_send, None, None, None);
+
+    assert!(matches!(responses.try_next(), Ok(None)), "expected no message");
+
+    sender.send(ResponseMessage::from("49\x001\x001678890000\x00")).unwrap();
+
+    let message = responses.try_next().unwrap().expect("expected queued message");
+    assert_eq!(message.message_type(), IncomingMessages::CurrentTime, "message.message_type()");
+    assert!(matches!(responses.try_next(), Ok(None)), "expected no message");
+
+    drop(sender);
+
+    assert!(matches!(responses.try_next(), Err(Error::Disconnected)), "expected disconnected error");
+}
+
+#[test]
+fn errors_are_returned_to_the_request() {
+    let (sender, receiver) = channel::unbounded();
+    let (signals_send, _signals_recv) = channel::unbounded();
+
+    let mut responses = ResponseIterator::new(receiver, signals_send, Some(9000), None, None);
+
+    sender
+        .send(ResponseMessage::from(
+            "4\x002\x009000\x002104\x00Market data farm connection is OK:usfarm\x00",
+        ))
+        .unwrap();
+    sender.send(ResponseMessage::from("52\x001\x009000\x00")).unwrap();
+    sender
+        .send(ResponseMessage::from(
+            "4\x002\x009000\x00200\x00No security definition has been found for the request\x00",
+        ))
+        .unwrap();
+
+    let message = responses.next_response().unwrap().expect("expected message after warning");
+    assert_eq!(message.message_type(), IncomingMessages::ContractDataEnd);
+
+    let result = responses.next_response();
+    assert!(matches!(result, Err(Error::TwsError { code: 200, .. })), "{result:?}");
+}
+
+#[test]
+#[cfg(feature = "market_data")]
+fn subscriptions_end_on_errors() {
+    let (sender, receiver) = channel::unbounded();
+    let (signals_send, _signals_recv) = channel::unbounded();
+
+    let mut responses = ResponseIterator::new(receiver, signals_send, Some(9001), None, None);
+    sender
+        .send(ResponseMessage::from(
+            "4\x002\x009001\x00354\x00Requested market data is not subscribed\x00",
+        ))
+        .unwrap();
+    assert!(responses.next_or_end().is_none());
+}
+
+#[test]
+fn shutdown_unblocks_waiting_consumers() {
+    // reads requests, without responding, until the client closes the connection
+    let gateway = fake_gateway(&[]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let mut responses = message_bus.send_durable_message(9000, &market_data_request(9000, "AAPL")).unwrap();
+    let consumer = thread::spawn(move || responses.next());
+    let mut responses = message_bus.send_durable_message(9001, &market_data_request(9001, "MSFT")).unwrap();
+    let response_consumer = thread::spawn(move || responses.next_response());
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(!consumer.is_finished(), "consumer should be waiting on a response");
+    assert!(!response_consumer.is_finished(), "consumer should be waiting on a response");
+
+    message_bus.shutdown().unwrap();
+
+    assert!(consumer.join().unwrap().is_none(), "expected no response after shutdown");
+    let result = response_consumer.join().unwrap();
+    assert!(matches!(result, Err(Error::Shutdown)), "expected shutdown error, got {result:?}");
+
+    let result = message_bus.write_message(&market_data_request(9001, "TSLA"));
+    assert!(matches!(result, Err(Error::Shutdown)), "expected shutdown error, got {result:?}");
+
+    gateway.join();
+}
+
+#[test]
+fn disconnect_stops_message_processing() {
+    let gateway = fake_gateway(&[]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let mut responses = message_bus.send_durable_message(9000, &market_data_request(9000, "AAPL")).unwrap();
+    let consumer = thread::spawn(move || responses.next());
+    let events = message_bus.connection_events();
+
+    message_bus.disconnect().unwrap();
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Disconnected));
+
+    assert!(message_bus.handles.is_empty(), "message processing threads should be joined");
+    assert!(consumer.join().unwrap().is_none(), "expected no response after disconnect");
+
+    let result = message_bus.write_message(&market_data_request(9001, "TSLA"));
+    assert!(matches!(result, Err(Error::Shutdown)), "expected shutdown error, got {result:?}");
+
+    // pending writes reached the server before the connection closed
+    assert_eq!(gateway.join(), vec!["1|11|9000|AAPL|"]);
+}
+
+#[test]
+fn heartbeat_detects_dead_connection() {
+    // answers the first heartbeat, then stops responding
+    let gateway = fake_gateway(&[&["49|1|1678890000|"]]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let mut heartbeat = RequestMessage::new();
+    heartbeat.push_field(&OutgoingMessages::RequestCurrentTime);
+    heartbeat.push_field(&1);
+    message_bus
+        .start_heartbeat(&heartbeat, Duration::from_millis(50), Duration::from_millis(500))
+        .unwrap();
+
+    gateway.requests.recv_timeout(Duration::from_secs(5)).expect("heartbeat sent");
+    assert!(message_bus.is_healthy(), "answered heartbeat");
+
+    let started = std::time::Instant::now();
+    while message_bus.is_healthy() && started.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(!message_bus.is_healthy(), "unanswered heartbeat");
+    assert!(
+        message_bus.globals.recv_current_time.try_recv().is_err(),
+        "heartbeat responses should not be dispatched"
+    );
+
+    message_bus.shutdown().unwrap();
+
+    assert!(gateway.join().len() >= 2, "heartbeats sent");
+}
+
+#[test]
+fn stale_current_time_answers_are_discarded() {
+    // the first request is answered with a current time, as if requested earlier
+    let gateway = fake_gateway(&[&["49|1|1|"], &["49|1|1678890000|"]]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    message_bus.write_message(&market_data_request(9000, "AAPL")).unwrap();
+    let started = std::time::Instant::now();
+    while message_bus.globals.recv_current_time.is_empty() && started.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut current_time = RequestMessage::new();
+    current_time.push_field(&OutgoingMessages::RequestCurrentTime);
+    current_time.push_field(&1);
+
+    let mut responses = message_bus.request_current_time(&current_time).unwrap();
+    let server_time = crate::client::decode_current_time(&mut responses.next().unwrap()).unwrap();
+    assert_eq!(server_time.unix_timestamp(), 1678890000, "answer to the request");
+
+    message_bus.shutdown().unwrap();
+    gateway.join();
+}
+
+#[test]
+fn heartbeat_answers_matched_in_order() {
+    let heartbeat = Heartbeat::new();
+    let current_time = || ResponseMessage::from("49\x001\x001678890000\x00");
+
+    heartbeat.send_current_time(CurrentTimeRequester::Client, || Ok(())).unwrap();
+    heartbeat.send_current_time(CurrentTimeRequester::Heartbeat, || Ok(())).unwrap();
+    let result = heartbeat.send_current_time(CurrentTimeRequester::Heartbeat, || Err(Error::Shutdown));
+    assert!(matches!(result, Err(Error::Shutdown)), "{result:?}");
+
+    assert!(
+        !heartbeat.received(&ResponseMessage::from("4\x002\x00-1\x002104\x00farm OK\x00")),
+        "other messages dispatched"
+    );
+    assert!(!heartbeat.received(&current_time()), "client request answered first");
+    assert!(heartbeat.received(&current_time()), "heartbeat answered second");
+    assert!(!heartbeat.received(&current_time()), "unsent heartbeat not awaited");
+
+    // requests on a lost connection are never answered
+    heartbeat.send_current_time(CurrentTimeRequester::Heartbeat, || Ok(())).unwrap();
+    heartbeat.reconnected();
+    assert!(!heartbeat.received(&current_time()), "answer dispatched after reconnecting");
+}
+
+#[test]
+fn reconnect_after_connection_lost() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let (requests_send, requests_recv) = channel::unbounded();
+
+    let read_request = |stream: &mut TcpStream| {
+        let size = stream.read_u32::<BigEndian>().unwrap();
+        let mut request = vec![0_u8; size as usize];
+        stream.read_exact(&mut request).unwrap();
+        String::from_utf8(request).unwrap().replace('\0', "|")
+    };
+
+    let server = thread::spawn(move || {
+        // first connection drops after receiving the start api and subscription requests
+        let (mut stream, _) = listener.accept().unwrap();
+        requests_send.send(read_request(&mut stream)).unwrap();
+        requests_send.send(read_request(&mut stream)).unwrap();
+        drop(stream);
+
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let mut prefix = [0_u8; 4];
+        stream.read_exact(&mut prefix).unwrap();
+        assert_eq!(&prefix, b"API\0", "handshake prefix");
+        requests_send.send(read_request(&mut stream)).unwrap();
+
+        let ack = "173\x0020230405 22:20:39 PST\x00";
+        stream.write_u32::<BigEndian>(ack.len() as u32).unwrap();
+        stream.write_all(ack.as_bytes()).unwrap();
+
+        requests_send.send(read_request(&mut stream)).unwrap();
+        requests_send.send(read_request(&mut stream)).unwrap();
+
+        // keeps the connection open until the client shuts down
+        while stream.read_u32::<BigEndian>().is_ok() {}
+    });
+
+    let mut message_bus = TcpMessageBus::connect(&address).unwrap();
+
+    let mut start_api = RequestMessage::new();
+    start_api.push_field(&OutgoingMessages::StartApi);
+    start_api.push_field(&2);
+    start_api.push_field(&100);
+    start_api.push_field(&"");
+
+    message_bus.start_api(&start_api).unwrap();
+    let events = message_bus.connection_events();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let _subscription = message_bus.send_durable_message(9000, &market_data_request(9000, "AAPL")).unwrap();
+
+    assert_eq!(requests_recv.recv_timeout(Duration::from_secs(5)).unwrap(), "71|2|100||");
+    assert_eq!(requests_recv.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|9000|AAPL|");
+
+    // after reconnecting: handshake, start api and the replayed subscription
+    assert!(
+        requests_recv.recv_timeout(Duration::from_secs(5)).unwrap().starts_with("v100.."),
+        "handshake"
+    );
+    assert_eq!(requests_recv.recv_timeout(Duration::from_secs(5)).unwrap(), "71|2|100||");
+    assert_eq!(requests_recv.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|9000|AAPL|");
+
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Disconnected));
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Reconnecting));
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Connected));
+
+    message_bus.shutdown().unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn error_event_publishes_connection_events() {
+    let globals = Arc::new(GlobalChannels::new());
+    let events = globals.connection_events.subscribe();
+    let errors = globals.error_events.subscribe();
+    let requests = Arc::new(SenderHash::new());
+    let orders = Arc::new(SenderHash::new());
+    let executions = SenderHash::new();
+
+    let error = |request_id: i32, code: i32, message: &str| ResponseMessage::from(&format!("4|2|{request_id}|{code}|{message}|").replace('|', "\0"));
+    let dispatch = |message: ResponseMessage| dispatch_message(message, server_versions::SIZE_RULES, &requests, &orders, &globals, &executions);
+
+    dispatch(error(-1, 1100, "Connectivity between IB and TWS has been lost."));
+    dispatch(error(-1, 2103, "Market data farm connection is broken:usfarm"));
+    dispatch(error(-1, 2104, "Market data farm connection is OK:usfarm"));
+    dispatch(error(-1, 1102, "Connectivity between IB and TWS has been restored."));
+    // not a connection event
+    dispatch(error(-1, 2100, "API client has been unsubscribed from account data."));
+
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::ConnectivityLost));
+    assert_eq!(
+        events.try_recv(),
+        Ok(ConnectionEvent::FarmDisconnected {
+            code: 2103,
+            message: "Market data farm connection is broken:usfarm".into()
+        })
+    );
+    assert_eq!(
+        events.try_recv(),
+        Ok(ConnectionEvent::FarmConnected {
+            code: 2104,
+            message: "Market data farm connection is OK:usfarm".into()
+        })
+    );
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::ConnectivityRestored));
+    assert!(events.try_recv().is_err(), "unexpected event");
+
+    // every error message is published, including those about requests
+    let codes: Vec<i32> = errors.try_iter().map(|event| event.code).collect();
+    assert_eq!(codes, [1100, 2103, 2104, 1102, 2100]);
+
+    let (sender, receiver) = channel::unbounded();
+    requests.insert(9000, sender);
+    dispatch(error(9000, 200, "No security definition has been found for the request"));
+
+    assert_eq!(
+        errors.try_recv(),
+        Ok(ErrorEvent {
+            request_id: 9000,
+            code: 200,
+            message: "No security definition has been found for the request".into(),
+            advanced_order_reject_json: "".into(),
+        })
+    );
+    assert!(receiver.try_recv().is_ok(), "error routed to the request");
+}
+
+#[derive(Default)]
+struct RecordingObserver {
+    sent: Mutex<Vec<(i32, usize)>>,
+    received: Mutex<Vec<(IncomingMessages, usize)>>,
+    decode_errors: Mutex<Vec<IncomingMessages>>,
+    round_trips: Mutex<Vec<i32>>,
+}
+
+impl MetricsObserver for RecordingObserver {
+    fn message_sent(&self, message_id: i32, bytes: usize) {
+        self.sent.lock().unwrap().push((message_id, bytes));
+    }
+
+    fn message_received(&self, message_type: IncomingMessages, bytes: usize) {
+        self.received.lock().unwrap().push((message_type, bytes));
+    }
+
+    fn decode_error(&self, message_type: IncomingMessages) {
+        self.decode_errors.lock().unwrap().push(message_type);
+    }
+
+    fn round_trip(&self, message_id: i32, _latency: Duration) {
+        self.round_trips.lock().unwrap().push(message_id);
+    }
+}
+
+#[test]
+fn metrics_observer_measures_traffic() {
+    // two ticks, only the first completes the round trip
+    let gateway = fake_gateway(&[&["1|6|9000|1|185.50|100|0|", "1|6|9000|2|price|100|0|"]]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    let observer = Arc::new(RecordingObserver::default());
+    message_bus.set_metrics_observer(Some(observer.clone()));
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let mut responses = message_bus.send_durable_message(9000, &market_data_request(9000, "AAPL")).unwrap();
+    responses.next().unwrap();
+    let mut message = responses.next().unwrap();
+
+    message.skip(); // message type
+    message.skip(); // version
+    message.skip(); // request id
+    message.skip(); // tick type
+    assert!(message.next_double().is_err());
+
+    assert_eq!(*observer.sent.lock().unwrap(), [(1, "1|11|9000|AAPL|".len() + 4)]);
+    assert_eq!(
+        *observer.received.lock().unwrap(),
+        [(IncomingMessages::TickPrice, 24 + 4), (IncomingMessages::TickPrice, 23 + 4)]
+    );
+    assert_eq!(*observer.round_trips.lock().unwrap(), [1]);
+    assert_eq!(*observer.decode_errors.lock().unwrap(), [IncomingMessages::TickPrice]);
+
+    message_bus.shutdown().unwrap();
+    gateway.join();
+}
+
+#[test]
+fn reconnect_fails_over_to_backup() {
+    let primary = TcpListener::bind("127.0.0.1:0").unwrap();
+    let primary_address = primary.local_addr().unwrap().to_string();
+    let backup = fake_gateway(&[&["173|20230405 22:20:39 PST|"]]);
+    let backup_address = backup.address.clone();
+
+    // the primary accepts the first connection, then stops listening
+    let primary_server = thread::spawn(move || {
+        let (stream, _) = primary.accept().unwrap();
+        drop(primary);
+        drop(stream);
+    });
+
+    let connect_options = ConnectOptions {
+        failover: vec![backup_address.clone()],
+        ..ConnectOptions::default()
+    };
+    let mut message_bus = TcpMessageBus::connect_with_options(&primary_address, connect_options).unwrap();
+    assert_eq!(message_bus.connected_address, primary_address, "primary preferred");
+
+    let events = message_bus.connection_events();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+    let _subscription = message_bus.send_durable_message(9000, &market_data_request(9000, "AAPL")).unwrap();
+    primary_server.join().unwrap();
+
+    // the subscription is replayed to the backup, after the handshake
+    assert!(
+        backup.requests.recv_timeout(Duration::from_secs(5)).unwrap().starts_with("v100.."),
+        "handshake"
+    );
+    assert_eq!(backup.requests.recv_timeout(Duration::from_secs(5)).unwrap(), "1|11|9000|AAPL|");
+
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Disconnected));
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Reconnecting));
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Connected));
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::FailedOver { address: backup_address }));
+
+    message_bus.shutdown().unwrap();
+    backup.join();
+}
+
+#[test]
+fn connect_fails_over_to_backup() {
+    // the primary address can't be resolved
+    let primary_address = "no port";
+    let backup = TcpListener::bind("127.0.0.1:0").unwrap();
+    let backup_address = backup.local_addr().unwrap().to_string();
+
+    let connect_options = ConnectOptions {
+        failover: vec!["no port either".into(), backup_address.clone()],
+        ..ConnectOptions::default()
+    };
+    let message_bus = TcpMessageBus::connect_with_options(primary_address, connect_options).unwrap();
+    assert_eq!(message_bus.connected_address, backup_address);
+
+    let connect_options = ConnectOptions {
+        failover: vec!["no port either".into()],
+        ..ConnectOptions::default()
+    };
+    assert!(
+        TcpMessageBus::connect_with_options(primary_address, connect_options).is_err(),
+        "expected error when no address can be reached"
+    );
+}
+
+#[test]
+fn reconnect_gives_up_after_max_attempts() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    // accepts the first connection, then stops listening so reconnection is refused
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        drop(stream);
+    });
+
+    let mut message_bus = TcpMessageBus::connect(&address).unwrap();
+    message_bus.set_reconnect_policy(RetryPolicy {
+        max_attempts: 2,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(10),
+        jitter: 0.0,
+    });
+    let events = message_bus.connection_events();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    server.join().unwrap();
+
+    let reader = &message_bus.handles[0];
+    let started = std::time::Instant::now();
+    while !reader.is_finished() && started.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(reader.is_finished(), "message reader should stop after exhausting reconnect attempts");
+
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Disconnected));
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::Reconnecting));
+    assert_eq!(events.try_recv(), Ok(ConnectionEvent::ReconnectFailed));
+    assert!(events.try_recv().is_err(), "should not reconnect");
+}
+
+#[test]
+fn reconnect_failure_unblocks_waiting_consumers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut message_bus = TcpMessageBus::connect(&address).unwrap();
+    message_bus.set_reconnect_policy(RetryPolicy::new(1));
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let mut responses = message_bus.send_durable_message(9000, &market_data_request(9000, "AAPL")).unwrap();
+    let consumer = thread::spawn(move || responses.next());
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(!consumer.is_finished(), "consumer should be waiting on a response");
+
+    // closes the connection, and refuses reconnection
+    let (stream, _) = listener.accept().unwrap();
+    drop(stream);
+    drop(listener);
+
+    assert!(consumer.join().unwrap().is_none(), "expected no response once reconnecting failed");
+
+    let result = message_bus.write_message(&market_data_request(9001, "TSLA"));
+    assert!(matches!(result, Err(Error::Shutdown)), "expected shutdown error, got {result:?}");
+}
+
+#[test]
+fn requests_refused_during_maintenance_window() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut message_bus = TcpMessageBus::connect(&address).unwrap();
+
+    let started = OffsetDateTime::now_utc().time() - time::Duration::minutes(1);
+    message_bus.set_maintenance_window(Some(MaintenanceWindow::new(
+        started,
+        Duration::from_secs(60 * 60),
+        time_tz::timezones::db::UTC,
+    )));
+
+    let result = message_bus.write_message(&market_data_request(9000, "AAPL"));
+    assert!(
+        matches!(result, Err(Error::MaintenanceWindow)),
+        "expected maintenance window error, got {result:?}"
+    );
+
+    message_bus.set_maintenance_window(None);
+    message_bus.write_message(&market_data_request(9000, "AAPL")).unwrap();
+}
+
+#[test]
+fn connect_through_socks5_proxy() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_address = listener.local_addr().unwrap().to_string();
+
+    // a proxy that relays to a gateway known only by name on its network
+    let proxy = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let mut greeting = [0_u8; 4];
+        stream.read_exact(&mut greeting).unwrap();
+        assert_eq!(greeting, [5, 2, 0, 2], "offers no authentication and username/password");
+        stream.write_all(&[5, 2]).unwrap();
+
+        let mut credentials = [0_u8; 15];
+        stream.read_exact(&mut credentials).unwrap();
+        assert_eq!(&credentials, b"\x01\x06trader\x06secret", "credentials");
+        stream.write_all(&[1, 0]).unwrap();
+
+        let mut request = [0_u8; 13];
+        stream.read_exact(&mut request).unwrap();
+        assert_eq!(&request, b"\x05\x01\x00\x03\x06gw.lan\x0f\xa2", "connect request");
+        stream.write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x0f, 0xa2]).unwrap();
+
+        let size = stream.read_u32::<BigEndian>().unwrap();
+        let mut request = vec![0_u8; size as usize];
+        stream.read_exact(&mut request).unwrap();
+        String::from_utf8(request).unwrap().replace('\0', "|")
+    });
+
+    let connect_options = ConnectOptions {
+        connect_timeout: Some(Duration::from_secs(1)),
+        proxy: Some(Socks5Proxy::new(&proxy_address).with_credentials("trader", "secret")),
+        ..ConnectOptions::default()
+    };
+    let mut message_bus = TcpMessageBus::connect_with_options("gw.lan:4002", connect_options).unwrap();
+
+    message_bus.write_message(&market_data_request(9000, "AAPL")).unwrap();
+
+    assert_eq!(proxy.join().unwrap(), "1|11|9000|AAPL|", "request relayed through proxy");
+}
+
+#[test]
+fn socks5_proxy_refuses_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_address = listener.local_addr().unwrap().to_string();
+
+    let proxy = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let mut greeting = [0_u8; 3];
+        stream.read_exact(&mut greeting).unwrap();
+        stream.write_all(&[5, 0]).unwrap();
+
+        let mut request = [0_u8; 10];
+        stream.read_exact(&mut request).unwrap();
+        // connection refused
+        stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+    });
+
+    let connect_options = ConnectOptions {
+        connect_timeout: Some(Duration::from_secs(1)),
+        proxy: Some(Socks5Proxy::new(&proxy_address)),
+        ..ConnectOptions::default()
+    };
+    let result = TcpMessageBus::connect_with_options("10.0.0.1:4002", connect_options);
+
+    assert!(
+        matches!(result, Err(Error::Io(ref err)) if err.kind() == ErrorKind::ConnectionRefused),
+        "expected connection refused, got {:?}",
+        result.err()
+    );
+
+    proxy.join().unwrap();
+}
+
+#[test]
+fn interleave_address_families() {
+    let addresses: Vec<SocketAddr> = ["[::1]:4002", "[::2]:4002", "[::3]:4002", "127.0.0.1:4002", "127.0.0.2:4002"]
+        .iter()
+        .map(|address| address.parse().unwrap())
+        .collect();
+
+    let interleaved: Vec<String> = interleave_families(addresses).iter().map(|address| address.to_string()).collect();
+    assert_eq!(
+        interleaved,
+        vec!["[::1]:4002", "127.0.0.1:4002", "[::2]:4002", "127.0.0.2:4002", "[::3]:4002"]
+    );
+
+    assert!(interleave_families(vec![]).is_empty());
+}
+
+#[test]
+fn connect_falls_back_to_next_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    // reserve a port, then close it so connections are refused
+    let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let stream = connect_any(vec![refused, listener.local_addr().unwrap()], Some(Duration::from_secs(1))).unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+
+    let result = connect_any(vec![refused, refused], None);
+    assert_eq!(result.err().map(|err| err.kind()), Some(ErrorKind::ConnectionRefused));
+}
+
+#[test]
+fn connect_to_ipv6_literal() {
+    // skipped where IPv6 is unavailable
+    let Ok(listener) = TcpListener::bind("[::1]:0") else {
+        return;
+    };
+    let address = format!("[::1]:{}", listener.local_addr().unwrap().port());
+
+    assert!(TcpMessageBus::connect(&address).is_ok(), "connect to {address}");
+}
+
+#[test]
+fn outgoing_messages_are_rate_limited() {
+    let gateway = fake_gateway(&[]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    // bursts of 20, then 80 messages per second
+    message_bus.set_rate_limit(Some(100));
+
+    let started = std::time::Instant::now();
+    for request_id in 0..40 {
+        message_bus.write_message(&market_data_request(request_id, "AAPL")).unwrap();
+    }
+    assert!(started.elapsed() >= Duration::from_millis(240), "elapsed {:?}", started.elapsed());
+
+    message_bus.set_rate_limit(None);
+    let started = std::time::Instant::now();
+    for request_id in 40..100 {
+        message_bus.write_message(&market_data_request(request_id, "AAPL")).unwrap();
+    }
+    // the limit would hold these back for 750ms
+    assert!(
+        started.elapsed() < Duration::from_millis(500),
+        "unlimited elapsed {:?}",
+        started.elapsed()
+    );
+
+    message_bus.shutdown().unwrap();
+    assert_eq!(gateway.join().len(), 100, "messages received");
+}
+
+#[test]
+fn queued_messages_arrive_in_order() {
+    let gateway = fake_gateway(&[]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.set_rate_limit(None);
+
+    let messages: Vec<RequestMessage> = (0..500).map(|request_id| market_data_request(request_id, "AAPL")).collect();
+    for message in &messages {
+        message_bus.write_message(message).unwrap();
+    }
+    message_bus.flush().unwrap();
+    message_bus.shutdown().unwrap();
+
+    let expected: Vec<String> = messages.iter().map(|message| message.encode_simple()).collect();
+    assert_eq!(gateway.join(), expected);
+}
+
+#[test]
+fn subscription_buffer_overflow_policies() {
+    let buffered = |overflow: OverflowPolicy| {
+        let (sender, receiver) = channel::bounded(2);
+        (ResponseSender::bounded(sender, overflow, &receiver), receiver)
+    };
+
+    let (sender, receiver) = buffered(OverflowPolicy::DropOldest);
+    for i in 0..5 {
+        sender.send(i).unwrap();
+    }
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![3, 4], "drop oldest keeps latest");
+
+    let (sender, receiver) = buffered(OverflowPolicy::DropNewest);
+    for i in 0..5 {
+        sender.send(i).unwrap();
+    }
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![0, 1], "drop newest keeps earliest");
+
+    let (sender, receiver) = buffered(OverflowPolicy::Error);
+    sender.send(0).unwrap();
+    sender.send(1).unwrap();
+    assert_eq!(sender.send(2), Err(SendError::Overflow));
+    drop(receiver);
+    assert_eq!(sender.send(3), Err(SendError::Disconnected));
+
+    let (sender, receiver) = buffered(OverflowPolicy::Block);
+    let consumer = thread::spawn(move || receiver.iter().collect::<Vec<_>>());
+    for i in 0..5 {
+        sender.send(i).unwrap();
+    }
+    drop(sender);
+    assert_eq!(consumer.join().unwrap(), vec![0, 1, 2, 3, 4], "block delivers everything");
+
+    // overflowing subscriptions are ended, after delivering what was buffered
+    let requests = SenderHash::new();
+    let (sender, receiver) = buffered(OverflowPolicy::Error);
+    requests.insert(9000, sender);
+    for i in 0..3 {
+        requests.send(&9000, i).unwrap();
+    }
+    assert!(!requests.contains(&9000), "overflowed subscription removed");
+    assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![0, 1]);
+}
+
+// Hands out data a few bytes at a time, timing out between chunks like a slow connection with a read timeout.
+struct ChunkedReader {
+    data: Vec<u8>,
+    position: usize,
+    chunk_size: usize,
+    timed_out: bool,
+}
+
+impl Read for ChunkedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.timed_out = !self.timed_out;
+        if self.timed_out {
+            return Err(std::io::Error::new(ErrorKind::WouldBlock, "timed out"));
+        }
+
+        let end = (self.position + self.chunk_size).min(self.data.len()).min(self.position + buf.len());
+        let read = end - self.position;
+        buf[..read].copy_from_slice(&self.data[self.position..end]);
+        self.position = end;
+        Ok(read)
+    }
+}
+
+fn frame(message: &str) -> Vec<u8> {
+    let mut frame = Vec::new();
+    frame.write_u32::<BigEndian>(message.len() as u32).unwrap();
+    frame.extend_from_slice(message.as_bytes());
+    frame
+}
+
+#[test]
+fn read_messages_split_across_reads() {
+    let messages = ["49\x003\x001700000000\x00", "9\x001\x0090\x00", "15\x001\x00DU1234567,DU7654321\x00"];
+
+    for chunk_size in [1, 3, 7] {
+        let mut reader = ChunkedReader {
+            data: messages.iter().flat_map(|message| frame(message)).collect(),
+            position: 0,
+            chunk_size,
+            timed_out: false,
+        };
+
+        for message in messages {
+            // a timeout before a message starts is reported
+            assert!(matches!(read_packet(&mut reader), Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock));
+
+            let received = read_packet(&mut reader).unwrap();
+            assert_eq!(received.encode(), ResponseMessage::from(message).encode(), "chunk size {chunk_size}");
+        }
+    }
+}
+
+#[test]
+fn read_message_validates_length() {
+    let mut oversized: &[u8] = &[0x01, 0x00, 0x00, 0x00, b'4'];
+    assert!(matches!(read_packet(&mut oversized), Err(Error::Io(err)) if err.kind() == ErrorKind::InvalidData));
+
+    let mut truncated: &[u8] = &frame("9\x001\x0090\x00")[..6];
+    assert!(matches!(read_packet(&mut truncated), Err(Error::Io(err)) if err.kind() == ErrorKind::UnexpectedEof));
+
+    let mut message = RequestMessage::default();
+    message.push_field(&"x".repeat(MAX_MESSAGE_LEN));
+    assert!(
+        matches!(write_frame(&mut Vec::new(), &message), Err(Error::InvalidArgument(_))),
+        "oversized message"
+    );
+}
+
+#[test]
+fn received_message_buffers_are_reused() {
+    let buffers = Arc::new(BufferPool::new());
+
+    let data = [frame("9\x001\x0090\x00"), frame("9\x001\x0091\x00")].concat();
+    let mut reader = &data[..];
+
+    let message = read_pooled_packet(&mut reader, &buffers).unwrap();
+    assert_eq!(message.peek_int(2).unwrap(), 90);
+    let cloned = message.clone();
+    drop(message);
+    drop(cloned);
+    assert_eq!(buffers.len(), 2, "buffers released when messages are dropped");
+
+    let message = read_pooled_packet(&mut reader, &buffers).unwrap();
+    assert_eq!(message.peek_int(2).unwrap(), 91);
+    assert_eq!(buffers.len(), 1, "buffer taken from pool");
+}
+
+#[test]
+fn wire_capture_records_messages() {
+    let gateway = fake_gateway(&[&["4|2|9000|"]]);
+
+    let path = std::env::temp_dir().join(format!("ibapi-wire-capture-{}.capture", std::process::id()));
+    let connect_options = ConnectOptions {
+        capture: Some(Arc::new(WireCapture::create(&path).unwrap())),
+        ..ConnectOptions::default()
+    };
+
+    let mut message_bus = TcpMessageBus::connect_with_options(&gateway.address, connect_options).unwrap();
+    message_bus.set_rate_limit(None);
+
+    let request = market_data_request(9000, "AAPL");
+    message_bus.write_message(&request).unwrap();
+    message_bus.read_message().unwrap();
+    message_bus.disconnect().unwrap();
+    gateway.join();
+
+    let capture = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+    let lines: Vec<(&str, &str)> = capture
+        .lines()
+        .map(|line| {
+            let mut parts = line.split(' ');
+            assert!(OffsetDateTime::parse(parts.next().unwrap(), &time::format_description::well_known::Rfc3339).is_ok());
+            (parts.next().unwrap(), parts.next().unwrap())
+        })
+        .collect();
+
+    let mut sent = Vec::new();
+    write_frame(&mut sent, &request).unwrap();
+    assert_eq!(
+        lines,
+        vec![("->", hex(&sent).as_str()), ("<-", hex(&frame("4\x002\x009000\x00")).as_str())]
+    );
+}
+
+#[cfg(feature = "tls")]
+fn tls_options(roots: rustls::RootCertStore) -> ConnectOptions {
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    ConnectOptions {
+        tls: Some(TlsConfig::new(config)),
+        ..ConnectOptions::default()
+    }
+}
+
+#[test]
+#[cfg(feature = "tls")]
+fn tls_connection() {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::CertificateDer;
+
+    let gateway = crate::stubs::fake_tls_gateway(&[&["1|6|9000|1|185.5|100|0|"], &["1|6|9001|1|185.75|100|0|"]]);
+
+    let mut roots = rustls::RootCertStore::empty();
+    let ca = CertificateDer::from_pem_file(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/tls/ca.pem")).unwrap();
+    roots.add(ca).unwrap();
+
+    let mut message_bus = TcpMessageBus::connect_with_options(&gateway.address, tls_options(roots)).unwrap();
+    message_bus.set_rate_limit(None);
+
+    message_bus.write_message(&market_data_request(9000, "AAPL")).unwrap();
+    assert_eq!(message_bus.read_message().unwrap().raw(), "1\x006\x009000\x001\x00185.5\x00100\x000\x00");
+
+    // the reader waits for records while the writer sends
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+    let mut responses = message_bus.send_generic_message(9001, &market_data_request(9001, "MSFT")).unwrap();
+    assert_eq!(responses.next().unwrap().peek_string(4).unwrap(), "185.75");
+
+    message_bus.disconnect().unwrap();
+    assert_eq!(gateway.join(), vec!["1|11|9000|AAPL|", "1|11|9001|MSFT|"]);
+}
+
+#[test]
+#[cfg(feature = "tls")]
+fn tls_rejects_unknown_certificates() {
+    let gateway = crate::stubs::fake_tls_gateway(&[]);
+
+    let result = TcpMessageBus::connect_with_options(&gateway.address, tls_options(rustls::RootCertStore::empty()));
+    assert!(matches!(result, Err(Error::Io(_))), "certificate not among roots: {result:?}");
+
+    assert!(gateway.join().is_empty());
+}
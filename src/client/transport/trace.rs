@@ -0,0 +1,292 @@
+//! Records of the traffic with the server, logged at debug level under the `ibapi::trace` target.
+//!
+//! Each record is a line of `key=value` fields following the record's name:
+//! `request` and `order` when a request or order is sent, `message` for other messages sent, e.g. cancellations,
+//! `response` for each message received, `released` once the consumer of a request or order is done with it,
+//! and `event` for connection events and messages from TWS.
+//! Records of the same request share its `request_id`, or `order_id`, so the latency of requests and orders can be measured
+//! from the time of each record.
+//!
+//! With the `tracing` feature, records are tracing events with the same target and fields instead. Each request and order then has a span
+//! lasting until its responses are released, and each connection a span holding the responses received on it.
+
+use std::fmt::Debug;
+
+#[cfg(not(feature = "tracing"))]
+use log::debug;
+
+use crate::messages::{RequestMessage, ResponseMessage};
+
+/// Target of the log records.
+pub(crate) const TARGET: &str = "ibapi::trace";
+
+// Records a request, whose responses are routed by request_id.
+pub(crate) fn request(request_id: i32, message: &RequestMessage) {
+    #[cfg(not(feature = "tracing"))]
+    debug!(target: TARGET, "{}", request_record("request", "request_id", request_id, message));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: TARGET, request_id, message_id = message_id(message), "request");
+}
+
+// Records an order request, whose messages are routed by order_id.
+pub(crate) fn order(order_id: i32, message: &RequestMessage) {
+    #[cfg(not(feature = "tracing"))]
+    debug!(target: TARGET, "{}", request_record("order", "order_id", order_id, message));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: TARGET, order_id, message_id = message_id(message), "order");
+}
+
+// Records a message sent without expecting responses of its own.
+pub(crate) fn message(message: &RequestMessage) {
+    #[cfg(not(feature = "tracing"))]
+    debug!(target: TARGET, "message message_id={}", message_id(message));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: TARGET, message_id = message_id(message), "message");
+}
+
+pub(crate) fn response(message: &ResponseMessage) {
+    #[cfg(not(feature = "tracing"))]
+    debug!(target: TARGET, "{}", response_record(message));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        target: TARGET,
+        message_type = ?message.message_type(),
+        request_id = message.request_id(),
+        order_id = message.order_id(),
+        "response"
+    );
+}
+
+// Records the end of a request or order, once its consumer released it.
+pub(crate) fn released(key: &str, id: i32) {
+    #[cfg(not(feature = "tracing"))]
+    debug!(target: TARGET, "released {key}={id}");
+    #[cfg(feature = "tracing")]
+    match key {
+        "order_id" => tracing::debug!(target: TARGET, order_id = id, "released"),
+        _ => tracing::debug!(target: TARGET, request_id = id, "released"),
+    }
+}
+
+pub(crate) fn event(event: &impl Debug) {
+    #[cfg(not(feature = "tracing"))]
+    debug!(target: TARGET, "event {event:?}");
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: TARGET, event = ?event, "event");
+}
+
+pub(crate) use spans::{connection, reconnecting, request_span};
+#[cfg(feature = "orders")]
+pub(crate) use spans::{order_span, order_spans};
+
+#[cfg(feature = "tracing")]
+mod spans {
+    use tracing::span::EnteredSpan;
+    use tracing::{debug_span, Span};
+
+    use super::{message_id, TARGET};
+    use crate::client::transport::ResponseIterator;
+    use crate::messages::RequestMessage;
+    use crate::Error;
+
+    // Sends a request within its span. The responses returned hold the span until they're released.
+    pub(crate) fn request_span(
+        request_id: i32,
+        message: &RequestMessage,
+        send: impl FnOnce() -> Result<ResponseIterator, Error>,
+    ) -> Result<ResponseIterator, Error> {
+        in_span(debug_span!(target: TARGET, "request", request_id, message_id = message_id(message)), send)
+    }
+
+    // Sends an order request within its span. The messages returned hold the span until they're released.
+    #[cfg(feature = "orders")]
+    pub(crate) fn order_span(
+        order_id: i32,
+        message: &RequestMessage,
+        send: impl FnOnce() -> Result<ResponseIterator, Error>,
+    ) -> Result<ResponseIterator, Error> {
+        in_span(debug_span!(target: TARGET, "order", order_id, message_id = message_id(message)), send)
+    }
+
+    // Sends orders together, with a span for each order.
+    #[cfg(feature = "orders")]
+    pub(crate) fn order_spans(
+        orders: &[(i32, RequestMessage)],
+        send: impl FnOnce() -> Result<Vec<ResponseIterator>, Error>,
+    ) -> Result<Vec<ResponseIterator>, Error> {
+        let spans: Vec<Span> = orders
+            .iter()
+            .map(|(order_id, message)| debug_span!(target: TARGET, "order", order_id, message_id = message_id(message)))
+            .collect();
+
+        let mut responses = send()?;
+        for (responses, span) in responses.iter_mut().zip(spans) {
+            responses.span = span;
+        }
+        Ok(responses)
+    }
+
+    fn in_span(span: Span, send: impl FnOnce() -> Result<ResponseIterator, Error>) -> Result<ResponseIterator, Error> {
+        let mut responses = span.in_scope(send)?;
+        responses.span = span;
+        Ok(responses)
+    }
+
+    // Enters the span of the connection to address, until dropped. Connections aren't nested, so each reconnection starts a new root span.
+    pub(crate) fn connection(address: &str) -> EnteredSpan {
+        debug_span!(target: TARGET, parent: None, "connection", address).entered()
+    }
+
+    // Enters the span of reconnecting after the connection to address was lost, until dropped.
+    pub(crate) fn reconnecting(address: &str) -> EnteredSpan {
+        debug_span!(target: TARGET, "reconnecting", address).entered()
+    }
+}
+
+// Without the tracing feature there are no spans.
+#[cfg(not(feature = "tracing"))]
+mod spans {
+    use crate::messages::RequestMessage;
+
+    pub(crate) fn request_span<T>(_request_id: i32, _message: &RequestMessage, send: impl FnOnce() -> T) -> T {
+        send()
+    }
+
+    #[cfg(feature = "orders")]
+    pub(crate) fn order_span<T>(_order_id: i32, _message: &RequestMessage, send: impl FnOnce() -> T) -> T {
+        send()
+    }
+
+    #[cfg(feature = "orders")]
+    pub(crate) fn order_spans<T>(_orders: &[(i32, RequestMessage)], send: impl FnOnce() -> T) -> T {
+        send()
+    }
+
+    pub(crate) struct Entered;
+
+    pub(crate) fn connection(_address: &str) -> Entered {
+        Entered
+    }
+
+    pub(crate) fn reconnecting(_address: &str) -> Entered {
+        Entered
+    }
+}
+
+#[cfg(any(test, not(feature = "tracing")))]
+fn request_record(name: &str, key: &str, id: i32, message: &RequestMessage) -> String {
+    format!("{name} {key}={id} message_id={}", message_id(message))
+}
+
+#[cfg(any(test, not(feature = "tracing")))]
+fn response_record(message: &ResponseMessage) -> String {
+    let mut record = format!("response message_type={:?}", message.message_type());
+    if let Some(request_id) = message.request_id() {
+        record.push_str(&format!(" request_id={request_id}"));
+    }
+    if let Some(order_id) = message.order_id() {
+        record.push_str(&format!(" order_id={order_id}"));
+    }
+    record
+}
+
+fn message_id(message: &RequestMessage) -> &str {
+    message.fields().first().map_or("", String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::OutgoingMessages;
+
+    #[test]
+    fn records() {
+        let mut message = RequestMessage::new();
+        message.push_field(&OutgoingMessages::RequestMarketData);
+        message.push_field(&11);
+        message.push_field(&9000);
+
+        assert_eq!(
+            request_record("request", "request_id", 9000, &message),
+            "request request_id=9000 message_id=1"
+        );
+
+        let response = ResponseMessage::from("1\x006\x009000\x001\x00185.5\x00100\x000\x00");
+        assert_eq!(response_record(&response), "response message_type=TickPrice request_id=9000");
+
+        let response = ResponseMessage::from("3\x0013\x00\x00Submitted\x00");
+        assert_eq!(response_record(&response), "response message_type=OrderStatus order_id=13");
+    }
+    // Records the spans opened and closed.
+    #[cfg(feature = "tracing")]
+    struct SpanRecorder {
+        records: std::sync::Mutex<Vec<String>>,
+        next_id: std::sync::atomic::AtomicU64,
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            self.records.lock().unwrap().push(format!("new {}", span.metadata().name()));
+            tracing::span::Id::from_u64(self.next_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            self.records.lock().unwrap().push(format!("event {}", event.metadata().name()));
+        }
+
+        fn enter(&self, _span: &tracing::span::Id) {}
+
+        fn exit(&self, _span: &tracing::span::Id) {}
+
+        fn try_close(&self, id: tracing::span::Id) -> bool {
+            self.records.lock().unwrap().push(format!("close {}", id.into_u64()));
+            true
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn request_span_lasts_until_released() {
+        use std::sync::Arc;
+
+        use crossbeam::channel;
+
+        use crate::client::transport::ResponseIterator;
+
+        let recorder = Arc::new(SpanRecorder {
+            records: std::sync::Mutex::new(Vec::new()),
+            next_id: std::sync::atomic::AtomicU64::new(0),
+        });
+
+        tracing::subscriber::with_default(Arc::clone(&recorder), || {
+            let mut message = RequestMessage::new();
+            message.push_field(&OutgoingMessages::RequestMarketData);
+
+            let (_sender, receiver) = channel::unbounded();
+            let (signals, _signals) = channel::unbounded();
+            let responses = request_span(9000, &message, || {
+                request(9000, &message);
+                Ok(ResponseIterator::new(receiver, signals, Some(9000), None, None))
+            })
+            .unwrap();
+            assert_eq!(recorder.records.lock().unwrap().len(), 2, "span open while responses are consumed");
+
+            drop(responses);
+        });
+
+        let records = recorder.records.lock().unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].starts_with("new request"), "{records:?}");
+        assert!(records[1].starts_with("event"), "{records:?}");
+        assert_eq!(records[2], "close 1", "span closed once responses are released");
+    }
+}
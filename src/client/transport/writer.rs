@@ -0,0 +1,218 @@
+//! Writes outgoing messages from a dedicated thread, coalescing messages queued in bursts into a single vectored write.
+
+use std::io::{self, ErrorKind, IoSlice, Write};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+
+use crossbeam::channel::{self, Receiver, Sender};
+use log::{debug, error};
+
+use super::capture::{Direction, WireCapture};
+use super::connection::Connection;
+use crate::Error;
+
+// Caps the number of buffers handed to a single vectored write. Remaining frames go out in the next write.
+const MAX_FRAMES_PER_WRITE: usize = 256;
+
+#[derive(Debug)]
+enum Command {
+    // length prefixed frames, written back to back
+    Frames(Vec<Vec<u8>>),
+    // answered once every frame queued before it was written
+    Flush(Sender<io::Result<()>>),
+    Stop,
+}
+
+// Queues frames for the writer thread. Frames are written to whichever connection is current when they are dequeued.
+#[derive(Debug)]
+pub(crate) struct MessageWriter {
+    commands: Sender<Command>,
+    handle: Option<JoinHandle<i32>>,
+}
+
+// Cheap handle for queueing frames from other threads.
+#[derive(Clone, Debug)]
+pub(crate) struct FrameSender {
+    commands: Sender<Command>,
+}
+
+impl MessageWriter {
+    // Frames written are recorded to capture when given.
+    pub(crate) fn spawn(stream: Arc<RwLock<Arc<Connection>>>, capture: Option<Arc<WireCapture>>) -> Self {
+        let (commands, receiver) = channel::unbounded();
+        let handle = thread::spawn(move || write_frames(&stream, receiver, capture.as_deref()));
+
+        MessageWriter {
+            commands,
+            handle: Some(handle),
+        }
+    }
+
+    pub(crate) fn sender(&self) -> FrameSender {
+        FrameSender {
+            commands: self.commands.clone(),
+        }
+    }
+
+    // Blocks until every frame queued so far was written.
+    pub(crate) fn flush(&self) -> Result<(), Error> {
+        let (sender, receiver) = channel::bounded(1);
+        self.commands.send(Command::Flush(sender)).map_err(|_| Error::Shutdown)?;
+        receiver.recv().map_err(|_| Error::Shutdown)??;
+        Ok(())
+    }
+
+    // Writes frames queued so far, then stops the writer thread.
+    pub(crate) fn stop(&self) {
+        self.commands.send(Command::Stop).ok();
+    }
+
+    pub(crate) fn join(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                error!("message writer thread panicked");
+            }
+        }
+    }
+}
+
+impl FrameSender {
+    // Queues frames to be written together, without other messages interleaved between them.
+    pub(crate) fn send(&self, frames: Vec<Vec<u8>>) -> Result<(), Error> {
+        self.commands.send(Command::Frames(frames)).map_err(|_| Error::Shutdown)
+    }
+
+    // Writes frames queued so far, then stops the writer thread.
+    pub(crate) fn stop(&self) {
+        self.commands.send(Command::Stop).ok();
+    }
+}
+
+fn write_frames(stream: &RwLock<Arc<Connection>>, commands: Receiver<Command>, capture: Option<&WireCapture>) -> i32 {
+    let mut frames = Vec::new();
+    let mut flushes = Vec::new();
+
+    while let Ok(command) = commands.recv() {
+        let mut stop = false;
+
+        // coalesces everything queued while the previous batch was being written
+        for command in std::iter::once(command).chain(commands.try_iter()) {
+            match command {
+                Command::Frames(batch) => frames.extend(batch),
+                Command::Flush(sender) => flushes.push(sender),
+                Command::Stop => {
+                    stop = true;
+                    break;
+                }
+            }
+        }
+
+        if let Some(capture) = capture {
+            for frame in &frames {
+                capture.record(Direction::Sent, frame);
+            }
+        }
+
+        let result = if frames.is_empty() {
+            Ok(())
+        } else {
+            let connection = Arc::clone(&stream.read().unwrap());
+            write_all_vectored(&mut connection.as_ref(), &frames)
+        };
+        frames.clear();
+
+        if let Err(err) = &result {
+            error!("error writing messages: {err}");
+        }
+        for sender in flushes.drain(..) {
+            let result = result.as_ref().map(|_| ()).map_err(|err| io::Error::new(err.kind(), err.to_string()));
+            sender.send(result).ok();
+        }
+
+        if stop {
+            break;
+        }
+    }
+
+    debug!("message writer stopped");
+    0
+}
+
+// Writes all frames, in order, with as few system calls as the connection allows.
+fn write_all_vectored<W: Write>(writer: &mut W, frames: &[Vec<u8>]) -> io::Result<()> {
+    for chunk in frames.chunks(MAX_FRAMES_PER_WRITE) {
+        let mut slices: Vec<IoSlice> = chunk.iter().map(|frame| IoSlice::new(frame)).collect();
+        let mut slices = &mut slices[..];
+
+        IoSlice::advance_slices(&mut slices, 0);
+        while !slices.is_empty() {
+            match writer.write_vectored(slices) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer")),
+                Ok(written) => IoSlice::advance_slices(&mut slices, written),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Accepts at most limit bytes per write, counting writes.
+    struct ShortWriter {
+        written: Vec<u8>,
+        writes: usize,
+        limit: usize,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.write_vectored(&[IoSlice::new(buf)])
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+            self.writes += 1;
+            let mut written = 0;
+            for buf in bufs {
+                let n = buf.len().min(self.limit - written);
+                self.written.extend_from_slice(&buf[..n]);
+                written += n;
+                if written == self.limit {
+                    break;
+                }
+            }
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_all_vectored_resumes_partial_writes() {
+        let frames = vec![b"abc".to_vec(), b"".to_vec(), b"defgh".to_vec(), b"ij".to_vec()];
+
+        let mut writer = ShortWriter {
+            written: vec![],
+            writes: 0,
+            limit: 1024,
+        };
+        write_all_vectored(&mut writer, &frames).unwrap();
+        assert_eq!(writer.written, b"abcdefghij");
+        assert_eq!(writer.writes, 1, "frames should be written together");
+
+        let mut writer = ShortWriter {
+            written: vec![],
+            writes: 0,
+            limit: 4,
+        };
+        write_all_vectored(&mut writer, &frames).unwrap();
+        assert_eq!(writer.written, b"abcdefghij");
+        assert_eq!(writer.writes, 3);
+    }
+}
@@ -1,17 +1,19 @@
 use std::convert::From;
-use std::fmt::Debug;
-use std::string::ToString;
+use std::fmt::{self, Debug};
 
 use log::{error, info};
 
+use crate::client::check_server_version;
+use crate::client::transport::ResponseIterator;
 use crate::encode_option_field;
 use crate::messages::IncomingMessages;
+#[cfg(feature = "market_data")]
 use crate::messages::RequestMessage;
 use crate::Client;
 use crate::{server_versions, Error, ToField};
 
-mod decoders;
-mod encoders;
+pub(crate) mod decoders;
+pub(crate) mod encoders;
 
 #[cfg(test)]
 pub(crate) mod contract_samples;
@@ -30,6 +32,10 @@ pub enum SecurityType {
     Option,
     /// Future
     Future,
+    /// Continuous future
+    ContinuousFuture,
+    /// Futures and continuous futures. Used when looking up contract details.
+    FutureAndContinuousFuture,
     /// Index
     Index,
     /// Futures option
@@ -48,6 +54,8 @@ pub enum SecurityType {
     News,
     /// Mutual fund
     MutualFund,
+    /// Security type code not known to this library. The code is preserved as received.
+    Other(String),
 }
 
 impl ToField for SecurityType {
@@ -62,22 +70,27 @@ impl ToField for Option<SecurityType> {
     }
 }
 
-impl ToString for SecurityType {
-    fn to_string(&self) -> String {
-        match self {
-            SecurityType::Stock => "STK".to_string(),
-            SecurityType::Option => "OPT".to_string(),
-            SecurityType::Future => "FUT".to_string(),
-            SecurityType::Index => "IND".to_string(),
-            SecurityType::FuturesOption => "FOP".to_string(),
-            SecurityType::ForexPair => "CASH".to_string(),
-            SecurityType::Spread => "BAG".to_string(),
-            SecurityType::Warrant => "WAR".to_string(),
-            SecurityType::Bond => "BOND".to_string(),
-            SecurityType::Commodity => "CMDTY".to_string(),
-            SecurityType::News => "NEWS".to_string(),
-            SecurityType::MutualFund => "FUND".to_string(),
-        }
+impl fmt::Display for SecurityType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            SecurityType::Stock => "STK",
+            SecurityType::Option => "OPT",
+            SecurityType::Future => "FUT",
+            SecurityType::ContinuousFuture => "CONTFUT",
+            SecurityType::FutureAndContinuousFuture => "FUT+CONTFUT",
+            SecurityType::Index => "IND",
+            SecurityType::FuturesOption => "FOP",
+            SecurityType::ForexPair => "CASH",
+            SecurityType::Spread => "BAG",
+            SecurityType::Warrant => "WAR",
+            SecurityType::Bond => "BOND",
+            SecurityType::Commodity => "CMDTY",
+            SecurityType::News => "NEWS",
+            SecurityType::MutualFund => "FUND",
+            SecurityType::Other(code) => code,
+        };
+
+        write!(f, "{text}")
     }
 }
 
@@ -87,6 +100,8 @@ impl SecurityType {
             "STK" => SecurityType::Stock,
             "OPT" => SecurityType::Option,
             "FUT" => SecurityType::Future,
+            "CONTFUT" => SecurityType::ContinuousFuture,
+            "FUT+CONTFUT" => SecurityType::FutureAndContinuousFuture,
             "IND" => SecurityType::Index,
             "FOP" => SecurityType::FuturesOption,
             "CASH" => SecurityType::ForexPair,
@@ -96,7 +111,7 @@ impl SecurityType {
             "CMDTY" => SecurityType::Commodity,
             "NEWS" => SecurityType::News,
             "FUND" => SecurityType::MutualFund,
-            &_ => todo!(),
+            other => SecurityType::Other(other.to_owned()),
         }
     }
 }
@@ -133,6 +148,7 @@ pub struct Contract {
     /// If set to true, contract details requests and historical data queries can be performed pertaining to expired futures contracts. Expired options or other instrument types are not available.
     pub include_expired: bool,
     /// Security's identifier when querying contract's details or placing orders ISIN - Example: Apple: US0378331005 CUSIP - Example: Apple: 037833100.
+    /// Valid values are ISIN, CUSIP, SEDOL and RIC.
     pub security_id_type: String,
     /// Identifier of the security type.
     pub security_id: String,
@@ -169,11 +185,49 @@ impl Contract {
         }
     }
 
+    /// Creates forex contract from specified pair and currency, e.g. EUR and USD for EUR.USD.
+    /// Exchange defaults to IDEALPRO.
+    pub fn forex(pair: &str, currency: &str) -> Contract {
+        Contract {
+            symbol: pair.to_string(),
+            security_type: SecurityType::ForexPair,
+            currency: currency.to_string(),
+            exchange: "IDEALPRO".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates continuous futures contract from specified symbol and exchange.
+    /// Contract details for a continuous future resolve to the current front month contract.
+    pub fn continuous_future(symbol: &str, exchange: &str) -> Contract {
+        Contract {
+            symbol: symbol.to_string(),
+            security_type: SecurityType::ContinuousFuture,
+            exchange: exchange.to_string(),
+            currency: "USD".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates contract identified by its ISIN, e.g. US0378331005 for Apple, on the specified exchange and currency.
+    /// Security type defaults to STK. Use to resolve contracts from an identifier with [crate::Client::contract_details].
+    pub fn by_isin(isin: &str, exchange: &str, currency: &str) -> Contract {
+        Contract {
+            security_type: SecurityType::Stock,
+            security_id_type: "ISIN".to_string(),
+            security_id: isin.to_string(),
+            exchange: exchange.to_string(),
+            currency: currency.to_string(),
+            ..Default::default()
+        }
+    }
+
     /// Is Bag request
     pub fn is_bag(&self) -> bool {
         self.security_type == SecurityType::Spread
     }
 
+    #[cfg(feature = "market_data")]
     pub(crate) fn push_fields(&self, message: &mut RequestMessage) {
         message.push_field(&self.contract_id);
         message.push_field(&self.symbol);
@@ -350,6 +404,43 @@ pub struct ContractDetails {
     pub suggested_size_increment: f64,
 }
 
+impl ContractDetails {
+    /// Returns the value of quantity contracts at the given quoted price, e.g. to compute position values.
+    ///
+    /// The price is scaled by the contract's multiplier and divided by the price magnifier.
+    /// A missing multiplier or price magnifier is treated as 1.
+    pub fn contract_value(&self, price: f64, quantity: f64) -> f64 {
+        let multiplier = self
+            .contract
+            .multiplier
+            .parse::<f64>()
+            .ok()
+            .filter(|multiplier| *multiplier > 0.0)
+            .unwrap_or(1.0);
+        let price_magnifier = if self.price_magnifier > 0 { self.price_magnifier as f64 } else { 1.0 };
+
+        price * quantity * multiplier / price_magnifier
+    }
+
+    /// For derivatives, returns a minimal contract for the underlying, identified by its contract id.
+    ///
+    /// Saves a contract details request per derivative. The exchange is not reported and must be set before requesting market data.
+    /// Returns None when the underlying is not reported.
+    pub fn underlying_contract(&self) -> Option<Contract> {
+        if self.under_contract_id == 0 {
+            return None;
+        }
+
+        Some(Contract {
+            contract_id: self.under_contract_id,
+            symbol: self.under_symbol.clone(),
+            security_type: SecurityType::from(&self.under_security_type),
+            currency: self.contract.currency.clone(),
+            ..Default::default()
+        })
+    }
+}
+
 /// TagValue is a convenience struct to define key-value pairs.
 #[derive(Clone, Debug)]
 pub struct TagValue {
@@ -357,6 +448,38 @@ pub struct TagValue {
     pub value: String,
 }
 
+/// Builds a list of [TagValue]s, e.g. for algo params or scanner filters.
+///
+/// ```
+/// use ibapi::contracts::TagValues;
+///
+/// let params = TagValues::new().add("maxPctVol", "10").add("startTime", "09:30:00").build();
+/// assert_eq!(params.len(), 2);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TagValues {
+    tag_values: Vec<TagValue>,
+}
+
+impl TagValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a tag/value pair.
+    pub fn add(mut self, tag: &str, value: &str) -> Self {
+        self.tag_values.push(TagValue {
+            tag: tag.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    pub fn build(self) -> Vec<TagValue> {
+        self.tag_values
+    }
+}
+
 impl ToField for Vec<TagValue> {
     fn to_field(&self) -> String {
         let mut values = Vec::new();
@@ -377,29 +500,58 @@ impl ToField for Vec<TagValue> {
 // * `client` - [Client] with an active connection to gateway.
 // * `contract` - The [Contract] used as sample to query the available contracts. Typically, it will contain the [Contract]'s symbol, currency, security_type, and exchange.
 pub(crate) fn contract_details(client: &Client, contract: &Contract) -> Result<Vec<ContractDetails>, Error> {
-    verify_contract(client, contract)?;
+    verify_contract(client.server_version(), contract)?;
 
     let request_id = client.next_request_id();
     let packet = encoders::request_contract_data(client.server_version(), request_id, contract)?;
 
     let responses = client.send_request(request_id, packet)?;
 
+    collect_contract_details(client.server_version(), responses)
+}
+
+// Requests contract information for many contracts.
+//
+// All requests are sent before any responses are read. Responses are routed by request id, so each request's results are collected independently.
+// Results are keyed by the index of the contract in the input. A failed request doesn't affect the results of the others.
+pub(crate) fn contract_details_batch(client: &Client, contracts: &[Contract]) -> Vec<(usize, Result<Vec<ContractDetails>, Error>)> {
+    let send = |contract: &Contract| -> Result<ResponseIterator, Error> {
+        verify_contract(client.server_version(), contract)?;
+
+        let request_id = client.next_request_id();
+        let packet = encoders::request_contract_data(client.server_version(), request_id, contract)?;
+
+        client.send_request(request_id, packet)
+    };
+
+    let pending: Vec<_> = contracts.iter().map(send).collect();
+
+    pending
+        .into_iter()
+        .enumerate()
+        .map(|(i, responses)| {
+            (
+                i,
+                responses.and_then(|responses| collect_contract_details(client.server_version(), responses)),
+            )
+        })
+        .collect()
+}
+
+// Reads contract details until ContractDataEnd is received.
+fn collect_contract_details(server_version: i32, mut responses: ResponseIterator) -> Result<Vec<ContractDetails>, Error> {
     let mut contract_details: Vec<ContractDetails> = Vec::default();
 
     // TODO create iterator
-    for mut message in responses {
+    while let Some(mut message) = responses.next_response()? {
         match message.message_type() {
             IncomingMessages::ContractData => {
-                let decoded = decoders::contract_details(client.server_version(), &mut message)?;
+                let decoded = decoders::contract_details(server_version, &mut message)?;
                 contract_details.push(decoded);
             }
             IncomingMessages::ContractDataEnd => {
                 break;
             }
-            IncomingMessages::Error => {
-                error!("error: {message:?}");
-                return Err(Error::Simple(format!("contract_details {message:?}")));
-            }
             _ => {
                 error!("unexpected message: {:?}", message);
             }
@@ -409,30 +561,34 @@ pub(crate) fn contract_details(client: &Client, contract: &Contract) -> Result<V
     Ok(contract_details)
 }
 
-fn verify_contract(client: &Client, contract: &Contract) -> Result<(), Error> {
+pub(crate) fn verify_contract(server_version: i32, contract: &Contract) -> Result<(), Error> {
     if !contract.security_id_type.is_empty() || !contract.security_id.is_empty() {
-        client.check_server_version(
+        check_server_version(
+            server_version,
             server_versions::SEC_ID_TYPE,
             "It does not support security_id_type or security_id attributes",
         )?
     }
 
     if !contract.trading_class.is_empty() {
-        client.check_server_version(
+        check_server_version(
+            server_version,
             server_versions::TRADING_CLASS,
             "It does not support the trading_class parameter when requesting contract details.",
         )?
     }
 
     if !contract.primary_exchange.is_empty() {
-        client.check_server_version(
+        check_server_version(
+            server_version,
             server_versions::LINKING,
             "It does not support primary_exchange parameter when requesting contract details.",
         )?
     }
 
     if !contract.issuer_id.is_empty() {
-        client.check_server_version(
+        check_server_version(
+            server_version,
             server_versions::BOND_ISSUERID,
             "It does not support issuer_id parameter when requesting contract details.",
         )?
@@ -445,6 +601,7 @@ fn verify_contract(client: &Client, contract: &Contract) -> Result<(), Error> {
 #[derive(Debug)]
 pub struct ContractDescription {
     pub contract: Contract,
+    /// Security type codes of derivatives available for the contract, e.g. OPT, WAR. Codes are preserved as received.
     pub derivative_security_types: Vec<String>,
 }
 
@@ -461,19 +618,14 @@ pub(crate) fn matching_symbols(client: &Client, pattern: &str) -> Result<Vec<Con
 
     let mut responses = client.send_request(request_id, request)?;
 
-    if let Some(mut message) = responses.next() {
+    if let Some(mut message) = responses.next_response()? {
         match message.message_type() {
             IncomingMessages::SymbolSamples => {
                 return decoders::contract_descriptions(client.server_version(), &mut message);
             }
-            IncomingMessages::Error => {
-                // TODO custom error
-                error!("unexpected error: {:?}", message);
-                return Err(Error::Simple(format!("unexpected error: {message:?}")));
-            }
             _ => {
                 info!("unexpected message: {:?}", message);
-                return Err(Error::Simple(format!("unexpected message: {message:?}")));
+                return Err(Error::UnexpectedResponse(message.message_type()));
             }
         }
     }
@@ -493,6 +645,76 @@ pub struct PriceIncrement {
     pub increment: f64,
 }
 
+impl MarketRule {
+    /// Returns the minimum price increment applicable at the given price.
+    pub fn price_increment(&self, price: f64) -> Option<f64> {
+        self.price_increments
+            .iter()
+            .filter(|price_increment| price_increment.low_edge <= price.abs())
+            .max_by(|a, b| a.low_edge.total_cmp(&b.low_edge))
+            .map(|price_increment| price_increment.increment)
+    }
+
+    /// Rounds the given price to the nearest valid price increment.
+    pub fn round_to_tick(&self, price: f64) -> Option<f64> {
+        self.price_increment(price).map(|increment| round_to_increment(price, increment))
+    }
+}
+
+// Rounds price to the nearest multiple of increment.
+fn round_to_increment(price: f64, increment: f64) -> f64 {
+    if increment <= 0.0 {
+        return price;
+    }
+
+    let rounded = (price / increment).round() * increment;
+
+    // removes floating point noise. e.g. 123.45000000000002
+    (rounded * 1e8).round() / 1e8
+}
+
+// Finds the market rule id for the contract's exchange. Falls back to the first market rule.
+fn market_rule_id(contract_details: &ContractDetails, exchange: &str) -> Option<i32> {
+    let market_rule_ids: Vec<&str> = contract_details.market_rule_ids.split(',').collect();
+    let position = contract_details
+        .valid_exchanges
+        .split(',')
+        .position(|valid_exchange| valid_exchange == exchange);
+
+    let market_rule_id = match position {
+        Some(i) if i < market_rule_ids.len() => market_rule_ids[i],
+        _ => market_rule_ids[0],
+    };
+
+    market_rule_id.trim().parse().ok()
+}
+
+// Rounds price to the contract's valid price increment.
+//
+// Resolves the contract's market rule and uses the increment band applicable to the price.
+// Falls back to min_tick when the contract has no market rule.
+pub(crate) fn round_to_tick(client: &Client, contract: &Contract, price: f64) -> Result<f64, Error> {
+    let contract_details = contract_details(client, contract)?;
+
+    let contract_details = match contract_details.first() {
+        Some(contract_details) => contract_details,
+        None => return Err(Error::InvalidArgument(format!("no contract details found for {}", contract.symbol))),
+    };
+
+    match market_rule_id(contract_details, &contract.exchange) {
+        Some(market_rule_id) => {
+            let market_rule = market_rule(client, market_rule_id)?;
+            match market_rule.round_to_tick(price) {
+                Some(rounded) => Ok(rounded),
+                None => Err(Error::InvalidArgument(format!(
+                    "no price increment found for {price} in market rule {market_rule_id}"
+                ))),
+            }
+        }
+        None => Ok(round_to_increment(price, contract_details.min_tick)),
+    }
+}
+
 /// Requests details about a given market rule
 ///
 /// The market rule for an instrument on a particular exchange provides details about how the minimum price increment changes with price.
@@ -506,6 +728,6 @@ pub(crate) fn market_rule(client: &Client, market_rule_id: i32) -> Result<Market
 
     match responses.next() {
         Some(mut message) => Ok(decoders::market_rule(&mut message)?),
-        None => Err(Error::Simple("no market rule found".into())),
+        None => Err(Error::Timeout),
     }
 }
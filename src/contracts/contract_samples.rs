@@ -3,6 +3,7 @@ use super::*;
 // https://github.com/InteractiveBrokers/tws-api/blob/5cb24aea5cef9d315985a7b13dea7efbcfe2b16a/samples/CSharp/Testbed/ContractSamples.cs
 
 // Future contracts also require an expiration date but are less complicated than options.
+#[cfg(feature = "market_data")]
 pub fn simple_future() -> Contract {
     Contract {
         symbol: "GBL".to_owned(),
@@ -16,6 +17,7 @@ pub fn simple_future() -> Contract {
 
 // Rather than giving expiration dates we can also provide the local symbol
 // attributes such as symbol, currency, strike, etc.
+#[cfg(any(feature = "market_data", feature = "orders"))]
 pub fn future_with_local_symbol() -> Contract {
     Contract {
         security_type: SecurityType::Future,
@@ -39,6 +41,7 @@ pub fn future_with_multiplier() -> Contract {
     }
 }
 
+#[cfg(feature = "orders")]
 pub fn smart_future_combo_contract() -> Contract {
     let leg_1 = ComboLeg {
         contract_id: 55928698, //WTI future June 2017
@@ -1,4 +1,4 @@
-use crate::{contracts::SecurityType, messages::ResponseMessage, orders::TagValue, server_versions, Error};
+use crate::{contracts::SecurityType, contracts::TagValue, messages::ResponseMessage, server_versions, Error};
 
 use super::{Contract, ContractDescription, ContractDetails, MarketRule, PriceIncrement};
 
@@ -146,7 +146,7 @@ pub(crate) fn contract_descriptions(server_version: i32, message: &mut ResponseM
             ..Default::default()
         };
 
-        let derivative_security_types_count = message.next_int()?;
+        let derivative_security_types_count = message.next_int()?.max(0);
         let mut derivative_security_types: Vec<String> = Vec::with_capacity(derivative_security_types_count as usize);
         for _ in 0..derivative_security_types_count {
             derivative_security_types.push(message.next_string()?);
@@ -189,9 +189,58 @@ pub(crate) fn market_rule(message: &mut ResponseMessage) -> Result<MarketRule, E
 mod tests {
     use super::*;
 
+    #[test]
+    fn decode_continuous_future_contract_details() {
+        let mut message = ResponseMessage::from("10\x009000\x00ES\x00CONTFUT\x0020231215 08:30 US/Central\x000\x00\x00CME\x00USD\x00ESZ3\x00ES\x00ES\x00495512563\x000.25\x0050\x00LMT,MKT,STP\x00CME,QBALGO\x001\x0011004968\x00E-mini S&P 500\x00\x00202312\x00\x00\x00\x00US/Central\x00\x00\x00\x00\x000\x002147483647\x00ES\x00IND\x0067,67\x0020231215\x00\x001\x001\x001\x00");
+
+        let results = contract_details(server_versions::SIZE_RULES, &mut message);
+
+        if let Ok(contract_details) = results {
+            assert_eq!(contract_details.contract.symbol, "ES", "contract.symbol");
+            assert_eq!(
+                contract_details.contract.security_type,
+                SecurityType::ContinuousFuture,
+                "contract.security_type"
+            );
+            assert_eq!(
+                contract_details.contract.last_trade_date_or_contract_month, "20231215",
+                "contract.last_trade_date_or_contract_month"
+            );
+            assert_eq!(contract_details.last_trade_time, "08:30", "last_trade_time");
+            assert_eq!(contract_details.contract.local_symbol, "ESZ3", "contract.local_symbol");
+            assert_eq!(contract_details.contract.contract_id, 495512563, "contract.contract_id");
+            assert_eq!(contract_details.contract.multiplier, "50", "contract.multiplier");
+            assert_eq!(contract_details.contract_month, "202312", "contract_month");
+            assert_eq!(contract_details.under_contract_id, 11004968, "under_contract_id");
+            assert_eq!(contract_details.market_rule_ids, "67,67", "market_rule_ids");
+            assert_eq!(contract_details.real_expiration_date, "20231215", "real_expiration_date");
+        } else if let Err(err) = results {
+            panic!("error decoding contract details: {err}");
+        }
+    }
+
+    #[test]
+    fn decode_option_contract_details() {
+        let mut message = ResponseMessage::from("10\x009000\x00AAPL\x00OPT\x0020240119 16:00 US/Eastern\x00180\x00C\x00SMART\x00USD\x00AAPL  240119C00180000\x00AAPL\x00AAPL\x00654321987\x000.01\x00100\x00LMT,MKT\x00SMART,CBOE\x001\x00265598\x00APPLE INC\x00\x00202401\x00Technology\x00Computers\x00Computers\x00US/Eastern\x00\x00\x00\x00\x000\x002147483647\x00AAPL\x00STK\x0032,32\x0020240119\x00\x001\x001\x001\x00");
+
+        let contract_details = contract_details(server_versions::SIZE_RULES, &mut message).expect("error decoding contract details");
+
+        assert_eq!(contract_details.contract.security_type, SecurityType::Option, "contract.security_type");
+        assert_eq!(contract_details.contract.contract_id, 654321987, "contract.contract_id");
+        assert_eq!(contract_details.under_contract_id, 265598, "under_contract_id");
+        assert_eq!(contract_details.under_symbol, "AAPL", "under_symbol");
+        assert_eq!(contract_details.under_security_type, "STK", "under_security_type");
+
+        let underlying = contract_details.underlying_contract().expect("missing underlying contract");
+        assert_eq!(underlying.contract_id, 265598, "underlying.contract_id");
+        assert_eq!(underlying.symbol, "AAPL", "underlying.symbol");
+        assert_eq!(underlying.security_type, SecurityType::Stock, "underlying.security_type");
+        assert_eq!(underlying.currency, "USD", "underlying.currency");
+    }
+
     #[test]
     fn decode_market_rule() {
-        let mut message = ResponseMessage::from("93\026\01\00\00.01\0");
+        let mut message = ResponseMessage::from("93\x0026\x001\x000\x000.01\x00");
 
         let results = market_rule(&mut message);
 
@@ -205,7 +254,7 @@ mod tests {
                 "market_rule.price_increments[0].increment"
             );
         } else if let Err(err) = results {
-            assert!(false, "error decoding market rule: {err}");
+            panic!("error decoding market rule: {err}");
         }
     }
 }
@@ -4,12 +4,9 @@ use crate::messages::RequestMessage;
 use crate::{server_versions, Error};
 
 pub(crate) fn request_contract_data(server_version: i32, request_id: i32, contract: &Contract) -> Result<RequestMessage, Error> {
-    const VERSION: i32 = 8;
-
     let mut packet = RequestMessage::default();
 
-    packet.push_field(&OutgoingMessages::RequestContractData);
-    packet.push_field(&VERSION);
+    packet.push_message_header(OutgoingMessages::RequestContractData, server_version);
 
     if server_version >= server_versions::CONTRACT_DATA_CHAIN {
         packet.push_field(&request_id);
@@ -81,18 +78,16 @@ pub(crate) fn request_market_rule(market_rule_id: i32) -> Result<RequestMessage,
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn request_market_rule() {
         let results = super::request_market_rule(26);
 
         match results {
             Ok(message) => {
-                assert_eq!(message.encode(), "91\026\0", "message.encode()");
+                assert_eq!(message.encode(), "91\x0026\x00", "message.encode()");
             }
             Err(err) => {
-                assert!(false, "error encoding market rule request: {err}");
+                panic!("error encoding market rule request: {err}");
             }
         }
     }
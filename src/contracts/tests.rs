@@ -1,27 +1,29 @@
 use std::cell::RefCell;
+use std::sync::Mutex;
 
 use super::*;
 
-use crate::stubs::MessageBusStub;
+use crate::client::transport::{MessageBus, TcpMessageBus};
+use crate::stubs::{fake_gateway, FakeGateway, MessageBusStub, RequestMessages};
 
 #[test]
 fn request_stock_contract_details() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub{
+    let message_bus = Mutex::new(Box::new(MessageBusStub{
         request_messages: RefCell::new(vec![]),
         response_messages: vec![
             "10|9001|TSLA|STK||0||SMART|USD|TSLA|NMS|NMS|76792991|0.01||ACTIVETIM,AD,ADJUST,ALERT,ALGO,ALLOC,AON,AVGCOST,BASKET,BENCHPX,CASHQTY,COND,CONDORDER,DARKONLY,DARKPOLL,DAY,DEACT,DEACTDIS,DEACTEOD,DIS,DUR,GAT,GTC,GTD,GTT,HID,IBKRATS,ICE,IMB,IOC,LIT,LMT,LOC,MIDPX,MIT,MKT,MOC,MTL,NGCOMB,NODARK,NONALGO,OCA,OPG,OPGREROUT,PEGBENCH,PEGMID,POSTATS,POSTONLY,PREOPGRTH,PRICECHK,REL,REL2MID,RELPCTOFS,RPI,RTH,SCALE,SCALEODD,SCALERST,SIZECHK,SNAPMID,SNAPMKT,SNAPREL,STP,STPLMT,SWEEP,TRAIL,TRAILLIT,TRAILLMT,TRAILMIT,WHATIF|SMART,AMEX,NYSE,CBOE,PHLX,ISE,CHX,ARCA,ISLAND,DRCTEDGE,BEX,BATS,EDGEA,CSFBALGO,JEFFALGO,BYX,IEX,EDGX,FOXRIVER,PEARL,NYSENAT,LTSE,MEMX,PSX|1|0|TESLA INC|NASDAQ||Consumer, Cyclical|Auto Manufacturers|Auto-Cars/Light Trucks|US/Eastern|20221229:0400-20221229:2000;20221230:0400-20221230:2000;20221231:CLOSED;20230101:CLOSED;20230102:CLOSED;20230103:0400-20230103:2000|20221229:0930-20221229:1600;20221230:0930-20221230:1600;20221231:CLOSED;20230101:CLOSED;20230102:CLOSED;20230103:0930-20230103:1600|||1|ISIN|US88160R1014|1|||26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26||COMMON|1|1|100||".to_string(),
             "10|9001|TSLA|STK||0||AMEX|USD|TSLA|NMS|NMS|76792991|0.01||ACTIVETIM,AD,ADJUST,ALERT,ALLOC,AVGCOST,BASKET,BENCHPX,CASHQTY,COND,CONDORDER,DAY,DEACT,DEACTDIS,DEACTEOD,GAT,GTC,GTD,GTT,HID,IOC,LIT,LMT,MIT,MKT,MTL,NGCOMB,NONALGO,OCA,PEGBENCH,SCALE,SCALERST,SNAPMID,SNAPMKT,SNAPREL,STP,STPLMT,TRAIL,TRAILLIT,TRAILLMT,TRAILMIT,WHATIF|SMART,AMEX,NYSE,CBOE,PHLX,ISE,CHX,ARCA,ISLAND,DRCTEDGE,BEX,BATS,EDGEA,CSFBALGO,JEFFALGO,BYX,IEX,EDGX,FOXRIVER,PEARL,NYSENAT,LTSE,MEMX,PSX|1|0|TESLA INC|NASDAQ||Consumer, Cyclical|Auto Manufacturers|Auto-Cars/Light Trucks|US/Eastern|20221229:0700-20221229:2000;20221230:0700-20221230:2000;20221231:CLOSED;20230101:CLOSED;20230102:CLOSED;20230103:0700-20230103:2000|20221229:0700-20221229:2000;20221230:0700-20221230:2000;20221231:CLOSED;20230101:CLOSED;20230102:CLOSED;20230103:0700-20230103:2000|||1|ISIN|US88160R1014|1|||26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26,26||COMMON|1|1|100||".to_string(),
             "52|1|9001||".to_string(),
         ]
-    }));
+    }) as Box<dyn MessageBus>);
 
-    let mut client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
     let contract = Contract::stock("TSLA");
 
     let results = client.contract_details(&contract);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     assert_eq!(request_messages[0].encode_simple(), "9|8|9000|0|TSLA|STK||0|||SMART||USD|||0|||");
 
@@ -75,11 +77,69 @@ fn request_stock_contract_details() {
     assert_eq!(contracts[0].suggested_size_increment, 100.0);
 }
 
+#[test]
+fn request_contract_details_by_isin() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["52|1|9000||".to_string()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::by_isin("US0378331005", "SMART", "USD");
+
+    let results = client.contract_details(&contract);
+    assert!(results.is_ok(), "failed to encode request: {:?}", results.err());
+
+    let request_messages = client.message_bus().request_messages();
+
+    assert_eq!(
+        request_messages[0].encode_simple(),
+        "9|8|9000|0||STK||0|||SMART||USD|||0|ISIN|US0378331005|"
+    );
+    assert_eq!(request_messages[0][16], "ISIN", "message.security_id_type");
+    assert_eq!(request_messages[0][17], "US0378331005", "message.security_id");
+}
+
+#[test]
+fn request_contract_details_error() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["4|2|9000|200|No security definition has been found for the request|".to_string()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let result = client.contract_details(&Contract::stock("UNKNOWN")).err();
+    assert!(matches!(result, Some(Error::TwsError { code: 200, .. })), "{result:?}");
+    assert_eq!(
+        result.unwrap().to_string(),
+        "TWS error 200: No security definition has been found for the request"
+    );
+}
+
 #[test]
 fn request_bond_contract_details() {}
 
 #[test]
-fn request_future_contract_details() {}
+fn request_future_contract_details() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["52|1|9000||".to_string()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = contract_samples::future_with_multiplier();
+
+    let results = client.contract_details(&contract);
+    assert!(results.is_ok(), "failed to encode request: {:?}", results.err());
+
+    let request_messages = client.message_bus().request_messages();
+
+    assert_eq!(request_messages[0].encode_simple(), "9|8|9000|0|DAX|FUT|202303|0||1|EUREX||EUR|||0|||");
+    assert_eq!(request_messages[0][9], "1", "message.multiplier");
+}
 
 #[test]
 fn test_read_last_trade_date() {
@@ -95,4 +155,299 @@ fn test_read_last_trade_date() {
 }
 
 #[test]
-fn request_matching_symbols() {}
+fn request_matching_symbols() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "79|9000|3|76792991|TSLA|STK|NASDAQ|USD|0|Tesla Inc|e1|265598|AAPL|STK|NASDAQ|USD|3|OPT|WAR|XYZ|Apple Inc|e2|12087792|EUR|CASH|IDEALPRO|USD|1|CFD|European Monetary Union Euro||".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::BOND_ISSUERID);
+
+    let contract_descriptions: Vec<ContractDescription> = client.matching_symbols("T").expect("request matching symbols failed").collect();
+
+    assert_eq!(contract_descriptions.len(), 3, "contract_descriptions.len()");
+
+    // no derivatives, next sample's fields are not consumed
+    assert_eq!(
+        contract_descriptions[0].contract.symbol, "TSLA",
+        "contract_descriptions[0].contract.symbol"
+    );
+    assert_eq!(
+        contract_descriptions[0].derivative_security_types.len(),
+        0,
+        "contract_descriptions[0].derivative_security_types"
+    );
+    assert_eq!(
+        contract_descriptions[0].contract.description, "Tesla Inc",
+        "contract_descriptions[0].contract.description"
+    );
+    assert_eq!(
+        contract_descriptions[0].contract.issuer_id, "e1",
+        "contract_descriptions[0].contract.issuer_id"
+    );
+
+    // unknown derivative codes are preserved
+    assert_eq!(
+        contract_descriptions[1].contract.contract_id, 265598,
+        "contract_descriptions[1].contract.contract_id"
+    );
+    assert_eq!(
+        contract_descriptions[1].contract.symbol, "AAPL",
+        "contract_descriptions[1].contract.symbol"
+    );
+    assert_eq!(
+        contract_descriptions[1].derivative_security_types,
+        vec!["OPT", "WAR", "XYZ"],
+        "contract_descriptions[1].derivative_security_types"
+    );
+    assert_eq!(
+        contract_descriptions[1].contract.description, "Apple Inc",
+        "contract_descriptions[1].contract.description"
+    );
+    assert_eq!(
+        contract_descriptions[1].contract.issuer_id, "e2",
+        "contract_descriptions[1].contract.issuer_id"
+    );
+
+    assert_eq!(
+        contract_descriptions[2].contract.symbol, "EUR",
+        "contract_descriptions[2].contract.symbol"
+    );
+    assert_eq!(
+        contract_descriptions[2].contract.security_type,
+        SecurityType::ForexPair,
+        "contract_descriptions[2].contract.security_type"
+    );
+    assert_eq!(
+        contract_descriptions[2].contract.primary_exchange, "IDEALPRO",
+        "contract_descriptions[2].contract.primary_exchange"
+    );
+    assert_eq!(
+        contract_descriptions[2].derivative_security_types,
+        vec!["CFD"],
+        "contract_descriptions[2].derivative_security_types"
+    );
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0].encode_simple(), "81|9000|T|", "request_messages[0]");
+}
+
+#[test]
+fn unknown_security_type() {
+    let security_type = SecurityType::from("XYZ");
+
+    assert_eq!(security_type, SecurityType::Other("XYZ".to_owned()));
+    assert_eq!(security_type.to_field(), "XYZ");
+}
+
+#[test]
+fn continuous_future_security_types() {
+    assert_eq!(SecurityType::ContinuousFuture.to_field(), "CONTFUT");
+    assert_eq!(SecurityType::FutureAndContinuousFuture.to_field(), "FUT+CONTFUT");
+
+    assert_eq!(SecurityType::from("CONTFUT"), SecurityType::ContinuousFuture);
+    assert_eq!(SecurityType::from("FUT+CONTFUT"), SecurityType::FutureAndContinuousFuture);
+}
+
+#[test]
+fn continuous_future_contract() {
+    let contract = Contract::continuous_future("ES", "CME");
+
+    assert_eq!(contract.symbol, "ES", "contract.symbol");
+    assert_eq!(contract.security_type, SecurityType::ContinuousFuture, "contract.security_type");
+    assert_eq!(contract.exchange, "CME", "contract.exchange");
+    assert_eq!(contract.currency, "USD", "contract.currency");
+}
+
+#[test]
+fn round_to_tick_single_band() {
+    let market_rule = MarketRule {
+        market_rule_id: 26,
+        price_increments: vec![PriceIncrement {
+            low_edge: 0.0,
+            increment: 0.01,
+        }],
+    };
+
+    assert_eq!(market_rule.price_increment(123.456), Some(0.01));
+    assert_eq!(market_rule.round_to_tick(123.456), Some(123.46));
+    assert_eq!(market_rule.round_to_tick(123.454), Some(123.45));
+    assert_eq!(market_rule.round_to_tick(0.004), Some(0.0));
+}
+
+#[test]
+fn round_to_tick_multi_band() {
+    let market_rule = MarketRule {
+        market_rule_id: 239,
+        price_increments: vec![
+            PriceIncrement {
+                low_edge: 0.0,
+                increment: 0.01,
+            },
+            PriceIncrement {
+                low_edge: 3.0,
+                increment: 0.05,
+            },
+        ],
+    };
+
+    assert_eq!(market_rule.price_increment(2.99), Some(0.01));
+    assert_eq!(market_rule.round_to_tick(2.994), Some(2.99));
+
+    assert_eq!(market_rule.price_increment(3.0), Some(0.05));
+    assert_eq!(market_rule.round_to_tick(3.12), Some(3.1));
+    assert_eq!(market_rule.round_to_tick(3.13), Some(3.15));
+}
+
+#[test]
+fn future_contract_value() {
+    let contract_details = ContractDetails {
+        contract: Contract {
+            multiplier: "50".to_owned(),
+            ..Contract::futures("ES")
+        },
+        price_magnifier: 1,
+        ..Default::default()
+    };
+
+    assert_eq!(contract_details.contract_value(4500.25, 2.0), 450025.0);
+    assert_eq!(contract_details.contract_value(4500.25, -1.0), -225012.5);
+}
+
+#[test]
+fn magnified_contract_value() {
+    // quoted in pence, valued in pounds
+    let contract_details = ContractDetails {
+        contract: Contract {
+            multiplier: "1000".to_owned(),
+            ..Contract::futures("Z")
+        },
+        price_magnifier: 100,
+        ..Default::default()
+    };
+
+    assert_eq!(contract_details.contract_value(7520.0, 3.0), 225600.0);
+
+    // missing multiplier and magnifier
+    let contract_details = ContractDetails::default();
+    assert_eq!(contract_details.contract_value(98.5, 10.0), 985.0);
+}
+
+#[test]
+fn market_rule_id_for_exchange() {
+    let contract_details = ContractDetails {
+        valid_exchanges: "SMART,AMEX,NYSE".to_owned(),
+        market_rule_ids: "26,239,26".to_owned(),
+        ..Default::default()
+    };
+
+    assert_eq!(market_rule_id(&contract_details, "AMEX"), Some(239));
+    assert_eq!(market_rule_id(&contract_details, "SMART"), Some(26));
+    assert_eq!(market_rule_id(&contract_details, "ISLAND"), Some(26));
+
+    let contract_details = ContractDetails::default();
+    assert_eq!(market_rule_id(&contract_details, "SMART"), None);
+}
+
+// Answers once all three batch requests were read.
+fn batch_gateway(responses: Vec<String>) -> (Client, FakeGateway) {
+    let responses: Vec<&str> = responses.iter().map(String::as_str).collect();
+    let gateway = fake_gateway(&[&[], &[], &responses]);
+
+    let mut message_bus = TcpMessageBus::connect(&gateway.address).unwrap();
+    message_bus.process_messages(server_versions::SIZE_RULES).unwrap();
+
+    let client = Client::stubbed(Mutex::new(Box::new(message_bus) as Box<dyn MessageBus>), server_versions::SIZE_RULES);
+
+    (client, gateway)
+}
+
+fn batch_contract_data(request_id: i32, symbol: &str, contract_id: i32) -> String {
+    format!("10|{request_id}|{symbol}|STK||0||SMART|USD|{symbol}|NMS|NMS|{contract_id}|0.01||LMT,MKT|SMART,ISLAND|1|0|{symbol} INC|NASDAQ|||||US/Eastern|||||0|0|||26,26||COMMON|1|1|100|")
+}
+
+fn batch_contract_data_end(request_id: i32) -> String {
+    format!("52|1|{request_id}|")
+}
+
+#[test]
+fn request_contract_details_batch() {
+    let (client, gateway) = batch_gateway(vec![
+        batch_contract_data(9000, "TSLA", 76792991),
+        batch_contract_data(9001, "AAPL", 265598),
+        batch_contract_data(9002, "MSFT", 272093),
+        batch_contract_data(9000, "TSLA", 76792992),
+        batch_contract_data_end(9001),
+        batch_contract_data_end(9000),
+        batch_contract_data(9002, "MSFT", 272094),
+        batch_contract_data_end(9002),
+    ]);
+
+    let contracts = vec![Contract::stock("TSLA"), Contract::stock("AAPL"), Contract::stock("MSFT")];
+
+    let results = client.contract_details_batch(&contracts);
+    assert_eq!(results.len(), 3, "results.len()");
+
+    let (i, tsla) = &results[0];
+    assert_eq!(*i, 0, "results[0].index");
+    let tsla = tsla.as_ref().expect("failed to request TSLA contract details");
+    assert_eq!(tsla.len(), 2, "tsla.len()");
+    assert_eq!(tsla[0].contract.contract_id, 76792991, "tsla[0].contract.contract_id");
+    assert_eq!(tsla[1].contract.contract_id, 76792992, "tsla[1].contract.contract_id");
+
+    let (i, aapl) = &results[1];
+    assert_eq!(*i, 1, "results[1].index");
+    let aapl = aapl.as_ref().expect("failed to request AAPL contract details");
+    assert_eq!(aapl.len(), 1, "aapl.len()");
+    assert_eq!(aapl[0].contract.symbol, "AAPL", "aapl[0].contract.symbol");
+
+    let (i, msft) = &results[2];
+    assert_eq!(*i, 2, "results[2].index");
+    let msft = msft.as_ref().expect("failed to request MSFT contract details");
+    assert_eq!(msft.len(), 2, "msft.len()");
+    assert!(msft.iter().all(|details| details.contract.symbol == "MSFT"), "msft symbols");
+
+    assert!(!gateway.is_finished(), "server closed connection");
+}
+
+#[test]
+fn contract_details_batch_reports_failures_per_contract() {
+    let (client, gateway) = batch_gateway(vec![
+        batch_contract_data(9000, "TSLA", 76792991),
+        "4|2|9001|200|No security definition has been found for the request|".to_owned(),
+        batch_contract_data_end(9000),
+        batch_contract_data(9002, "MSFT", 272093),
+        batch_contract_data_end(9002),
+    ]);
+
+    let contracts = vec![Contract::stock("TSLA"), Contract::stock("XXXX"), Contract::stock("MSFT")];
+
+    let results = client.contract_details_batch(&contracts);
+    assert_eq!(results.len(), 3, "results.len()");
+
+    let (i, tsla) = &results[0];
+    assert_eq!(*i, 0, "results[0].index");
+    assert_eq!(tsla.as_ref().map(Vec::len).ok(), Some(1), "tsla: {tsla:?}");
+
+    let (i, unknown) = &results[1];
+    assert_eq!(*i, 1, "results[1].index");
+    assert!(matches!(unknown, Err(Error::TwsError { code: 200, .. })), "unknown: {unknown:?}");
+
+    let (i, msft) = &results[2];
+    assert_eq!(*i, 2, "results[2].index");
+    assert_eq!(msft.as_ref().map(Vec::len).ok(), Some(1), "msft: {msft:?}");
+
+    assert!(!gateway.is_finished(), "server closed connection");
+}
+
+#[test]
+fn tag_values_builder() {
+    let tag_values = TagValues::new().add("maxPctVol", "10").add("startTime", "09:30:00").build();
+
+    assert_eq!(tag_values.len(), 2, "tag_values.len()");
+    assert_eq!(tag_values[0].tag, "maxPctVol", "tag_values[0].tag");
+    assert_eq!(tag_values[1].value, "09:30:00", "tag_values[1].value");
+    assert_eq!(tag_values.to_field(), "maxPctVol=10;startTime=09:30:00;");
+}
@@ -1,5 +1,34 @@
 use std::{num::ParseIntError, string::FromUtf8Error};
 
+use crate::messages::{IncomingMessages, ResponseMessage};
+
+#[cfg(test)]
+mod tests;
+
+/// Errors returned by the client.
+///
+/// Error messages from TWS about a request are returned as [Error::TwsError], so they can be told apart by code,
+/// or by category with [Error::kind].
+///
+/// # Examples
+///
+/// ```no_run
+/// use ibapi::contracts::Contract;
+/// use ibapi::errors::ErrorKind;
+/// use ibapi::Client;
+///
+/// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+///
+/// match client.contract_details(&Contract::stock("UNKNOWN")) {
+///     Ok(details) => {
+///         for contract_details in details {
+///             println!("{contract_details:?}");
+///         }
+///     }
+///     Err(err) if err.kind() == ErrorKind::NoSecurityDefinition => println!("no security definition found"),
+///     Err(err) => println!("request failed: {err}"),
+/// }
+/// ```
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
@@ -13,7 +42,32 @@ pub enum Error {
     NotImplemented,
     Parse(usize, String, String),
     ServerVersion(i32, i32, String),
+    /// No longer returned by the library. Kept for code matching on it.
     Simple(String),
+    /// The request was rejected before it was sent, e.g. an empty basket or an order type not allowed with the time in force.
+    InvalidArgument(String),
+    /// A message of an unexpected type arrived in response to the request.
+    UnexpectedResponse(IncomingMessages),
+    /// The server sent a value the library can't interpret, e.g. an unknown depth side.
+    InvalidResponse(String),
+    /// The client was shut down. See [crate::Client::shutdown].
+    Shutdown,
+    /// Requests are paused during the TWS maintenance window. See [crate::client::ClientBuilder::maintenance_window].
+    MaintenanceWindow,
+    /// No response arrived within the request timeout, or the connection did not start within the startup timeout.
+    /// See [crate::client::ClientBuilder::request_timeout] and [crate::client::ClientBuilder::startup_timeout].
+    Timeout,
+    /// The request was cancelled. See [crate::client::CancellationToken].
+    Cancelled,
+    /// No more responses will arrive for the request, e.g. because its responses ended or the client was shut down.
+    Disconnected,
+    /// The connection was closed by the server before it was established, e.g. when the host is not a trusted IP.
+    ConnectionFailed(String),
+    /// An error message from TWS about the request. e.g. code 200 when no security definition was found, or 162 for pacing violations.
+    TwsError {
+        code: i32,
+        message: String,
+    },
 }
 
 impl std::error::Error for Error {}
@@ -31,6 +85,39 @@ impl std::fmt::Display for Error {
             Error::ServerVersion(wanted, have, message) => write!(f, "server version {wanted} required, got {have}: {message}"),
 
             Error::Simple(ref err) => write!(f, "error occurred: {err}"),
+            Error::InvalidArgument(ref reason) => write!(f, "invalid argument: {reason}"),
+            Error::UnexpectedResponse(ref message) => write!(f, "unexpected response: {message:?}"),
+            Error::InvalidResponse(ref reason) => write!(f, "invalid response: {reason}"),
+            Error::Shutdown => write!(f, "client was shut down"),
+            Error::MaintenanceWindow => write!(f, "requests are paused during the maintenance window"),
+            Error::Timeout => write!(f, "timed out waiting for response"),
+            Error::Cancelled => write!(f, "request was cancelled"),
+            Error::Disconnected => write!(f, "no more responses for the request"),
+            Error::ConnectionFailed(ref reason) => write!(f, "connection failed: {reason}"),
+            Error::TwsError { code, message } => write!(f, "TWS error {code}: {message}"),
+        }
+    }
+}
+
+impl Error {
+    /// Returns true for TWS messages that don't fail the request, e.g. data farm status or delayed market data notices.
+    pub fn is_warning(&self) -> bool {
+        self.kind().is_warning()
+    }
+
+    /// Returns the category of TWS errors with well known codes, e.g. [ErrorKind::PacingViolation]. Other errors are [ErrorKind::Other].
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::TwsError { code, .. } => ErrorKind::from_code(*code),
+            _ => ErrorKind::Other,
+        }
+    }
+
+    // Decodes an error message received from TWS for a request.
+    pub(crate) fn from_tws_message(message: &ResponseMessage) -> Error {
+        Error::TwsError {
+            code: message.peek_int(3).unwrap_or(-1),
+            message: message.peek_string(4).unwrap_or_default(),
         }
     }
 }
@@ -58,3 +145,61 @@ impl From<time::error::Parse> for Error {
         Error::ParseTime(err)
     }
 }
+
+/// Categories of well known TWS error codes. See [Error::kind].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Requests were sent too fast, e.g. code 100 for more than 50 messages per second or 162 for historical data pacing violations.
+    PacingViolation,
+    /// Code 200. No contract matches the request.
+    NoSecurityDefinition,
+    /// The order was rejected, e.g. code 201, or 110 when the price does not conform to the minimum tick.
+    OrderRejected,
+    /// Code 202. The order was cancelled.
+    OrderCancelled,
+    /// Codes 354, 10089, 10090 and 10168. The account lacks the market data subscription required by the request.
+    MarketDataNotSubscribed,
+    /// Code 317. The market depth book was reset by the server. Rows received before must be discarded.
+    MarketDepthReset,
+    /// Code 10167. Delayed market data is displayed in place of the unsubscribed realtime data.
+    DelayedMarketData,
+    /// Code 10197. Market data is not available while the account is logged in elsewhere, e.g. on a mobile device.
+    CompetingLiveSession,
+    /// Code 1100. Connectivity between TWS and IB servers was lost.
+    ConnectivityLost,
+    /// Codes 1101 and 1102. Connectivity between TWS and IB servers was restored.
+    ConnectivityRestored,
+    /// Codes 502 and 504. TWS could not be reached or the client is not connected.
+    NotConnected,
+    /// Codes 2100 to 2169. Warnings and notices that don't fail the request, e.g. data farm status.
+    Warning,
+    /// Any other code.
+    Other,
+}
+
+impl ErrorKind {
+    /// Returns the category of a TWS error code.
+    pub fn from_code(code: i32) -> ErrorKind {
+        match code {
+            100 | 162 | 420 => ErrorKind::PacingViolation,
+            200 => ErrorKind::NoSecurityDefinition,
+            110 | 201 => ErrorKind::OrderRejected,
+            202 => ErrorKind::OrderCancelled,
+            317 => ErrorKind::MarketDepthReset,
+            354 | 10089 | 10090 | 10168 => ErrorKind::MarketDataNotSubscribed,
+            10167 => ErrorKind::DelayedMarketData,
+            10197 => ErrorKind::CompetingLiveSession,
+            1100 => ErrorKind::ConnectivityLost,
+            1101 | 1102 => ErrorKind::ConnectivityRestored,
+            502 | 504 => ErrorKind::NotConnected,
+            2100..=2169 => ErrorKind::Warning,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Returns true for warnings and notices, which don't fail the request.
+    pub fn is_warning(self) -> bool {
+        matches!(self, ErrorKind::Warning | ErrorKind::DelayedMarketData)
+    }
+}
@@ -0,0 +1,27 @@
+use super::*;
+
+#[test]
+fn error_kind_from_code() {
+    assert_eq!(ErrorKind::from_code(162), ErrorKind::PacingViolation);
+    assert_eq!(ErrorKind::from_code(200), ErrorKind::NoSecurityDefinition);
+    assert_eq!(ErrorKind::from_code(201), ErrorKind::OrderRejected);
+    assert_eq!(ErrorKind::from_code(317), ErrorKind::MarketDepthReset);
+    assert_eq!(ErrorKind::from_code(1100), ErrorKind::ConnectivityLost);
+    assert_eq!(ErrorKind::from_code(10167), ErrorKind::DelayedMarketData);
+    assert_eq!(ErrorKind::from_code(2104), ErrorKind::Warning);
+    assert_eq!(ErrorKind::from_code(321), ErrorKind::Other);
+}
+
+#[test]
+fn error_kind() {
+    let error = Error::TwsError {
+        code: 162,
+        message: "Historical Market Data Service error message:API historical data query cancelled: 1".into(),
+    };
+    assert_eq!(error.kind(), ErrorKind::PacingViolation);
+
+    assert!(!error.is_warning());
+
+    assert_eq!(Error::Timeout.kind(), ErrorKind::Other);
+    assert!(!Error::Timeout.is_warning());
+}
@@ -104,8 +104,13 @@
 //!```
 
 /// Describes items present in an account.
+#[cfg(feature = "accounts")]
 pub mod accounts;
 
+/// An async client on the tokio runtime, for applications that shouldn't block threads on requests.
+#[cfg(feature = "tokio")]
+pub mod async_client;
+
 /// TSW API Client.
 ///
 /// The Client establishes the connection to TWS or the Gateway.
@@ -120,14 +125,19 @@ pub mod contracts;
 //pub(crate) mod domain;
 pub mod errors;
 /// APIs for retrieving market data
+#[cfg(feature = "market_data")]
 pub mod market_data;
-mod messages;
-pub(crate) mod news;
+/// Messages exchanged with TWS, for implementing a custom [MessageBus](crate::client::MessageBus).
+pub mod messages;
 /// Data types for building and placing orders.
+#[cfg(feature = "orders")]
 pub mod orders;
 
 mod server_versions;
+#[cfg(test)]
 pub(crate) mod stubs;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 #[doc(inline)]
 pub use errors::Error;
@@ -135,6 +145,10 @@ pub use errors::Error;
 #[doc(inline)]
 pub use client::Client;
 
+/// The TLS library configuring [ClientBuilder::tls](crate::client::ClientBuilder::tls).
+#[cfg(feature = "tls")]
+pub use rustls;
+
 // ToField
 
 pub(crate) trait ToField {
@@ -163,6 +177,12 @@ impl ToField for &str {
     }
 }
 
+impl ToField for char {
+    fn to_field(&self) -> String {
+        self.to_string()
+    }
+}
+
 impl ToField for usize {
     fn to_field(&self) -> String {
         self.to_string()
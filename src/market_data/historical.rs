@@ -1,5 +1,5 @@
 use std::collections::VecDeque;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 
 use log::{error, warn};
 use time::{Date, OffsetDateTime};
@@ -11,7 +11,7 @@ use crate::messages::{IncomingMessages, RequestMessage, ResponseMessage};
 use crate::{server_versions, Client, Error, ToField};
 
 mod decoders;
-mod encoders;
+pub(crate) mod encoders;
 #[cfg(test)]
 mod tests;
 
@@ -60,29 +60,31 @@ pub enum BarSize {
     Month,
 }
 
-impl ToString for BarSize {
-    fn to_string(&self) -> String {
-        match self {
-            Self::Sec => "1 sec".into(),
-            Self::Sec5 => "5 secs".into(),
-            Self::Sec15 => "15 secs".into(),
-            Self::Sec30 => "30 secs".into(),
-            Self::Min => "1 min".into(),
-            Self::Min2 => "2 mins".into(),
-            Self::Min3 => "3 mins".into(),
-            Self::Min5 => "5 mins".into(),
-            Self::Min15 => "15 mins".into(),
-            Self::Min20 => "20 mins".into(),
-            Self::Min30 => "30 mins".into(),
-            Self::Hour => "1 hour".into(),
-            Self::Hour2 => "2 hours".into(),
-            Self::Hour3 => "3 hours".into(),
-            Self::Hour4 => "4 hours".into(),
-            Self::Hour8 => "8 hours".into(),
-            Self::Day => "1 day".into(),
-            Self::Week => "1 week".into(),
-            Self::Month => "1 month".into(),
-        }
+impl fmt::Display for BarSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::Sec => "1 sec",
+            Self::Sec5 => "5 secs",
+            Self::Sec15 => "15 secs",
+            Self::Sec30 => "30 secs",
+            Self::Min => "1 min",
+            Self::Min2 => "2 mins",
+            Self::Min3 => "3 mins",
+            Self::Min5 => "5 mins",
+            Self::Min15 => "15 mins",
+            Self::Min20 => "20 mins",
+            Self::Min30 => "30 mins",
+            Self::Hour => "1 hour",
+            Self::Hour2 => "2 hours",
+            Self::Hour3 => "3 hours",
+            Self::Hour4 => "4 hours",
+            Self::Hour8 => "8 hours",
+            Self::Day => "1 day",
+            Self::Week => "1 week",
+            Self::Month => "1 month",
+        };
+
+        write!(f, "{text}")
     }
 }
 
@@ -126,9 +128,9 @@ impl Duration {
     }
 }
 
-impl ToString for Duration {
-    fn to_string(&self) -> String {
-        format!("{} {}", self.value, self.unit)
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
     }
 }
 
@@ -167,6 +169,7 @@ impl ToDuration for i32 {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 struct HistogramData {
     pub price: f64,
@@ -263,21 +266,27 @@ pub enum WhatToShow {
     OptionImpliedVolatility,
     FeeRate,
     Schedule,
-}
+    /// Last trade prices adjusted for splits and dividends, e.g. for total return backtests.
+    /// Only available for stocks. The request must end now, see [Client::historical_data_ending_now].
+    AdjustedLast,
+}
+
+impl fmt::Display for WhatToShow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::Trades => "TRADES",
+            Self::MidPoint => "MIDPOINT",
+            Self::Bid => "BID",
+            Self::Ask => "ASK",
+            Self::BidAsk => "BID_ASK",
+            Self::HistoricalVolatility => "HISTORICAL_VOLATILITY",
+            Self::OptionImpliedVolatility => "OPTION_IMPLIED_VOLATILITY",
+            Self::FeeRate => "FEE_RATE",
+            Self::Schedule => "SCHEDULE",
+            Self::AdjustedLast => "ADJUSTED_LAST",
+        };
 
-impl ToString for WhatToShow {
-    fn to_string(&self) -> String {
-        match self {
-            Self::Trades => "TRADES".to_string(),
-            Self::MidPoint => "MIDPOINT".to_string(),
-            Self::Bid => "BID".to_string(),
-            Self::Ask => "ASK".to_string(),
-            Self::BidAsk => "BID_ASK".to_string(),
-            Self::HistoricalVolatility => "HISTORICAL_VOLATILITY".to_string(),
-            Self::OptionImpliedVolatility => "OPTION_IMPLIED_VOLATILITY".to_string(),
-            Self::FeeRate => "FEE_RATE".to_string(),
-            Self::Schedule => "SCHEDULE".to_string(),
-        }
+        write!(f, "{text}")
     }
 }
 
@@ -304,11 +313,12 @@ pub(crate) fn head_timestamp(client: &Client, contract: &Contract, what_to_show:
     let request = encoders::encode_request_head_timestamp(request_id, contract, what_to_show, use_rth)?;
 
     let mut messages = client.send_request(request_id, request)?;
+    messages.set_cancel_message(encoders::encode_cancel_head_timestamp(request_id)?);
 
-    if let Some(mut message) = messages.next() {
+    if let Some(mut message) = messages.next_response()? {
         decoders::decode_head_timestamp(&mut message)
     } else {
-        Err(Error::Simple("did not receive head timestamp message".into()))
+        Err(Error::Disconnected)
     }
 }
 
@@ -337,6 +347,12 @@ pub(crate) fn historical_data(
         )?;
     }
 
+    if what_to_show == Some(WhatToShow::AdjustedLast) && end_date.is_some() {
+        return Err(Error::InvalidArgument(
+            "adjusted last data must be requested ending now, without an end date".into(),
+        ));
+    }
+
     if what_to_show == Some(WhatToShow::Schedule) {
         client.check_server_version(
             server_versions::HISTORICAL_SCHEDULE,
@@ -359,8 +375,9 @@ pub(crate) fn historical_data(
     )?;
 
     let mut messages = client.send_request(request_id, request)?;
+    messages.set_cancel_message(encoders::encode_cancel_historical_data(request_id)?);
 
-    if let Some(mut message) = messages.next() {
+    if let Some(mut message) = messages.next_response()? {
         let time_zone = if let Some(tz) = client.time_zone {
             tz
         } else {
@@ -369,14 +386,54 @@ pub(crate) fn historical_data(
         };
         match message.message_type() {
             IncomingMessages::HistoricalData => decoders::decode_historical_data(client.server_version, time_zone, &mut message),
-            IncomingMessages::Error => Err(Error::Simple(message.peek_string(4))),
-            _ => Err(Error::Simple(format!("unexpected message: {:?}", message.message_type()))),
+            message_type => Err(Error::UnexpectedResponse(message_type)),
         }
     } else {
-        Err(Error::Simple("did not receive historical data response".into()))
+        Err(Error::Disconnected)
     }
 }
 
+// Requests historical bars and keeps receiving updates to the most recent bar until the iterator is dropped.
+pub(crate) fn historical_data_streaming<'a>(
+    client: &'a Client,
+    contract: &Contract,
+    duration: Duration,
+    bar_size: BarSize,
+    what_to_show: Option<WhatToShow>,
+    use_rth: bool,
+) -> Result<HistoricalDataStreamingIterator<'a>, Error> {
+    if !contract.trading_class.is_empty() || contract.contract_id > 0 {
+        client.check_server_version(
+            server_versions::TRADING_CLASS,
+            "It does not support contract_id nor trading class parameters when requesting historical data.",
+        )?;
+    }
+
+    client.check_server_version(
+        server_versions::SYNT_REALTIME_BARS,
+        "It does not support keeping historical data up to date.",
+    )?;
+
+    let request_id = client.next_request_id();
+    let request = encoders::encode_request_historical_data(
+        client.server_version(),
+        request_id,
+        contract,
+        None,
+        duration,
+        bar_size,
+        what_to_show,
+        use_rth,
+        true,
+        Vec::<crate::contracts::TagValue>::default(),
+    )?;
+
+    let responses = client.send_request(request_id, request)?;
+    responses.set_cancel_message(encoders::encode_cancel_historical_data(request_id)?);
+
+    Ok(HistoricalDataStreamingIterator::new(client, request_id, responses))
+}
+
 pub(crate) fn historical_schedule(
     client: &Client,
     contract: &Contract,
@@ -410,15 +467,15 @@ pub(crate) fn historical_schedule(
     )?;
 
     let mut messages = client.send_request(request_id, request)?;
+    messages.set_cancel_message(encoders::encode_cancel_historical_data(request_id)?);
 
-    if let Some(mut message) = messages.next() {
+    if let Some(mut message) = messages.next_response()? {
         match message.message_type() {
             IncomingMessages::HistoricalSchedule => decoders::decode_historical_schedule(&mut message),
-            IncomingMessages::Error => Err(Error::Simple(message.peek_string(4))),
-            _ => Err(Error::Simple(format!("unexpected message: {:?}", message.message_type()))),
+            message_type => Err(Error::UnexpectedResponse(message_type)),
         }
     } else {
-        Err(Error::Simple("did not receive historical schedule response".into()))
+        Err(Error::Disconnected)
     }
 }
 
@@ -547,7 +604,7 @@ impl<T: TickDecoder<T> + Debug> Iterator for TickIterator<T> {
         }
 
         loop {
-            match self.messages.next() {
+            match self.messages.next_or_end() {
                 Some(mut message) => {
                     if message.message_type() == Self::Item::message_type() {
                         let (ticks, done) = Self::Item::decode(&mut message).unwrap();
@@ -562,9 +619,6 @@ impl<T: TickDecoder<T> + Debug> Iterator for TickIterator<T> {
                         if !self.buffer.is_empty() {
                             return self.buffer.pop_front();
                         }
-                    } else if message.message_type() == IncomingMessages::Error {
-                        error!("error reading ticks: {:?}", message.peek_string(4));
-                        return None;
                     } else {
                         error!("unexpected message: {:?}", message)
                     }
@@ -575,4 +629,91 @@ impl<T: TickDecoder<T> + Debug> Iterator for TickIterator<T> {
     }
 }
 
+/// HistoricalDataStreamingIterator supports iteration over the initial historical [Bar]s followed by updates to the most recent bar.
+pub(crate) struct HistoricalDataStreamingIterator<'a> {
+    client: &'a Client,
+    request_id: i32,
+    responses: ResponseIterator,
+    buffer: VecDeque<Bar>,
+}
+
+impl<'a> HistoricalDataStreamingIterator<'a> {
+    fn new(client: &'a Client, request_id: i32, responses: ResponseIterator) -> HistoricalDataStreamingIterator<'a> {
+        HistoricalDataStreamingIterator {
+            client,
+            request_id,
+            responses,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    fn time_zone(&self) -> &'static Tz {
+        if let Some(tz) = self.client.time_zone {
+            tz
+        } else {
+            warn!("server timezone unknown. assuming UTC, but that may be incorrect!");
+            time_tz::timezones::db::UTC
+        }
+    }
+
+    /// Cancels request to keep historical data up to date
+    fn cancel_historical_data(&mut self) {
+        // the client may be shut down, or in its maintenance window
+        let message = encoders::encode_cancel_historical_data(self.request_id);
+        if let Err(e) = message.and_then(|message| self.client.send_message(message)) {
+            warn!("error cancelling historical data request {}: {e}", self.request_id);
+        }
+    }
+}
+
+impl<'a> Iterator for HistoricalDataStreamingIterator<'a> {
+    type Item = Bar;
+
+    /// Advances the iterator and returns the next value.
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.buffer.is_empty() {
+            return self.buffer.pop_front();
+        }
+
+        loop {
+            let mut message = self.responses.next_or_end()?;
+
+            match message.message_type() {
+                IncomingMessages::HistoricalData => {
+                    match decoders::decode_historical_data(self.client.server_version, self.time_zone(), &mut message) {
+                        Ok(historical_data) => {
+                            self.buffer.append(&mut historical_data.bars.into());
+                            if !self.buffer.is_empty() {
+                                return self.buffer.pop_front();
+                            }
+                        }
+                        Err(err) => {
+                            error!("error decoding historical data: {err}");
+                            return None;
+                        }
+                    }
+                }
+                IncomingMessages::HistoricalDataUpdate => match decoders::decode_historical_data_update(self.time_zone(), &mut message) {
+                    Ok(bar) => return Some(bar),
+                    Err(err) => {
+                        error!("error decoding historical data update: {err}");
+                        return None;
+                    }
+                },
+                _ => error!("unexpected message: {message:?}"),
+            }
+        }
+    }
+}
+
+impl<'a> Drop for HistoricalDataStreamingIterator<'a> {
+    // cancelled requests were already cancelled with the server
+    fn drop(&mut self) {
+        if !self.responses.is_cancelled() {
+            self.cancel_historical_data()
+        }
+    }
+}
+
+#[allow(dead_code)]
 struct HistogramDataIterator {}
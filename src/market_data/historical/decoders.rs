@@ -73,6 +73,33 @@ pub(super) fn decode_historical_data(server_version: i32, time_zone: &Tz, messag
     Ok(HistoricalData { start, end, bars })
 }
 
+// HistoricalDataUpdate carries a single bar with no date range header. Note that
+// bar count precedes the date and close precedes high and low.
+pub(super) fn decode_historical_data_update(time_zone: &Tz, message: &mut ResponseMessage) -> Result<Bar, Error> {
+    message.skip(); // message type
+    message.skip(); // request_id
+
+    let count = message.next_int()?;
+    let date = message.next_string()?;
+    let open = message.next_double()?;
+    let close = message.next_double()?;
+    let high = message.next_double()?;
+    let low = message.next_double()?;
+    let wap = message.next_double()?;
+    let volume = message.next_double()?;
+
+    Ok(Bar {
+        date: parse_bar_date(&date, time_zone)?,
+        open,
+        high,
+        low,
+        close,
+        volume,
+        wap,
+        count,
+    })
+}
+
 pub(super) fn decode_historical_schedule(message: &mut ResponseMessage) -> Result<Schedule, Error> {
     message.skip(); // message type
     message.skip(); // request_id
@@ -241,14 +268,14 @@ mod tests {
 
     #[test]
     fn test_decode_head_timestamp() {
-        let mut message = ResponseMessage::from("88\09000\01560346200\0");
+        let mut message = ResponseMessage::from("88\x009000\x001560346200\x00");
 
         let results = super::decode_head_timestamp(&mut message);
 
         if let Ok(head_timestamp) = results {
             assert_eq!(head_timestamp, datetime!(2019-06-12 13:30).assume_utc(), "head_timestamp");
         } else if let Err(err) = results {
-            assert!(false, "error decoding trade tick: {err}");
+            panic!("error decoding trade tick: {err}");
         }
     }
 
@@ -256,8 +283,9 @@ mod tests {
     fn test_decode_historical_schedule() {
         let time_zone: &Tz = time_tz::timezones::db::america::NEW_YORK;
 
-        let mut message =
-            ResponseMessage::from("106\09000\020230414-09:30:00\020230414-16:00:00\0US/Eastern\01\020230414-09:30:00\020230414-16:00:00\020230414\0");
+        let mut message = ResponseMessage::from(
+            "106\x009000\x0020230414-09:30:00\x0020230414-16:00:00\x00US/Eastern\x001\x0020230414-09:30:00\x0020230414-16:00:00\x0020230414\x00",
+        );
 
         let results = decode_historical_schedule(&mut message);
 
@@ -287,13 +315,13 @@ mod tests {
                 "schedule.sessions[0].end"
             );
         } else if let Err(err) = results {
-            assert!(false, "error decoding historical schedule {err}");
+            panic!("error decoding historical schedule {err}");
         }
     }
 
     #[test]
     fn test_decode_historical_data() {
-        let mut message = ResponseMessage::from("17\09000\020230413  16:31:22\020230415  16:31:22\02\020230413\0182.9400\0186.5000\0180.9400\0185.9000\0948837.22\0184.869\0324891\020230414\0183.8800\0186.2800\0182.0100\0185.0000\0810998.27\0183.9865\0277547\0");
+        let mut message = ResponseMessage::from("17\x009000\x0020230413  16:31:22\x0020230415  16:31:22\x002\x0020230413\x00182.9400\x00186.5000\x00180.9400\x00185.9000\x00948837.22\x00184.869\x00324891\x0020230414\x00183.8800\x00186.2800\x00182.0100\x00185.0000\x00810998.27\x00183.9865\x00277547\x00");
 
         let server_version = server_versions::HISTORICAL_SCHEDULE;
         let time_zone: &Tz = time_tz::timezones::db::america::NEW_YORK;
@@ -326,19 +354,38 @@ mod tests {
             assert_eq!(historical_data.bars[0].wap, 184.869, "historical_data.bars[0].wap");
             assert_eq!(historical_data.bars[0].count, 324891, "historical_data.bars[0].count");
         } else if let Err(err) = results {
-            assert!(false, "error decoding historical data {err}");
+            panic!("error decoding historical data {err}");
         }
     }
 
+    #[test]
+    fn test_decode_historical_data_update() {
+        let mut message =
+            ResponseMessage::from("90\x009000\x00312\x001681488000\x00185.0000\x00185.4100\x00185.6200\x00184.9100\x00185.2374\x001436.51\x00");
+
+        let time_zone: &Tz = time_tz::timezones::db::america::NEW_YORK;
+
+        let bar = decode_historical_data_update(time_zone, &mut message).expect("error decoding historical data update");
+
+        assert_eq!(bar.date, datetime!(2023-04-14 16:00:00 UTC), "bar.date");
+        assert_eq!(bar.open, 185.00, "bar.open");
+        assert_eq!(bar.high, 185.62, "bar.high");
+        assert_eq!(bar.low, 184.91, "bar.low");
+        assert_eq!(bar.close, 185.41, "bar.close");
+        assert_eq!(bar.volume, 1436.51, "bar.volume");
+        assert_eq!(bar.wap, 185.2374, "bar.wap");
+        assert_eq!(bar.count, 312, "bar.count");
+    }
+
     #[test]
     fn test_decode_historical_tick_bid_ask() {
-        let sample_message = "97\09000\04\01681133399\00\011.63\011.83\02800\0100\01681133400\00\011.63\011.83\02800\0200\01681133400\00\011.63\011.72\02800\0100\01681133400\00\011.63\011.83\02800\0200\01\0";
+        let sample_message = "97\x009000\x004\x001681133399\x000\x0011.63\x0011.83\x002800\x00100\x001681133400\x000\x0011.63\x0011.83\x002800\x00200\x001681133400\x000\x0011.63\x0011.72\x002800\x00100\x001681133400\x000\x0011.63\x0011.83\x002800\x00200\x001\x00";
         let mut message = ResponseMessage::from(sample_message);
 
         let (ticks, done) = decode_historical_ticks_bid_ask(&mut message).unwrap();
 
         assert_eq!(ticks.len(), 4, "ticks.len()");
-        assert_eq!(done, true, "done");
+        assert!(done, "done");
 
         assert_eq!(ticks[0].timestamp, datetime!(2023-04-10 13:29:59 UTC), "ticks[0].timestamp");
         assert_eq!(
@@ -371,13 +418,13 @@ mod tests {
 
     #[test]
     fn test_decode_historical_tick_last() {
-        let sample_message = "98\09000\07\01681133400\00\011.63\024547\0ISLAND\0 O X\01681133400\02\011.73\01\0DRCTEDGE\0   I\01681133401\00\011.63\0179\0FINRA\0\01681133401\02\011.73\01\0FINRA\0   I\01681133402\02\011.63\01\0FINRA\0 4 I\01681133402\02\011.73\01\0FINRA\0   I\01681133402\02\011.73\01\0FINRA\0   I\01\0";
+        let sample_message = "98\x009000\x007\x001681133400\x000\x0011.63\x0024547\x00ISLAND\x00 O X\x001681133400\x002\x0011.73\x001\x00DRCTEDGE\x00   I\x001681133401\x000\x0011.63\x00179\x00FINRA\x00\x001681133401\x002\x0011.73\x001\x00FINRA\x00   I\x001681133402\x002\x0011.63\x001\x00FINRA\x00 4 I\x001681133402\x002\x0011.73\x001\x00FINRA\x00   I\x001681133402\x002\x0011.73\x001\x00FINRA\x00   I\x001\x00";
         let mut message = ResponseMessage::from(sample_message);
 
         let (ticks, done) = decode_historical_ticks_last(&mut message).unwrap();
 
         assert_eq!(ticks.len(), 7, "ticks.len()");
-        assert_eq!(done, true, "done");
+        assert!(done, "done");
 
         assert_eq!(ticks[0].timestamp, datetime!(2023-04-10 13:30:0 UTC), "ticks[0].timestamp");
         assert_eq!(
@@ -410,13 +457,13 @@ mod tests {
 
     #[test]
     fn test_decode_historical_tick_midpoint() {
-        let sample_message = "96\09000\024\01681133398\00\091.36\00\01681133400\00\091.355\00\01681133400\00\091.35\00\01681133400\00\091.345\00\01681133400\00\091.35\00\01681133400\00\091.355\00\01681133400\00\091.35\00\01681133400\00\091.34\00\01681133400\00\091.345\00\01681133400\00\091.34\00\01681133400\00\091.345\00\01681133400\00\091.34\00\01681133400\00\091.335\00\01681133400\00\091.33\00\01681133400\00\091.325\00\01681133400\00\091.32\00\01681133400\00\091.325\00\01681133400\00\091.32\00\01681133400\00\091.315\00\01681133400\00\091.32\00\01681133400\00\091.325\00\01681133400\00\091.32\00\01681133400\00\091.315\00\01681133400\00\091.31\00\01\0";
+        let sample_message = "96\x009000\x0024\x001681133398\x000\x0091.36\x000\x001681133400\x000\x0091.355\x000\x001681133400\x000\x0091.35\x000\x001681133400\x000\x0091.345\x000\x001681133400\x000\x0091.35\x000\x001681133400\x000\x0091.355\x000\x001681133400\x000\x0091.35\x000\x001681133400\x000\x0091.34\x000\x001681133400\x000\x0091.345\x000\x001681133400\x000\x0091.34\x000\x001681133400\x000\x0091.345\x000\x001681133400\x000\x0091.34\x000\x001681133400\x000\x0091.335\x000\x001681133400\x000\x0091.33\x000\x001681133400\x000\x0091.325\x000\x001681133400\x000\x0091.32\x000\x001681133400\x000\x0091.325\x000\x001681133400\x000\x0091.32\x000\x001681133400\x000\x0091.315\x000\x001681133400\x000\x0091.32\x000\x001681133400\x000\x0091.325\x000\x001681133400\x000\x0091.32\x000\x001681133400\x000\x0091.315\x000\x001681133400\x000\x0091.31\x000\x001\x00";
         let mut message = ResponseMessage::from(sample_message);
 
         let (ticks, done) = decode_historical_ticks_mid_point(&mut message).unwrap();
 
         assert_eq!(ticks.len(), 24, "ticks.len()");
-        assert_eq!(done, true, "done");
+        assert!(done, "done");
 
         assert_eq!(ticks[0].timestamp, datetime!(2023-04-10 13:29:58 UTC), "ticks[0].timestamp");
         assert_eq!(ticks[0].price, 91.36, "ticks[0].price");
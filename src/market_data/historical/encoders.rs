@@ -26,7 +26,7 @@ impl ToField for Option<OffsetDateTime> {
 }
 
 // Encodes the head timestamp request
-pub(super) fn encode_request_head_timestamp(
+pub(crate) fn encode_request_head_timestamp(
     request_id: i32,
     contract: &Contract,
     what_to_show: WhatToShow,
@@ -46,7 +46,7 @@ pub(super) fn encode_request_head_timestamp(
 
 // Encodes the historical data request
 #[allow(clippy::too_many_arguments)]
-pub(super) fn encode_request_historical_data(
+pub(crate) fn encode_request_historical_data(
     server_version: i32,
     request_id: i32,
     contract: &Contract,
@@ -58,15 +58,9 @@ pub(super) fn encode_request_historical_data(
     keep_up_to_data: bool,
     chart_options: Vec<crate::contracts::TagValue>,
 ) -> Result<RequestMessage, Error> {
-    const VERSION: i32 = 6;
-
     let mut message = RequestMessage::default();
 
-    message.push_field(&OutgoingMessages::RequestHistoricalData);
-
-    if server_version < server_versions::SYNT_REALTIME_BARS {
-        message.push_field(&VERSION);
-    }
+    message.push_message_header(OutgoingMessages::RequestHistoricalData, server_version);
 
     message.push_field(&request_id);
 
@@ -122,8 +116,32 @@ pub(super) fn encode_request_historical_data(
     Ok(message)
 }
 
+// Encodes the cancel historical data request
+pub(crate) fn encode_cancel_historical_data(request_id: i32) -> Result<RequestMessage, Error> {
+    const VERSION: i32 = 1;
+
+    let mut message = RequestMessage::default();
+
+    message.push_field(&OutgoingMessages::CancelHistoricalData);
+    message.push_field(&VERSION);
+    message.push_field(&request_id);
+
+    Ok(message)
+}
+
+// Encodes the cancel head timestamp request
+pub(crate) fn encode_cancel_head_timestamp(request_id: i32) -> Result<RequestMessage, Error> {
+    let mut message = RequestMessage::default();
+
+    message.push_field(&OutgoingMessages::CancelHeadTimestamp);
+    message.push_field(&request_id);
+
+    Ok(message)
+}
+
 // Encodes message to request historical ticks
-pub(super) fn encode_request_historical_ticks(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn encode_request_historical_ticks(
     request_id: i32,
     contract: &Contract,
     start: Option<OffsetDateTime>,
@@ -193,11 +211,18 @@ mod tests {
                 assert_eq!(message[17], DATE_FORMAT.to_field(), "message.date_format");
             }
             Err(err) => {
-                assert!(false, "error encoding head_timestamp request: {err}");
+                panic!("error encoding head_timestamp request: {err}");
             }
         }
     }
 
+    #[test]
+    fn test_encode_cancel_head_timestamp() {
+        let message = encode_cancel_head_timestamp(9000).expect("error encoding cancel head timestamp");
+
+        assert_eq!(message.encode_simple(), "90|9000|");
+    }
+
     #[test]
     fn test_encode_request_historical_data() {
         let request_id = 9000;
@@ -271,7 +296,7 @@ mod tests {
                 assert_eq!(message[i + 1], "", "message.chart_options");
             }
             Err(err) => {
-                assert!(false, "error encoding historical data request: {err}");
+                panic!("error encoding historical data request: {err}");
             }
         }
     }
@@ -331,7 +356,7 @@ mod tests {
                 assert_eq!(message[21], "", "message.misc_options");
             }
             Err(err) => {
-                assert!(false, "error encoding historical ticks request: {err}");
+                panic!("error encoding historical ticks request: {err}");
             }
         }
     }
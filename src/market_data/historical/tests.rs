@@ -1,19 +1,21 @@
 use std::cell::RefCell;
+use std::sync::Mutex;
 
 use time::macros::datetime;
 
+use crate::client::transport::MessageBus;
 use crate::market_data::historical::ToDuration;
 use crate::messages::OutgoingMessages;
-use crate::stubs::MessageBusStub;
+use crate::stubs::{MessageBusStub, RequestMessages};
 
 use super::*;
 
 #[test]
 fn test_head_timestamp() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec!["9|9000|1678323335|".to_owned()],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
@@ -27,7 +29,7 @@ fn test_head_timestamp() {
 
     assert_eq!(head_timestamp, OffsetDateTime::from_unix_timestamp(1678323335).unwrap(), "bar.date");
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     let head_timestamp_request = &request_messages[0];
     assert_eq!(
@@ -70,12 +72,12 @@ fn test_histogram_data() {
 
 #[test]
 fn test_historical_data() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec![
-            "17\09000\020230413  16:31:22\020230415  16:31:22\02\020230413\0182.9400\0186.5000\0180.9400\0185.9000\0948837.22\0184.869\0324891\020230414\0183.8800\0186.2800\0182.0100\0185.0000\0810998.27\0183.9865\0277547\0".to_owned()
+            "17\x009000\x0020230413  16:31:22\x0020230415  16:31:22\x002\x0020230413\x00182.9400\x00186.5000\x00180.9400\x00185.9000\x00948837.22\x00184.869\x00324891\x0020230414\x00183.8800\x00186.2800\x00182.0100\x00185.0000\x00810998.27\x00183.9865\x00277547\x00".to_owned()
         ],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
@@ -107,7 +109,7 @@ fn test_historical_data() {
 
     // Assert Request
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     let head_timestamp_request = &request_messages[0];
     assert_eq!(
@@ -147,6 +149,51 @@ fn test_historical_data() {
     assert_eq!(head_timestamp_request[22], "", "message.chart_options");
 }
 
+#[test]
+fn test_historical_data_streaming() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "17\x009000\x0020230413  16:31:22\x0020230415  16:31:22\x002\x0020230413\x00182.9400\x00186.5000\x00180.9400\x00185.9000\x00948837.22\x00184.869\x00324891\x0020230414\x00183.8800\x00186.2800\x00182.0100\x00185.0000\x00810998.27\x00183.9865\x00277547\x00".to_owned(),
+            "90\x009000\x00312\x001681488000\x00185.0000\x00185.4100\x00185.6200\x00184.9100\x00185.2374\x001436.51\x00".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("MSFT");
+
+    let bars: Vec<Bar> = client
+        .historical_data_streaming(&contract, 2.days(), BarSize::Hour, WhatToShow::Trades, true)
+        .expect("historical data request failed")
+        .collect();
+
+    // Assert Response
+
+    assert_eq!(bars.len(), 3, "bars.len()");
+    assert_eq!(bars[1].close, 185.00, "bars[1].close");
+    assert_eq!(bars[2].date, datetime!(2023-04-14 16:00:00 UTC), "bars[2].date");
+    assert_eq!(bars[2].high, 185.62, "bars[2].high");
+    assert_eq!(bars[2].low, 184.91, "bars[2].low");
+    assert_eq!(bars[2].close, 185.41, "bars[2].close");
+    assert_eq!(bars[2].count, 312, "bars[2].count");
+
+    // Assert Request
+
+    let request_messages = client.message_bus().request_messages();
+
+    assert_eq!(request_messages.len(), 2, "request_messages.len()");
+    assert_eq!(
+        request_messages[0][0],
+        OutgoingMessages::RequestHistoricalData.to_field(),
+        "message.message_type"
+    );
+    assert_eq!(request_messages[0][15], "", "message.interval_end");
+    assert_eq!(request_messages[0][21], "1", "message.keep_up_to_data");
+
+    assert_eq!(request_messages[1].encode_simple(), "25|1|9000|", "cancel historical data");
+}
+
 #[test]
 fn test_bar_size() {
     assert_eq!(BarSize::Sec.to_string(), "1 sec");
@@ -174,6 +221,71 @@ fn test_what_to_show() {
     assert_eq!(WhatToShow::OptionImpliedVolatility.to_string(), "OPTION_IMPLIED_VOLATILITY");
     assert_eq!(WhatToShow::FeeRate.to_string(), "FEE_RATE");
     assert_eq!(WhatToShow::Schedule.to_string(), "SCHEDULE");
+    assert_eq!(WhatToShow::AdjustedLast.to_string(), "ADJUSTED_LAST");
+}
+
+#[test]
+fn test_head_timestamp_without_response() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let result = client.head_timestamp(&Contract::stock("MSFT"), WhatToShow::Trades, true);
+    assert!(matches!(result, Err(Error::Disconnected)), "{result:?}");
+}
+
+#[test]
+fn test_historical_data_unexpected_response() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["88|9000|1678323335|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let result = client.historical_data(
+        &Contract::stock("MSFT"),
+        datetime!(2023-04-15 16:31:22 UTC),
+        2.days(),
+        BarSize::Hour,
+        WhatToShow::Trades,
+        true,
+    );
+    assert!(
+        matches!(result, Err(Error::UnexpectedResponse(IncomingMessages::HeadTimestamp))),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn test_adjusted_last_requires_no_end_date() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("MSFT");
+
+    let result = client.historical_data(
+        &contract,
+        datetime!(2023-04-15 0:00 UTC),
+        7.days(),
+        BarSize::Day,
+        WhatToShow::AdjustedLast,
+        true,
+    );
+    assert!(
+        matches!(result, Err(Error::InvalidArgument(_))),
+        "expected adjusted last with an end date to fail: {result:?}"
+    );
+
+    let request_messages = client.message_bus().request_messages();
+    assert!(request_messages.is_empty(), "no request should be sent");
 }
 
 #[test]
@@ -1,19 +1,24 @@
-use log::error;
+use std::fmt;
+
+use log::{error, warn};
 use time::OffsetDateTime;
 
 use crate::client::transport::ResponseIterator;
-use crate::contracts::Contract;
-use crate::messages::IncomingMessages;
-use crate::orders::TagValue;
+use crate::contracts::{Contract, SecurityType, TagValue};
+use crate::errors::ErrorKind;
+use crate::messages::{IncomingMessages, ResponseMessage};
 use crate::server_versions;
 use crate::ToField;
 use crate::{Client, Error};
 
-mod decoders;
-mod encoders;
+pub(crate) mod decoders;
+pub(crate) mod encoders;
+mod order_book;
 #[cfg(test)]
 mod tests;
 
+pub use order_book::{BookChange, BookLevel, OrderBook};
+
 // === Models ===
 
 #[derive(Clone, Debug, Copy)]
@@ -48,12 +53,103 @@ pub struct BidAsk {
     pub bid_ask_attribute: BidAskAttribute,
 }
 
-#[derive(Debug)]
+/// Attributes of a tick-by-tick bid/ask tick.
+#[derive(Debug, PartialEq)]
 pub struct BidAskAttribute {
+    /// Bid price is lower than the day's lowest value.
     pub bid_past_low: bool,
+    /// Ask price is higher than the day's highest value.
     pub ask_past_high: bool,
 }
 
+impl From<u8> for BidAskAttribute {
+    // Unpacks the bitmask. Bit 0 - bid past low, bit 1 - ask past high.
+    fn from(mask: u8) -> Self {
+        BidAskAttribute {
+            bid_past_low: mask & 0x1 != 0,
+            ask_past_high: mask & 0x2 != 0,
+        }
+    }
+}
+
+/// Attributes of a market data price tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TickAttribute {
+    /// The bid or ask can be executed automatically.
+    pub can_auto_execute: bool,
+    /// The bid is lower than the day's lowest value or the ask is higher than the day's highest value.
+    pub past_limit: bool,
+    /// The bid or ask was quoted in the pre-open session.
+    pub pre_open: bool,
+}
+
+impl From<u8> for TickAttribute {
+    // Unpacks the bitmask. Bit 0 - can auto execute, bit 1 - past limit, bit 2 - pre open.
+    fn from(mask: u8) -> Self {
+        TickAttribute {
+            can_auto_execute: mask & 0x1 != 0,
+            past_limit: mask & 0x2 != 0,
+            pre_open: mask & 0x4 != 0,
+        }
+    }
+}
+
+/// Top of book for a contract, assembled from market data ticks until the server signals the end of the snapshot.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarketSnapshot {
+    pub bid: Option<f64>,
+    pub bid_size: Option<f64>,
+    pub ask: Option<f64>,
+    pub ask_size: Option<f64>,
+    /// Last traded price. Never populated for forex, which is quoted on bid and ask only.
+    pub last: Option<f64>,
+    pub last_size: Option<f64>,
+    /// Day's volume. Never populated for forex.
+    pub volume: Option<f64>,
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub close: Option<f64>,
+    /// Time of the last trade. Never populated for forex.
+    pub timestamp: Option<OffsetDateTime>,
+    /// True for regulatory snapshots, which are billed per request.
+    pub billed: bool,
+}
+
+impl MarketSnapshot {
+    /// Midpoint between the bid and ask, when both sides are quoted.
+    pub fn midpoint(&self) -> Option<f64> {
+        match (self.bid, self.ask) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / 2.0),
+            _ => None,
+        }
+    }
+
+    /// Best available price: the last trade, falling back to the bid/ask midpoint for contracts, like forex, that don't report trades.
+    pub fn price(&self) -> Option<f64> {
+        self.last.or_else(|| self.midpoint())
+    }
+
+    // Folds a price or size tick into the snapshot. Delayed ticks are treated as their real-time equivalents.
+    // Trade ticks are ignored when the contract is only quoted.
+    fn update(&mut self, tick_type: TickType, value: Option<f64>, quoted_only: bool) {
+        match tick_type.normalized() {
+            TickType::BidSize => self.bid_size = value,
+            TickType::Bid => self.bid = value,
+            TickType::Ask => self.ask = value,
+            TickType::AskSize => self.ask_size = value,
+            TickType::Last if !quoted_only => self.last = value,
+            TickType::LastSize if !quoted_only => self.last_size = value,
+            TickType::High => self.high = value,
+            TickType::Low => self.low = value,
+            TickType::Volume if !quoted_only => self.volume = value,
+            TickType::Close => self.close = value,
+            TickType::Open => self.open = value,
+            _ => {}
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct MidPoint {
     /// The trade's date and time (either as a yyyymmss hh:mm:ss formatted string or as system time according to the request). Time zone is the TWS time zone chosen on login.
@@ -76,7 +172,7 @@ pub struct Bar {
 
 #[derive(Debug)]
 pub struct Trade {
-    /// Tick type: "Last" or "AllLast"
+    /// Tick type: "1" for Last or "2" for AllLast ticks.
     pub tick_type: String,
     /// The trade's date and time (either as a yyyymmss hh:mm:ss formatted string or as system time according to the request). Time zone is the TWS time zone chosen on login.
     pub time: OffsetDateTime,
@@ -86,18 +182,31 @@ pub struct Trade {
     pub size: i64,
     /// Tick attribs (bit 0 - past limit, bit 1 - unreported)
     pub trade_attribute: TradeAttribute,
-    /// Tick exchange
+    /// Tick exchange. For SMART routed contracts a single letter code, which maps to a venue name via the SMART components of the exchange.
     pub exchange: String,
-    /// Tick special conditions
+    /// Tick special conditions. Space separated trade condition codes, e.g. odd lot or out of sequence trades.
     pub special_conditions: String,
 }
 
-#[derive(Debug)]
+/// Attributes of a tick-by-tick trade tick.
+#[derive(Debug, PartialEq)]
 pub struct TradeAttribute {
+    /// Trade occurred outside the limit price.
     pub past_limit: bool,
+    /// Trade was not reported to the tape.
     pub unreported: bool,
 }
 
+impl From<u8> for TradeAttribute {
+    // Unpacks the bitmask. Bit 0 - past limit, bit 1 - unreported.
+    fn from(mask: u8) -> Self {
+        TradeAttribute {
+            past_limit: mask & 0x1 != 0,
+            unreported: mask & 0x2 != 0,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Copy)]
 pub enum WhatToShow {
     Trades,
@@ -106,14 +215,16 @@ pub enum WhatToShow {
     Ask,
 }
 
-impl ToString for WhatToShow {
-    fn to_string(&self) -> String {
-        match self {
-            Self::Trades => "TRADES".to_string(),
-            Self::MidPoint => "MIDPOINT".to_string(),
-            Self::Bid => "BID".to_string(),
-            Self::Ask => "ASK".to_string(),
-        }
+impl fmt::Display for WhatToShow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::Trades => "TRADES",
+            Self::MidPoint => "MIDPOINT",
+            Self::Bid => "BID",
+            Self::Ask => "ASK",
+        };
+
+        write!(f, "{text}")
     }
 }
 
@@ -123,6 +234,430 @@ impl ToField for WhatToShow {
     }
 }
 
+/// Type of the market data returned by market data requests. See [Client::set_market_data_type].
+///
+/// Accounts without a live market data subscription can request delayed data. Frozen data is the last data recorded when the market closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarketDataType {
+    /// Real-time streaming data. Requires a market data subscription.
+    Realtime = 1,
+    /// The last data recorded at market close.
+    Frozen = 2,
+    /// Data delayed by 15-20 minutes, available without a market data subscription.
+    Delayed = 3,
+    /// Delayed data, frozen at market close.
+    DelayedFrozen = 4,
+}
+
+impl ToField for MarketDataType {
+    fn to_field(&self) -> String {
+        (*self as i32).to_string()
+    }
+}
+
+impl TryFrom<i32> for MarketDataType {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(MarketDataType::Realtime),
+            2 => Ok(MarketDataType::Frozen),
+            3 => Ok(MarketDataType::Delayed),
+            4 => Ok(MarketDataType::DelayedFrozen),
+            _ => Err(Error::InvalidResponse(format!("unknown market data type: {value}"))),
+        }
+    }
+}
+
+/// An update to the order book of a contract. See [Client::market_depth].
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarketDepthUpdate {
+    /// An update to the book of a single exchange.
+    MarketDepth(MarketDepth),
+    /// An update to the book of a single exchange or to an aggregated book, carrying the market maker or exchange quoting.
+    MarketDepthL2(MarketDepthL2),
+    /// The server reset the book. Rows received before are discarded and the book is rebuilt by the updates that follow.
+    Reset,
+}
+
+/// An update to the order book of a single exchange.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarketDepth {
+    /// The order book's row being updated.
+    pub position: i32,
+    /// How to apply the update to the row.
+    pub operation: DepthOperation,
+    /// Side of the book the row belongs to.
+    pub side: DepthSide,
+    /// The order's price.
+    pub price: f64,
+    /// The order's size.
+    pub size: f64,
+}
+
+/// An update to the order book, carrying the market maker or exchange quoting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarketDepthL2 {
+    /// The order book's row being updated.
+    pub position: i32,
+    /// The exchange holding the order if smart depth was requested, otherwise the market maker id.
+    pub market_maker: String,
+    /// How to apply the update to the row.
+    pub operation: DepthOperation,
+    /// Side of the book the row belongs to.
+    pub side: DepthSide,
+    /// The order's price.
+    pub price: f64,
+    /// The order's size.
+    pub size: f64,
+    /// Whether the update belongs to an aggregated book of all exchanges.
+    pub smart_depth: bool,
+}
+
+/// An exchange offering market depth. See [Client::market_depth_exchanges].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DepthMarketDataDescription {
+    /// The exchange name.
+    pub exchange: String,
+    /// The security type the exchange offers market depth for.
+    pub security_type: SecurityType,
+    /// The listing exchange name.
+    pub listing_exchange: String,
+    /// "Deep" for level I or "Deep2" for level II market depth.
+    pub service_data_type: String,
+    /// The aggregated group the exchange belongs to in smart depth books. None when the exchange is not aggregated.
+    pub aggregated_group: Option<i32>,
+}
+
+/// How a [MarketDepth] update applies to its row of the order book.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthOperation {
+    /// Inserts the row, shifting the rows below it down.
+    Insert,
+    /// Replaces the row.
+    Update,
+    /// Removes the row, shifting the rows below it up.
+    Delete,
+}
+
+impl TryFrom<i32> for DepthOperation {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(DepthOperation::Insert),
+            1 => Ok(DepthOperation::Update),
+            2 => Ok(DepthOperation::Delete),
+            _ => Err(Error::InvalidResponse(format!("unknown depth operation: {value}"))),
+        }
+    }
+}
+
+/// Side of the order book.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthSide {
+    Ask,
+    Bid,
+}
+
+impl TryFrom<i32> for DepthSide {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(DepthSide::Ask),
+            1 => Ok(DepthSide::Bid),
+            _ => Err(Error::InvalidResponse(format!("unknown depth side: {value}"))),
+        }
+    }
+}
+
+/// A tick received on a market data subscription. See [Client::market_data].
+#[derive(Clone, Debug, PartialEq)]
+pub enum TickEvent {
+    /// A price tick. The price is none when the server reports no data for the tick type.
+    Price {
+        tick_type: TickType,
+        price: Option<f64>,
+        attributes: TickAttribute,
+    },
+    /// A size tick.
+    Size { tick_type: TickType, size: f64 },
+    /// A tick carrying text, such as the last trade timestamp.
+    String { tick_type: TickType, value: String },
+    /// Last trade details, received for the [RTVolume](GenericTick::RTVolume) and [RTTradeVolume](GenericTick::RTTradeVolume) generic ticks.
+    RTVolume { tick_type: TickType, volume: RTVolume },
+    /// A tick carrying a single value requested as a generic tick, such as shortable shares.
+    Generic { tick_type: TickType, value: f64 },
+    /// Parameters of the subscription, received before its first ticks.
+    RequestParameters(TickRequestParameters),
+    /// Type of the data that follows, e.g. delayed data when no live data is available for the contract.
+    MarketDataType(MarketDataType),
+    /// All ticks of a snapshot were received. The last event of a snapshot subscription.
+    SnapshotEnd,
+}
+
+/// Parameters of a market data subscription, received as [TickEvent::RequestParameters].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickRequestParameters {
+    /// Minimum price increment of the contract. Prices are rendered at its precision.
+    pub min_tick: f64,
+    /// Exchange code of the best bid and offer. Maps to an exchange name via the smart components of the exchange.
+    pub bbo_exchange: String,
+    /// Market data permissions required for snapshots of the contract.
+    pub snapshot_permissions: i32,
+}
+
+/// Last trade details and the day's volume, as reported by the RTVolume and RTTradeVolume ticks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RTVolume {
+    /// Price of the last trade. None for updates only reporting volume.
+    pub last_price: Option<f64>,
+    /// Size of the last trade. None for updates only reporting volume.
+    pub last_size: Option<f64>,
+    /// Time of the last trade.
+    pub time: OffsetDateTime,
+    /// The day's volume.
+    pub total_volume: f64,
+    /// The day's volume weighted average price.
+    pub vwap: f64,
+    /// Whether the trade was filled by a single market maker.
+    pub single_trade: bool,
+}
+
+/// Additional ticks to request with market data. See [Client::market_data].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GenericTick {
+    /// Call and put option volume, for stocks.
+    OptionVolume,
+    /// Call and put option open interest, for stocks.
+    OptionOpenInterest,
+    /// 30-day historical volatility, for stocks.
+    HistoricalVolatility,
+    /// Average option volume over 90 days, for stocks.
+    AverageOptionVolume,
+    /// 30-day implied volatility, for stocks.
+    OptionImpliedVolatility,
+    /// Index future premium.
+    IndexFuturePremium,
+    /// 13, 26 and 52 week highs and lows, and the 90 day average volume.
+    MiscellaneousStats,
+    /// Mark price used in TWS P&L computations.
+    MarkPrice,
+    /// Auction volume, price and imbalance.
+    AuctionValues,
+    /// Last trade price, size and time, with the day's volume and VWAP, in a single string.
+    RTVolume,
+    /// Shortable shares indicator.
+    Shortable,
+    /// Inventory of shares available to short.
+    Inventory,
+    /// Fundamental ratios.
+    FundamentalRatios,
+    /// Number of trades of the day.
+    TradeCount,
+    /// Trades per minute.
+    TradeRate,
+    /// Shares traded per minute.
+    VolumeRate,
+    /// Last trade of the regular trading hours.
+    LastRTHTrade,
+    /// Last trade price, size and time, excluding unreportable trades, in a single string.
+    RTTradeVolume,
+    /// Historical volatility computed in real time.
+    RTHistoricalVolatility,
+    /// Dividends of the past and next 12 months, with the next dividend's date and amount.
+    Dividends,
+    /// Bond factor multiplier.
+    BondFactorMultiplier,
+    /// Open interest of futures.
+    FuturesOpenInterest,
+    /// Volume of the last 3, 5 and 10 minutes.
+    ShortTermVolume,
+    /// A generic tick this library does not name, by id.
+    Other(i32),
+}
+
+impl GenericTick {
+    /// The id of the generic tick, as sent to the server.
+    pub fn id(&self) -> i32 {
+        match self {
+            GenericTick::OptionVolume => 100,
+            GenericTick::OptionOpenInterest => 101,
+            GenericTick::HistoricalVolatility => 104,
+            GenericTick::AverageOptionVolume => 105,
+            GenericTick::OptionImpliedVolatility => 106,
+            GenericTick::IndexFuturePremium => 162,
+            GenericTick::MiscellaneousStats => 165,
+            GenericTick::MarkPrice => 221,
+            GenericTick::AuctionValues => 225,
+            GenericTick::RTVolume => 233,
+            GenericTick::Shortable => 236,
+            GenericTick::Inventory => 256,
+            GenericTick::FundamentalRatios => 258,
+            GenericTick::TradeCount => 293,
+            GenericTick::TradeRate => 294,
+            GenericTick::VolumeRate => 295,
+            GenericTick::LastRTHTrade => 318,
+            GenericTick::RTTradeVolume => 375,
+            GenericTick::RTHistoricalVolatility => 411,
+            GenericTick::Dividends => 456,
+            GenericTick::BondFactorMultiplier => 460,
+            GenericTick::FuturesOpenInterest => 588,
+            GenericTick::ShortTermVolume => 595,
+            GenericTick::Other(id) => *id,
+        }
+    }
+
+    /// Renders generic ticks as the comma separated list of ids sent to the server.
+    ///
+    /// ```
+    /// use ibapi::market_data::realtime::GenericTick;
+    ///
+    /// let ticks = GenericTick::list(&[GenericTick::RTVolume, GenericTick::Shortable, GenericTick::Other(577)]);
+    /// assert_eq!(ticks, "233,236,577");
+    /// ```
+    pub fn list(generic_ticks: &[GenericTick]) -> String {
+        generic_ticks.iter().map(GenericTick::to_string).collect::<Vec<_>>().join(",")
+    }
+}
+
+impl fmt::Display for GenericTick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+impl From<i32> for GenericTick {
+    fn from(id: i32) -> GenericTick {
+        match id {
+            100 => GenericTick::OptionVolume,
+            101 => GenericTick::OptionOpenInterest,
+            104 => GenericTick::HistoricalVolatility,
+            105 => GenericTick::AverageOptionVolume,
+            106 => GenericTick::OptionImpliedVolatility,
+            162 => GenericTick::IndexFuturePremium,
+            165 => GenericTick::MiscellaneousStats,
+            221 => GenericTick::MarkPrice,
+            225 => GenericTick::AuctionValues,
+            233 => GenericTick::RTVolume,
+            236 => GenericTick::Shortable,
+            256 => GenericTick::Inventory,
+            258 => GenericTick::FundamentalRatios,
+            293 => GenericTick::TradeCount,
+            294 => GenericTick::TradeRate,
+            295 => GenericTick::VolumeRate,
+            318 => GenericTick::LastRTHTrade,
+            375 => GenericTick::RTTradeVolume,
+            411 => GenericTick::RTHistoricalVolatility,
+            456 => GenericTick::Dividends,
+            460 => GenericTick::BondFactorMultiplier,
+            588 => GenericTick::FuturesOpenInterest,
+            595 => GenericTick::ShortTermVolume,
+            _ => GenericTick::Other(id),
+        }
+    }
+}
+
+/// Type of a market data tick.
+///
+/// When delayed market data is requested, the server reports the Delayed variants in place of their real-time equivalents.
+/// Use [TickType::normalized] to handle both the same way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TickType {
+    BidSize,
+    Bid,
+    Ask,
+    AskSize,
+    Last,
+    LastSize,
+    High,
+    Low,
+    Volume,
+    Close,
+    Open,
+    DelayedBid,
+    DelayedAsk,
+    DelayedLast,
+    DelayedBidSize,
+    DelayedAskSize,
+    DelayedLastSize,
+    DelayedHigh,
+    DelayedLow,
+    DelayedVolume,
+    DelayedClose,
+    DelayedOpen,
+    /// Last trade details, received as [TickEvent::RTVolume] when [GenericTick::RTVolume] is requested.
+    RTVolume,
+    /// Last trade details excluding unreportable trades, received as [TickEvent::RTVolume] when [GenericTick::RTTradeVolume] is requested.
+    RTTradeVolume,
+    /// Time of the last trade in seconds since the epoch, received as [TickEvent::String].
+    LastTimestamp,
+    DelayedLastTimestamp,
+    /// A tick type this library does not name. Carries the tick type as received.
+    Unknown(i32),
+}
+
+impl TickType {
+    /// Maps delayed tick types to their real-time equivalents. Other tick types are returned unchanged.
+    pub fn normalized(&self) -> TickType {
+        match self {
+            TickType::DelayedBid => TickType::Bid,
+            TickType::DelayedAsk => TickType::Ask,
+            TickType::DelayedLast => TickType::Last,
+            TickType::DelayedBidSize => TickType::BidSize,
+            TickType::DelayedAskSize => TickType::AskSize,
+            TickType::DelayedLastSize => TickType::LastSize,
+            TickType::DelayedHigh => TickType::High,
+            TickType::DelayedLow => TickType::Low,
+            TickType::DelayedVolume => TickType::Volume,
+            TickType::DelayedClose => TickType::Close,
+            TickType::DelayedOpen => TickType::Open,
+            TickType::DelayedLastTimestamp => TickType::LastTimestamp,
+            tick_type => *tick_type,
+        }
+    }
+
+    /// Returns true for tick types only sent with delayed market data.
+    pub fn is_delayed(&self) -> bool {
+        *self != self.normalized()
+    }
+}
+
+impl From<i32> for TickType {
+    fn from(value: i32) -> TickType {
+        match value {
+            0 => TickType::BidSize,
+            1 => TickType::Bid,
+            2 => TickType::Ask,
+            3 => TickType::AskSize,
+            4 => TickType::Last,
+            5 => TickType::LastSize,
+            6 => TickType::High,
+            7 => TickType::Low,
+            8 => TickType::Volume,
+            9 => TickType::Close,
+            14 => TickType::Open,
+            45 => TickType::LastTimestamp,
+            48 => TickType::RTVolume,
+            66 => TickType::DelayedBid,
+            67 => TickType::DelayedAsk,
+            68 => TickType::DelayedLast,
+            69 => TickType::DelayedBidSize,
+            70 => TickType::DelayedAskSize,
+            71 => TickType::DelayedLastSize,
+            72 => TickType::DelayedHigh,
+            73 => TickType::DelayedLow,
+            74 => TickType::DelayedVolume,
+            75 => TickType::DelayedClose,
+            76 => TickType::DelayedOpen,
+            77 => TickType::RTTradeVolume,
+            88 => TickType::DelayedLastTimestamp,
+            _ => TickType::Unknown(value),
+        }
+    }
+}
+
 // === Implementation ===
 
 // Requests realtime bars.
@@ -147,10 +682,165 @@ pub(crate) fn realtime_bars<'a>(
     let packet = encoders::encode_request_realtime_bars(client.server_version(), request_id, contract, bar_size, what_to_show, use_rth, options)?;
 
     let responses = client.send_durable_request(request_id, packet)?;
+    responses.set_cancel_message(encoders::cancel_realtime_bars(request_id)?);
 
     Ok(RealTimeBarIterator::new(client, request_id, responses))
 }
 
+// Subscribes to the market data of a contract.
+pub(crate) fn market_data<'a>(
+    client: &'a Client,
+    contract: &Contract,
+    generic_ticks: &[GenericTick],
+    snapshot: bool,
+) -> Result<MarketDataIterator<'a>, Error> {
+    let request_id = client.next_request_id();
+    let message = encoders::encode_request_market_data(
+        client.server_version(),
+        request_id,
+        contract,
+        &GenericTick::list(generic_ticks),
+        snapshot,
+        false,
+    )?;
+
+    let responses = client.send_durable_request(request_id, message)?;
+    responses.set_cancel_message(encoders::cancel_market_data(request_id)?);
+
+    Ok(MarketDataIterator {
+        client,
+        request_id,
+        responses,
+        ended: false,
+    })
+}
+
+// Subscribes to the order book of a contract.
+pub(crate) fn market_depth<'a>(
+    client: &'a Client,
+    contract: &Contract,
+    number_of_rows: i32,
+    is_smart_depth: bool,
+) -> Result<MarketDepthIterator<'a>, Error> {
+    if !contract.trading_class.is_empty() || contract.contract_id > 0 {
+        client.check_server_version(
+            server_versions::TRADING_CLASS,
+            "It does not support ConId nor TradingClass parameters in reqMktDepth.",
+        )?;
+    }
+
+    if is_smart_depth {
+        client.check_server_version(server_versions::SMART_DEPTH, "It does not support SMART depth request.")?;
+    }
+
+    if !contract.primary_exchange.is_empty() {
+        client.check_server_version(
+            server_versions::MKT_DEPTH_PRIM_EXCHANGE,
+            "It does not support primary_exchange parameter in reqMktDepth.",
+        )?;
+    }
+
+    let server_version = client.server_version();
+    let request_id = client.next_request_id();
+
+    let message = encoders::encode_request_market_depth(server_version, request_id, contract, number_of_rows, is_smart_depth)?;
+    let responses = client.send_durable_request(request_id, message)?;
+    responses.set_cancel_message(encoders::cancel_market_depth(server_version, request_id, is_smart_depth)?);
+
+    Ok(MarketDepthIterator {
+        client,
+        request_id,
+        is_smart_depth,
+        responses,
+    })
+}
+
+// Requests the exchanges offering market depth.
+pub(crate) fn market_depth_exchanges(client: &Client) -> Result<Vec<DepthMarketDataDescription>, Error> {
+    client.check_server_version(
+        server_versions::REQ_MKT_DEPTH_EXCHANGES,
+        "It does not support market depth exchanges requests.",
+    )?;
+
+    let message = encoders::encode_request_market_depth_exchanges()?;
+
+    let mut messages = client.request_market_depth_exchanges(message)?;
+
+    if let Some(mut message) = messages.next() {
+        decoders::decode_market_depth_exchanges(client.server_version(), &mut message)
+    } else {
+        Ok(Vec::default())
+    }
+}
+
+// Switches the type of market data returned by subsequent market data requests.
+pub(crate) fn set_market_data_type(client: &Client, market_data_type: MarketDataType) -> Result<(), Error> {
+    client.check_server_version(server_versions::REQ_MARKET_DATA_TYPE, "It does not support market data type requests.")?;
+
+    let message = encoders::encode_request_market_data_type(market_data_type)?;
+    client.send_message(message)
+}
+
+// Requests a one time snapshot of market data.
+pub(crate) fn market_snapshot(client: &Client, contract: &Contract, regulatory: bool) -> Result<MarketSnapshot, Error> {
+    // Regulatory snapshots are billed per request. Never fall back to sending the request without the flag.
+    if regulatory {
+        client.check_server_version(server_versions::REQ_SMART_COMPONENTS, "It does not support regulatory snapshot requests.")?;
+    }
+
+    let request_id = client.next_request_id();
+    let message = encoders::encode_request_market_data(client.server_version(), request_id, contract, "", true, regulatory)?;
+
+    if regulatory {
+        warn!("request {request_id}: regulatory snapshot of {} is billed per request", contract.symbol);
+    }
+
+    let responses = client.send_request(request_id, message)?;
+    responses.set_cancel_message(encoders::cancel_market_data(request_id)?);
+
+    let mut snapshot = collect_market_snapshot(contract, responses)?;
+    snapshot.billed = regulatory;
+
+    Ok(snapshot)
+}
+
+// Forex on IDEALPRO is quoted on bid and ask only. Last and volume ticks never arrive.
+fn is_quoted_only(contract: &Contract) -> bool {
+    contract.security_type == SecurityType::ForexPair
+}
+
+fn collect_market_snapshot(contract: &Contract, mut responses: ResponseIterator) -> Result<MarketSnapshot, Error> {
+    let quoted_only = is_quoted_only(contract);
+    let mut snapshot = MarketSnapshot::default();
+
+    while let Some(mut message) = responses.next_response()? {
+        match message.message_type() {
+            IncomingMessages::TickPrice => {
+                let (tick_type, price, _) = decoders::decode_tick_price(&mut message)?;
+                snapshot.update(tick_type, price, quoted_only);
+            }
+            IncomingMessages::TickSize => {
+                let (tick_type, size) = decoders::decode_tick_size(&mut message)?;
+                snapshot.update(tick_type, size, quoted_only);
+            }
+            IncomingMessages::Tickstring => {
+                let (tick_type, value) = decoders::decode_tick_string(&mut message)?;
+                if tick_type.normalized() == TickType::LastTimestamp && !quoted_only {
+                    snapshot.timestamp = value.parse().ok().and_then(|time| OffsetDateTime::from_unix_timestamp(time).ok());
+                }
+            }
+            IncomingMessages::TickSnapshotEnd => {
+                return Ok(snapshot);
+            }
+            _ => {
+                // generic ticks and request parameters are not part of the snapshot
+            }
+        }
+    }
+
+    Err(Error::Disconnected)
+}
+
 // Requests tick by tick AllLast ticks.
 pub(crate) fn tick_by_tick_all_last<'a>(
     client: &'a Client,
@@ -165,6 +855,7 @@ pub(crate) fn tick_by_tick_all_last<'a>(
 
     let message = encoders::tick_by_tick(server_version, request_id, contract, "AllLast", number_of_ticks, ignore_size)?;
     let responses = client.send_durable_request(request_id, message)?;
+    responses.set_cancel_message(encoders::cancel_tick_by_tick(request_id)?);
 
     Ok(TradeIterator {
         client,
@@ -201,6 +892,7 @@ pub(crate) fn tick_by_tick_last<'a>(
 
     let message = encoders::tick_by_tick(server_version, request_id, contract, "Last", number_of_ticks, ignore_size)?;
     let responses = client.send_durable_request(request_id, message)?;
+    responses.set_cancel_message(encoders::cancel_tick_by_tick(request_id)?);
 
     Ok(TradeIterator {
         client,
@@ -223,6 +915,7 @@ pub(crate) fn tick_by_tick_bid_ask<'a>(
 
     let message = encoders::tick_by_tick(server_version, request_id, contract, "BidAsk", number_of_ticks, ignore_size)?;
     let responses = client.send_durable_request(request_id, message)?;
+    responses.set_cancel_message(encoders::cancel_tick_by_tick(request_id)?);
 
     Ok(BidAskIterator {
         client,
@@ -245,6 +938,7 @@ pub(crate) fn tick_by_tick_midpoint<'a>(
 
     let message = encoders::tick_by_tick(server_version, request_id, contract, "MidPoint", number_of_ticks, ignore_size)?;
     let responses = client.send_durable_request(request_id, message)?;
+    responses.set_cancel_message(encoders::cancel_tick_by_tick(request_id)?);
 
     Ok(MidPointIterator {
         client,
@@ -273,8 +967,11 @@ impl<'a> RealTimeBarIterator<'a> {
 
     /// Cancels request to stream realtime bars
     fn cancel_realtime_bars(&mut self) {
-        let message = encoders::cancel_realtime_bars(self.request_id).unwrap();
-        self.client.send_message(message).unwrap();
+        // the client may be shut down, or in its maintenance window
+        let message = encoders::cancel_realtime_bars(self.request_id);
+        if let Err(e) = message.and_then(|message| self.client.send_message(message)) {
+            warn!("error cancelling realtime bars request {}: {e}", self.request_id);
+        }
     }
 }
 
@@ -283,7 +980,7 @@ impl<'a> Iterator for RealTimeBarIterator<'a> {
 
     /// Advances the iterator and returns the next value.
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(mut message) = self.responses.next() {
+        if let Some(mut message) = self.responses.next_or_end() {
             match message.message_type() {
                 IncomingMessages::RealTimeBars => {
                     let decoded = decoders::decode_realtime_bar(&mut message);
@@ -307,8 +1004,145 @@ impl<'a> Iterator for RealTimeBarIterator<'a> {
 }
 
 impl<'a> Drop for RealTimeBarIterator<'a> {
+    // cancelled requests were already cancelled with the server
     fn drop(&mut self) {
-        self.cancel_realtime_bars()
+        if !self.responses.is_cancelled() {
+            self.cancel_realtime_bars()
+        }
+    }
+}
+
+/// MarketDataIterator supports iteration over [TickEvent]s of a market data subscription.
+pub(crate) struct MarketDataIterator<'a> {
+    client: &'a Client,
+    request_id: i32,
+    responses: ResponseIterator,
+    // the server releases snapshot subscriptions once the snapshot ends
+    ended: bool,
+}
+
+impl<'a> MarketDataIterator<'a> {
+    fn decode(message: &mut ResponseMessage) -> Result<Option<TickEvent>, Error> {
+        match message.message_type() {
+            IncomingMessages::TickPrice => {
+                let (tick_type, price, attributes) = decoders::decode_tick_price(message)?;
+                Ok(Some(TickEvent::Price {
+                    tick_type,
+                    price,
+                    attributes,
+                }))
+            }
+            IncomingMessages::TickSize => {
+                let (tick_type, size) = decoders::decode_tick_size(message)?;
+                Ok(Some(TickEvent::Size {
+                    tick_type,
+                    size: size.unwrap_or_default(),
+                }))
+            }
+            IncomingMessages::Tickstring => {
+                let (tick_type, value) = decoders::decode_tick_string(message)?;
+                if matches!(tick_type, TickType::RTVolume | TickType::RTTradeVolume) {
+                    match decoders::parse_rt_volume(&value) {
+                        Ok(volume) => return Ok(Some(TickEvent::RTVolume { tick_type, volume })),
+                        Err(e) => warn!("passing {tick_type:?} tick through unparsed: {e}"),
+                    }
+                }
+                Ok(Some(TickEvent::String { tick_type, value }))
+            }
+            IncomingMessages::TickGeneric => {
+                let (tick_type, value) = decoders::decode_tick_generic(message)?;
+                Ok(Some(TickEvent::Generic { tick_type, value }))
+            }
+            IncomingMessages::MarketDataType => Ok(Some(TickEvent::MarketDataType(decoders::decode_market_data_type(message)?))),
+            IncomingMessages::TickSnapshotEnd => Ok(Some(TickEvent::SnapshotEnd)),
+            IncomingMessages::TickReqParams => Ok(Some(TickEvent::RequestParameters(decoders::decode_tick_request_parameters(message)?))),
+            message_type => Err(Error::UnexpectedResponse(message_type)),
+        }
+    }
+}
+
+impl<'a> Drop for MarketDataIterator<'a> {
+    // Ensures the market data subscription is cancelled
+    fn drop(&mut self) {
+        if !self.ended && !self.responses.is_cancelled() {
+            let message = encoders::cancel_market_data(self.request_id);
+            if let Err(e) = message.and_then(|message| self.client.send_message(message)) {
+                warn!("error cancelling market data request {}: {e}", self.request_id);
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for MarketDataIterator<'a> {
+    type Item = TickEvent;
+
+    /// Advances the iterator and returns the next value.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ended {
+            return None;
+        }
+
+        loop {
+            let mut message = self.responses.next_or_end()?;
+            match Self::decode(&mut message) {
+                Ok(Some(event)) => {
+                    self.ended = event == TickEvent::SnapshotEnd;
+                    return Some(event);
+                }
+                Ok(None) => {}
+                Err(e) => error!("unexpected message {message:?}: {e:?}"),
+            }
+        }
+    }
+}
+
+/// MarketDepthIterator supports iteration over [MarketDepthUpdate]s of a market depth subscription.
+pub(crate) struct MarketDepthIterator<'a> {
+    client: &'a Client,
+    request_id: i32,
+    is_smart_depth: bool,
+    responses: ResponseIterator,
+}
+
+impl<'a> Drop for MarketDepthIterator<'a> {
+    // Ensures the market depth subscription is cancelled
+    fn drop(&mut self) {
+        if !self.responses.is_cancelled() {
+            let message = encoders::cancel_market_depth(self.client.server_version(), self.request_id, self.is_smart_depth);
+            if let Err(e) = message.and_then(|message| self.client.send_message(message)) {
+                warn!("error cancelling market depth request {}: {e}", self.request_id);
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for MarketDepthIterator<'a> {
+    type Item = MarketDepthUpdate;
+
+    /// Advances the iterator and returns the next value.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut message = match self.responses.next_response() {
+                Ok(message) => message?,
+                Err(e) if e.kind() == ErrorKind::MarketDepthReset => return Some(MarketDepthUpdate::Reset),
+                Err(Error::Cancelled) => return None,
+                Err(e) => {
+                    error!("request {} ended: {e}", self.request_id);
+                    return None;
+                }
+            };
+            let decoded = match message.message_type() {
+                IncomingMessages::MarketDepth => decoders::decode_market_depth(&mut message).map(MarketDepthUpdate::MarketDepth),
+                IncomingMessages::MarketDepthL2 => {
+                    decoders::decode_market_depth_l2(self.client.server_version(), &mut message).map(MarketDepthUpdate::MarketDepthL2)
+                }
+                message_type => Err(Error::UnexpectedResponse(message_type)),
+            };
+            match decoded {
+                Ok(update) => return Some(update),
+                Err(e) => error!("unexpected message {message:?}: {e:?}"),
+            }
+        }
     }
 }
 
@@ -322,7 +1156,9 @@ pub(crate) struct TradeIterator<'a> {
 impl<'a> Drop for TradeIterator<'a> {
     // Ensures tick by tick request is cancelled
     fn drop(&mut self) {
-        cancel_tick_by_tick(self.client, self.request_id);
+        if !self.responses.is_cancelled() {
+            cancel_tick_by_tick(self.client, self.request_id);
+        }
     }
 }
 
@@ -332,7 +1168,7 @@ impl<'a> Iterator for TradeIterator<'a> {
     /// Advances the iterator and returns the next value.
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            match self.responses.next() {
+            match self.responses.next_or_end() {
                 Some(mut message) => match message.message_type() {
                     IncomingMessages::TickByTick => match decoders::trade_tick(&mut message) {
                         Ok(tick) => return Some(tick),
@@ -356,15 +1192,19 @@ pub(crate) struct BidAskIterator<'a> {
 /// Cancels the tick by tick request
 fn cancel_tick_by_tick(client: &Client, request_id: i32) {
     if client.server_version() >= server_versions::TICK_BY_TICK {
-        let message = encoders::cancel_tick_by_tick(request_id).unwrap();
-        client.send_message(message).unwrap();
+        let message = encoders::cancel_tick_by_tick(request_id);
+        if let Err(e) = message.and_then(|message| client.send_message(message)) {
+            warn!("error cancelling tick by tick request {request_id}: {e}");
+        }
     }
 }
 
 impl<'a> Drop for BidAskIterator<'a> {
     // Ensures tick by tick request is cancelled
     fn drop(&mut self) {
-        cancel_tick_by_tick(self.client, self.request_id);
+        if !self.responses.is_cancelled() {
+            cancel_tick_by_tick(self.client, self.request_id);
+        }
     }
 }
 
@@ -374,7 +1214,7 @@ impl<'a> Iterator for BidAskIterator<'a> {
     /// Advances the iterator and returns the next value.
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            match self.responses.next() {
+            match self.responses.next_or_end() {
                 Some(mut message) => match message.message_type() {
                     IncomingMessages::TickByTick => match decoders::bid_ask_tick(&mut message) {
                         Ok(tick) => return Some(tick),
@@ -398,7 +1238,9 @@ pub(crate) struct MidPointIterator<'a> {
 impl<'a> Drop for MidPointIterator<'a> {
     // Ensures tick by tick request is cancelled
     fn drop(&mut self) {
-        cancel_tick_by_tick(self.client, self.request_id);
+        if !self.responses.is_cancelled() {
+            cancel_tick_by_tick(self.client, self.request_id);
+        }
     }
 }
 
@@ -408,7 +1250,7 @@ impl<'a> Iterator for MidPointIterator<'a> {
     /// Advances the iterator and returns the next value.
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            match self.responses.next() {
+            match self.responses.next_or_end() {
                 Some(mut message) => match message.message_type() {
                     IncomingMessages::TickByTick => match decoders::mid_point_tick(&mut message) {
                         Ok(tick) => return Some(tick),
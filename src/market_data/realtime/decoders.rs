@@ -1,7 +1,174 @@
+use time::OffsetDateTime;
+
+use crate::contracts::SecurityType;
 use crate::messages::ResponseMessage;
-use crate::Error;
+use crate::{server_versions, Error};
+
+use super::{
+    Bar, BidAsk, BidAskAttribute, DepthMarketDataDescription, DepthOperation, DepthSide, MarketDataType, MarketDepth, MarketDepthL2, MidPoint,
+    RTVolume, TickAttribute, TickRequestParameters, TickType, Trade, TradeAttribute,
+};
+
+// Decodes tick type, price and attributes. A price of -1 means no data is available.
+pub(crate) fn decode_tick_price(message: &mut ResponseMessage) -> Result<(TickType, Option<f64>, TickAttribute), Error> {
+    message.skip(); // message type
+    message.skip(); // message version
+    message.skip(); // message request id
+
+    let tick_type = TickType::from(message.next_int()?);
+    let price = message.next_double()?;
+    message.skip(); // size, also sent as a size tick
+    let mask = message.next_u8()?;
+
+    Ok((tick_type, if price == -1.0 { None } else { Some(price) }, TickAttribute::from(mask)))
+}
+
+pub(crate) fn decode_tick_size(message: &mut ResponseMessage) -> Result<(TickType, Option<f64>), Error> {
+    message.skip(); // message type
+    message.skip(); // message version
+    message.skip(); // message request id
+
+    let tick_type = TickType::from(message.next_int()?);
+    let size = message.next_double()?;
+
+    Ok((tick_type, Some(size)))
+}
+
+pub(crate) fn decode_tick_string(message: &mut ResponseMessage) -> Result<(TickType, String), Error> {
+    message.skip(); // message type
+    message.skip(); // message version
+    message.skip(); // message request id
+
+    let tick_type = TickType::from(message.next_int()?);
+    let value = message.next_string()?;
+
+    Ok((tick_type, value))
+}
+
+// Parses the value of RTVolume and RTTradeVolume ticks: price;size;time in milliseconds;total volume;vwap;single trade flag.
+// Price and size are empty for updates only reporting volume.
+pub(crate) fn parse_rt_volume(value: &str) -> Result<RTVolume, Error> {
+    let fields: Vec<&str> = value.split(';').collect();
+    let [price, size, time, total_volume, vwap, single_trade] = fields[..] else {
+        return Err(Error::InvalidResponse(format!("expected 6 fields in RTVolume: {value}")));
+    };
+
+    let parse_double = |field: &str| {
+        field
+            .parse::<f64>()
+            .map_err(|e| Error::InvalidResponse(format!("invalid number {field} in RTVolume: {e}")))
+    };
+    let parse_optional = |field: &str| if field.is_empty() { Ok(None) } else { parse_double(field).map(Some) };
+
+    let millis: i128 = time
+        .parse()
+        .map_err(|e| Error::InvalidResponse(format!("invalid time {time} in RTVolume: {e}")))?;
+
+    Ok(RTVolume {
+        last_price: parse_optional(price)?,
+        last_size: parse_optional(size)?,
+        time: OffsetDateTime::from_unix_timestamp_nanos(millis * 1_000_000)
+            .map_err(|e| Error::InvalidResponse(format!("invalid time {time} in RTVolume: {e}")))?,
+        total_volume: parse_double(total_volume)?,
+        vwap: parse_double(vwap)?,
+        single_trade: single_trade == "true",
+    })
+}
+
+pub(crate) fn decode_tick_generic(message: &mut ResponseMessage) -> Result<(TickType, f64), Error> {
+    message.skip(); // message type
+    message.skip(); // message version
+    message.skip(); // message request id
+
+    let tick_type = TickType::from(message.next_int()?);
+    let value = message.next_double()?;
+
+    Ok((tick_type, value))
+}
+
+pub(crate) fn decode_tick_request_parameters(message: &mut ResponseMessage) -> Result<TickRequestParameters, Error> {
+    message.skip(); // message type
+    message.skip(); // message request id
+
+    Ok(TickRequestParameters {
+        min_tick: message.next_double()?,
+        bbo_exchange: message.next_string()?,
+        snapshot_permissions: message.next_int()?,
+    })
+}
+
+pub(crate) fn decode_market_data_type(message: &mut ResponseMessage) -> Result<MarketDataType, Error> {
+    message.skip(); // message type
+    message.skip(); // message version
+    message.skip(); // message request id
+
+    MarketDataType::try_from(message.next_int()?)
+}
+
+pub(crate) fn decode_market_depth(message: &mut ResponseMessage) -> Result<MarketDepth, Error> {
+    message.skip(); // message type
+    message.skip(); // message version
+    message.skip(); // message request id
+
+    Ok(MarketDepth {
+        position: message.next_int()?,
+        operation: DepthOperation::try_from(message.next_int()?)?,
+        side: DepthSide::try_from(message.next_int()?)?,
+        price: message.next_double()?,
+        size: message.next_double()?,
+    })
+}
+
+pub(crate) fn decode_market_depth_l2(server_version: i32, message: &mut ResponseMessage) -> Result<MarketDepthL2, Error> {
+    message.skip(); // message type
+    message.skip(); // message version
+    message.skip(); // message request id
+
+    let mut depth = MarketDepthL2 {
+        position: message.next_int()?,
+        market_maker: message.next_string()?,
+        operation: DepthOperation::try_from(message.next_int()?)?,
+        side: DepthSide::try_from(message.next_int()?)?,
+        price: message.next_double()?,
+        size: message.next_double()?,
+        smart_depth: false,
+    };
 
-use super::{Bar, BidAsk, BidAskAttribute, MidPoint, Trade, TradeAttribute};
+    if server_version >= server_versions::SMART_DEPTH {
+        depth.smart_depth = message.next_bool()?;
+    }
+
+    Ok(depth)
+}
+
+pub(crate) fn decode_market_depth_exchanges(server_version: i32, message: &mut ResponseMessage) -> Result<Vec<DepthMarketDataDescription>, Error> {
+    message.skip(); // message type
+
+    let count = message.next_int()?;
+    let mut descriptions = Vec::with_capacity(count.max(0) as usize);
+
+    for _ in 0..count {
+        let description = if server_version >= server_versions::SERVICE_DATA_TYPE {
+            DepthMarketDataDescription {
+                exchange: message.next_string()?,
+                security_type: SecurityType::from(&message.next_string()?),
+                listing_exchange: message.next_string()?,
+                service_data_type: message.next_string()?,
+                aggregated_group: message.next_optional_int()?,
+            }
+        } else {
+            DepthMarketDataDescription {
+                exchange: message.next_string()?,
+                security_type: SecurityType::from(&message.next_string()?),
+                service_data_type: if message.next_bool()? { "Deep2" } else { "Deep" }.to_owned(),
+                ..Default::default()
+            }
+        };
+        descriptions.push(description);
+    }
+
+    Ok(descriptions)
+}
 
 pub(crate) fn decode_realtime_bar(message: &mut ResponseMessage) -> Result<Bar, Error> {
     message.skip(); // message type
@@ -26,13 +193,13 @@ pub(crate) fn trade_tick(message: &mut ResponseMessage) -> Result<Trade, Error>
 
     let tick_type = message.next_int()?;
     if !(tick_type == 1 || tick_type == 2) {
-        return Err(Error::Simple(format!("Unexpected tick_type: {tick_type}")));
+        return Err(Error::InvalidResponse(format!("unexpected tick type: {tick_type}")));
     }
 
     let date = message.next_date_time()?;
     let price = message.next_double()?;
     let size = message.next_long()?;
-    let mask = message.next_int()?;
+    let mask = message.next_u8()?;
     let exchange = message.next_string()?;
     let special_conditions = message.next_string()?;
 
@@ -41,10 +208,7 @@ pub(crate) fn trade_tick(message: &mut ResponseMessage) -> Result<Trade, Error>
         time: date,
         price,
         size,
-        trade_attribute: TradeAttribute {
-            past_limit: mask & 0x1 != 0,
-            unreported: mask & 0x2 != 0,
-        },
+        trade_attribute: TradeAttribute::from(mask),
         exchange,
         special_conditions,
     })
@@ -56,7 +220,7 @@ pub(crate) fn bid_ask_tick(message: &mut ResponseMessage) -> Result<BidAsk, Erro
 
     let tick_type = message.next_int()?;
     if tick_type != 3 {
-        return Err(Error::Simple(format!("Unexpected tick_type: {tick_type}")));
+        return Err(Error::InvalidResponse(format!("unexpected tick type: {tick_type}")));
     }
 
     let date = message.next_date_time()?;
@@ -64,7 +228,7 @@ pub(crate) fn bid_ask_tick(message: &mut ResponseMessage) -> Result<BidAsk, Erro
     let ask_price = message.next_double()?;
     let bid_size = message.next_long()?;
     let ask_size = message.next_long()?;
-    let mask = message.next_int()?;
+    let mask = message.next_u8()?;
 
     Ok(BidAsk {
         time: date,
@@ -72,10 +236,7 @@ pub(crate) fn bid_ask_tick(message: &mut ResponseMessage) -> Result<BidAsk, Erro
         ask_price,
         bid_size,
         ask_size,
-        bid_ask_attribute: BidAskAttribute {
-            bid_past_low: mask & 0x1 != 0,
-            ask_past_high: mask & 0x2 != 0,
-        },
+        bid_ask_attribute: BidAskAttribute::from(mask),
     })
 }
 
@@ -85,7 +246,7 @@ pub(crate) fn mid_point_tick(message: &mut ResponseMessage) -> Result<MidPoint,
 
     let tick_type = message.next_int()?;
     if tick_type != 4 {
-        return Err(Error::Simple(format!("Unexpected tick_type: {tick_type}")));
+        return Err(Error::InvalidResponse(format!("unexpected tick type: {tick_type}")));
     }
 
     Ok(MidPoint {
@@ -102,7 +263,7 @@ mod tests {
 
     #[test]
     fn decode_trade() {
-        let mut message = ResponseMessage::from("99\09000\01\01678740829\03895.25\07\02\0\0\0");
+        let mut message = ResponseMessage::from("99\x009000\x001\x001678740829\x003895.25\x007\x002\x00\x00\x00");
 
         let results = trade_tick(&mut message);
 
@@ -111,37 +272,186 @@ mod tests {
             assert_eq!(trade.time, OffsetDateTime::from_unix_timestamp(1678740829).unwrap(), "trade.time");
             assert_eq!(trade.price, 3895.25, "trade.price");
             assert_eq!(trade.size, 7, "trade.size");
-            assert_eq!(trade.trade_attribute.past_limit, false, "trade.trade_attribute.past_limit");
-            assert_eq!(trade.trade_attribute.unreported, true, "trade.trade_attribute.unreported");
+            assert!(!trade.trade_attribute.past_limit, "trade.trade_attribute.past_limit");
+            assert!(trade.trade_attribute.unreported, "trade.trade_attribute.unreported");
             assert_eq!(trade.exchange, "", "trade.exchange");
             assert_eq!(trade.special_conditions, "", "trade.special_conditions");
         } else if let Err(err) = results {
-            assert!(false, "error decoding trade tick: {err}");
+            panic!("error decoding trade tick: {err}");
         }
     }
 
+    #[test]
+    fn decode_trade_with_special_conditions() {
+        let mut message = ResponseMessage::from("99\x009000\x002\x001678740829\x003895.25\x00100\x000\x00Q\x00 O X\x00");
+
+        let trade = trade_tick(&mut message).expect("error decoding trade tick");
+
+        assert_eq!(trade.tick_type, "2", "trade.tick_type");
+        assert_eq!(trade.size, 100, "trade.size");
+        assert!(!trade.trade_attribute.past_limit, "trade.trade_attribute.past_limit");
+        assert!(!trade.trade_attribute.unreported, "trade.trade_attribute.unreported");
+        assert_eq!(trade.exchange, "Q", "trade.exchange");
+        assert_eq!(trade.special_conditions, " O X", "trade.special_conditions");
+    }
+
+    #[test]
+    fn decode_delayed_tick_price() {
+        let mut message = ResponseMessage::from("1\x006\x009000\x0066\x00180.25\x00300\x001\x00");
+
+        let (tick_type, price, attributes) = decode_tick_price(&mut message).expect("error decoding tick price");
+
+        assert_eq!(tick_type, TickType::DelayedBid, "tick_type");
+        assert_eq!(tick_type.normalized(), TickType::Bid, "tick_type.normalized()");
+        assert_eq!(price, Some(180.25), "price");
+        assert_eq!(
+            attributes,
+            TickAttribute {
+                can_auto_execute: true,
+                past_limit: false,
+                pre_open: false
+            },
+            "attributes"
+        );
+    }
+
+    #[test]
+    fn decode_market_depth_l2() {
+        let mut message = ResponseMessage::from("13\x001\x009000\x000\x00ISLAND\x001\x001\x00185.50\x00300\x001\x00");
+
+        let depth = super::decode_market_depth_l2(server_versions::SMART_DEPTH, &mut message).expect("error decoding market depth");
+
+        assert_eq!(depth.position, 0, "depth.position");
+        assert_eq!(depth.market_maker, "ISLAND", "depth.market_maker");
+        assert_eq!(depth.operation, DepthOperation::Update, "depth.operation");
+        assert_eq!(depth.side, DepthSide::Bid, "depth.side");
+        assert_eq!(depth.price, 185.50, "depth.price");
+        assert_eq!(depth.size, 300.0, "depth.size");
+        assert!(depth.smart_depth, "depth.smart_depth");
+    }
+
+    #[test]
+    fn decode_market_depth_exchanges() {
+        let mut message = ResponseMessage::from("80\x002\x00ISLAND\x00STK\x00NASDAQ\x00Deep2\x007\x00IDEALPRO\x00CASH\x00\x00Deep\x00\x00");
+
+        let descriptions = super::decode_market_depth_exchanges(server_versions::SERVICE_DATA_TYPE, &mut message).expect("error decoding exchanges");
+
+        assert_eq!(descriptions.len(), 2, "descriptions.len()");
+        assert_eq!(descriptions[0].exchange, "ISLAND", "descriptions[0].exchange");
+        assert_eq!(descriptions[0].security_type, SecurityType::Stock, "descriptions[0].security_type");
+        assert_eq!(descriptions[0].listing_exchange, "NASDAQ", "descriptions[0].listing_exchange");
+        assert_eq!(descriptions[0].service_data_type, "Deep2", "descriptions[0].service_data_type");
+        assert_eq!(descriptions[0].aggregated_group, Some(7), "descriptions[0].aggregated_group");
+        assert_eq!(descriptions[1].security_type, SecurityType::ForexPair, "descriptions[1].security_type");
+        assert_eq!(descriptions[1].aggregated_group, None, "descriptions[1].aggregated_group");
+    }
+
+    #[test]
+    fn decode_market_depth_exchanges_without_service_data_type() {
+        let mut message = ResponseMessage::from("80\x001\x00ISLAND\x00STK\x001\x00");
+
+        let descriptions =
+            super::decode_market_depth_exchanges(server_versions::SERVICE_DATA_TYPE - 1, &mut message).expect("error decoding exchanges");
+
+        assert_eq!(descriptions[0].service_data_type, "Deep2", "descriptions[0].service_data_type");
+        assert_eq!(descriptions[0].aggregated_group, None, "descriptions[0].aggregated_group");
+    }
+
+    #[test]
+    fn parse_rt_volume() {
+        let volume = super::parse_rt_volume("701.28;1;1348075471534;67854;701.46918464;true").expect("error parsing RTVolume");
+
+        assert_eq!(volume.last_price, Some(701.28), "volume.last_price");
+        assert_eq!(volume.last_size, Some(1.0), "volume.last_size");
+        assert_eq!(
+            volume.time,
+            OffsetDateTime::from_unix_timestamp_nanos(1348075471534 * 1_000_000).unwrap(),
+            "volume.time"
+        );
+        assert_eq!(volume.total_volume, 67854.0, "volume.total_volume");
+        assert_eq!(volume.vwap, 701.46918464, "volume.vwap");
+        assert!(volume.single_trade, "volume.single_trade");
+
+        let volume = super::parse_rt_volume(";;1348075471534;67855;701.46918464;false").expect("error parsing volume only RTVolume");
+        assert_eq!(volume.last_price, None, "volume.last_price");
+        assert_eq!(volume.last_size, None, "volume.last_size");
+        assert!(!volume.single_trade, "volume.single_trade");
+
+        assert!(super::parse_rt_volume("701.28;1;1348075471534").is_err(), "missing fields");
+    }
+
     #[test]
     fn decode_bid_ask() {
-        let mut message = ResponseMessage::from("99\09000\03\01678745793\03895.50\03896.00\09\011\01\0");
+        let mut message = ResponseMessage::from("99\x009000\x003\x001678745793\x003895.50\x003896.00\x009\x0011\x001\x00");
 
         let results = bid_ask_tick(&mut message);
 
         if let Ok(bid_ask) = results {
-            assert_eq!(bid_ask.time, OffsetDateTime::from_unix_timestamp(01678745793).unwrap(), "bid_ask.time");
+            assert_eq!(bid_ask.time, OffsetDateTime::from_unix_timestamp(1678745793).unwrap(), "bid_ask.time");
             assert_eq!(bid_ask.bid_price, 3895.5, "bid_ask.bid_price");
             assert_eq!(bid_ask.ask_price, 3896.0, "bid_ask.ask_price");
             assert_eq!(bid_ask.bid_size, 9, "bid_ask.bid_size");
             assert_eq!(bid_ask.ask_size, 11, "bid_ask.ask_size");
-            assert_eq!(bid_ask.bid_ask_attribute.bid_past_low, true, "bid_ask.bid_ask_attribute.bid_past_low");
-            assert_eq!(bid_ask.bid_ask_attribute.ask_past_high, false, "bid_ask.bid_ask_attribute.ask_past_high");
+            assert!(bid_ask.bid_ask_attribute.bid_past_low, "bid_ask.bid_ask_attribute.bid_past_low");
+            assert!(!bid_ask.bid_ask_attribute.ask_past_high, "bid_ask.bid_ask_attribute.ask_past_high");
         } else if let Err(err) = results {
-            assert!(false, "error decoding trade tick: {err}");
+            panic!("error decoding trade tick: {err}");
+        }
+    }
+
+    #[test]
+    fn unpack_bid_ask_attribute() {
+        let cases = [(0, false, false), (1, true, false), (2, false, true), (3, true, true)];
+
+        for (mask, bid_past_low, ask_past_high) in cases {
+            let attribute = BidAskAttribute::from(mask);
+            assert_eq!(attribute.bid_past_low, bid_past_low, "bid_past_low for mask {mask}");
+            assert_eq!(attribute.ask_past_high, ask_past_high, "ask_past_high for mask {mask}");
         }
     }
 
+    #[test]
+    fn unpack_trade_attribute() {
+        let cases = [(0, false, false), (1, true, false), (2, false, true), (3, true, true)];
+
+        for (mask, past_limit, unreported) in cases {
+            let attribute = TradeAttribute::from(mask);
+            assert_eq!(attribute.past_limit, past_limit, "past_limit for mask {mask}");
+            assert_eq!(attribute.unreported, unreported, "unreported for mask {mask}");
+        }
+    }
+
+    #[test]
+    fn unpack_tick_attribute() {
+        let cases = [
+            (0, false, false, false),
+            (1, true, false, false),
+            (2, false, true, false),
+            (6, false, true, true),
+        ];
+
+        for (mask, can_auto_execute, past_limit, pre_open) in cases {
+            let attribute = TickAttribute::from(mask);
+            assert_eq!(attribute.can_auto_execute, can_auto_execute, "can_auto_execute for mask {mask}");
+            assert_eq!(attribute.past_limit, past_limit, "past_limit for mask {mask}");
+            assert_eq!(attribute.pre_open, pre_open, "pre_open for mask {mask}");
+        }
+    }
+
+    #[test]
+    fn decode_tick_request_parameters() {
+        let mut message = ResponseMessage::from("81\x009000\x000.01\x009c0001\x003\x00");
+
+        let parameters = super::decode_tick_request_parameters(&mut message).expect("error decoding tick request parameters");
+
+        assert_eq!(parameters.min_tick, 0.01, "parameters.min_tick");
+        assert_eq!(parameters.bbo_exchange, "9c0001", "parameters.bbo_exchange");
+        assert_eq!(parameters.snapshot_permissions, 3, "parameters.snapshot_permissions");
+    }
+
     #[test]
     fn decode_mid_point() {
-        let mut message = ResponseMessage::from("99\09000\04\01678746113\03896.875\0");
+        let mut message = ResponseMessage::from("99\x009000\x004\x001678746113\x003896.875\x00");
 
         let results = mid_point_tick(&mut message);
 
@@ -149,7 +459,7 @@ mod tests {
             assert_eq!(mid_point.time, OffsetDateTime::from_unix_timestamp(1678746113).unwrap(), "mid_point.time");
             assert_eq!(mid_point.mid_point, 3896.875, "mid_point.mid_point");
         } else if let Err(err) = results {
-            assert!(false, "error decoding mid point tick: {err}");
+            panic!("error decoding mid point tick: {err}");
         }
     }
 }
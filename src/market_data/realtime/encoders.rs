@@ -1,8 +1,7 @@
-use super::{BarSize, WhatToShow};
-use crate::contracts::Contract;
+use super::{BarSize, MarketDataType, WhatToShow};
+use crate::contracts::{Contract, TagValue};
 use crate::messages::OutgoingMessages;
 use crate::messages::RequestMessage;
-use crate::orders::TagValue;
 use crate::{server_versions, Error};
 
 pub(crate) fn encode_request_realtime_bars(
@@ -14,12 +13,9 @@ pub(crate) fn encode_request_realtime_bars(
     use_rth: bool,
     options: Vec<TagValue>,
 ) -> Result<RequestMessage, Error> {
-    const VERSION: i32 = 8;
-
     let mut packet = RequestMessage::default();
 
-    packet.push_field(&OutgoingMessages::RequestRealTimeBars);
-    packet.push_field(&VERSION);
+    packet.push_message_header(OutgoingMessages::RequestRealTimeBars, server_version);
     packet.push_field(&ticker_id);
 
     if server_version >= server_versions::TRADING_CLASS {
@@ -107,6 +103,163 @@ pub(crate) fn cancel_tick_by_tick(request_id: i32) -> Result<RequestMessage, Err
     Ok(message)
 }
 
+pub(crate) fn cancel_market_data(request_id: i32) -> Result<RequestMessage, Error> {
+    const VERSION: i32 = 2;
+
+    let mut message = RequestMessage::default();
+
+    message.push_field(&OutgoingMessages::CancelMarketData);
+    message.push_field(&VERSION);
+    message.push_field(&request_id);
+
+    Ok(message)
+}
+
+pub(crate) fn encode_request_market_data(
+    server_version: i32,
+    request_id: i32,
+    contract: &Contract,
+    generic_ticks: &str,
+    snapshot: bool,
+    regulatory_snapshot: bool,
+) -> Result<RequestMessage, Error> {
+    let mut message = RequestMessage::default();
+
+    message.push_message_header(OutgoingMessages::RequestMarketData, server_version);
+    message.push_field(&request_id);
+    message.push_field(&contract.contract_id);
+    message.push_field(&contract.symbol);
+    message.push_field(&contract.security_type);
+    message.push_field(&contract.last_trade_date_or_contract_month);
+    message.push_field(&contract.strike);
+    message.push_field(&contract.right);
+    message.push_field(&contract.multiplier);
+    message.push_field(&contract.exchange);
+    message.push_field(&contract.primary_exchange);
+    message.push_field(&contract.currency);
+    message.push_field(&contract.local_symbol);
+    message.push_field(&contract.trading_class);
+
+    if contract.is_bag() {
+        message.push_field(&contract.combo_legs.len());
+        for leg in &contract.combo_legs {
+            message.push_field(&leg.contract_id);
+            message.push_field(&leg.ratio);
+            message.push_field(&leg.action);
+            message.push_field(&leg.exchange);
+        }
+    }
+
+    if let Some(delta_neutral_contract) = &contract.delta_neutral_contract {
+        message.push_field(&true);
+        message.push_field(&delta_neutral_contract.contract_id);
+        message.push_field(&delta_neutral_contract.delta);
+        message.push_field(&delta_neutral_contract.price);
+    } else {
+        message.push_field(&false);
+    }
+
+    message.push_field(&generic_ticks);
+    message.push_field(&snapshot);
+
+    if server_version >= server_versions::REQ_SMART_COMPONENTS {
+        message.push_field(&regulatory_snapshot);
+    }
+
+    if server_version >= server_versions::LINKING {
+        message.push_field(&""); // market data options -- not supported
+    }
+
+    Ok(message)
+}
+
+pub(crate) fn encode_request_market_depth(
+    server_version: i32,
+    request_id: i32,
+    contract: &Contract,
+    number_of_rows: i32,
+    is_smart_depth: bool,
+) -> Result<RequestMessage, Error> {
+    const VERSION: i32 = 5;
+
+    let mut message = RequestMessage::default();
+
+    message.push_field(&OutgoingMessages::RequestMarketDepth);
+    message.push_field(&VERSION);
+    message.push_field(&request_id);
+
+    if server_version >= server_versions::TRADING_CLASS {
+        message.push_field(&contract.contract_id);
+    }
+
+    message.push_field(&contract.symbol);
+    message.push_field(&contract.security_type);
+    message.push_field(&contract.last_trade_date_or_contract_month);
+    message.push_field(&contract.strike);
+    message.push_field(&contract.right);
+    message.push_field(&contract.multiplier);
+    message.push_field(&contract.exchange);
+
+    if server_version >= server_versions::MKT_DEPTH_PRIM_EXCHANGE {
+        message.push_field(&contract.primary_exchange);
+    }
+
+    message.push_field(&contract.currency);
+    message.push_field(&contract.local_symbol);
+
+    if server_version >= server_versions::TRADING_CLASS {
+        message.push_field(&contract.trading_class);
+    }
+
+    message.push_field(&number_of_rows);
+
+    if server_version >= server_versions::SMART_DEPTH {
+        message.push_field(&is_smart_depth);
+    }
+
+    if server_version >= server_versions::LINKING {
+        message.push_field(&""); // market depth options, reserved for internal use
+    }
+
+    Ok(message)
+}
+
+pub(crate) fn cancel_market_depth(server_version: i32, request_id: i32, is_smart_depth: bool) -> Result<RequestMessage, Error> {
+    const VERSION: i32 = 1;
+
+    let mut message = RequestMessage::default();
+
+    message.push_field(&OutgoingMessages::CancelMarketDepth);
+    message.push_field(&VERSION);
+    message.push_field(&request_id);
+
+    if server_version >= server_versions::SMART_DEPTH {
+        message.push_field(&is_smart_depth);
+    }
+
+    Ok(message)
+}
+
+pub(crate) fn encode_request_market_depth_exchanges() -> Result<RequestMessage, Error> {
+    let mut message = RequestMessage::default();
+
+    message.push_field(&OutgoingMessages::RequestMktDepthExchanges);
+
+    Ok(message)
+}
+
+pub(crate) fn encode_request_market_data_type(market_data_type: MarketDataType) -> Result<RequestMessage, Error> {
+    const VERSION: i32 = 1;
+
+    let mut message = RequestMessage::default();
+
+    message.push_field(&OutgoingMessages::RequestMarketDataType);
+    message.push_field(&VERSION);
+    message.push_field(&market_data_type);
+
+    Ok(message)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{contracts::contract_samples, ToField};
@@ -125,11 +278,66 @@ mod tests {
                 assert_eq!(message[1], request_id.to_string(), "message.request_id");
             }
             Err(err) => {
-                assert!(false, "error encoding cancel_tick_by_tick request: {err}");
+                panic!("error encoding cancel_tick_by_tick request: {err}");
             }
         }
     }
 
+    #[test]
+    fn cancel_market_data() {
+        let message = super::cancel_market_data(9000).expect("error encoding cancel_market_data request");
+
+        assert_eq!(message[0], OutgoingMessages::CancelMarketData.to_field(), "message.type");
+        assert_eq!(message[1], "2", "message.version");
+        assert_eq!(message[2], "9000", "message.request_id");
+    }
+
+    #[test]
+    fn request_market_depth() {
+        let contract = Contract::stock("AAPL");
+
+        let message = super::encode_request_market_depth(server_versions::MKT_DEPTH_PRIM_EXCHANGE, 9000, &contract, 5, false)
+            .expect("error encoding market depth request");
+        assert_eq!(
+            message.encode_simple(),
+            "10|5|9000|0|AAPL|STK||0|||SMART||USD|||5|0||",
+            "market depth request"
+        );
+
+        let message = super::encode_request_market_depth(server_versions::SMART_DEPTH - 1, 9000, &contract, 5, false)
+            .expect("error encoding market depth request");
+        assert_eq!(
+            message.encode_simple(),
+            "10|5|9000|0|AAPL|STK||0|||SMART|USD|||5||",
+            "market depth request"
+        );
+    }
+
+    #[test]
+    fn cancel_market_depth() {
+        let message = super::cancel_market_depth(server_versions::SMART_DEPTH, 9000, true).expect("error encoding cancel market depth request");
+        assert_eq!(message.encode_simple(), "11|1|9000|1|", "cancel market depth request");
+
+        let message = super::cancel_market_depth(server_versions::SMART_DEPTH - 1, 9000, false).expect("error encoding cancel market depth request");
+        assert_eq!(message.encode_simple(), "11|1|9000|", "cancel market depth request");
+    }
+
+    #[test]
+    fn request_market_depth_exchanges() {
+        let message = super::encode_request_market_depth_exchanges().expect("error encoding market depth exchanges request");
+
+        assert_eq!(message.encode_simple(), "82|", "market depth exchanges request");
+    }
+
+    #[test]
+    fn request_market_data_type() {
+        let message = super::encode_request_market_data_type(MarketDataType::DelayedFrozen).expect("error encoding market data type request");
+
+        assert_eq!(message[0], OutgoingMessages::RequestMarketDataType.to_field(), "message.type");
+        assert_eq!(message[1], "1", "message.version");
+        assert_eq!(message[2], "4", "message.market_data_type");
+    }
+
     #[test]
     fn cancel_realtime_bars() {
         let request_id = 9000;
@@ -143,7 +351,7 @@ mod tests {
                 assert_eq!(message[2], request_id.to_string(), "message.request_id");
             }
             Err(err) => {
-                assert!(false, "error encoding cancel_tick_by_tick request: {err}");
+                panic!("error encoding cancel_tick_by_tick request: {err}");
             }
         }
     }
@@ -181,7 +389,7 @@ mod tests {
                 assert_eq!(message[14], tick_type, "message.tick_type");
             }
             Err(err) => {
-                assert!(false, "error encoding tick_by_tick request: {err}");
+                panic!("error encoding tick_by_tick request: {err}");
             }
         }
     }
@@ -224,7 +432,7 @@ mod tests {
                 assert_eq!(message[18], "", "message.options"); // TODO what should this be?
             }
             Err(err) => {
-                assert!(false, "error encoding realtime_bars request: {err}");
+                panic!("error encoding realtime_bars request: {err}");
             }
         }
     }
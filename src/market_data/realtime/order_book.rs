@@ -0,0 +1,284 @@
+//! A local copy of the order book of a contract, maintained from the updates of a [market depth](crate::Client::market_depth) subscription.
+
+use log::warn;
+
+use super::{DepthOperation, DepthSide, MarketDepthUpdate};
+
+/// A row of the order book.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BookLevel {
+    pub price: f64,
+    pub size: f64,
+    /// The market maker or, for smart depth books, the exchange quoting. Empty for level I books.
+    pub market_maker: String,
+}
+
+/// A change applied to an [OrderBook]. See [OrderBook::apply].
+#[derive(Clone, Debug, PartialEq)]
+pub enum BookChange {
+    /// A row was inserted at position, shifting the rows below it down.
+    Inserted { side: DepthSide, position: usize, level: BookLevel },
+    /// The row at position was replaced.
+    Updated { side: DepthSide, position: usize, level: BookLevel },
+    /// The row at position was removed, shifting the rows below it up. Carries the row removed.
+    Deleted { side: DepthSide, position: usize, level: BookLevel },
+    /// All rows were removed after the server reset the book.
+    Cleared,
+}
+
+/// The order book of a contract, built by applying the updates of a market depth subscription in the order received.
+///
+/// Rows are ordered from the best price outwards, as numbered by the server.
+///
+/// ```no_run
+/// use ibapi::Client;
+/// use ibapi::contracts::Contract;
+/// use ibapi::market_data::realtime::OrderBook;
+///
+/// let client = Client::connect("127.0.0.1:4002", 100).expect("connection failed");
+///
+/// let contract = Contract::stock("AAPL");
+/// let updates = client.market_depth(&contract, 10, false).expect("request failed");
+///
+/// let mut book = OrderBook::new();
+/// for update in updates {
+///     if book.apply(&update).is_some() {
+///         println!("mid: {:?}, microprice: {:?}, top: {:?}", book.midpoint(), book.microprice(), book.top(3));
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrderBook {
+    bids: Vec<BookLevel>,
+    asks: Vec<BookLevel>,
+}
+
+impl OrderBook {
+    /// Creates an empty book.
+    pub fn new() -> OrderBook {
+        OrderBook::default()
+    }
+
+    /// Applies an update, returning the change made.
+    ///
+    /// Returns None, leaving the book unchanged, for updates referring to rows the book doesn't have. The server only sends
+    /// those when updates were missed, after which the book is only consistent again once the server resets it.
+    pub fn apply(&mut self, update: &MarketDepthUpdate) -> Option<BookChange> {
+        let (position, operation, side, level) = match update {
+            MarketDepthUpdate::MarketDepth(depth) => (
+                depth.position,
+                depth.operation,
+                depth.side,
+                BookLevel {
+                    price: depth.price,
+                    size: depth.size,
+                    market_maker: String::new(),
+                },
+            ),
+            MarketDepthUpdate::MarketDepthL2(depth) => (
+                depth.position,
+                depth.operation,
+                depth.side,
+                BookLevel {
+                    price: depth.price,
+                    size: depth.size,
+                    market_maker: depth.market_maker.clone(),
+                },
+            ),
+            MarketDepthUpdate::Reset => {
+                self.clear();
+                return Some(BookChange::Cleared);
+            }
+        };
+
+        let Ok(position) = usize::try_from(position) else {
+            warn!("skipping depth update with invalid position: {update:?}");
+            return None;
+        };
+
+        let rows = match side {
+            DepthSide::Bid => &mut self.bids,
+            DepthSide::Ask => &mut self.asks,
+        };
+
+        match operation {
+            DepthOperation::Insert if position <= rows.len() => {
+                rows.insert(position, level.clone());
+                Some(BookChange::Inserted { side, position, level })
+            }
+            DepthOperation::Update if position < rows.len() => {
+                rows[position] = level.clone();
+                Some(BookChange::Updated { side, position, level })
+            }
+            // some servers insert rows with updates
+            DepthOperation::Update if position == rows.len() => {
+                rows.push(level.clone());
+                Some(BookChange::Inserted { side, position, level })
+            }
+            DepthOperation::Delete if position < rows.len() => {
+                let level = rows.remove(position);
+                Some(BookChange::Deleted { side, position, level })
+            }
+            _ => {
+                warn!("skipping depth update for missing row: {update:?}");
+                None
+            }
+        }
+    }
+
+    /// Removes all rows.
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    /// Bid rows, from the highest price down.
+    pub fn bids(&self) -> &[BookLevel] {
+        &self.bids
+    }
+
+    /// Ask rows, from the lowest price up.
+    pub fn asks(&self) -> &[BookLevel] {
+        &self.asks
+    }
+
+    /// The highest bid.
+    pub fn best_bid(&self) -> Option<&BookLevel> {
+        self.bids.first()
+    }
+
+    /// The lowest ask.
+    pub fn best_ask(&self) -> Option<&BookLevel> {
+        self.asks.first()
+    }
+
+    /// Returns a copy of the book limited to the best `levels` rows of each side.
+    pub fn top(&self, levels: usize) -> OrderBook {
+        OrderBook {
+            bids: self.bids.iter().take(levels).cloned().collect(),
+            asks: self.asks.iter().take(levels).cloned().collect(),
+        }
+    }
+
+    /// Difference between the best ask and bid, when both sides are quoted.
+    pub fn spread(&self) -> Option<f64> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some(ask.price - bid.price),
+            _ => None,
+        }
+    }
+
+    /// Midpoint between the best bid and ask, when both sides are quoted.
+    pub fn midpoint(&self) -> Option<f64> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some((bid.price + ask.price) / 2.0),
+            _ => None,
+        }
+    }
+
+    /// Midpoint weighted by the sizes of the best bid and ask. It leans towards the ask when the bid is larger, where the next trade is more likely.
+    /// None when either side is missing or both sizes are zero.
+    pub fn microprice(&self) -> Option<f64> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) if bid.size + ask.size > 0.0 => Some((bid.price * ask.size + ask.price * bid.size) / (bid.size + ask.size)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{MarketDepth, MarketDepthL2};
+    use super::*;
+
+    fn depth(position: i32, operation: DepthOperation, side: DepthSide, price: f64, size: f64) -> MarketDepthUpdate {
+        MarketDepthUpdate::MarketDepth(MarketDepth {
+            position,
+            operation,
+            side,
+            price,
+            size,
+        })
+    }
+
+    #[test]
+    fn apply_updates() {
+        let mut book = OrderBook::new();
+
+        book.apply(&depth(0, DepthOperation::Insert, DepthSide::Bid, 100.0, 300.0));
+        book.apply(&depth(1, DepthOperation::Insert, DepthSide::Bid, 99.5, 200.0));
+        book.apply(&depth(0, DepthOperation::Insert, DepthSide::Ask, 101.0, 100.0));
+
+        let change = book.apply(&depth(0, DepthOperation::Insert, DepthSide::Bid, 100.5, 50.0));
+        assert_eq!(
+            change,
+            Some(BookChange::Inserted {
+                side: DepthSide::Bid,
+                position: 0,
+                level: BookLevel {
+                    price: 100.5,
+                    size: 50.0,
+                    market_maker: String::new()
+                }
+            })
+        );
+        let prices: Vec<f64> = book.bids().iter().map(|level| level.price).collect();
+        assert_eq!(prices, vec![100.5, 100.0, 99.5], "inserts shift rows down");
+
+        book.apply(&depth(1, DepthOperation::Update, DepthSide::Bid, 100.0, 400.0));
+        assert_eq!(book.bids()[1].size, 400.0, "bids[1].size");
+
+        let change = book.apply(&depth(0, DepthOperation::Delete, DepthSide::Bid, 100.5, 50.0));
+        assert!(matches!(change, Some(BookChange::Deleted { position: 0, .. })), "change: {change:?}");
+        assert_eq!(book.best_bid().map(|level| level.price), Some(100.0), "deletes shift rows up");
+
+        assert_eq!(book.spread(), Some(1.0), "spread");
+        assert_eq!(book.midpoint(), Some(100.5), "midpoint");
+        assert_eq!(book.microprice(), Some((100.0 * 100.0 + 101.0 * 400.0) / 500.0), "microprice");
+
+        let top = book.top(1);
+        assert_eq!(top.bids().len(), 1, "top.bids().len()");
+        assert_eq!(top.asks().len(), 1, "top.asks().len()");
+    }
+
+    #[test]
+    fn apply_missing_rows() {
+        let mut book = OrderBook::new();
+
+        assert_eq!(book.apply(&depth(2, DepthOperation::Insert, DepthSide::Bid, 100.0, 300.0)), None);
+        assert_eq!(book.apply(&depth(0, DepthOperation::Delete, DepthSide::Ask, 101.0, 100.0)), None);
+        assert_eq!(book, OrderBook::new(), "book unchanged");
+
+        assert!(
+            matches!(
+                book.apply(&depth(0, DepthOperation::Update, DepthSide::Ask, 101.0, 100.0)),
+                Some(BookChange::Inserted { .. })
+            ),
+            "updates past the last row insert it"
+        );
+        assert_eq!(book.microprice(), None, "one sided book");
+    }
+
+    #[test]
+    fn apply_reset() {
+        let mut book = OrderBook::new();
+
+        book.apply(&MarketDepthUpdate::MarketDepthL2(MarketDepthL2 {
+            position: 0,
+            market_maker: "ARCA".to_owned(),
+            operation: DepthOperation::Insert,
+            side: DepthSide::Ask,
+            price: 101.0,
+            size: 100.0,
+            smart_depth: true,
+        }));
+        assert_eq!(
+            book.best_ask().map(|level| level.market_maker.as_str()),
+            Some("ARCA"),
+            "best_ask.market_maker"
+        );
+
+        assert_eq!(book.apply(&MarketDepthUpdate::Reset), Some(BookChange::Cleared));
+        assert_eq!(book.best_ask(), None, "best_ask after reset");
+    }
+}
@@ -1,20 +1,22 @@
 use std::cell::RefCell;
+use std::sync::Mutex;
 
 use time::OffsetDateTime;
 
+use crate::client::transport::MessageBus;
 use crate::contracts::contract_samples;
 use crate::messages::OutgoingMessages;
-use crate::stubs::MessageBusStub;
+use crate::stubs::{MessageBusStub, RequestMessages};
 use crate::ToField;
 
 use super::*;
 
 #[test]
 fn realtime_bars() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec!["50|3|9001|1678323335|4028.75|4029.00|4028.25|4028.50|2|4026.75|1|".to_owned()],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
@@ -40,13 +42,13 @@ fn realtime_bars() {
         assert_eq!(bar.wap, 4026.75, "bar.wap");
         assert_eq!(bar.count, 1, "bar.count");
     } else {
-        assert!(false, "expected a real time bar");
+        panic!("expected a real time bar");
     }
 
     // Should trigger cancel realtime bars
     drop(bars);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     // Verify Requests
     let realtime_bars_request = &request_messages[0];
@@ -95,3 +97,596 @@ fn what_to_show() {
     assert_eq!(WhatToShow::Bid.to_string(), "BID");
     assert_eq!(WhatToShow::Ask.to_string(), "ASK");
 }
+
+#[test]
+fn forex_market_snapshot() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "81|9000|0.00005|IDEALPRO|3|".to_owned(),
+            "1|6|9000|1|1.08745|1000000|1|".to_owned(),
+            "2|6|9000|0|1000000|".to_owned(),
+            "1|6|9000|2|1.08755|2000000|1|".to_owned(),
+            "2|6|9000|3|2000000|".to_owned(),
+            "1|6|9000|4|-1|0|0|".to_owned(),
+            "1|6|9000|9|1.0862|0|0|".to_owned(),
+            "57|1|9000|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::forex("EUR", "USD");
+    assert_eq!(contract.security_type, SecurityType::ForexPair, "contract.security_type");
+    assert_eq!(contract.exchange, "IDEALPRO", "contract.exchange");
+
+    let snapshot = client.market_snapshot(&contract, false);
+    assert!(snapshot.is_ok(), "failed to request market snapshot: {}", snapshot.err().unwrap());
+
+    let snapshot = snapshot.unwrap();
+    assert_eq!(snapshot.bid, Some(1.08745), "snapshot.bid");
+    assert_eq!(snapshot.bid_size, Some(1000000.0), "snapshot.bid_size");
+    assert_eq!(snapshot.ask, Some(1.08755), "snapshot.ask");
+    assert_eq!(snapshot.ask_size, Some(2000000.0), "snapshot.ask_size");
+    assert_eq!(snapshot.last, None, "snapshot.last");
+    assert_eq!(snapshot.volume, None, "snapshot.volume");
+    assert_eq!(snapshot.close, Some(1.0862), "snapshot.close");
+    assert_eq!(snapshot.timestamp, None, "snapshot.timestamp");
+    assert_eq!(snapshot.price(), Some((1.08745 + 1.08755) / 2.0), "snapshot.price()");
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(
+        request_messages[0].encode_simple(),
+        "1|11|9000|0|EUR|CASH||0|||IDEALPRO||USD|||0||1|0||",
+        "market data request"
+    );
+}
+
+#[test]
+fn tick_type_normalized() {
+    assert_eq!(TickType::from(66), TickType::DelayedBid);
+    assert_eq!(TickType::DelayedBid.normalized(), TickType::Bid);
+    assert_eq!(TickType::DelayedAskSize.normalized(), TickType::AskSize);
+    assert_eq!(TickType::DelayedOpen.normalized(), TickType::Open);
+    assert_eq!(TickType::Bid.normalized(), TickType::Bid);
+    assert_eq!(TickType::from(45), TickType::LastTimestamp);
+    assert_eq!(TickType::from(88).normalized(), TickType::LastTimestamp);
+    assert_eq!(TickType::from(46).normalized(), TickType::Unknown(46));
+
+    assert!(TickType::DelayedLast.is_delayed());
+    assert!(!TickType::Last.is_delayed());
+}
+
+#[test]
+fn delayed_market_snapshot() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "1|6|9000|66|180.25|300|1|".to_owned(),
+            "1|6|9000|67|180.30|200|1|".to_owned(),
+            "1|6|9000|68|180.27|100|0|".to_owned(),
+            "2|6|9000|74|125000|".to_owned(),
+            "46|6|9000|88|1681133400|".to_owned(),
+            "57|1|9000|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("AAPL");
+
+    let snapshot = client.market_snapshot(&contract, false).expect("failed to request market snapshot");
+    assert!(!snapshot.billed, "snapshot.billed");
+    assert_eq!(snapshot.bid, Some(180.25), "snapshot.bid");
+    assert_eq!(snapshot.ask, Some(180.30), "snapshot.ask");
+    assert_eq!(snapshot.last, Some(180.27), "snapshot.last");
+    assert_eq!(snapshot.volume, Some(125000.0), "snapshot.volume");
+    assert_eq!(
+        snapshot.timestamp,
+        Some(OffsetDateTime::from_unix_timestamp(1681133400).unwrap()),
+        "snapshot.timestamp"
+    );
+}
+
+#[test]
+fn regulatory_snapshot() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["1|6|9000|1|180.25|300|1|".to_owned(), "57|1|9000|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::REQ_SMART_COMPONENTS);
+
+    let contract = Contract::stock("AAPL");
+
+    let snapshot = client.market_snapshot(&contract, true).expect("failed to request regulatory snapshot");
+    assert_eq!(snapshot.bid, Some(180.25), "snapshot.bid");
+    assert!(snapshot.billed, "snapshot.billed");
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0][17], "1", "message.snapshot");
+    assert_eq!(request_messages[0][18], "1", "message.regulatory_snapshot");
+}
+
+#[test]
+fn regulatory_snapshot_not_supported() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::REQ_SMART_COMPONENTS - 1);
+
+    let contract = Contract::stock("AAPL");
+
+    let result = client.market_snapshot(&contract, true);
+    assert!(
+        matches!(result, Err(Error::ServerVersion(_, _, _))),
+        "expected server version error, got {result:?}"
+    );
+
+    // nothing is sent, so nothing is billed
+    assert_eq!(client.message_bus().request_messages().len(), 0, "request_messages.len()");
+}
+
+#[test]
+fn market_data() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "81|9000|0.01|9c0001|3|".to_owned(),
+            "1|6|9000|1|180.25|300|1|".to_owned(),
+            "2|6|9000|0|300|".to_owned(),
+            "46|6|9000|45|1681133400|".to_owned(),
+            "45|6|9000|46|3.0|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("AAPL");
+
+    let ticks = client
+        .market_data(&contract, &[GenericTick::RTVolume, GenericTick::Shortable], false)
+        .expect("failed to request market data");
+    let ticks: Vec<TickEvent> = ticks.collect();
+
+    assert_eq!(
+        ticks,
+        vec![
+            TickEvent::RequestParameters(TickRequestParameters {
+                min_tick: 0.01,
+                bbo_exchange: "9c0001".to_owned(),
+                snapshot_permissions: 3,
+            }),
+            TickEvent::Price {
+                tick_type: TickType::Bid,
+                price: Some(180.25),
+                attributes: TickAttribute {
+                    can_auto_execute: true,
+                    ..Default::default()
+                }
+            },
+            TickEvent::Size {
+                tick_type: TickType::BidSize,
+                size: 300.0
+            },
+            TickEvent::String {
+                tick_type: TickType::LastTimestamp,
+                value: "1681133400".to_owned()
+            },
+            TickEvent::Generic {
+                tick_type: TickType::Unknown(46),
+                value: 3.0
+            },
+        ]
+    );
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0][16], "233,236", "message.generic_ticks");
+    assert_eq!(request_messages[0][17], "0", "message.snapshot");
+    assert_eq!(request_messages[1].encode_simple(), "2|2|9000|", "cancel market data on drop");
+}
+
+#[test]
+fn market_data_snapshot() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["1|6|9000|2|180.30|200|1|".to_owned(), "57|1|9000|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("AAPL");
+
+    let mut ticks = client.market_data(&contract, &[], true).expect("failed to request market data");
+
+    assert_eq!(
+        ticks.next(),
+        Some(TickEvent::Price {
+            tick_type: TickType::Ask,
+            price: Some(180.30),
+            attributes: TickAttribute {
+                can_auto_execute: true,
+                ..Default::default()
+            }
+        })
+    );
+    assert_eq!(ticks.next(), Some(TickEvent::SnapshotEnd));
+    assert_eq!(ticks.next(), None, "snapshot ended");
+    drop(ticks);
+
+    // the server releases snapshots, there is nothing to cancel
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages.len(), 1, "request_messages.len()");
+    assert_eq!(request_messages[0][17], "1", "message.snapshot");
+}
+
+#[test]
+fn delayed_market_data() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["58|1|9000|3|".to_owned(), "1|6|9000|66|180.25|300|1|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    client
+        .set_market_data_type(MarketDataType::Delayed)
+        .expect("failed to set market data type");
+
+    let contract = Contract::stock("AAPL");
+    let ticks: Vec<TickEvent> = client
+        .market_data(&contract, &[], false)
+        .expect("failed to request market data")
+        .collect();
+
+    assert_eq!(
+        ticks,
+        vec![
+            TickEvent::MarketDataType(MarketDataType::Delayed),
+            TickEvent::Price {
+                tick_type: TickType::DelayedBid,
+                price: Some(180.25),
+                attributes: TickAttribute {
+                    can_auto_execute: true,
+                    ..Default::default()
+                }
+            },
+        ]
+    );
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0].encode_simple(), "59|1|3|", "market data type request");
+    assert_eq!(
+        request_messages[1][0],
+        OutgoingMessages::RequestMarketData.to_field(),
+        "market data request"
+    );
+}
+
+#[test]
+fn market_data_type_not_supported() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::REQ_MARKET_DATA_TYPE - 1);
+
+    let result = client.set_market_data_type(MarketDataType::Frozen);
+    assert!(
+        matches!(result, Err(Error::ServerVersion(_, _, _))),
+        "expected server version error, got {result:?}"
+    );
+}
+
+#[test]
+fn tick_by_tick_last() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "99|9000|1|1678740829|3895.25|7|2|NASDAQ|O X|".to_owned(),
+            "99|9000|1|1678740830|3895.50|100|0|ISLAND||".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::TICK_BY_TICK_IGNORE_SIZE);
+
+    let contract = Contract::stock("AAPL");
+    let trades: Vec<Trade> = client.tick_by_tick_last(&contract, 0, false).expect("failed to request ticks").collect();
+
+    assert_eq!(trades.len(), 2, "trades.len()");
+    assert_eq!(trades[0].time, OffsetDateTime::from_unix_timestamp(1678740829).unwrap(), "trades[0].time");
+    assert_eq!(trades[0].price, 3895.25, "trades[0].price");
+    assert_eq!(trades[0].size, 7, "trades[0].size");
+    assert!(trades[0].trade_attribute.unreported, "trades[0].trade_attribute.unreported");
+    assert_eq!(trades[0].exchange, "NASDAQ", "trades[0].exchange");
+    assert_eq!(trades[0].special_conditions, "O X", "trades[0].special_conditions");
+    assert_eq!(trades[1].size, 100, "trades[1].size");
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(
+        request_messages[0].encode_simple(),
+        "97|9000|0|AAPL|STK||0|||SMART||USD|||Last|0|0|",
+        "tick by tick request"
+    );
+    assert_eq!(request_messages[1].encode_simple(), "98|9000|", "cancel tick by tick on drop");
+}
+
+#[test]
+fn tick_by_tick_bid_ask() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["99|9000|3|1678745793|3895.50|3896.00|9|11|3|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::TICK_BY_TICK_IGNORE_SIZE);
+
+    let contract = Contract::stock("AAPL");
+    let mut ticks = client.tick_by_tick_bid_ask(&contract, 0, true).expect("failed to request ticks");
+
+    let bid_ask = ticks.next().expect("expected a bid/ask tick");
+    assert_eq!(bid_ask.bid_price, 3895.50, "bid_ask.bid_price");
+    assert_eq!(bid_ask.ask_price, 3896.00, "bid_ask.ask_price");
+    assert_eq!(bid_ask.bid_size, 9, "bid_ask.bid_size");
+    assert_eq!(bid_ask.ask_size, 11, "bid_ask.ask_size");
+    assert_eq!(
+        bid_ask.bid_ask_attribute,
+        BidAskAttribute {
+            bid_past_low: true,
+            ask_past_high: true
+        },
+        "bid_ask.bid_ask_attribute"
+    );
+    drop(ticks);
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0][14], "BidAsk", "message.tick_type");
+    assert_eq!(request_messages[0][16], "1", "message.ignore_size");
+    assert_eq!(request_messages[1].encode_simple(), "98|9000|", "cancel tick by tick on drop");
+}
+
+#[test]
+fn tick_by_tick_midpoint() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["99|9000|4|1678746113|3896.875|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::TICK_BY_TICK_IGNORE_SIZE);
+
+    let contract = Contract::stock("AAPL");
+    let mid_points: Vec<MidPoint> = client
+        .tick_by_tick_midpoint(&contract, 0, false)
+        .expect("failed to request ticks")
+        .collect();
+
+    assert_eq!(mid_points.len(), 1, "mid_points.len()");
+    assert_eq!(mid_points[0].mid_point, 3896.875, "mid_points[0].mid_point");
+}
+
+#[test]
+fn tick_by_tick_not_supported() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::TICK_BY_TICK);
+
+    let contract = Contract::stock("AAPL");
+    let result = client.tick_by_tick_all_last(&contract, 10, false);
+    assert!(
+        matches!(result, Err(Error::ServerVersion(_, _, _))),
+        "number_of_ticks requires a newer server"
+    );
+}
+
+#[test]
+fn market_depth() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "12|1|9000|0|0|1|185.50|300|".to_owned(),
+            "12|1|9000|0|0|0|185.60|200|".to_owned(),
+            "13|1|9000|1|ARCA|1|1|185.45|100|0|".to_owned(),
+            "12|1|9000|0|2|1|185.50|0|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::MKT_DEPTH_PRIM_EXCHANGE);
+
+    let contract = Contract::stock("AAPL");
+    let updates: Vec<MarketDepthUpdate> = client
+        .market_depth(&contract, 5, false)
+        .expect("failed to request market depth")
+        .collect();
+
+    assert_eq!(updates.len(), 4, "updates.len()");
+    assert_eq!(
+        updates[0],
+        MarketDepthUpdate::MarketDepth(MarketDepth {
+            position: 0,
+            operation: DepthOperation::Insert,
+            side: DepthSide::Bid,
+            price: 185.50,
+            size: 300.0,
+        })
+    );
+    assert_eq!(
+        updates[2],
+        MarketDepthUpdate::MarketDepthL2(MarketDepthL2 {
+            position: 1,
+            market_maker: "ARCA".to_owned(),
+            operation: DepthOperation::Update,
+            side: DepthSide::Bid,
+            price: 185.45,
+            size: 100.0,
+            smart_depth: false,
+        })
+    );
+    assert!(
+        matches!(&updates[3], MarketDepthUpdate::MarketDepth(depth) if depth.operation == DepthOperation::Delete),
+        "updates[3].operation"
+    );
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(
+        request_messages[0][0],
+        OutgoingMessages::RequestMarketDepth.to_field(),
+        "market depth request"
+    );
+    assert_eq!(request_messages[0][15], "5", "message.number_of_rows");
+    assert_eq!(request_messages[1].encode_simple(), "11|1|9000|0|", "cancel market depth on drop");
+}
+
+#[test]
+fn market_depth_primary_exchange_not_supported() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::MKT_DEPTH_PRIM_EXCHANGE - 1);
+
+    let mut contract = Contract::stock("AAPL");
+    contract.primary_exchange = "NASDAQ".to_owned();
+
+    let result = client.market_depth(&contract, 5, false);
+    assert!(matches!(result, Err(Error::ServerVersion(_, _, _))), "expected server version error");
+}
+
+#[test]
+fn smart_market_depth() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["13|1|9000|0|ISLAND|0|1|185.50|300|1|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::MKT_DEPTH_PRIM_EXCHANGE);
+
+    let contract = Contract::stock("AAPL");
+    let mut updates = client.market_depth(&contract, 5, true).expect("failed to request market depth");
+
+    match updates.next() {
+        Some(MarketDepthUpdate::MarketDepthL2(depth)) => {
+            assert_eq!(depth.market_maker, "ISLAND", "depth.market_maker");
+            assert!(depth.smart_depth, "depth.smart_depth");
+        }
+        update => panic!("expected a level II update, got {update:?}"),
+    }
+    drop(updates);
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0][16], "1", "message.is_smart_depth");
+    assert_eq!(request_messages[1].encode_simple(), "11|1|9000|1|", "cancel smart market depth on drop");
+}
+
+#[test]
+fn smart_market_depth_not_supported() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SMART_DEPTH - 1);
+
+    let contract = Contract::stock("AAPL");
+    let result = client.market_depth(&contract, 5, true);
+    assert!(matches!(result, Err(Error::ServerVersion(_, _, _))), "expected server version error");
+}
+
+#[test]
+fn market_depth_exchanges() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec!["80|2|ISLAND|STK|NASDAQ|Deep2|7|ARCA|STK|NYSE|Deep|7|".to_owned()],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let exchanges = client.market_depth_exchanges().expect("failed to request market depth exchanges");
+
+    assert_eq!(exchanges.len(), 2, "exchanges.len()");
+    assert_eq!(exchanges[1].exchange, "ARCA", "exchanges[1].exchange");
+    assert_eq!(exchanges[1].service_data_type, "Deep", "exchanges[1].service_data_type");
+    assert_eq!(exchanges[1].aggregated_group, Some(7), "exchanges[1].aggregated_group");
+
+    let request_messages = client.message_bus().request_messages();
+    assert_eq!(request_messages[0].encode_simple(), "82|", "market depth exchanges request");
+}
+
+#[test]
+fn market_depth_reset() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "12|1|9000|0|0|1|185.50|300|".to_owned(),
+            "4|2|9000|317|Market depth data has been RESET. Please empty deep book contents before applying any new entries.|".to_owned(),
+            "12|1|9000|0|0|1|185.55|100|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::MKT_DEPTH_PRIM_EXCHANGE);
+
+    let contract = Contract::stock("AAPL");
+    let updates = client.market_depth(&contract, 5, false).expect("failed to request market depth");
+
+    let mut book = OrderBook::new();
+    let changes: Vec<Option<BookChange>> = updates.map(|update| book.apply(&update)).collect();
+
+    assert_eq!(changes.len(), 3, "reset doesn't end the subscription");
+    assert_eq!(changes[1], Some(BookChange::Cleared), "changes[1]");
+    assert_eq!(book.bids().len(), 1, "book.bids().len()");
+    assert_eq!(book.best_bid().map(|level| level.price), Some(185.55), "book.best_bid().price");
+}
+
+#[test]
+fn generic_tick_ids() {
+    assert_eq!(GenericTick::OptionVolume.id(), 100);
+    assert_eq!(GenericTick::Dividends.id(), 456);
+    assert_eq!(GenericTick::from(233), GenericTick::RTVolume);
+    assert_eq!(GenericTick::from(577), GenericTick::Other(577));
+    assert_eq!(GenericTick::list(&[]), "");
+    assert_eq!(
+        GenericTick::list(&[GenericTick::OptionOpenInterest, GenericTick::HistoricalVolatility]),
+        "101,104"
+    );
+}
+
+#[test]
+fn market_data_rt_volume() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "46|6|9000|48|701.28;1;1348075471534;67854;701.46918464;true|".to_owned(),
+            "46|6|9000|77|garbled|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("AAPL");
+    let ticks: Vec<TickEvent> = client
+        .market_data(&contract, &[GenericTick::RTVolume, GenericTick::RTTradeVolume], false)
+        .expect("failed to request market data")
+        .collect();
+
+    match &ticks[0] {
+        TickEvent::RTVolume { tick_type, volume } => {
+            assert_eq!(*tick_type, TickType::RTVolume, "tick_type");
+            assert_eq!(volume.last_price, Some(701.28), "volume.last_price");
+            assert_eq!(volume.total_volume, 67854.0, "volume.total_volume");
+        }
+        tick => panic!("expected an RTVolume tick, got {tick:?}"),
+    }
+    assert_eq!(
+        ticks[1],
+        TickEvent::String {
+            tick_type: TickType::RTTradeVolume,
+            value: "garbled".to_owned()
+        },
+        "unparseable ticks are passed through"
+    );
+}
@@ -1,17 +1,33 @@
-use std::ops::Index;
+use std::fmt::Debug;
+use std::ops::{Index, Range};
 use std::str::{self, FromStr};
+use std::sync::Arc;
 
-use log::error;
+use log::{error, warn};
 use time::OffsetDateTime;
 
-use crate::{Error, ToField};
+use crate::client::transport::BufferPool;
+use crate::client::MetricsObserver;
+use crate::client::ParseMode;
+use crate::{server_versions, Error, ToField};
 
+mod describe;
+mod encoders;
+
+pub use describe::{describe, describe_for_server_version, DecodedField, DecodedMessage};
+
+#[cfg(feature = "orders")]
 const INFINITY_STR: &str = "Infinity";
+#[cfg(feature = "orders")]
 const UNSET_DOUBLE: &str = "1.7976931348623157E308";
+#[cfg(any(feature = "market_data", feature = "orders"))]
 const UNSET_INTEGER: &str = "2147483647";
+#[cfg(feature = "orders")]
 const UNSET_LONG: &str = "9223372036854775807";
 
-#[derive(Debug, PartialEq)]
+/// Ids of the messages sent by TWS. The first field of each [ResponseMessage].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(i32)]
 pub enum IncomingMessages {
     NotValid = -1,
     TickPrice = 1,
@@ -96,6 +112,10 @@ pub enum IncomingMessages {
     WshEventData = 105,
     HistoricalSchedule = 106,
     UserInfo = 107,
+    HistoricalDataEnd = 108,
+    CurrentTimeInMillis = 109,
+    /// A message id this library does not support yet. Carries the id as received.
+    Unknown(i32),
 }
 
 impl From<i32> for IncomingMessages {
@@ -183,12 +203,14 @@ impl From<i32> for IncomingMessages {
             105 => IncomingMessages::WshEventData,
             106 => IncomingMessages::HistoricalSchedule,
             107 => IncomingMessages::UserInfo,
-            _ => IncomingMessages::NotValid,
+            108 => IncomingMessages::HistoricalDataEnd,
+            109 => IncomingMessages::CurrentTimeInMillis,
+            _ => IncomingMessages::Unknown(value),
         }
     }
 }
 
-pub fn order_id_index(kind: IncomingMessages) -> Option<usize> {
+pub(crate) fn order_id_index(kind: IncomingMessages) -> Option<usize> {
     match kind {
         IncomingMessages::OpenOrder | IncomingMessages::OrderStatus => Some(1),
         IncomingMessages::ExecutionData | IncomingMessages::ExecutionDataEnd => Some(2),
@@ -196,7 +218,7 @@ pub fn order_id_index(kind: IncomingMessages) -> Option<usize> {
     }
 }
 
-pub fn request_id_index(kind: IncomingMessages) -> Option<usize> {
+pub(crate) fn request_id_index(kind: IncomingMessages) -> Option<usize> {
     match kind {
         IncomingMessages::ContractData
         | IncomingMessages::TickByTick
@@ -205,11 +227,25 @@ pub fn request_id_index(kind: IncomingMessages) -> Option<usize> {
         | IncomingMessages::ExecutionData
         | IncomingMessages::HeadTimestamp
         | IncomingMessages::HistoricalData
+        | IncomingMessages::HistoricalDataUpdate
         | IncomingMessages::HistoricalSchedule
         | IncomingMessages::HistoricalTick
         | IncomingMessages::HistoricalTickBidAsk
-        | IncomingMessages::HistoricalTickLast => Some(1),
-        IncomingMessages::ContractDataEnd | IncomingMessages::RealTimeBars | IncomingMessages::Error | IncomingMessages::ExecutionDataEnd => Some(2),
+        | IncomingMessages::HistoricalTickLast
+        | IncomingMessages::ReplaceFAEnd
+        | IncomingMessages::TickReqParams => Some(1),
+        IncomingMessages::ContractDataEnd
+        | IncomingMessages::RealTimeBars
+        | IncomingMessages::Error
+        | IncomingMessages::ExecutionDataEnd
+        | IncomingMessages::TickPrice
+        | IncomingMessages::TickSize
+        | IncomingMessages::Tickstring
+        | IncomingMessages::TickGeneric
+        | IncomingMessages::TickSnapshotEnd
+        | IncomingMessages::MarketDataType
+        | IncomingMessages::MarketDepth
+        | IncomingMessages::MarketDepthL2 => Some(2),
         _ => {
             error!("could not determine request id index for {kind:?}");
             None
@@ -217,6 +253,7 @@ pub fn request_id_index(kind: IncomingMessages) -> Option<usize> {
     }
 }
 
+/// Ids of the messages sent to TWS. The first field of each [RequestMessage].
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone)]
 pub enum OutgoingMessages {
@@ -300,6 +337,7 @@ pub enum OutgoingMessages {
     RequestWshEventData = 102,
     CancelWshEventData = 103,
     RequestUserInfo = 104,
+    RequestCurrentTimeInMillis = 105,
 }
 
 impl ToField for OutgoingMessages {
@@ -308,22 +346,70 @@ impl ToField for OutgoingMessages {
     }
 }
 
+/// Returns the version field sent after the message id, or None when the server no longer expects one.
+pub(crate) fn message_version(message_type: OutgoingMessages, server_version: i32) -> Option<i32> {
+    match message_type {
+        OutgoingMessages::PlaceOrder if server_version >= server_versions::ORDER_CONTAINER => None,
+        OutgoingMessages::PlaceOrder if server_version >= server_versions::NOT_HELD => Some(45),
+        OutgoingMessages::PlaceOrder => Some(27),
+        OutgoingMessages::RequestHistoricalData if server_version >= server_versions::SYNT_REALTIME_BARS => None,
+        OutgoingMessages::RequestHistoricalData => Some(6),
+        OutgoingMessages::RequestMarketData => Some(11),
+        OutgoingMessages::CancelMarketData => Some(2),
+        OutgoingMessages::RequestContractData => Some(8),
+        OutgoingMessages::RequestRealTimeBars => Some(8),
+        OutgoingMessages::RequestExecutions => Some(3),
+        OutgoingMessages::StartApi => Some(2),
+        OutgoingMessages::RequestCompletedOrders
+        | OutgoingMessages::RequestHeadTimestamp
+        | OutgoingMessages::RequestHistoricalTicks
+        | OutgoingMessages::RequestTickByTickData
+        | OutgoingMessages::CancelTickByTickData
+        | OutgoingMessages::RequestMatchingSymbols
+        | OutgoingMessages::RequestMarketRule
+        | OutgoingMessages::RequestCurrentTimeInMillis => None,
+        _ => Some(1),
+    }
+}
+
+/// A message to send to TWS. Built by the client, and encoded by the [MessageBus](crate::client::MessageBus) sending it.
+///
+/// Fields are indexed in the order sent, starting with the message id, see [OutgoingMessages]. Requests are created by
+/// constructors named after them, e.g. [RequestMessage::market_rule], which encode the fields expected by the server version given.
+///
+/// ```
+/// use ibapi::messages::RequestMessage;
+///
+/// let message = RequestMessage::market_rule(26).expect("encoding failed");
+/// assert_eq!(message.encode(), "91\026\0");
+/// ```
 #[derive(Default, Debug, Clone)]
-pub(crate) struct RequestMessage {
+pub struct RequestMessage {
     fields: Vec<String>,
 }
 
 impl RequestMessage {
-    pub fn new() -> Self {
+    #[cfg(any(test, feature = "accounts"))]
+    pub(crate) fn new() -> Self {
         Self::default()
     }
 
-    pub fn push_field<T: ToField>(&mut self, val: &T) -> &RequestMessage {
+    /// Pushes the message id followed by its version field, if the server still expects one.
+    pub(crate) fn push_message_header(&mut self, message_type: OutgoingMessages, server_version: i32) -> &RequestMessage {
+        self.push_field(&message_type);
+        if let Some(version) = message_version(message_type, server_version) {
+            self.push_field(&version);
+        }
+        self
+    }
+
+    pub(crate) fn push_field<T: ToField>(&mut self, val: &T) -> &RequestMessage {
         let field = val.to_field();
         self.fields.push(field);
         self
     }
 
+    /// Returns the fields, each terminated by a nul character, as sent to TWS after the 4 byte length prefix.
     pub fn encode(&self) -> String {
         let mut data = self.fields.join("\0");
         data.push('\0');
@@ -336,6 +422,11 @@ impl RequestMessage {
         data.push('|');
         data
     }
+
+    /// Returns the fields of the message.
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
 }
 
 impl Index<usize> for RequestMessage {
@@ -346,22 +437,52 @@ impl Index<usize> for RequestMessage {
     }
 }
 
-#[derive(Clone, Default, Debug)]
-pub(crate) struct ResponseMessage {
-    pub i: usize,
-    pub fields: Vec<String>,
+/// A message received from TWS. Created by the [MessageBus](crate::client::MessageBus) receiving it, and decoded by the client.
+// Fields are slices of the message as received, so decoding numeric fields doesn't allocate.
+#[derive(Clone, Default)]
+pub struct ResponseMessage {
+    pub(crate) i: usize,
+    // nul separated fields, as received
+    data: String,
+    // byte range of each field in data
+    pub(crate) fields: Vec<Range<usize>>,
+    pub(crate) parse_mode: ParseMode,
+    // receives data once the message is dropped
+    pool: Option<Arc<BufferPool>>,
+    // notified of fields that fail to parse
+    pub(crate) metrics: Option<Arc<dyn MetricsObserver>>,
+}
+
+impl Debug for ResponseMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields: Vec<&str> = self.fields.iter().map(|range| &self.data[range.clone()]).collect();
+        f.debug_struct("ResponseMessage")
+            .field("i", &self.i)
+            .field("fields", &fields)
+            .field("parse_mode", &self.parse_mode)
+            .finish()
+    }
+}
+
+impl Drop for ResponseMessage {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.release(std::mem::take(&mut self.data).into_bytes());
+        }
+    }
 }
 
 impl ResponseMessage {
+    /// Returns the id of the message, its first field.
     pub fn message_type(&self) -> IncomingMessages {
-        if self.fields.is_empty() {
-            IncomingMessages::NotValid
-        } else {
-            let message_id = i32::from_str(&self.fields[0]).unwrap_or(-1);
-            IncomingMessages::from(message_id)
+        let message_id = self.fields.first().map(|range| i32::from_str(&self.data[range.clone()]));
+        match message_id {
+            Some(Ok(message_id)) => IncomingMessages::from(message_id),
+            _ => IncomingMessages::NotValid,
         }
     }
 
+    /// Returns the id of the request the message answers, for messages that answer a request.
     pub fn request_id(&self) -> Option<i32> {
         if let Some(i) = request_id_index(self.message_type()) {
             if let Ok(request_id) = self.peek_int(i) {
@@ -371,6 +492,7 @@ impl ResponseMessage {
         None
     }
 
+    /// Returns the id of the order the message is about, for order messages.
     pub fn order_id(&self) -> Option<i32> {
         if let Some(i) = order_id_index(self.message_type()) {
             if let Ok(order_id) = self.peek_int(i) {
@@ -380,39 +502,64 @@ impl ResponseMessage {
         None
     }
 
+    /// Returns the execution id of execution and commission report messages.
     pub fn execution_id(&self) -> Option<String> {
         match self.message_type() {
-            IncomingMessages::ExecutionData => Some(self.peek_string(14)),
-            IncomingMessages::CommissionsReport => Some(self.peek_string(2)),
+            IncomingMessages::ExecutionData => self.peek_string(14).ok(),
+            IncomingMessages::CommissionsReport => self.peek_string(2).ok(),
             _ => None,
         }
     }
 
-    pub fn peek_int(&self, i: usize) -> Result<i32, Error> {
-        let field = &self.fields[i];
+    pub(crate) fn peek_int(&self, i: usize) -> Result<i32, Error> {
+        let field = self.field(i)?;
         match field.parse() {
             Ok(val) => Ok(val),
             Err(err) => Err(Error::Parse(i, field.into(), err.to_string())),
         }
     }
 
-    pub fn peek_string(&self, i: usize) -> String {
-        self.fields[i].to_owned()
+    pub(crate) fn peek_string(&self, i: usize) -> Result<String, Error> {
+        Ok(self.field(i)?.to_owned())
+    }
+
+    pub(crate) fn next_int(&mut self) -> Result<i32, Error> {
+        self.i += 1;
+        let field = self.field(self.i - 1)?;
+
+        match field.parse() {
+            Ok(val) => Ok(val),
+            Err(err) => self.parse_failure(field, err.to_string(), 0),
+        }
     }
 
-    pub fn next_int(&mut self) -> Result<i32, Error> {
-        let field = &self.fields[self.i];
+    #[cfg(any(feature = "market_data", feature = "orders"))]
+    pub(crate) fn next_u8(&mut self) -> Result<u8, Error> {
         self.i += 1;
+        let field = self.field(self.i - 1)?;
 
         match field.parse() {
             Ok(val) => Ok(val),
-            Err(err) => Err(Error::Parse(self.i, field.into(), err.to_string())),
+            Err(err) => Err(self.parse_error(field, err.to_string())),
         }
     }
 
-    pub fn next_optional_int(&mut self) -> Result<Option<i32>, Error> {
-        let field = &self.fields[self.i];
+    // Returns the first character of the field, for single character flags.
+    #[cfg(feature = "orders")]
+    pub(crate) fn next_char(&mut self) -> Result<char, Error> {
         self.i += 1;
+        let field = self.field(self.i - 1)?;
+
+        match field.chars().next() {
+            Some(val) => Ok(val),
+            None => Err(self.parse_error(field, "expected a character and found empty string".into())),
+        }
+    }
+
+    #[cfg(any(feature = "market_data", feature = "orders"))]
+    pub(crate) fn next_optional_int(&mut self) -> Result<Option<i32>, Error> {
+        self.i += 1;
+        let field = self.field(self.i - 1)?;
 
         if field.is_empty() || field == UNSET_INTEGER {
             return Ok(None);
@@ -420,30 +567,32 @@ impl ResponseMessage {
 
         match field.parse::<i32>() {
             Ok(val) => Ok(Some(val)),
-            Err(err) => Err(Error::Parse(self.i, field.into(), err.to_string())),
+            Err(err) => Err(self.parse_error(field, err.to_string())),
         }
     }
 
-    pub fn next_bool(&mut self) -> Result<bool, Error> {
-        let field = &self.fields[self.i];
+    #[cfg(any(feature = "market_data", feature = "orders"))]
+    pub(crate) fn next_bool(&mut self) -> Result<bool, Error> {
         self.i += 1;
+        let field = self.field(self.i - 1)?;
 
         Ok(field == "1")
     }
 
-    pub fn next_long(&mut self) -> Result<i64, Error> {
-        let field = &self.fields[self.i];
+    pub(crate) fn next_long(&mut self) -> Result<i64, Error> {
         self.i += 1;
+        let field = self.field(self.i - 1)?;
 
         match field.parse() {
             Ok(val) => Ok(val),
-            Err(err) => Err(Error::Parse(self.i, field.into(), err.to_string())),
+            Err(err) => Err(self.parse_error(field, err.to_string())),
         }
     }
 
-    pub fn next_optional_long(&mut self) -> Result<Option<i64>, Error> {
-        let field = &self.fields[self.i];
+    #[cfg(feature = "orders")]
+    pub(crate) fn next_optional_long(&mut self) -> Result<Option<i64>, Error> {
         self.i += 1;
+        let field = self.field(self.i - 1)?;
 
         if field.is_empty() || field == UNSET_LONG {
             return Ok(None);
@@ -451,35 +600,41 @@ impl ResponseMessage {
 
         match field.parse::<i64>() {
             Ok(val) => Ok(Some(val)),
-            Err(err) => Err(Error::Parse(self.i, field.into(), err.to_string())),
+            Err(err) => Err(self.parse_error(field, err.to_string())),
         }
     }
 
-    pub fn next_date_time(&mut self) -> Result<OffsetDateTime, Error> {
-        let field = &self.fields[self.i];
+    pub(crate) fn next_date_time(&mut self) -> Result<OffsetDateTime, Error> {
         self.i += 1;
+        let field = self.field(self.i - 1)?;
 
         if field.is_empty() {
-            return Err(Error::Simple("expected timestamp and found empty string".into()));
+            return Err(self.parse_error(field, "expected timestamp and found empty string".into()));
         }
 
         // from_unix_timestamp
         let timestamp: i64 = field.parse()?;
         match OffsetDateTime::from_unix_timestamp(timestamp) {
             Ok(val) => Ok(val),
-            Err(err) => Err(Error::Parse(self.i, field.into(), err.to_string())),
+            Err(err) => Err(self.parse_error(field, err.to_string())),
         }
     }
 
-    pub fn next_string(&mut self) -> Result<String, Error> {
-        let field = &self.fields[self.i];
+    pub(crate) fn next_string(&mut self) -> Result<String, Error> {
+        self.i += 1;
+        Ok(String::from(self.field(self.i - 1)?))
+    }
+
+    // Borrows the next field, avoiding an allocation when the value is only inspected.
+    #[cfg(feature = "orders")]
+    pub(crate) fn next_str(&mut self) -> Result<&str, Error> {
         self.i += 1;
-        Ok(String::from(field))
+        self.field(self.i - 1)
     }
 
-    pub fn next_double(&mut self) -> Result<f64, Error> {
-        let field = &self.fields[self.i];
+    pub(crate) fn next_double(&mut self) -> Result<f64, Error> {
         self.i += 1;
+        let field = self.field(self.i - 1)?;
 
         if field.is_empty() || field == "0" || field == "0.0" {
             return Ok(0.0);
@@ -487,13 +642,14 @@ impl ResponseMessage {
 
         match field.parse() {
             Ok(val) => Ok(val),
-            Err(err) => Err(Error::Parse(self.i, field.into(), err.to_string())),
+            Err(err) => self.parse_failure(field, err.to_string(), 0.0),
         }
     }
 
-    pub fn next_optional_double(&mut self) -> Result<Option<f64>, Error> {
-        let field = &self.fields[self.i];
+    #[cfg(feature = "orders")]
+    pub(crate) fn next_optional_double(&mut self) -> Result<Option<f64>, Error> {
         self.i += 1;
+        let field = self.field(self.i - 1)?;
 
         if field.is_empty() || field == UNSET_DOUBLE {
             return Ok(None);
@@ -505,27 +661,117 @@ impl ResponseMessage {
 
         match field.parse() {
             Ok(val) => Ok(Some(val)),
-            Err(err) => Err(Error::Parse(self.i, field.into(), err.to_string())),
+            Err(err) => self.parse_failure(field, err.to_string(), None),
         }
     }
 
+    // Fails in strict mode. In lenient mode logs the unparseable field and substitutes the default.
+    fn parse_failure<T: Debug>(&self, field: &str, err: String, default: T) -> Result<T, Error> {
+        match self.parse_mode {
+            ParseMode::Strict => Err(self.parse_error(field, err)),
+            ParseMode::Lenient => {
+                self.report_decode_error();
+                warn!("substituting {default:?} for unparseable field {} {field:?}: {err}", self.i);
+                Ok(default)
+            }
+        }
+    }
+
+    fn parse_error(&self, field: &str, err: String) -> Error {
+        self.report_decode_error();
+        Error::Parse(self.i, field.into(), err)
+    }
+
+    fn report_decode_error(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.decode_error(self.message_type());
+        }
+    }
+
+    #[cfg(test)]
     pub fn from(fields: &str) -> ResponseMessage {
+        ResponseMessage::from_string(fields.to_owned())
+    }
+
+    /// Creates a message from the data received after its 4 byte length prefix: nul terminated fields, starting with the message id.
+    /// The data is split into fields without copying.
+    pub fn from_string(data: String) -> ResponseMessage {
+        let mut fields = Vec::new();
+        let mut start = 0;
+        for (end, _) in data.match_indices('\x00') {
+            fields.push(start..end);
+            start = end + 1;
+        }
+        fields.push(start..data.len());
+
         ResponseMessage {
             i: 0,
-            fields: fields.split('\x00').map(|x| x.to_string()).collect(),
+            data,
+            fields,
+            parse_mode: ParseMode::default(),
+            pool: None,
+            metrics: None,
         }
     }
 
-    pub fn skip(&mut self) {
-        self.i += 1;
+    // Returns the message's buffer to pool once the message is dropped.
+    pub(crate) fn recycle_into(mut self, pool: &Arc<BufferPool>) -> Self {
+        self.pool = Some(Arc::clone(pool));
+        self
     }
 
-    pub fn reset(&mut self) {
-        self.i = 0;
+    /// Returns the message as received, without its length prefix.
+    pub fn raw(&self) -> &str {
+        &self.data
     }
 
-    pub fn encode(&self) -> String {
-        let mut data = self.fields.join("\0");
+    /// Returns the fields following those decoded so far. Decoders read the fields known for the negotiated server version,
+    /// so fields appended by newer servers are left here rather than failing the decode.
+    pub fn unparsed_fields(&self) -> Vec<&str> {
+        self.fields[..self.len()]
+            .iter()
+            .skip(self.i)
+            .map(|range| &self.data[range.clone()])
+            .collect()
+    }
+
+    /// Returns the number of fields in the message, including the message id.
+    pub fn len(&self) -> usize {
+        // data received ends with a nul, leaving an empty field after it
+        if self.data.is_empty() || self.data.ends_with('\0') {
+            self.fields.len() - 1
+        } else {
+            self.fields.len()
+        }
+    }
+
+    /// Returns true for messages without fields.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of fields following those decoded so far. Decoders can check messages hold the fields they expect up front.
+    pub fn remaining(&self) -> usize {
+        self.len().saturating_sub(self.i)
+    }
+
+    // Fails rather than panics for fields past the end of the message, as short messages from the server must not stop the reader thread.
+    fn field(&self, i: usize) -> Result<&str, Error> {
+        match self.fields.get(i) {
+            Some(range) => Ok(&self.data[range.clone()]),
+            None => {
+                self.report_decode_error();
+                Err(Error::Parse(i, String::new(), format!("message has only {} fields", self.fields.len())))
+            }
+        }
+    }
+
+    pub(crate) fn skip(&mut self) {
+        self.i += 1;
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        let mut data = self.data.clone();
         data.push('\0');
         data
     }
@@ -0,0 +1,432 @@
+use std::fmt::{self, Display};
+
+use crate::client::MAX_SERVER_VERSION;
+use crate::server_versions;
+
+use super::{IncomingMessages, ResponseMessage};
+
+/// A message received from TWS with each field labeled by its meaning. Created by [describe].
+#[derive(Debug, PartialEq)]
+pub struct DecodedMessage {
+    /// Id of the message, its first field.
+    pub message_type: IncomingMessages,
+    /// Every field of the message, including the message id.
+    pub fields: Vec<DecodedField>,
+}
+
+/// A field of a [DecodedMessage].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedField {
+    /// Name of the field, e.g. `request_id` or `bars[0].open`, or None for fields this library doesn't know of.
+    pub label: Option<String>,
+    /// Value of the field as received.
+    pub value: String,
+}
+
+impl DecodedMessage {
+    /// Returns the value of the field with the given label.
+    pub fn field(&self, label: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.label.as_deref() == Some(label))
+            .map(|field| field.value.as_str())
+    }
+}
+
+impl Display for DecodedMessage {
+    /// Formats the message type followed by one `label = value` line per field.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.message_type)?;
+        for (i, field) in self.fields.iter().enumerate() {
+            match &field.label {
+                Some(label) => write!(f, "\n  {label} = {:?}", field.value)?,
+                None => write!(f, "\n  [{i}] = {:?}", field.value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Labels the fields of a raw message received from TWS, assuming the latest server version supported by this library.
+///
+/// Messages are described as sent by the server, nul separated and without their length prefix, e.g. as logged or
+/// captured. Fields of messages this library doesn't decode, and fields following the known ones, are left unlabeled.
+///
+/// # Arguments
+/// * `raw` - Fields of the message, each terminated by a nul character.
+///
+/// # Examples
+///
+/// ```
+/// use ibapi::messages::describe;
+///
+/// let message = describe("1\x006\x009000\x004\x00185.5\x00100\x000\x00");
+/// assert_eq!(message.field("request_id"), Some("9000"));
+/// assert_eq!(message.field("price"), Some("185.5"));
+///
+/// println!("{message}");
+/// ```
+pub fn describe(raw: &str) -> DecodedMessage {
+    describe_for_server_version(raw, MAX_SERVER_VERSION)
+}
+
+/// Labels the fields of a raw message received from TWS, as laid out by a server of the given version.
+/// Pass [Client::server_version](crate::Client::server_version) to describe messages of a connection.
+///
+/// # Arguments
+/// * `raw`            - Fields of the message, each terminated by a nul character.
+/// * `server_version` - Version of the server that sent the message.
+pub fn describe_for_server_version(raw: &str, server_version: i32) -> DecodedMessage {
+    let message = ResponseMessage::from_string(raw.to_owned());
+
+    let mut labeler = Labeler::new(&message);
+    labeler.next("message_type");
+    label_fields(&mut labeler, &message.message_type(), server_version);
+
+    let mut fields = labeler.fields;
+    for i in fields.len()..message.len() {
+        fields.push(DecodedField {
+            label: None,
+            value: message.peek_string(i).unwrap_or_default(),
+        });
+    }
+
+    DecodedMessage {
+        message_type: message.message_type(),
+        fields,
+    }
+}
+
+// Labels fields in the order decoders read them.
+struct Labeler<'a> {
+    message: &'a ResponseMessage,
+    fields: Vec<DecodedField>,
+    // prefixes of the fields of the repeated groups being labeled
+    prefixes: Vec<String>,
+}
+
+impl<'a> Labeler<'a> {
+    fn new(message: &'a ResponseMessage) -> Self {
+        Self {
+            message,
+            fields: Vec::new(),
+            prefixes: Vec::new(),
+        }
+    }
+
+    // Labels the next field, returning its value, or an empty value past the end of the message.
+    fn next(&mut self, label: &str) -> String {
+        let i = self.fields.len();
+        if i >= self.message.len() {
+            return String::new();
+        }
+
+        let value = self.message.peek_string(i).unwrap_or_default();
+        self.fields.push(DecodedField {
+            label: Some(format!("{}{label}", self.prefixes.concat())),
+            value: value.clone(),
+        });
+        value
+    }
+
+    fn labels(&mut self, labels: &[&str]) {
+        for label in labels {
+            self.next(label);
+        }
+    }
+
+    fn next_int(&mut self, label: &str) -> i32 {
+        self.next(label).parse().unwrap_or_default()
+    }
+
+    // Labels a count followed by that many groups of fields, e.g. the bars of historical data.
+    fn group(&mut self, count_label: &str, item: &str, mut label_item: impl FnMut(&mut Self)) {
+        let count = self.next_int(count_label);
+        for k in 0..count {
+            if self.fields.len() >= self.message.len() {
+                break;
+            }
+            self.prefixes.push(format!("{item}[{k}]."));
+            label_item(self);
+            self.prefixes.pop();
+        }
+    }
+}
+
+fn label_fields(labeler: &mut Labeler, message_type: &IncomingMessages, server_version: i32) {
+    match message_type {
+        IncomingMessages::TickPrice => labeler.labels(&["version", "request_id", "tick_type", "price", "size", "attributes"]),
+        IncomingMessages::TickSize => labeler.labels(&["version", "request_id", "tick_type", "size"]),
+        IncomingMessages::Tickstring | IncomingMessages::TickGeneric => labeler.labels(&["version", "request_id", "tick_type", "value"]),
+        IncomingMessages::OrderStatus => {
+            if server_version < server_versions::MARKET_CAP_PRICE {
+                labeler.next("version");
+            }
+            labeler.labels(&[
+                "order_id",
+                "status",
+                "filled",
+                "remaining",
+                "average_fill_price",
+                "perm_id",
+                "parent_id",
+                "last_fill_price",
+                "client_id",
+                "why_held",
+            ]);
+            if server_version >= server_versions::MARKET_CAP_PRICE {
+                labeler.next("market_cap_price");
+            }
+        }
+        IncomingMessages::Error => {
+            labeler.labels(&["version", "request_id", "code", "message"]);
+            if server_version >= server_versions::ADVANCED_ORDER_REJECT {
+                labeler.next("advanced_order_reject_json");
+            }
+        }
+        IncomingMessages::AccountValue => labeler.labels(&["version", "key", "value", "currency", "account"]),
+        IncomingMessages::AccountUpdateTime => labeler.labels(&["version", "time"]),
+        IncomingMessages::AccountDownloadEnd => labeler.labels(&["version", "account"]),
+        IncomingMessages::NextValidId => labeler.labels(&["version", "order_id"]),
+        IncomingMessages::ManagedAccounts => labeler.labels(&["version", "accounts"]),
+        IncomingMessages::CurrentTime => labeler.labels(&["version", "time"]),
+        IncomingMessages::CurrentTimeInMillis => labeler.labels(&["time"]),
+        IncomingMessages::MarketDataType => labeler.labels(&["version", "request_id", "market_data_type"]),
+        IncomingMessages::MarketDepth => labeler.labels(&["version", "request_id", "position", "operation", "side", "price", "size"]),
+        IncomingMessages::MarketDepthL2 => {
+            labeler.labels(&["version", "request_id", "position", "market_maker", "operation", "side", "price", "size"]);
+            if server_version >= server_versions::SMART_DEPTH {
+                labeler.next("smart_depth");
+            }
+        }
+        IncomingMessages::TickSnapshotEnd | IncomingMessages::ContractDataEnd | IncomingMessages::ExecutionDataEnd => {
+            labeler.labels(&["version", "request_id"])
+        }
+        IncomingMessages::AccountSummaryEnd | IncomingMessages::PositionMultiEnd | IncomingMessages::AccountUpdateMultiEnd => {
+            labeler.labels(&["version", "request_id"])
+        }
+        IncomingMessages::OpenOrderEnd | IncomingMessages::PositionEnd => labeler.labels(&["version"]),
+        IncomingMessages::AccountSummary => labeler.labels(&["version", "request_id", "account", "tag", "value", "currency"]),
+        IncomingMessages::Position => {
+            labeler.labels(&["version", "account"]);
+            label_contract(labeler);
+            labeler.labels(&["position", "average_cost"]);
+        }
+        IncomingMessages::PnL => labeler.labels(&["request_id", "daily_pnl", "unrealized_pnl", "realized_pnl"]),
+        IncomingMessages::PnLSingle => labeler.labels(&["request_id", "position", "daily_pnl", "unrealized_pnl", "realized_pnl", "value"]),
+        IncomingMessages::ExecutionData => {
+            if server_version < server_versions::LAST_LIQUIDITY {
+                labeler.next("version");
+            }
+            labeler.labels(&["request_id", "order_id"]);
+            label_contract(labeler);
+            labeler.labels(&[
+                "execution_id",
+                "time",
+                "account_number",
+                "execution_exchange",
+                "side",
+                "shares",
+                "price",
+                "perm_id",
+                "client_id",
+                "liquidation",
+                "cumulative_quantity",
+                "average_price",
+                "order_reference",
+                "ev_rule",
+                "ev_multiplier",
+            ]);
+            if server_version >= server_versions::MODELS_SUPPORT {
+                labeler.next("model_code");
+            }
+            if server_version >= server_versions::LAST_LIQUIDITY {
+                labeler.next("last_liquidity");
+            }
+        }
+        IncomingMessages::CommissionsReport => labeler.labels(&[
+            "version",
+            "execution_id",
+            "commission",
+            "currency",
+            "realized_pnl",
+            "yield",
+            "yield_redemption_date",
+        ]),
+        IncomingMessages::ContractData => label_contract_data(labeler, server_version),
+        IncomingMessages::SymbolSamples => {
+            labeler.next("request_id");
+            labeler.group("contract_descriptions_count", "contract_descriptions", |labeler| {
+                labeler.labels(&["contract_id", "symbol", "security_type", "primary_exchange", "currency"]);
+                labeler.group("derivative_security_types_count", "derivative_security_types", |labeler| {
+                    labeler.next("security_type");
+                });
+                if server_version >= server_versions::BOND_ISSUERID {
+                    labeler.labels(&["description", "issuer_id"]);
+                }
+            });
+        }
+        IncomingMessages::MarketRule => {
+            labeler.next("market_rule_id");
+            labeler.group("price_increments_count", "price_increments", |labeler| {
+                labeler.labels(&["low_edge", "increment"])
+            });
+        }
+        IncomingMessages::FamilyCodes => {
+            labeler.group("family_codes_count", "family_codes", |labeler| {
+                labeler.labels(&["account_id", "family_code"])
+            });
+        }
+        IncomingMessages::ReplaceFAEnd => labeler.labels(&["request_id", "text"]),
+        IncomingMessages::HeadTimestamp => labeler.labels(&["request_id", "head_timestamp"]),
+        IncomingMessages::HistoricalData => {
+            if server_version < server_versions::SYNT_REALTIME_BARS {
+                labeler.next("version");
+            }
+            labeler.labels(&["request_id", "start", "end"]);
+            labeler.group("bars_count", "bars", |labeler| {
+                labeler.labels(&["date", "open", "high", "low", "close", "volume", "wap"]);
+                if server_version < server_versions::SYNT_REALTIME_BARS {
+                    labeler.next("has_gaps");
+                }
+                labeler.next("count");
+            });
+        }
+        IncomingMessages::HistoricalDataUpdate => labeler.labels(&["request_id", "count", "date", "open", "close", "high", "low", "wap", "volume"]),
+        IncomingMessages::HistoricalDataEnd => labeler.labels(&["request_id", "start", "end"]),
+        IncomingMessages::HistoricalSchedule => {
+            labeler.labels(&["request_id", "start", "end", "time_zone"]);
+            labeler.group("sessions_count", "sessions", |labeler| {
+                labeler.labels(&["start", "end", "reference_date"])
+            });
+        }
+        IncomingMessages::HistoricalTick => {
+            labeler.next("request_id");
+            labeler.group("ticks_count", "ticks", |labeler| labeler.labels(&["time", "unused", "price", "size"]));
+            labeler.next("done");
+        }
+        IncomingMessages::HistoricalTickBidAsk => {
+            labeler.next("request_id");
+            labeler.group("ticks_count", "ticks", |labeler| {
+                labeler.labels(&["time", "attributes", "bid_price", "ask_price", "bid_size", "ask_size"])
+            });
+            labeler.next("done");
+        }
+        IncomingMessages::HistoricalTickLast => {
+            labeler.next("request_id");
+            labeler.group("ticks_count", "ticks", |labeler| {
+                labeler.labels(&["time", "attributes", "price", "size", "exchange", "special_conditions"])
+            });
+            labeler.next("done");
+        }
+        IncomingMessages::RealTimeBars => {
+            labeler.labels(&["version", "request_id", "time", "open", "high", "low", "close", "volume", "wap", "count"])
+        }
+        IncomingMessages::TickByTick => {
+            labeler.next("request_id");
+            let tick_type = labeler.next_int("tick_type");
+            labeler.next("time");
+            match tick_type {
+                1 | 2 => labeler.labels(&["price", "size", "attributes", "exchange", "special_conditions"]),
+                3 => labeler.labels(&["bid_price", "ask_price", "bid_size", "ask_size", "attributes"]),
+                4 => labeler.labels(&["mid_point"]),
+                _ => (),
+            }
+        }
+        IncomingMessages::TickReqParams => labeler.labels(&["request_id", "min_tick", "bbo_exchange", "snapshot_permissions"]),
+        _ => (),
+    }
+}
+
+// Fields of the contract in position and execution messages.
+fn label_contract(labeler: &mut Labeler) {
+    labeler.labels(&[
+        "contract_id",
+        "symbol",
+        "security_type",
+        "last_trade_date_or_contract_month",
+        "strike",
+        "right",
+        "multiplier",
+        "exchange",
+        "currency",
+        "local_symbol",
+        "trading_class",
+    ]);
+}
+
+fn label_contract_data(labeler: &mut Labeler, server_version: i32) {
+    let mut message_version = 8;
+    if server_version < server_versions::SIZE_RULES {
+        message_version = labeler.next_int("version");
+    }
+    if message_version >= 3 {
+        labeler.next("request_id");
+    }
+
+    labeler.labels(&[
+        "symbol",
+        "security_type",
+        "last_trade_date_or_contract_month",
+        "strike",
+        "right",
+        "exchange",
+        "currency",
+        "local_symbol",
+        "market_name",
+        "trading_class",
+        "contract_id",
+        "min_tick",
+    ]);
+    if (server_versions::MD_SIZE_MULTIPLIER..server_versions::SIZE_RULES).contains(&server_version) {
+        labeler.next("md_size_multiplier");
+    }
+    labeler.labels(&["multiplier", "order_types", "valid_exchanges"]);
+    if message_version >= 2 {
+        labeler.next("price_magnifier");
+    }
+    if message_version >= 4 {
+        labeler.next("under_contract_id");
+    }
+    if message_version >= 5 {
+        labeler.labels(&["long_name", "primary_exchange"]);
+    }
+    if message_version >= 6 {
+        labeler.labels(&[
+            "contract_month",
+            "industry",
+            "category",
+            "subcategory",
+            "time_zone_id",
+            "trading_hours",
+            "liquid_hours",
+        ]);
+    }
+    if message_version >= 8 {
+        labeler.labels(&["ev_rule", "ev_multiplier"]);
+    }
+    if message_version >= 7 {
+        labeler.group("sec_id_list_count", "sec_id_list", |labeler| labeler.labels(&["tag", "value"]));
+    }
+    if server_version > server_versions::AGG_GROUP {
+        labeler.next("agg_group");
+    }
+    if server_version > server_versions::UNDERLYING_INFO {
+        labeler.labels(&["under_symbol", "under_security_type"]);
+    }
+    if server_version > server_versions::MARKET_RULES {
+        labeler.next("market_rule_ids");
+    }
+    if server_version > server_versions::REAL_EXPIRATION_DATE {
+        labeler.next("real_expiration_date");
+    }
+    if server_version > server_versions::STOCK_TYPE {
+        labeler.next("stock_type");
+    }
+    if (server_versions::FRACTIONAL_SIZE_SUPPORT..server_versions::SIZE_RULES).contains(&server_version) {
+        labeler.next("size_min_tick");
+    }
+    if server_version >= server_versions::SIZE_RULES {
+        labeler.labels(&["min_size", "size_increment", "suggested_size_increment"]);
+    }
+}
@@ -0,0 +1,318 @@
+// Typed constructors of the requests sent to TWS. The field order and version gating of each request live in the encoders
+// module of its API, these name them after the request so messages can be built without pushing loose fields.
+
+use super::{OutgoingMessages, RequestMessage};
+use crate::contracts::{self, Contract};
+use crate::{server_versions, Error};
+
+#[cfg(feature = "accounts")]
+use crate::accounts::{self, FaDataType};
+#[cfg(feature = "market_data")]
+use crate::contracts::TagValue;
+#[cfg(feature = "market_data")]
+use crate::market_data::{historical, realtime};
+#[cfg(feature = "orders")]
+use crate::orders::{self, ExecutionFilter, Order};
+#[cfg(feature = "market_data")]
+use time::OffsetDateTime;
+
+impl RequestMessage {
+    // === Connection ===
+
+    /// Creates the request starting the api once the handshake completed.
+    ///
+    /// # Arguments
+    /// * `server_version`        - server version negotiated in the handshake.
+    /// * `client_id`             - id of the client connecting.
+    /// * `optional_capabilities` - optional capabilities announced, sent to servers supporting them.
+    pub fn start_api(server_version: i32, client_id: i32, optional_capabilities: &str) -> RequestMessage {
+        let mut message = RequestMessage::default();
+
+        message.push_message_header(OutgoingMessages::StartApi, server_version);
+        message.push_field(&client_id);
+
+        if server_version > server_versions::OPTIONAL_CAPABILITIES {
+            message.push_field(&optional_capabilities);
+        }
+
+        message
+    }
+
+    /// Creates the request for the current time of the server, in seconds.
+    pub fn current_time(server_version: i32) -> RequestMessage {
+        let mut message = RequestMessage::default();
+        message.push_message_header(OutgoingMessages::RequestCurrentTime, server_version);
+        message
+    }
+
+    /// Creates the request for the current time of the server, in milliseconds.
+    pub fn current_time_millis(server_version: i32) -> RequestMessage {
+        let mut message = RequestMessage::default();
+        message.push_message_header(OutgoingMessages::RequestCurrentTimeInMillis, server_version);
+        message
+    }
+
+    // === Contracts ===
+
+    /// Creates the request for the details of the contracts matching `contract`.
+    pub fn contract_details(server_version: i32, request_id: i32, contract: &Contract) -> Result<RequestMessage, Error> {
+        contracts::encoders::request_contract_data(server_version, request_id, contract)
+    }
+
+    /// Creates the request for the contracts with a symbol or name matching `pattern`.
+    pub fn matching_symbols(request_id: i32, pattern: &str) -> Result<RequestMessage, Error> {
+        contracts::encoders::request_matching_symbols(request_id, pattern)
+    }
+
+    /// Creates the request for the price increments of a market rule.
+    pub fn market_rule(market_rule_id: i32) -> Result<RequestMessage, Error> {
+        contracts::encoders::request_market_rule(market_rule_id)
+    }
+
+    // === Accounts ===
+
+    /// Creates the request subscribing to the positions of all accounts.
+    #[cfg(feature = "accounts")]
+    pub fn positions() -> Result<RequestMessage, Error> {
+        accounts::encoders::request_positions()
+    }
+
+    /// Creates the request cancelling the positions subscription.
+    #[cfg(feature = "accounts")]
+    pub fn cancel_positions() -> Result<RequestMessage, Error> {
+        accounts::encoders::cancel_positions()
+    }
+
+    /// Creates the request for the family codes of the accounts.
+    #[cfg(feature = "accounts")]
+    pub fn family_codes() -> Result<RequestMessage, Error> {
+        accounts::encoders::request_family_codes()
+    }
+
+    /// Creates the request replacing the financial advisor configuration of the given kind.
+    #[cfg(feature = "accounts")]
+    pub fn replace_fa(server_version: i32, request_id: i32, fa_data_type: FaDataType, xml: &str) -> Result<RequestMessage, Error> {
+        accounts::encoders::encode_replace_fa(server_version, request_id, fa_data_type, xml)
+    }
+
+    // === Orders ===
+
+    /// Creates the request placing, or modifying, order `order_id`.
+    #[cfg(feature = "orders")]
+    pub fn place_order(server_version: i32, order_id: i32, contract: &Contract, order: &Order) -> Result<RequestMessage, Error> {
+        orders::encoders::encode_place_order(server_version, order_id, contract, order)
+    }
+
+    /// Creates the request cancelling order `order_id`.
+    #[cfg(feature = "orders")]
+    pub fn cancel_order(server_version: i32, order_id: i32, manual_order_cancel_time: &str) -> Result<RequestMessage, Error> {
+        orders::encoders::encode_cancel_order(server_version, order_id, manual_order_cancel_time)
+    }
+
+    /// Creates the request cancelling all open orders.
+    #[cfg(feature = "orders")]
+    pub fn global_cancel() -> Result<RequestMessage, Error> {
+        orders::encoders::encode_global_cancel()
+    }
+
+    /// Creates the request for the next valid order id.
+    #[cfg(feature = "orders")]
+    pub fn next_valid_order_id() -> Result<RequestMessage, Error> {
+        orders::encoders::encode_next_valid_order_id()
+    }
+
+    /// Creates the request for the open orders placed by this client.
+    #[cfg(feature = "orders")]
+    pub fn open_orders() -> Result<RequestMessage, Error> {
+        orders::encoders::encode_open_orders()
+    }
+
+    /// Creates the request for the open orders placed by all clients.
+    #[cfg(feature = "orders")]
+    pub fn all_open_orders() -> Result<RequestMessage, Error> {
+        orders::encoders::encode_all_open_orders()
+    }
+
+    /// Creates the request binding orders placed in TWS to this client.
+    #[cfg(feature = "orders")]
+    pub fn auto_open_orders(auto_bind: bool) -> Result<RequestMessage, Error> {
+        orders::encoders::encode_auto_open_orders(auto_bind)
+    }
+
+    /// Creates the request for the completed orders.
+    #[cfg(feature = "orders")]
+    pub fn completed_orders(api_only: bool) -> Result<RequestMessage, Error> {
+        orders::encoders::encode_completed_orders(api_only)
+    }
+
+    /// Creates the request for the executions matching `filter`.
+    #[cfg(feature = "orders")]
+    pub fn executions(server_version: i32, request_id: i32, filter: &ExecutionFilter) -> Result<RequestMessage, Error> {
+        orders::encoders::encode_executions(server_version, request_id, filter)
+    }
+
+    // === Market Data ===
+
+    /// Creates the request subscribing to the market data of `contract`.
+    #[cfg(feature = "market_data")]
+    pub fn market_data(
+        server_version: i32,
+        request_id: i32,
+        contract: &Contract,
+        generic_ticks: &str,
+        snapshot: bool,
+        regulatory_snapshot: bool,
+    ) -> Result<RequestMessage, Error> {
+        realtime::encoders::encode_request_market_data(server_version, request_id, contract, generic_ticks, snapshot, regulatory_snapshot)
+    }
+
+    /// Creates the request cancelling market data subscription `request_id`.
+    #[cfg(feature = "market_data")]
+    pub fn cancel_market_data(request_id: i32) -> Result<RequestMessage, Error> {
+        realtime::encoders::cancel_market_data(request_id)
+    }
+
+    /// Creates the request subscribing to the order book of `contract`.
+    #[cfg(feature = "market_data")]
+    pub fn market_depth(
+        server_version: i32,
+        request_id: i32,
+        contract: &Contract,
+        number_of_rows: i32,
+        is_smart_depth: bool,
+    ) -> Result<RequestMessage, Error> {
+        realtime::encoders::encode_request_market_depth(server_version, request_id, contract, number_of_rows, is_smart_depth)
+    }
+
+    /// Creates the request cancelling market depth subscription `request_id`.
+    #[cfg(feature = "market_data")]
+    pub fn cancel_market_depth(server_version: i32, request_id: i32, is_smart_depth: bool) -> Result<RequestMessage, Error> {
+        realtime::encoders::cancel_market_depth(server_version, request_id, is_smart_depth)
+    }
+
+    /// Creates the request for the exchanges offering market depth.
+    #[cfg(feature = "market_data")]
+    pub fn market_depth_exchanges() -> Result<RequestMessage, Error> {
+        realtime::encoders::encode_request_market_depth_exchanges()
+    }
+
+    /// Creates the request switching the type of market data returned by subsequent market data requests.
+    #[cfg(feature = "market_data")]
+    pub fn market_data_type(market_data_type: realtime::MarketDataType) -> Result<RequestMessage, Error> {
+        realtime::encoders::encode_request_market_data_type(market_data_type)
+    }
+
+    /// Creates the request subscribing to the realtime bars of `contract`.
+    #[cfg(feature = "market_data")]
+    pub fn realtime_bars(
+        server_version: i32,
+        request_id: i32,
+        contract: &Contract,
+        bar_size: &realtime::BarSize,
+        what_to_show: &realtime::WhatToShow,
+        use_rth: bool,
+        options: Vec<TagValue>,
+    ) -> Result<RequestMessage, Error> {
+        realtime::encoders::encode_request_realtime_bars(server_version, request_id, contract, bar_size, what_to_show, use_rth, options)
+    }
+
+    /// Creates the request cancelling realtime bars subscription `request_id`.
+    #[cfg(feature = "market_data")]
+    pub fn cancel_realtime_bars(request_id: i32) -> Result<RequestMessage, Error> {
+        realtime::encoders::cancel_realtime_bars(request_id)
+    }
+
+    /// Creates the request subscribing to the tick by tick data of `contract`.
+    ///
+    /// # Arguments
+    /// * `tick_type` - one of `Last`, `AllLast`, `BidAsk` or `MidPoint`.
+    #[cfg(feature = "market_data")]
+    pub fn tick_by_tick(
+        server_version: i32,
+        request_id: i32,
+        contract: &Contract,
+        tick_type: &str,
+        number_of_ticks: i32,
+        ignore_size: bool,
+    ) -> Result<RequestMessage, Error> {
+        realtime::encoders::tick_by_tick(server_version, request_id, contract, tick_type, number_of_ticks, ignore_size)
+    }
+
+    /// Creates the request cancelling tick by tick subscription `request_id`.
+    #[cfg(feature = "market_data")]
+    pub fn cancel_tick_by_tick(request_id: i32) -> Result<RequestMessage, Error> {
+        realtime::encoders::cancel_tick_by_tick(request_id)
+    }
+
+    /// Creates the request for the timestamp of the earliest data available for `contract`.
+    #[cfg(feature = "market_data")]
+    pub fn head_timestamp(
+        request_id: i32,
+        contract: &Contract,
+        what_to_show: historical::WhatToShow,
+        use_rth: bool,
+    ) -> Result<RequestMessage, Error> {
+        historical::encoders::encode_request_head_timestamp(request_id, contract, what_to_show, use_rth)
+    }
+
+    /// Creates the request cancelling head timestamp request `request_id`.
+    #[cfg(feature = "market_data")]
+    pub fn cancel_head_timestamp(request_id: i32) -> Result<RequestMessage, Error> {
+        historical::encoders::encode_cancel_head_timestamp(request_id)
+    }
+
+    /// Creates the request for the historical bars of `contract`.
+    ///
+    /// # Arguments
+    /// * `interval_end`    - end of the bars requested, now if none.
+    /// * `keep_up_to_date` - whether to keep receiving the bar in progress.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "market_data")]
+    pub fn historical_data(
+        server_version: i32,
+        request_id: i32,
+        contract: &Contract,
+        interval_end: Option<OffsetDateTime>,
+        duration: historical::Duration,
+        bar_size: historical::BarSize,
+        what_to_show: Option<historical::WhatToShow>,
+        use_rth: bool,
+        keep_up_to_date: bool,
+        chart_options: Vec<TagValue>,
+    ) -> Result<RequestMessage, Error> {
+        historical::encoders::encode_request_historical_data(
+            server_version,
+            request_id,
+            contract,
+            interval_end,
+            duration,
+            bar_size,
+            what_to_show,
+            use_rth,
+            keep_up_to_date,
+            chart_options,
+        )
+    }
+
+    /// Creates the request cancelling historical data request `request_id`.
+    #[cfg(feature = "market_data")]
+    pub fn cancel_historical_data(request_id: i32) -> Result<RequestMessage, Error> {
+        historical::encoders::encode_cancel_historical_data(request_id)
+    }
+
+    /// Creates the request for the historical ticks of `contract` between `start` and `end`.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "market_data")]
+    pub fn historical_ticks(
+        request_id: i32,
+        contract: &Contract,
+        start: Option<OffsetDateTime>,
+        end: Option<OffsetDateTime>,
+        number_of_ticks: i32,
+        what_to_show: historical::WhatToShow,
+        use_rth: bool,
+        ignore_size: bool,
+    ) -> Result<RequestMessage, Error> {
+        historical::encoders::encode_request_historical_ticks(request_id, contract, start, end, number_of_ticks, what_to_show, use_rth, ignore_size)
+    }
+}
@@ -1,5 +1,6 @@
-use crate::contracts::{ComboLegOpenClose, SecurityType};
-use crate::orders::{Action, OrderCondition, OrderOpenClose, Rule80A};
+use crate::contracts::{ComboLegOpenClose, Contract, SecurityType};
+#[cfg(feature = "orders")]
+use crate::orders::{Action, OrderOpenClose, Rule80A, TriggerMethod};
 
 use super::*;
 
@@ -11,7 +12,7 @@ fn test_message_encodes_bool() {
     message.push_field(&true);
 
     assert_eq!(2, message.fields.len());
-    assert_eq!("0\01\0", message.encode());
+    assert_eq!("0\x001\x00", message.encode());
 }
 
 #[test]
@@ -23,7 +24,7 @@ fn test_message_encodes_i32() {
     message.push_field(&Option::<i32>::None);
 
     assert_eq!(3, message.fields.len());
-    assert_eq!("1\02\0\0", message.encode());
+    assert_eq!("1\x002\x00\x00", message.encode());
 }
 
 #[test]
@@ -50,6 +51,58 @@ fn test_message_encodes_string() {
 }
 
 #[test]
+fn test_message_encodes_char() {
+    let mut message = RequestMessage::new();
+
+    message.push_field(&'C');
+
+    assert_eq!("C\0", message.encode());
+}
+
+#[test]
+#[cfg(any(feature = "market_data", feature = "orders"))]
+fn test_message_decodes_u8() {
+    let mut message = ResponseMessage::from("2\0x\0");
+
+    assert_eq!(message.next_u8().unwrap(), 2);
+    assert!(message.next_u8().is_err(), "expected parse error");
+}
+
+#[test]
+#[cfg(feature = "orders")]
+fn test_message_decodes_malformed_double_strict() {
+    let mut message = ResponseMessage::from("1\x00abc\x00xyz\x001.5\x00");
+
+    assert!(message.next_int().is_ok());
+    assert!(message.next_double().is_err(), "expected parse error");
+    assert!(message.next_optional_double().is_err(), "expected parse error");
+    assert_eq!(message.next_double().unwrap(), 1.5);
+}
+
+#[test]
+#[cfg(feature = "orders")]
+fn test_message_decodes_malformed_double_lenient() {
+    let mut message = ResponseMessage::from("1\x00abc\x00xyz\x00x\x001.5\x00");
+    message.parse_mode = ParseMode::Lenient;
+
+    assert_eq!(message.next_int().unwrap(), 1);
+    assert_eq!(message.next_double().unwrap(), 0.0);
+    assert_eq!(message.next_optional_double().unwrap(), None);
+    assert_eq!(message.next_int().unwrap(), 0);
+    assert_eq!(message.next_double().unwrap(), 1.5);
+}
+
+#[test]
+#[cfg(feature = "orders")]
+fn test_message_decodes_char() {
+    let mut message = ResponseMessage::from("C\0\0");
+
+    assert_eq!(message.next_char().unwrap(), 'C');
+    assert!(message.next_char().is_err(), "expected error on empty field");
+}
+
+#[test]
+#[cfg(feature = "orders")]
 fn test_message_encodes_rule_80_a() {
     let mut message = RequestMessage::new();
 
@@ -69,21 +122,24 @@ fn test_message_encodes_rule_80_a() {
 }
 
 #[test]
-fn test_message_encodes_order_condition() {
+#[cfg(feature = "orders")]
+fn test_message_encodes_trigger_method() {
     let mut message = RequestMessage::new();
 
-    message.push_field(&OrderCondition::Price);
-    message.push_field(&OrderCondition::Time);
-    message.push_field(&OrderCondition::Margin);
-    message.push_field(&OrderCondition::Execution);
-    message.push_field(&OrderCondition::Volume);
-    message.push_field(&OrderCondition::PercentChange);
+    message.push_field(&TriggerMethod::Default);
+    message.push_field(&TriggerMethod::DoubleBidAsk);
+    message.push_field(&TriggerMethod::Last);
+    message.push_field(&TriggerMethod::DoubleLast);
+    message.push_field(&TriggerMethod::BidAsk);
+    message.push_field(&TriggerMethod::LastOrBidAsk);
+    message.push_field(&TriggerMethod::MidPoint);
 
-    assert_eq!(6, message.fields.len());
-    assert_eq!("1\03\04\05\06\07\0", message.encode());
+    assert_eq!(7, message.fields.len());
+    assert_eq!("0\x001\x002\x003\x004\x007\x008\x00", message.encode());
 }
 
 #[test]
+#[cfg(feature = "orders")]
 fn test_message_encodes_action() {
     let mut message = RequestMessage::new();
 
@@ -127,10 +183,11 @@ fn test_message_encodes_outgoing_message() {
     message.push_field(&OutgoingMessages::RequestUserInfo);
 
     assert_eq!(4, message.fields.len());
-    assert_eq!("1\02\03\0104\0", message.encode());
+    assert_eq!("1\x002\x003\x00104\x00", message.encode());
 }
 
 #[test]
+#[cfg(feature = "orders")]
 fn test_message_encodes_order_open_close() {
     let mut message = RequestMessage::new();
 
@@ -152,7 +209,7 @@ fn test_message_encodes_combo_leg_open_close() {
     message.push_field(&ComboLegOpenClose::Unknown);
 
     assert_eq!(4, message.fields.len());
-    assert_eq!("0\01\02\03\0", message.encode());
+    assert_eq!("0\x001\x002\x003\x00", message.encode());
 }
 
 #[test]
@@ -239,7 +296,9 @@ fn test_incoming_message_from_i32() {
     assert_eq!(IncomingMessages::from(105), IncomingMessages::WshEventData);
     assert_eq!(IncomingMessages::from(106), IncomingMessages::HistoricalSchedule);
     assert_eq!(IncomingMessages::from(107), IncomingMessages::UserInfo);
-    assert_eq!(IncomingMessages::from(108), IncomingMessages::NotValid);
+    assert_eq!(IncomingMessages::from(108), IncomingMessages::HistoricalDataEnd);
+    assert_eq!(IncomingMessages::from(109), IncomingMessages::CurrentTimeInMillis);
+    assert_eq!(IncomingMessages::from(110), IncomingMessages::Unknown(110));
 }
 
 #[test]
@@ -274,3 +333,187 @@ fn test_request_id_index() {
 fn test_request_id_index_invalid() {
     assert_eq!(request_id_index(IncomingMessages::NotValid), None);
 }
+
+#[test]
+fn test_unknown_message_type() {
+    let message = ResponseMessage::from("9999\x001\x00");
+    assert_eq!(message.message_type(), IncomingMessages::Unknown(9999));
+
+    let message = ResponseMessage::from("x\x001\x00");
+    assert_eq!(message.message_type(), IncomingMessages::NotValid);
+
+    assert_eq!(request_id_index(IncomingMessages::Unknown(9999)), None);
+}
+
+#[test]
+fn test_unparsed_fields() {
+    let mut message = ResponseMessage::from("9\x001\x0090\x00");
+    assert_eq!(message.unparsed_fields(), vec!["9", "1", "90"]);
+
+    message.skip();
+    message.skip();
+    assert_eq!(message.unparsed_fields(), vec!["90"]);
+
+    message.next_int().unwrap();
+    assert!(message.unparsed_fields().is_empty(), "all fields decoded");
+
+    let message = ResponseMessage::from("9\x001");
+    assert_eq!(message.unparsed_fields(), vec!["9", "1"], "without trailing nul");
+}
+
+#[test]
+fn test_short_messages_fail_to_decode() {
+    let mut message = ResponseMessage::from("9\x001\x00");
+    assert_eq!(message.len(), 2);
+    assert_eq!(message.remaining(), 2);
+
+    message.skip();
+    assert_eq!(message.remaining(), 1);
+    assert_eq!(message.next_int().unwrap(), 1);
+    assert_eq!(message.remaining(), 0);
+
+    // the empty field following the trailing nul
+    assert_eq!(message.next_string().unwrap(), "");
+    assert_eq!(message.remaining(), 0);
+
+    assert!(matches!(message.next_int(), Err(Error::Parse(3, _, _))), "expected error past the end");
+    assert!(message.next_string().is_err());
+    assert!(message.next_double().is_err());
+    assert!(message.peek_int(10).is_err());
+    assert!(message.peek_string(10).is_err());
+
+    let message = ResponseMessage::from("");
+    assert!(message.is_empty());
+    assert_eq!(message.message_type(), IncomingMessages::NotValid);
+}
+
+#[test]
+fn test_message_version_still_sent() {
+    assert_eq!(message_version(OutgoingMessages::RequestExecutions, server_versions::SIZE_RULES), Some(3));
+    assert_eq!(message_version(OutgoingMessages::PlaceOrder, server_versions::NOT_HELD), Some(45));
+
+    let mut message = RequestMessage::new();
+    message.push_message_header(OutgoingMessages::RequestExecutions, server_versions::SIZE_RULES);
+
+    assert_eq!("7\x003\x00", message.encode());
+}
+
+#[test]
+fn test_message_version_dropped() {
+    assert_eq!(message_version(OutgoingMessages::PlaceOrder, server_versions::ORDER_CONTAINER), None);
+    assert_eq!(
+        message_version(OutgoingMessages::RequestHistoricalData, server_versions::SYNT_REALTIME_BARS),
+        None
+    );
+
+    let mut message = RequestMessage::new();
+    message.push_message_header(OutgoingMessages::PlaceOrder, server_versions::SIZE_RULES);
+
+    assert_eq!("3\0", message.encode());
+}
+
+#[test]
+#[cfg(feature = "orders")]
+fn test_response_message_fields() {
+    let mut message = ResponseMessage::from_string("4\x002\x009000\x00\x00AAPL\x00".to_owned());
+
+    assert_eq!(6, message.fields.len());
+    assert_eq!(IncomingMessages::Error, message.message_type());
+    assert_eq!(Some(9000), message.request_id());
+
+    assert_eq!(4, message.next_int().unwrap());
+    assert_eq!("2", message.next_str().unwrap());
+    assert_eq!("9000", message.next_string().unwrap());
+    assert_eq!("", message.next_str().unwrap());
+    assert_eq!("AAPL", message.next_str().unwrap());
+    assert_eq!("", message.next_str().unwrap());
+
+    assert_eq!("4\x002\x009000\x00\x00AAPL\x00\x00", message.encode());
+}
+
+#[test]
+fn test_describe_labels_fields() {
+    let message = describe("1\x006\x009000\x004\x00185.5\x00100\x000\x00");
+
+    assert_eq!(message.message_type, IncomingMessages::TickPrice);
+    assert_eq!(message.field("message_type"), Some("1"));
+    assert_eq!(message.field("request_id"), Some("9000"));
+    assert_eq!(message.field("tick_type"), Some("4"));
+    assert_eq!(message.field("price"), Some("185.5"));
+    assert_eq!(message.field("attributes"), Some("0"));
+    assert_eq!(
+        message.to_string(),
+        "TickPrice\n  message_type = \"1\"\n  version = \"6\"\n  request_id = \"9000\"\n  tick_type = \"4\"\n  price = \"185.5\"\n  size = \"100\"\n  attributes = \"0\""
+    );
+}
+
+#[test]
+fn test_describe_labels_repeated_groups() {
+    let message = describe("17\x009000\x0020230413  16:31:22\x0020230415  16:31:22\x002\x0020230413\x00182.9400\x00186.5000\x00180.9400\x00185.9000\x00948837.22\x00184.869\x00324891\x0020230414\x00183.8800\x00186.2800\x00182.0100\x00185.0000\x00810998.27\x00183.9865\x00277547\x00");
+
+    assert_eq!(message.field("bars_count"), Some("2"));
+    assert_eq!(message.field("bars[0].open"), Some("182.9400"));
+    assert_eq!(message.field("bars[1].date"), Some("20230414"));
+    assert_eq!(message.field("bars[1].count"), Some("277547"));
+    assert_eq!(message.fields.len(), 21);
+}
+
+#[test]
+fn test_describe_depends_on_server_version() {
+    let raw = "3\x0013\x00Submitted\x000\x00100\x000\x00123\x000\x000\x00100\x00\x000\x00";
+
+    let message = describe_for_server_version(raw, server_versions::MARKET_CAP_PRICE);
+    assert_eq!(message.field("order_id"), Some("13"));
+    assert_eq!(message.field("market_cap_price"), Some("0"));
+
+    let message = describe_for_server_version("3\x001\x0013\x00Submitted\x00", server_versions::MARKET_CAP_PRICE - 1);
+    assert_eq!(message.field("version"), Some("1"));
+    assert_eq!(message.field("order_id"), Some("13"));
+    assert_eq!(message.field("filled"), None, "missing fields are not labeled");
+}
+
+#[test]
+fn test_describe_leaves_unknown_fields_unlabeled() {
+    let message = describe("9\x001\x0090\x00extra\x00");
+    assert_eq!(message.field("order_id"), Some("90"));
+    assert_eq!(
+        message.fields[3],
+        DecodedField {
+            label: None,
+            value: "extra".into()
+        }
+    );
+
+    let message = describe("999\0a\0");
+    assert_eq!(message.message_type, IncomingMessages::Unknown(999));
+    assert_eq!(message.fields[1].label, None);
+    assert!(message.to_string().ends_with("[1] = \"a\""));
+}
+
+#[test]
+fn test_request_constructors() {
+    let message = RequestMessage::start_api(server_versions::OPTIONAL_CAPABILITIES + 1, 100, "+PACEAPI");
+    assert_eq!(message.encode_simple(), "71|2|100|+PACEAPI|");
+
+    assert_eq!(RequestMessage::current_time(server_versions::SIZE_RULES).encode_simple(), "49|1|");
+    assert_eq!(RequestMessage::current_time_millis(server_versions::SIZE_RULES).encode_simple(), "105|");
+
+    let message = RequestMessage::contract_details(server_versions::SIZE_RULES, 9000, &Contract::stock("TSLA")).unwrap();
+    assert_eq!(message.encode_simple(), "9|8|9000|0|TSLA|STK||0|||SMART||USD|||0|||");
+}
+
+#[test]
+#[cfg(feature = "market_data")]
+fn test_market_data_request_constructors() {
+    let message = RequestMessage::market_data(server_versions::SIZE_RULES, 9000, &Contract::stock("MSFT"), "233", false, false).unwrap();
+    assert_eq!(message[0], OutgoingMessages::RequestMarketData.to_field());
+    assert_eq!(message[2], "9000");
+
+    assert_eq!(RequestMessage::cancel_market_data(9000).unwrap().encode_simple(), "2|2|9000|");
+    assert_eq!(
+        RequestMessage::market_data_type(crate::market_data::realtime::MarketDataType::Delayed)
+            .unwrap()
+            .encode_simple(),
+        "59|1|3|"
+    );
+}
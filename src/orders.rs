@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::convert::From;
 use std::fmt::{self, Debug};
 
@@ -7,12 +8,12 @@ use crate::client::transport::{GlobalResponseIterator, ResponseIterator};
 use crate::contracts::{ComboLeg, ComboLegOpenClose, Contract, DeltaNeutralContract, SecurityType};
 use crate::messages::{IncomingMessages, OutgoingMessages};
 use crate::messages::{RequestMessage, ResponseMessage};
-use crate::Client;
+use crate::{contracts, Client};
 use crate::{encode_option_field, ToField};
 use crate::{server_versions, Error};
 
 mod decoders;
-mod encoders;
+pub(crate) mod encoders;
 
 /// Make sure to test using only your paper trading account when applicable. A good way of finding out if an order type/exchange combination
 /// is possible is by trying to place such order manually using the TWS.
@@ -20,7 +21,7 @@ mod encoders;
 pub mod order_builder;
 
 /// New description
-pub use crate::contracts::TagValue;
+pub use crate::contracts::{TagValue, TagValues};
 
 const COMPETE_AGAINST_BEST_OFFSET_UP_TO_MID: Option<f64> = Some(f64::INFINITY);
 
@@ -61,7 +62,8 @@ pub struct Order {
     /// Orders submitted to IB that remain in force for more than one day will not be reduced for dividends. To allow adjustment to your order price on ex-dividend date, consider using a Good-Til-Date/Time (GTD) or Good-after-Time/Date (GAT) order type, or a combination of the two.
     /// IOC - Immediate or Cancel. Any portion that is not filled as soon as it becomes available in the market is canceled.
     /// GTD - Good until Date. It will remain working within the system and in the marketplace until it executes or until the close of the market on the date specified
-    /// OPG - Use OPG to send a market-on-open (MOO) or limit-on-open (LOO) order.
+    /// OPG - Use OPG to send a market-on-open (MOO) or limit-on-open (LOO) order. Only MKT and LMT order types are accepted with OPG,
+    /// closing auction types such as MOC or LOC are rejected when the order is placed.
     /// FOK - If the entire Fill-or-Kill order does not execute as soon as it becomes available, the entire order is canceled.
     /// DTC - Day until Canceled.
     pub tif: String, // FIXME create enum
@@ -84,22 +86,17 @@ pub struct Order {
     /// If set to true, specifies that the order is an ISE Block order.
     pub block_order: bool,
     /// If set to true, specifies that the order is a Sweep-to-Fill order.
+    /// The order is split across price levels to fill as quickly as possible.
     pub sweep_to_fill: bool,
     /// The publicly disclosed order size, used when placing Iceberg orders.
+    /// Only applies to limit orders on venues that support reserve orders.
     pub display_size: Option<i32>,
     /// Specifies how Simulated Stop, Stop-Limit and Trailing Stop orders are triggered.
-    /// Valid values are:
-    /// 0 - The default value. The "double bid/ask" function will be used for orders for OTC stocks and US options. All other orders will used the "last" function.
-    /// 1 - use "double bid/ask" function, where stop orders are triggered based on two consecutive bid or ask prices.
-    /// 2 - "last" function, where stop orders are triggered based on the last price.
-    /// 3 - double last function.
-    /// 4 - bid/ask function.
-    /// 7 - last or bid/ask function.
-    /// 8 - mid-point function.    
-    pub trigger_method: i32,
+    pub trigger_method: TriggerMethod,
     /// If set to true, allows orders to also trigger or fill outside of regular trading hours.
     pub outside_rth: bool,
     /// If set to true, the order will not be visible when viewing the market depth. This option only applies to orders routed to the NASDAQ exchange.
+    /// The flag is sent regardless of the destination; exchanges that do not support hidden orders ignore it or reject the order.
     pub hidden: bool,
     /// Specifies the date and time after which the order will be active.
     /// Format: yyyymmdd hh:mm:ss {optional Timezone}.
@@ -114,9 +111,10 @@ pub struct Order {
     pub rule_80_a: Option<Rule80A>,
     /// Indicates whether or not all the order has to be filled on a single execution.
     pub all_or_none: bool,
-    /// Identifies a minimum quantity order type.
+    /// Identifies a minimum quantity order type. The order only fills when at least this quantity can be filled at once.
     pub min_qty: Option<i32>,
-    /// The percent offset amount for relative orders.
+    /// The percent offset amount for relative orders. REL orders peg to the NBBO offset by this percentage, e.g. 0.01 for 1%.
+    /// Sent as an empty field when not set.
     pub percent_offset: Option<f64>,
     /// Trail stop price for TRAIL LIMIT orders.
     pub trail_stop_price: Option<f64>,
@@ -162,12 +160,8 @@ pub struct Order {
     /// When set to false, orders routed directly to ASX will NOT use SmartRouting.
     /// When set to true, orders routed directly to ASX orders WILL use SmartRouting.
     pub opt_out_smart_routing: bool,
-    /// For BOX orders only.
-    /// Values include:
-    /// 1 - Match
-    /// 2 - Improvement
-    /// 3 - Transparent.
-    pub auction_strategy: Option<i32>, // FIXME enum
+    /// For BOX orders only. Strategy used when participating in the price improvement auction.
+    pub auction_strategy: Option<AuctionStrategy>,
     /// The auction's starting price. For BOX orders only.
     pub starting_price: Option<f64>,
     /// The stock's reference price.
@@ -280,8 +274,9 @@ pub struct Order {
     pub what_if: bool,
     /// Identifies orders generated by algorithmic trading.
     pub algo_id: String,
+    /// Not held orders give the broker discretion over the time and price of execution, relieving it of its best execution obligations.
     /// Orders routed to IBDARK are tagged as “post only” and are held in IB's order book, where incoming SmartRouted orders from other IB customers are eligible to trade against them.
-    /// For IBDARK orders only.
+    /// Required for IBDARK orders.
     pub not_held: bool,
     /// Advanced parameters for Smart combo routing.
     /// These features are for both guaranteed and nonguaranteed combination orders routed to Smart, and are available based on combo type and order type. SmartComboRoutingParams is similar to AlgoParams in that it makes use of tag/value pairs to add parameters to combo orders.
@@ -357,7 +352,7 @@ pub struct Order {
     pub imbalance_only: bool,
     /// Routes market order to Best Bid Offer.
     pub route_marketable_to_bbo: bool,
-    /// Parent order Id.
+    /// Permanent id of the parent order, e.g. for the children of bracket orders. Reported for completed orders.
     pub parent_perm_id: Option<i64>,
     /// Accepts a list with parameters obtained from advancedOrderRejectJson.
     pub advanced_error_override: String,
@@ -442,7 +437,7 @@ impl Default for Order {
             block_order: false,
             sweep_to_fill: false,
             display_size: Some(0), // TODO - default to None?
-            trigger_method: 0,
+            trigger_method: TriggerMethod::Default,
             outside_rth: false,
             hidden: false,
             good_after_time: "".to_owned(),
@@ -465,7 +460,7 @@ impl Default for Order {
             exempt_code: -1,
             discretionary_amt: 0.0,
             opt_out_smart_routing: false,
-            auction_strategy: Some(0), // TODO - use enum
+            auction_strategy: None,
             starting_price: None,
             stock_ref_price: None,
             delta: None,
@@ -600,14 +595,16 @@ impl ToField for Action {
     }
 }
 
-impl ToString for Action {
-    fn to_string(&self) -> String {
-        match self {
-            Action::Buy => String::from("BUY"),
-            Action::Sell => String::from("SELL"),
-            Action::SellShort => String::from("SSHORT"),
-            Action::SellLong => String::from("SLONG"),
-        }
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Action::Buy => "BUY",
+            Action::Sell => "SELL",
+            Action::SellShort => "SSHORT",
+            Action::SellLong => "SLONG",
+        };
+
+        write!(f, "{text}")
     }
 }
 
@@ -657,19 +654,21 @@ impl ToField for Option<Rule80A> {
     }
 }
 
-impl ToString for Rule80A {
-    fn to_string(&self) -> String {
-        match self {
-            Rule80A::Individual => String::from('I'),
-            Rule80A::Agency => String::from('A'),
-            Rule80A::AgentOtherMember => String::from('W'),
-            Rule80A::IndividualPTIA => String::from('J'),
-            Rule80A::AgencyPTIA => String::from('U'),
-            Rule80A::AgentOtherMemberPTIA => String::from('M'),
-            Rule80A::IndividualPT => String::from('K'),
-            Rule80A::AgencyPT => String::from('Y'),
-            Rule80A::AgentOtherMemberPT => String::from('N'),
-        }
+impl fmt::Display for Rule80A {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Rule80A::Individual => "I",
+            Rule80A::Agency => "A",
+            Rule80A::AgentOtherMember => "W",
+            Rule80A::IndividualPTIA => "J",
+            Rule80A::AgencyPTIA => "U",
+            Rule80A::AgentOtherMemberPTIA => "M",
+            Rule80A::IndividualPT => "K",
+            Rule80A::AgencyPT => "Y",
+            Rule80A::AgentOtherMemberPT => "N",
+        };
+
+        write!(f, "{text}")
     }
 }
 
@@ -690,53 +689,248 @@ impl Rule80A {
     }
 }
 
-pub enum AuctionStrategy {
-    Match,
-    Improvement,
-    Transparent,
-}
-
 #[derive(Clone, Debug, Default)]
 pub struct OrderComboLeg {
     price: Option<f64>,
 }
 
-#[derive(Copy, Clone, Debug)]
-pub enum OrderCondition {
-    Price = 1,
-    Time = 3,
-    Margin = 4,
-    Execution = 5,
-    Volume = 6,
-    PercentChange = 7,
+/// Specifies how Simulated Stop, Stop-Limit and Trailing Stop orders and price conditions are triggered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TriggerMethod {
+    /// The "double bid/ask" function will be used for orders for OTC stocks and US options. All other orders will used the "last" function.
+    #[default]
+    Default = 0,
+    /// Stop orders are triggered based on two consecutive bid or ask prices.
+    DoubleBidAsk = 1,
+    /// Stop orders are triggered based on the last price.
+    Last = 2,
+    /// Stop orders are triggered based on two consecutive last prices.
+    DoubleLast = 3,
+    /// Stop orders are triggered based on bid or ask price.
+    BidAsk = 4,
+    /// Stop orders are triggered based on the last or bid/ask price.
+    LastOrBidAsk = 7,
+    /// Stop orders are triggered based on the mid-point price.
+    MidPoint = 8,
 }
 
-impl ToField for OrderCondition {
+impl ToField for TriggerMethod {
     fn to_field(&self) -> String {
-        (*self as u8).to_string()
+        (*self as i32).to_string()
+    }
+}
+
+impl From<i32> for TriggerMethod {
+    fn from(val: i32) -> Self {
+        match val {
+            0 => TriggerMethod::Default,
+            1 => TriggerMethod::DoubleBidAsk,
+            2 => TriggerMethod::Last,
+            3 => TriggerMethod::DoubleLast,
+            4 => TriggerMethod::BidAsk,
+            7 => TriggerMethod::LastOrBidAsk,
+            8 => TriggerMethod::MidPoint,
+            _ => panic!("TriggerMethod({val}) is unsupported"),
+        }
     }
 }
 
-impl ToField for Option<OrderCondition> {
+/// Strategy for BOX price improvement auction orders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuctionStrategy {
+    Match = 1,
+    Improvement = 2,
+    Transparent = 3,
+}
+
+impl ToField for AuctionStrategy {
     fn to_field(&self) -> String {
-        encode_option_field(self)
+        (*self as i32).to_string()
     }
 }
 
-impl From<i32> for OrderCondition {
+impl ToField for Option<AuctionStrategy> {
+    // The server expects 0 when no auction strategy is set.
+    fn to_field(&self) -> String {
+        match self {
+            Some(strategy) => strategy.to_field(),
+            None => "0".into(),
+        }
+    }
+}
+
+impl From<i32> for AuctionStrategy {
     fn from(val: i32) -> Self {
         match val {
-            1 => OrderCondition::Price,
-            3 => OrderCondition::Time,
-            4 => OrderCondition::Volume,
-            5 => OrderCondition::Execution,
-            6 => OrderCondition::Volume,
-            7 => OrderCondition::PercentChange,
-            _ => panic!("OrderCondition({val}) is unsupported"),
+            1 => AuctionStrategy::Match,
+            2 => AuctionStrategy::Improvement,
+            3 => AuctionStrategy::Transparent,
+            _ => panic!("AuctionStrategy({val}) is unsupported"),
+        }
+    }
+}
+
+/// Conditions determining when an order will be activated or canceled.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrderCondition {
+    Price(PriceCondition),
+    Time(TimeCondition),
+    Margin(MarginCondition),
+    Execution(ExecutionCondition),
+    Volume(VolumeCondition),
+    PercentChange(PercentChangeCondition),
+}
+
+impl OrderCondition {
+    /// Returns the condition type sent to the server.
+    pub fn condition_type(&self) -> i32 {
+        match self {
+            OrderCondition::Price(_) => 1,
+            OrderCondition::Time(_) => 3,
+            OrderCondition::Margin(_) => 4,
+            OrderCondition::Execution(_) => 5,
+            OrderCondition::Volume(_) => 6,
+            OrderCondition::PercentChange(_) => 7,
+        }
+    }
+
+    pub(crate) fn push_fields(&self, message: &mut RequestMessage) {
+        message.push_field(&self.condition_type());
+
+        match self {
+            OrderCondition::Price(condition) => {
+                message.push_field(&conjunction_field(condition.is_conjunction));
+                message.push_field(&condition.is_more);
+                message.push_field(&condition.price);
+                message.push_field(&condition.contract_id);
+                message.push_field(&condition.exchange);
+                message.push_field(&condition.trigger_method);
+            }
+            OrderCondition::Time(condition) => {
+                message.push_field(&conjunction_field(condition.is_conjunction));
+                message.push_field(&condition.is_more);
+                message.push_field(&condition.time);
+            }
+            OrderCondition::Margin(condition) => {
+                message.push_field(&conjunction_field(condition.is_conjunction));
+                message.push_field(&condition.is_more);
+                message.push_field(&condition.percent);
+            }
+            OrderCondition::Execution(condition) => {
+                message.push_field(&conjunction_field(condition.is_conjunction));
+                message.push_field(&condition.security_type);
+                message.push_field(&condition.exchange);
+                message.push_field(&condition.symbol);
+            }
+            OrderCondition::Volume(condition) => {
+                message.push_field(&conjunction_field(condition.is_conjunction));
+                message.push_field(&condition.is_more);
+                message.push_field(&condition.volume);
+                message.push_field(&condition.contract_id);
+                message.push_field(&condition.exchange);
+            }
+            OrderCondition::PercentChange(condition) => {
+                message.push_field(&conjunction_field(condition.is_conjunction));
+                message.push_field(&condition.is_more);
+                message.push_field(&condition.change_percent);
+                message.push_field(&condition.contract_id);
+                message.push_field(&condition.exchange);
+            }
         }
     }
 }
 
+// Conditions are joined with AND (a) or OR (o) to the next condition.
+fn conjunction_field(is_conjunction: bool) -> char {
+    if is_conjunction {
+        'a'
+    } else {
+        'o'
+    }
+}
+
+/// Activates an order when the price of a contract crosses a threshold.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PriceCondition {
+    /// Contract whose price is observed.
+    pub contract_id: i32,
+    /// Exchange where the price is observed.
+    pub exchange: String,
+    /// True when the condition triggers above the price, false when below.
+    pub is_more: bool,
+    /// Price that triggers the condition.
+    pub price: f64,
+    /// Method used to evaluate the price.
+    pub trigger_method: TriggerMethod,
+    /// AND (true) or OR (false) with the next condition.
+    pub is_conjunction: bool,
+}
+
+/// Activates an order before or after a given time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TimeCondition {
+    /// Time that triggers the condition. Format: yyyymmdd hh:mm:ss {optional Timezone}.
+    pub time: String,
+    /// True when the condition triggers after the time, false when before.
+    pub is_more: bool,
+    /// AND (true) or OR (false) with the next condition.
+    pub is_conjunction: bool,
+}
+
+/// Activates an order when the margin cushion crosses a percentage.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarginCondition {
+    /// Margin cushion percent that triggers the condition.
+    pub percent: i32,
+    /// True when the condition triggers above the percent, false when below.
+    pub is_more: bool,
+    /// AND (true) or OR (false) with the next condition.
+    pub is_conjunction: bool,
+}
+
+/// Activates an order when an execution occurs on a given symbol.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecutionCondition {
+    /// Symbol of the executed contract.
+    pub symbol: String,
+    /// Security type of the executed contract.
+    pub security_type: String,
+    /// Exchange of the execution.
+    pub exchange: String,
+    /// AND (true) or OR (false) with the next condition.
+    pub is_conjunction: bool,
+}
+
+/// Activates an order when the traded volume of a contract crosses a threshold.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VolumeCondition {
+    /// Contract whose volume is observed.
+    pub contract_id: i32,
+    /// Exchange where the volume is observed.
+    pub exchange: String,
+    /// True when the condition triggers above the volume, false when below.
+    pub is_more: bool,
+    /// Volume that triggers the condition.
+    pub volume: i32,
+    /// AND (true) or OR (false) with the next condition.
+    pub is_conjunction: bool,
+}
+
+/// Activates an order when the price of a contract changes by a percentage from the last close.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PercentChangeCondition {
+    /// Contract whose price change is observed.
+    pub contract_id: i32,
+    /// Exchange where the price change is observed.
+    pub exchange: String,
+    /// True when the condition triggers above the percent change, false when below.
+    pub is_more: bool,
+    /// Percent change that triggers the condition.
+    pub change_percent: f64,
+    /// AND (true) or OR (false) with the next condition.
+    pub is_conjunction: bool,
+}
+
 /// Stores Soft Dollar Tier information.
 #[derive(Clone, Debug, Default)]
 pub struct SoftDollarTier {
@@ -757,6 +951,32 @@ pub struct OrderData {
     pub order_state: OrderState,
 }
 
+/// Reconstructs parent/child relationships between orders, e.g. to display bracket orders.
+///
+/// Maps the permanent id of each parent order to the permanent ids of its children. Standalone and top level orders are mapped under None.
+/// Children are linked by [Order::parent_perm_id] when reported, as for completed orders, and otherwise by [Order::parent_id].
+pub fn order_tree(orders: &[OrderData]) -> HashMap<Option<i64>, Vec<i64>> {
+    let perm_ids: HashMap<i32, i64> = orders
+        .iter()
+        .map(|order_data| (order_data.order_id, order_data.order.perm_id as i64))
+        .collect();
+
+    let mut tree: HashMap<Option<i64>, Vec<i64>> = HashMap::new();
+    for order_data in orders {
+        let order = &order_data.order;
+
+        let parent_perm_id = match order.parent_perm_id.filter(|perm_id| *perm_id > 0) {
+            Some(perm_id) => Some(perm_id),
+            None if order.parent_id > 0 => perm_ids.get(&order.parent_id).copied(),
+            None => None,
+        };
+
+        tree.entry(parent_perm_id).or_default().push(order.perm_id as i64);
+    }
+
+    tree
+}
+
 /// Provides an active order's current state.
 #[derive(Clone, Debug, Default)]
 pub struct OrderState {
@@ -768,7 +988,8 @@ pub struct OrderState {
     pub maintenance_margin_before: Option<f64>,
     /// The account's current equity with loan
     pub equity_with_loan_before: Option<f64>,
-    /// The change of the account's initial margin.
+    /// The change of the account's initial margin, i.e. the order's incremental margin impact.
+    /// The before and change values are only reported by servers supporting extended what-if fields.
     pub initial_margin_change: Option<f64>,
     /// The change of the account's maintenance margin
     pub maintenance_margin_change: Option<f64>,
@@ -782,7 +1003,7 @@ pub struct OrderState {
     pub equity_with_loan_after: Option<f64>,
     /// The order's generated commission.
     pub commission: Option<f64>,
-    // The execution's minimum commission.
+    /// The execution's minimum commission.
     pub minimum_commission: Option<f64>,
     /// The executions maximum commission.
     pub maximum_commission: Option<f64>,
@@ -790,7 +1011,9 @@ pub struct OrderState {
     pub commission_currency: String,
     /// If the order is warranted, a descriptive message will be provided.
     pub warning_text: String,
+    /// Time the order was completed. Only populated for completed orders.
     pub completed_time: String,
+    /// Final status of a completed order, e.g. "Filled Size: 100" or the reason it was cancelled.
     pub completed_status: String,
 }
 
@@ -816,12 +1039,14 @@ impl ToField for Option<OrderOpenClose> {
     }
 }
 
-impl ToString for OrderOpenClose {
-    fn to_string(&self) -> String {
-        match self {
-            OrderOpenClose::Open => String::from("O"),
-            OrderOpenClose::Close => String::from("C"),
-        }
+impl fmt::Display for OrderOpenClose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            OrderOpenClose::Open => "O",
+            OrderOpenClose::Close => "C",
+        };
+
+        write!(f, "{text}")
     }
 }
 
@@ -1014,10 +1239,7 @@ pub(crate) fn place_order(
     contract: &Contract,
     order: &Order,
 ) -> Result<impl Iterator<Item = OrderNotification>, Error> {
-    verify_order(client, order, order_id)?;
-    verify_order_contract(client, contract, order_id)?;
-
-    let message = encoders::encode_place_order(client.server_version(), order_id, contract, order)?;
+    let message = encode_place_order(client, order_id, contract, order)?;
 
     let messages = client.send_order(order_id, message)?;
 
@@ -1027,65 +1249,210 @@ pub(crate) fn place_order(
     })
 }
 
+// Places the orders of a basket with a single write. All but the last order are placed with transmit
+// set to false, so that transmitting the last order releases the whole basket.
+pub(crate) fn place_basket(client: &Client, orders: &[(i32, Contract, Order)]) -> Result<Vec<OrderNotificationIterator>, Error> {
+    if orders.is_empty() {
+        return Err(Error::InvalidArgument("basket must contain at least one order".into()));
+    }
+
+    let last = orders.len() - 1;
+
+    let mut messages = Vec::with_capacity(orders.len());
+    for (i, (order_id, contract, order)) in orders.iter().enumerate() {
+        let mut order = order.clone();
+        if i < last {
+            order.transmit = false;
+        }
+
+        messages.push((*order_id, encode_place_order(client, *order_id, contract, &order)?));
+    }
+
+    let responses = client.send_orders(&messages)?;
+
+    Ok(responses
+        .into_iter()
+        .map(|messages| OrderNotificationIterator {
+            messages,
+            server_version: client.server_version(),
+        })
+        .collect())
+}
+
+// Places the order. When the order is rejected because its price does not conform to the minimum price variation,
+// the prices are rounded to a valid increment and the order is resubmitted once with the same order id.
+pub(crate) fn place_order_auto_round<'a>(
+    client: &'a Client,
+    order_id: i32,
+    contract: &Contract,
+    order: &Order,
+) -> Result<AutoRoundOrderNotificationIterator<'a>, Error> {
+    let message = encode_place_order(client, order_id, contract, order)?;
+
+    let messages = client.send_order(order_id, message)?;
+
+    Ok(AutoRoundOrderNotificationIterator {
+        client,
+        order_id,
+        contract: contract.clone(),
+        order: order.clone(),
+        resubmitted: false,
+        notifications: OrderNotificationIterator {
+            messages,
+            server_version: client.server_version(),
+        },
+    })
+}
+
+// Encodes the place order request, as it would be sent, after verifying the order is supported.
+pub(crate) fn encode_place_order(client: &Client, order_id: i32, contract: &Contract, order: &Order) -> Result<RequestMessage, Error> {
+    verify_order(client, order, order_id)?;
+    verify_order_contract(client, contract, order_id)?;
+
+    encoders::encode_place_order(client.server_version(), order_id, contract, order)
+}
+
 // Supports iteration over OrderNotification
 pub(crate) struct OrderNotificationIterator {
     server_version: i32,
     messages: ResponseIterator,
 }
 
+impl OrderNotificationIterator {
+    // Decodes message into an OrderNotification. Returns None for messages that are not order notifications.
+    fn decode(&self, mut message: ResponseMessage) -> Option<Result<OrderNotification, Error>> {
+        fn convert<T: Into<OrderNotification>>(result: Result<T, Error>) -> Option<Result<OrderNotification, Error>> {
+            Some(result.map(|val| val.into()))
+        }
+
+        match message.message_type() {
+            IncomingMessages::OpenOrder => {
+                let open_order = decoders::decode_open_order(self.server_version, message);
+                convert(open_order)
+            }
+            IncomingMessages::OrderStatus => {
+                let order_status = decoders::decode_order_status(self.server_version, &mut message);
+                convert(order_status)
+            }
+            IncomingMessages::ExecutionData => {
+                let execution_data = decoders::decode_execution_data(self.server_version, &mut message);
+                convert(execution_data)
+            }
+            IncomingMessages::CommissionsReport => {
+                let commission_report = decoders::decode_commission_report(self.server_version, &mut message);
+                convert(commission_report)
+            }
+            IncomingMessages::Error => Some(message.peek_string(4).map(OrderNotification::Message)),
+            message => {
+                error!("unexpected message: {message:?}");
+                None
+            }
+        }
+    }
+}
+
 impl Iterator for OrderNotificationIterator {
     type Item = OrderNotification;
 
     /// Returns the next [OrderNotification]. Waits up to x seconds for next [OrderNotification].
     fn next(&mut self) -> Option<Self::Item> {
-        fn convert<T: Into<OrderNotification>>(result: Result<T, Error>) -> Option<OrderNotification> {
-            match result {
-                Ok(val) => Some(val.into()),
-                Err(err) => {
+        loop {
+            let message = self.messages.next()?;
+
+            match self.decode(message) {
+                Some(Ok(notification)) => return Some(notification),
+                Some(Err(err)) => {
                     info!("error: {err:?}");
-                    None
+                    return None;
                 }
+                None => continue,
             }
         }
+    }
+}
+
+// Error code sent when the order's price does not conform to the minimum price variation.
+const MIN_TICK_REJECTION: i32 = 110;
+
+// Supports iteration over OrderNotification. Resubmits the order once with its prices rounded
+// to a valid increment when it is rejected for not conforming to the minimum price variation.
+pub(crate) struct AutoRoundOrderNotificationIterator<'a> {
+    client: &'a Client,
+    order_id: i32,
+    contract: Contract,
+    order: Order,
+    resubmitted: bool,
+    notifications: OrderNotificationIterator,
+}
+
+impl<'a> AutoRoundOrderNotificationIterator<'a> {
+    fn is_min_tick_rejection(message: &ResponseMessage) -> bool {
+        message.message_type() == IncomingMessages::Error && message.peek_int(3).ok() == Some(MIN_TICK_REJECTION)
+    }
+
+    // Rounds the order's prices and places it again with the same order id.
+    // Responses are routed to the channel already registered for the order id.
+    fn resubmit(&mut self) -> Result<(), Error> {
+        if let Some(limit_price) = self.order.limit_price {
+            self.order.limit_price = Some(contracts::round_to_tick(self.client, &self.contract, limit_price)?);
+        }
+        if let Some(aux_price) = self.order.aux_price {
+            self.order.aux_price = Some(contracts::round_to_tick(self.client, &self.contract, aux_price)?);
+        }
+
+        let message = encode_place_order(self.client, self.order_id, &self.contract, &self.order)?;
+        self.client.send_message(message)
+    }
+}
 
+impl<'a> Iterator for AutoRoundOrderNotificationIterator<'a> {
+    type Item = OrderNotification;
+
+    /// Returns the next [OrderNotification]. A first minimum tick rejection is not returned when the order is resubmitted.
+    fn next(&mut self) -> Option<Self::Item> {
         loop {
-            if let Some(mut message) = self.messages.next() {
-                match message.message_type() {
-                    IncomingMessages::OpenOrder => {
-                        let open_order = decoders::decode_open_order(self.server_version, message);
-                        return convert(open_order);
-                    }
-                    IncomingMessages::OrderStatus => {
-                        let order_status = decoders::decode_order_status(self.server_version, &mut message);
-                        return convert(order_status);
-                    }
-                    IncomingMessages::ExecutionData => {
-                        let execution_data = decoders::decode_execution_data(self.server_version, &mut message);
-                        return convert(execution_data);
-                    }
-                    IncomingMessages::CommissionsReport => {
-                        let commission_report = decoders::decode_commission_report(self.server_version, &mut message);
-                        return convert(commission_report);
-                    }
-                    IncomingMessages::Error => {
-                        let message = message.peek_string(4);
-                        return Some(OrderNotification::Message(message));
-                    }
-                    message => {
-                        error!("unexpected message: {message:?}");
-                    }
+            let message = self.notifications.messages.next()?;
+
+            if !self.resubmitted && Self::is_min_tick_rejection(&message) {
+                self.resubmitted = true;
+
+                match self.resubmit() {
+                    Ok(()) => continue,
+                    Err(err) => error!("error resubmitting order {} with rounded prices: {err}", self.order_id),
                 }
-            } else {
-                return None;
+            }
+
+            match self.notifications.decode(message) {
+                Some(Ok(notification)) => return Some(notification),
+                Some(Err(err)) => {
+                    info!("error: {err:?}");
+                    return None;
+                }
+                None => continue,
             }
         }
     }
 }
 
 // Verifies that Order is properly formed.
+// Orders with the OPG time in force participate in the opening auction, as Market-on-Open (MKT) or Limit-on-Open (LMT) orders.
+// Closing auction types (MOC, LOC) and other order types are rejected.
+fn verify_opening_auction(order: &Order) -> Result<(), Error> {
+    if order.tif == "OPG" && !matches!(order.order_type.as_str(), "MKT" | "LMT") {
+        return Err(Error::InvalidArgument(format!(
+            "OPG time in force requires a MKT or LMT order type, found {}",
+            order.order_type
+        )));
+    }
+
+    Ok(())
+}
+
 fn verify_order(client: &Client, order: &Order, _order_id: i32) -> Result<(), Error> {
     let is_bag_order: bool = false; // StringsAreEqual(Constants.BagSecType, contract.SecType)
 
+    verify_opening_auction(order)?;
+
     if order.scale_init_level_size.is_some() || order.scale_price_increment.is_some() {
         client.check_server_version(server_versions::SCALE_ORDERS, "It does not support Scale orders.")?
     }
@@ -1290,14 +1657,7 @@ fn verify_order_contract(client: &Client, contract: &Contract, _order_id: i32) -
 
 // Cancels an open [Order].
 pub(crate) fn cancel_order(client: &Client, order_id: i32, manual_order_cancel_time: &str) -> Result<CancelOrderResultIterator, Error> {
-    if !manual_order_cancel_time.is_empty() {
-        client.check_server_version(
-            server_versions::MANUAL_ORDER_TIME,
-            "It does not support manual order cancel time attribute",
-        )?
-    }
-
-    let message = encoders::encode_cancel_order(client.server_version(), order_id, manual_order_cancel_time)?;
+    let message = encode_cancel_order(client, order_id, manual_order_cancel_time)?;
 
     let messages = client.send_order(order_id, message)?;
 
@@ -1335,7 +1695,7 @@ impl Iterator for CancelOrderResultIterator {
                         }
                     },
                     IncomingMessages::Error => {
-                        let message = message.peek_string(4);
+                        let message = message.peek_string(4).unwrap_or_default();
                         return Some(CancelOrderResult::Notice(Notice(message)));
                     }
                     message => {
@@ -1349,11 +1709,21 @@ impl Iterator for CancelOrderResultIterator {
     }
 }
 
+// Encodes the cancel order request, as it would be sent, after verifying it is supported.
+pub(crate) fn encode_cancel_order(client: &Client, order_id: i32, manual_order_cancel_time: &str) -> Result<RequestMessage, Error> {
+    if !manual_order_cancel_time.is_empty() {
+        client.check_server_version(
+            server_versions::MANUAL_ORDER_TIME,
+            "It does not support manual order cancel time attribute",
+        )?
+    }
+
+    encoders::encode_cancel_order(client.server_version(), order_id, manual_order_cancel_time)
+}
+
 // Cancels all open [Order]s.
 pub(crate) fn global_cancel(client: &Client) -> Result<(), Error> {
-    client.check_server_version(server_versions::REQ_GLOBAL_CANCEL, "It does not support global cancel requests.")?;
-
-    let message = encoders::encode_global_cancel()?;
+    let message = encode_global_cancel(client)?;
 
     let request_id = client.next_request_id();
     client.send_order(request_id, message)?;
@@ -1361,6 +1731,13 @@ pub(crate) fn global_cancel(client: &Client) -> Result<(), Error> {
     Ok(())
 }
 
+// Encodes the global cancel request, as it would be sent, after verifying it is supported.
+pub(crate) fn encode_global_cancel(client: &Client) -> Result<RequestMessage, Error> {
+    client.check_server_version(server_versions::REQ_GLOBAL_CANCEL, "It does not support global cancel requests.")?;
+
+    encoders::encode_global_cancel()
+}
+
 // Gets next valid order id
 pub(crate) fn next_valid_order_id(client: &Client) -> Result<i32, Error> {
     let message = encoders::encode_next_valid_order_id()?;
@@ -1375,7 +1752,7 @@ pub(crate) fn next_valid_order_id(client: &Client) -> Result<i32, Error> {
 
         Ok(next_order_id)
     } else {
-        Err(Error::Simple("no response from server".into()))
+        Err(Error::Timeout)
     }
 }
 
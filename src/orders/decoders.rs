@@ -43,8 +43,7 @@ impl OrderDecoder {
         contract.contract_id = message.next_int()?;
         contract.symbol = message.next_string()?;
 
-        let security_type = message.next_string()?;
-        contract.security_type = SecurityType::from(&security_type);
+        contract.security_type = SecurityType::from(message.next_str()?);
 
         contract.last_trade_date_or_contract_month = message.next_string()?;
         contract.strike = message.next_double()?;
@@ -59,8 +58,7 @@ impl OrderDecoder {
     }
 
     fn read_action(&mut self) -> Result<(), Error> {
-        let action = self.message.next_string()?;
-        self.order.action = Action::from(&action);
+        self.order.action = Action::from(self.message.next_str()?);
 
         Ok(())
     }
@@ -101,8 +99,7 @@ impl OrderDecoder {
     }
 
     fn read_open_close(&mut self) -> Result<(), Error> {
-        let open_close = self.message.next_string()?;
-        self.order.open_close = OrderOpenClose::from(&open_close);
+        self.order.open_close = OrderOpenClose::from(self.message.next_str()?);
         Ok(())
     }
 
@@ -174,8 +171,7 @@ impl OrderDecoder {
     }
 
     fn read_rule_80_a(&mut self) -> Result<(), Error> {
-        let rule_80_a = self.message.next_string()?;
-        self.order.rule_80_a = Rule80A::from(&rule_80_a);
+        self.order.rule_80_a = Rule80A::from(self.message.next_str()?);
         Ok(())
     }
 
@@ -197,7 +193,10 @@ impl OrderDecoder {
     }
 
     fn read_auction_strategy(&mut self) -> Result<(), Error> {
-        self.order.auction_strategy = self.message.next_optional_int()?;
+        self.order.auction_strategy = match self.message.next_optional_int()? {
+            None | Some(0) => None,
+            Some(strategy) => Some(AuctionStrategy::from(strategy)),
+        };
         Ok(())
     }
 
@@ -262,7 +261,7 @@ impl OrderDecoder {
     }
 
     fn read_trigger_method(&mut self) -> Result<(), Error> {
-        self.order.trigger_method = self.message.next_int()?;
+        self.order.trigger_method = TriggerMethod::from(self.message.next_int()?);
         Ok(())
     }
 
@@ -474,8 +473,8 @@ impl OrderDecoder {
         if self.server_version >= server_versions::PEGGED_TO_BENCHMARK {
             let conditions_count = self.message.next_int()?;
             for _ in 0..conditions_count {
-                let order_condition = self.message.next_int()?;
-                self.order.conditions.push(OrderCondition::from(order_condition));
+                let order_condition = self.read_condition()?;
+                self.order.conditions.push(order_condition);
             }
             if conditions_count > 0 {
                 self.order.conditions_ignore_rth = self.message.next_bool()?;
@@ -485,6 +484,55 @@ impl OrderDecoder {
         Ok(())
     }
 
+    fn read_condition(&mut self) -> Result<OrderCondition, Error> {
+        let condition_type = self.message.next_u8()?;
+        let is_conjunction = self.message.next_char()? == 'a';
+
+        let condition = match condition_type {
+            1 => OrderCondition::Price(PriceCondition {
+                is_more: self.message.next_bool()?,
+                price: self.message.next_double()?,
+                contract_id: self.message.next_int()?,
+                exchange: self.message.next_string()?,
+                trigger_method: TriggerMethod::from(self.message.next_int()?),
+                is_conjunction,
+            }),
+            3 => OrderCondition::Time(TimeCondition {
+                is_more: self.message.next_bool()?,
+                time: self.message.next_string()?,
+                is_conjunction,
+            }),
+            4 => OrderCondition::Margin(MarginCondition {
+                is_more: self.message.next_bool()?,
+                percent: self.message.next_int()?,
+                is_conjunction,
+            }),
+            5 => OrderCondition::Execution(ExecutionCondition {
+                security_type: self.message.next_string()?,
+                exchange: self.message.next_string()?,
+                symbol: self.message.next_string()?,
+                is_conjunction,
+            }),
+            6 => OrderCondition::Volume(VolumeCondition {
+                is_more: self.message.next_bool()?,
+                volume: self.message.next_int()?,
+                contract_id: self.message.next_int()?,
+                exchange: self.message.next_string()?,
+                is_conjunction,
+            }),
+            7 => OrderCondition::PercentChange(PercentChangeCondition {
+                is_more: self.message.next_bool()?,
+                change_percent: self.message.next_double()?,
+                contract_id: self.message.next_int()?,
+                exchange: self.message.next_string()?,
+                is_conjunction,
+            }),
+            _ => return Err(Error::InvalidResponse(format!("unsupported order condition: {condition_type}"))),
+        };
+
+        Ok(condition)
+    }
+
     fn read_adjusted_order_params(&mut self) -> Result<(), Error> {
         if self.server_version >= server_versions::PEGGED_TO_BENCHMARK {
             self.order.adjusted_order_type = self.message.next_string()?;
@@ -767,8 +815,7 @@ pub(crate) fn decode_execution_data(server_version: i32, message: &mut ResponseM
     execution.order_id = message.next_int()?;
     contract.contract_id = message.next_int()?;
     contract.symbol = message.next_string()?;
-    let secutity_type = message.next_string()?;
-    contract.security_type = SecurityType::from(&secutity_type);
+    contract.security_type = SecurityType::from(message.next_str()?);
     contract.last_trade_date_or_contract_month = message.next_string()?;
     contract.strike = message.next_double()?;
     contract.right = message.next_string()?;
@@ -4,13 +4,8 @@ use super::*;
 
 pub(crate) fn encode_place_order(server_version: i32, order_id: i32, contract: &Contract, order: &Order) -> Result<RequestMessage, Error> {
     let mut message = RequestMessage::default();
-    let message_version = message_version_for(server_version);
 
-    message.push_field(&OutgoingMessages::PlaceOrder);
-
-    if server_version < server_versions::ORDER_CONTAINER {
-        message.push_field(&message_version);
-    }
+    message.push_message_header(OutgoingMessages::PlaceOrder, server_version);
 
     message.push_field(&order_id);
 
@@ -285,9 +280,8 @@ pub(crate) fn encode_place_order(server_version: i32, order_id: i32, contract: &
 
         if !order.conditions.is_empty() {
             for condition in &order.conditions {
-                // verify
                 // https://github.com/InteractiveBrokers/tws-api/blob/817a905d52299028ac5af08581c8ffde7644cea9/source/csharpclient/client/EClient.cs#L1187
-                message.push_field(condition);
+                condition.push_fields(&mut message);
             }
 
             message.push_field(&order.conditions_ignore_rth);
@@ -386,12 +380,9 @@ pub(crate) fn encode_place_order(server_version: i32, order_id: i32, contract: &
 }
 
 pub(crate) fn encode_cancel_order(server_version: i32, order_id: i32, manual_order_cancel_time: &str) -> Result<RequestMessage, Error> {
-    const VERSION: i32 = 1;
-
     let mut message = RequestMessage::default();
 
-    message.push_field(&OutgoingMessages::CancelOrder);
-    message.push_field(&VERSION);
+    message.push_message_header(OutgoingMessages::CancelOrder, server_version);
     message.push_field(&order_id);
 
     if server_version >= server_versions::MANUAL_ORDER_TIME {
@@ -468,12 +459,9 @@ pub(crate) fn encode_auto_open_orders(auto_bind: bool) -> Result<RequestMessage,
 }
 
 pub(crate) fn encode_executions(server_version: i32, request_id: i32, filter: &ExecutionFilter) -> Result<RequestMessage, Error> {
-    const VERSION: i32 = 3;
-
     let mut message = RequestMessage::default();
 
-    message.push_field(&OutgoingMessages::RequestExecutions);
-    message.push_field(&VERSION);
+    message.push_message_header(OutgoingMessages::RequestExecutions, server_version);
 
     if server_version >= server_versions::EXECUTION_DATA_CHAIN {
         message.push_field(&request_id);
@@ -498,13 +486,5 @@ fn f64_max_to_zero(num: Option<f64>) -> Option<f64> {
     }
 }
 
-fn message_version_for(server_version: i32) -> i32 {
-    if server_version < server_versions::NOT_HELD {
-        27
-    } else {
-        45
-    }
-}
-
 #[cfg(test)]
 mod tests;
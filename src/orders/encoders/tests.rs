@@ -4,8 +4,13 @@ use super::*;
 
 #[test]
 fn message_version_for() {
-    assert_eq!(super::message_version_for(server_versions::NOT_HELD), 45);
-    assert_eq!(super::message_version_for(server_versions::EXECUTION_DATA_CHAIN), 27);
+    use crate::messages::message_version;
+
+    assert_eq!(message_version(OutgoingMessages::PlaceOrder, server_versions::NOT_HELD), Some(45));
+    assert_eq!(
+        message_version(OutgoingMessages::PlaceOrder, server_versions::EXECUTION_DATA_CHAIN),
+        Some(27)
+    );
 }
 
 #[test]
@@ -14,3 +19,82 @@ fn f64_max_to_zero() {
     assert_eq!(super::f64_max_to_zero(Some(0.0)), Some(0.0));
     assert_eq!(super::f64_max_to_zero(Some(50.0)), Some(50.0));
 }
+
+#[test]
+fn encode_iceberg_order() {
+    let contract = Contract::stock("AAPL");
+    let mut order = order_builder::iceberg(Action::Buy, 1000.0, 180.25, 100);
+    order.sweep_to_fill = true;
+
+    let message = super::encode_place_order(server_versions::SIZE_RULES, 13, &contract, &order).expect("failed to encode place order");
+
+    assert_eq!(message[16], "BUY", "message.action");
+    assert_eq!(message[17], "1000", "message.total_quantity");
+    assert_eq!(message[18], "LMT", "message.order_type");
+    assert_eq!(message[19], "180.25", "message.limit_price");
+    assert_eq!(message[29], "0", "message.block_order");
+    assert_eq!(message[30], "1", "message.sweep_to_fill");
+    assert_eq!(message[31], "100", "message.display_size");
+    assert_eq!(message[32], "0", "message.trigger_method");
+}
+
+#[test]
+fn encode_outside_rth_hidden_order() {
+    let mut contract = Contract::stock("AAPL");
+    contract.exchange = "ISLAND".to_owned();
+
+    let mut order = order_builder::limit_order(Action::Sell, 200.0, 181.5);
+    order.outside_rth = true;
+    order.hidden = true;
+
+    let message = super::encode_place_order(server_versions::SIZE_RULES, 13, &contract, &order).expect("failed to encode place order");
+
+    assert_eq!(message[9], "ISLAND", "message.exchange");
+    assert_eq!(message[32], "0", "message.trigger_method");
+    assert_eq!(message[33], "1", "message.outside_rth");
+    assert_eq!(message[34], "1", "message.hidden");
+
+    order.outside_rth = false;
+    order.hidden = false;
+
+    let message = super::encode_place_order(server_versions::SIZE_RULES, 13, &contract, &order).expect("failed to encode place order");
+
+    assert_eq!(message[33], "0", "message.outside_rth");
+    assert_eq!(message[34], "0", "message.hidden");
+}
+
+#[test]
+fn encode_min_qty_and_percent_offset() {
+    let contract = Contract::stock("AAPL");
+
+    let order = Order {
+        action: Action::Buy,
+        order_type: "REL".to_owned(),
+        total_quantity: 100.0,
+        percent_offset: Some(0.25),
+        ..Order::default()
+    };
+
+    let message = super::encode_place_order(server_versions::SIZE_RULES, 13, &contract, &order).expect("failed to encode place order");
+
+    assert_eq!(message[18], "REL", "message.order_type");
+    assert_eq!(message[51], "", "message.min_qty");
+    assert_eq!(message[52], "0.25", "message.percent_offset");
+
+    let mut order = order_builder::limit_order(Action::Buy, 100.0, 181.5);
+    order.min_qty = Some(25);
+
+    let message = super::encode_place_order(server_versions::SIZE_RULES, 13, &contract, &order).expect("failed to encode place order");
+
+    assert_eq!(message[51], "25", "message.min_qty");
+    assert_eq!(message[52], "", "message.percent_offset");
+}
+
+#[test]
+fn encode_completed_orders() {
+    let message = super::encode_completed_orders(true).expect("failed to encode completed orders");
+    assert_eq!(message.encode_simple(), "99|1|", "api_only = true");
+
+    let message = super::encode_completed_orders(false).expect("failed to encode completed orders");
+    assert_eq!(message.encode_simple(), "99|0|", "api_only = false");
+}
@@ -1,4 +1,4 @@
-use super::{Action, Order, OrderComboLeg, TagValue};
+use super::{Action, AuctionStrategy, Order, OrderComboLeg, TagValue};
 
 /// An auction order is entered into the electronic trading system during the pre-market opening period for execution at the
 /// Calculated Opening Price (COP). If your order is not filled on the open, the order is re-submitted as a limit order with
@@ -192,7 +192,7 @@ pub fn sweep_to_fill(action: Action, quantity: f64, price: f64) -> Order {
 /// limit order price and the nearest listed increment.
 /// Products: OPT
 /// Supported Exchanges: BOX
-pub fn auction_limit(action: Action, quantity: f64, price: f64, auction_strategy: i32) -> Order {
+pub fn auction_limit(action: Action, quantity: f64, price: f64, auction_strategy: AuctionStrategy) -> Order {
     Order {
         action,
         order_type: "LMT".to_owned(),
@@ -288,6 +288,20 @@ pub fn limit_order(action: Action, quantity: f64, limit_price: f64) -> Order {
     }
 }
 
+/// An Iceberg order is a limit order that only shows display_size of its total quantity in the book. The rest is held in reserve
+/// and released as the displayed portion fills. Display size is only honoured for limit orders on venues that support reserve orders.
+/// Products: BOND, CASH, FUT, FOP, OPT, STK, WAR
+pub fn iceberg(action: Action, quantity: f64, limit_price: f64, display_size: i32) -> Order {
+    Order {
+        action,
+        order_type: "LMT".to_owned(),
+        total_quantity: quantity,
+        limit_price: Some(limit_price),
+        display_size: Some(display_size),
+        ..Order::default()
+    }
+}
+
 /// Forex orders can be placed in denomination of second currency in pair using cash_qty field
 /// Requires TWS or IBG 963+
 /// <https://www.interactivebrokers.com/en/index.php?f=23876#963-02>
@@ -1,13 +1,15 @@
 use std::cell::RefCell;
+use std::sync::Mutex;
 
+use crate::client::transport::MessageBus;
 use crate::contracts::{contract_samples, Contract, SecurityType};
-use crate::stubs::MessageBusStub;
+use crate::stubs::{MessageBusStub, RequestMessages};
 
 use super::*;
 
 #[test]
 fn place_order() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub{
+    let message_bus = Mutex::new(Box::new(MessageBusStub{
         request_messages: RefCell::new(vec![]),
         response_messages: vec![
             "5|13|76792991|TSLA|STK||0|?||SMART|USD|TSLA|NMS|BUY|100|MKT|0.0|0.0|DAY||DU1236109||0||100|1376327563|0|0|0||1376327563.0/DU1236109/100||||||||||0||-1|0||||||2147483647|0|0|0||3|0|0||0|0||0|None||0||||?|0|0||0|0||||||0|0|0|2147483647|2147483647|||0||IB|0|0||0|0|PreSubmitted|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308||||||0|0|0|None|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|0||||0|1|0|0|0|||0||".to_owned(),
@@ -18,7 +20,7 @@ fn place_order() {
             "5|13|76792991|TSLA|STK||0|?||SMART|USD|TSLA|NMS|BUY|100|MKT|0.0|0.0|DAY||DU1236109||0||100|1376327563|0|0|0||1376327563.0/DU1236109/100||||||||||0||-1|0||||||2147483647|0|0|0||3|0|0||0|0||0|None||0||||?|0|0||0|0||||||0|0|0|2147483647|2147483647|||0||IB|0|0||0|0|Filled|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.0|||USD||0|0|0|None|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|0||||0|1|0|0|0|||0||".to_owned(),
             "59|1|00025b46.63f8f39c.01.01|1.0|USD|1.7976931348623157E308|1.7976931348623157E308|||".to_owned(),
         ]
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
@@ -35,7 +37,7 @@ fn place_order() {
 
     let result = client.place_order(order_id, &contract, &order);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     assert_eq!(
         request_messages[0].encode().replace('\0', "|"),
@@ -82,8 +84,8 @@ fn place_order() {
         assert_eq!(order.order_ref, "", "order.order_ref");
         assert_eq!(order.client_id, 100, "order.client_id");
         assert_eq!(order.perm_id, 1376327563, "order.perm_id");
-        assert_eq!(order.outside_rth, false, "order.outside_rth");
-        assert_eq!(order.hidden, false, "order.hidden");
+        assert!(!order.outside_rth, "order.outside_rth");
+        assert!(!order.hidden, "order.hidden");
         assert_eq!(order.discretionary_amt, 0.0, "order.discretionary_amt");
         assert_eq!(order.good_after_time, "", "order.good_after_time");
         assert_eq!(order.fa_group, "", "order.fa_group");
@@ -98,20 +100,20 @@ fn place_order() {
         assert_eq!(order.short_sale_slot, 0, "order.short_sale_slot");
         assert_eq!(order.designated_location, "", "order.designated_location");
         assert_eq!(order.exempt_code, -1, "order.exempt_code");
-        assert_eq!(order.auction_strategy, Some(0), "order.auction_strategy");
+        assert_eq!(order.auction_strategy, None, "order.auction_strategy");
         assert_eq!(order.starting_price, None, "order.starting_price");
         assert_eq!(order.stock_ref_price, None, "order.stock_ref_price");
         assert_eq!(order.delta, None, "order.delta");
         assert_eq!(order.stock_range_lower, None, "order.stock_range_lower");
         assert_eq!(order.stock_range_upper, None, "order.stock_range_upper");
         assert_eq!(order.display_size, None, "order.display_size");
-        assert_eq!(order.block_order, false, "order.block_order");
-        assert_eq!(order.sweep_to_fill, false, "order.sweep_to_fill");
-        assert_eq!(order.all_or_none, false, "order.all_or_none");
+        assert!(!order.block_order, "order.block_order");
+        assert!(!order.sweep_to_fill, "order.sweep_to_fill");
+        assert!(!order.all_or_none, "order.all_or_none");
         assert_eq!(order.min_qty, None, "order.min_qty");
         assert_eq!(order.oca_type, 3, "order.oca_type");
         assert_eq!(order.parent_id, 0, "order.parent_id");
-        assert_eq!(order.trigger_method, 0, "order.trigger_method");
+        assert_eq!(order.trigger_method, TriggerMethod::Default, "order.trigger_method");
         assert_eq!(order.volatility, None, "order.volatility");
         assert_eq!(order.volatility_type, Some(0), "order.volatility_type");
         assert_eq!(order.delta_neutral_order_type, "None", "order.delta_neutral_order_type");
@@ -121,10 +123,10 @@ fn place_order() {
         assert_eq!(order.delta_neutral_clearing_account, "", "order.delta_neutral_clearing_account");
         assert_eq!(order.delta_neutral_clearing_intent, "", "order.delta_neutral_clearing_intent");
         assert_eq!(order.delta_neutral_open_close, "?", "order.delta_neutral_open_close");
-        assert_eq!(order.delta_neutral_short_sale, false, "order.delta_neutral_short_sale");
+        assert!(!order.delta_neutral_short_sale, "order.delta_neutral_short_sale");
         assert_eq!(order.delta_neutral_short_sale_slot, 0, "order.delta_neutral_short_sale_slot");
         assert_eq!(order.delta_neutral_designated_location, "", "order.delta_neutral_designated_location");
-        assert_eq!(order.continuous_update, false, "order.continuous_update");
+        assert!(!order.continuous_update, "order.continuous_update");
         assert_eq!(order.reference_price_type, Some(0), "order.reference_price_type");
         assert_eq!(order.trail_stop_price, None, "order.trail_stop_price");
         assert_eq!(order.trailing_percent, None, "order.trailing_percent");
@@ -138,14 +140,14 @@ fn place_order() {
         assert_eq!(order.scale_subs_level_size, None, "order.scale_subs_level_size");
         assert_eq!(order.scale_price_increment, None, "order.scale_price_increment");
         assert_eq!(order.hedge_type, "", "order.hedge_type");
-        assert_eq!(order.opt_out_smart_routing, false, "order.opt_out_smart_routing");
+        assert!(!order.opt_out_smart_routing, "order.opt_out_smart_routing");
         assert_eq!(order.clearing_account, "", "order.clearing_account");
         assert_eq!(order.clearing_intent, "IB", "order.clearing_intent");
-        assert_eq!(order.not_held, false, "order.not_held");
+        assert!(!order.not_held, "order.not_held");
         assert_eq!(order.algo_strategy, "", "order.algo_strategy");
         assert_eq!(order.algo_params.len(), 0, "order.algo_params.len()");
-        assert_eq!(order.solicited, false, "order.solicited");
-        assert_eq!(order.what_if, false, "order.what_if");
+        assert!(!order.solicited, "order.solicited");
+        assert!(!order.what_if, "order.what_if");
         assert_eq!(order_state.status, "PreSubmitted", "order_state.status");
         assert_eq!(order_state.initial_margin_before, None, "order_state.initial_margin_before");
         assert_eq!(order_state.maintenance_margin_before, None, "order_state.maintenance_margin_before");
@@ -161,8 +163,8 @@ fn place_order() {
         assert_eq!(order_state.maximum_commission, None, "order_state.maximum_commission");
         assert_eq!(order_state.commission_currency, "", "order_state.commission_currency");
         assert_eq!(order_state.warning_text, "", "order_state.warning_text");
-        assert_eq!(order.randomize_size, false, "order.randomize_size");
-        assert_eq!(order.randomize_price, false, "order.randomize_price");
+        assert!(!order.randomize_size, "order.randomize_size");
+        assert!(!order.randomize_price, "order.randomize_price");
         assert_eq!(order.conditions.len(), 0, "order.conditions.len()");
         assert_eq!(order.adjusted_order_type, "None", "order.adjusted_order_type");
         assert_eq!(order.trigger_price, None, "order.trigger_price");
@@ -176,20 +178,20 @@ fn place_order() {
         assert_eq!(order.soft_dollar_tier.value, "", "order.soft_dollar_tier.value");
         assert_eq!(order.soft_dollar_tier.display_name, "", "order.soft_dollar_tier.display_name");
         assert_eq!(order.cash_qty, Some(0.0), "order.cash_qty");
-        assert_eq!(order.dont_use_auto_price_for_hedge, true, "order.dont_use_auto_price_for_hedge");
-        assert_eq!(order.is_oms_container, false, "order.is_oms_container");
-        assert_eq!(order.discretionary_up_to_limit_price, false, "order.discretionary_up_to_limit_price");
-        assert_eq!(order.use_price_mgmt_algo, false, "order.use_price_mgmt_algo");
+        assert!(order.dont_use_auto_price_for_hedge, "order.dont_use_auto_price_for_hedge");
+        assert!(!order.is_oms_container, "order.is_oms_container");
+        assert!(!order.discretionary_up_to_limit_price, "order.discretionary_up_to_limit_price");
+        assert!(!order.use_price_mgmt_algo, "order.use_price_mgmt_algo");
         assert_eq!(order.duration, None, "order.duration");
         assert_eq!(order.post_to_ats, None, "order.post_to_ats");
-        assert_eq!(order.auto_cancel_parent, false, "order.auto_cancel_parent");
+        assert!(!order.auto_cancel_parent, "order.auto_cancel_parent");
         assert_eq!(order.min_trade_qty, None, "order.min_trade_qty");
         assert_eq!(order.min_compete_size, None, "order.min_compete_size");
         assert_eq!(order.compete_against_best_offset, None, "order.compete_against_best_offset");
         assert_eq!(order.mid_offset_at_whole, None, "order.mid_offset_at_whole");
         assert_eq!(order.mid_offset_at_half, None, "order.mid_offset_at_half");
     } else {
-        assert!(false, "message[0] expected an open order notification");
+        panic!("message[0] expected an open order notification");
     }
 
     if let Some(OrderNotification::OrderStatus(order_status)) = notifications.next() {
@@ -205,7 +207,7 @@ fn place_order() {
         assert_eq!(order_status.why_held, "", "order_status.why_held");
         assert_eq!(order_status.market_cap_price, 0.0, "order_status.market_cap_price");
     } else {
-        assert!(false, "message[1] expected order status notification");
+        panic!("message[1] expected order status notification");
     }
 
     if let Some(OrderNotification::ExecutionData(execution_data)) = notifications.next() {
@@ -246,7 +248,7 @@ fn place_order() {
         assert_eq!(execution.model_code, "", "execution.model_code");
         assert_eq!(execution.last_liquidity, Liquidity::RemovedLiquidity, "execution.last_liquidity");
     } else {
-        assert!(false, "message[2] expected execution notification");
+        panic!("message[2] expected execution notification");
     }
 
     if let Some(OrderNotification::OpenOrder(open_order)) = notifications.next() {
@@ -255,7 +257,7 @@ fn place_order() {
         assert_eq!(open_order.order_id, 13, "open_order.order_id");
         assert_eq!(order_state.status, "Filled", "order_state.status");
     } else {
-        assert!(false, "message[3] expected an open order notification");
+        panic!("message[3] expected an open order notification");
     }
 
     if let Some(OrderNotification::OrderStatus(order_status)) = notifications.next() {
@@ -266,7 +268,7 @@ fn place_order() {
         assert_eq!(order_status.average_fill_price, 196.52, "order_status.average_fill_price");
         assert_eq!(order_status.last_fill_price, 196.52, "order_status.last_fill_price");
     } else {
-        assert!(false, "message[4] expected order status notification");
+        panic!("message[4] expected order status notification");
     }
 
     if let Some(OrderNotification::OpenOrder(open_order)) = notifications.next() {
@@ -279,7 +281,7 @@ fn place_order() {
         assert_eq!(order_state.maximum_commission, None, "order_state.maximum_commission");
         assert_eq!(order_state.commission_currency, "USD", "order_state.commission_currency");
     } else {
-        assert!(false, "message[5] expected an open order notification");
+        panic!("message[5] expected an open order notification");
     }
 
     if let Some(OrderNotification::CommissionReport(report)) = notifications.next() {
@@ -290,28 +292,28 @@ fn place_order() {
         assert_eq!(report.yields, None, "report.yielded");
         assert_eq!(report.yield_redemption_date, "", "report.yield_redemption_date");
     } else {
-        assert!(false, "message[6] expected a commission report notification");
+        panic!("message[6] expected a commission report notification");
     }
 }
 
 #[test]
 fn cancel_order() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec![
             "3|41|Cancelled|0|100|0|71270927|0|0|100||0||".to_owned(),
             "4|2|41|202|Order Canceled - reason:||".to_owned(),
         ],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
     let order_id = 41;
     let results = client.cancel_order(order_id, "");
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
-    assert_eq!(request_messages[0].encode(), "4\01\041\0");
+    assert_eq!(request_messages[0].encode(), "4\x001\x0041\x00");
 
     assert!(results.is_ok(), "failed to cancel order: {}", results.err().unwrap());
 
@@ -338,35 +340,35 @@ fn cancel_order() {
 
 #[test]
 fn global_cancel() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec![],
-    }));
+    }) as Box<dyn MessageBus>);
 
-    let mut client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
-    let results = super::global_cancel(&mut client);
+    let results = super::global_cancel(&client);
 
-    let request_messages = client.message_bus.borrow_mut().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
-    assert_eq!(request_messages[0].encode(), "58\01\0");
+    assert_eq!(request_messages[0].encode(), "58\x001\x00");
     assert!(results.is_ok(), "failed to cancel order: {}", results.err().unwrap());
 }
 
 #[test]
 fn next_valid_order_id() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec!["9|1|43||".to_owned()],
-    }));
+    }) as Box<dyn MessageBus>);
 
-    let mut client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
-    let results = super::next_valid_order_id(&mut client);
+    let results = super::next_valid_order_id(&client);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
-    assert_eq!(request_messages[0].encode(), "8\01\00\0");
+    assert_eq!(request_messages[0].encode(), "8\x001\x000\x00");
 
     assert!(results.is_ok(), "failed to request next order id: {}", results.err().unwrap());
     assert_eq!(43, results.unwrap(), "next order id");
@@ -374,22 +376,22 @@ fn next_valid_order_id() {
 
 #[test]
 fn completed_orders() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub{
+    let message_bus = Mutex::new(Box::new(MessageBusStub{
         request_messages: RefCell::new(vec![]),
         response_messages: vec![
             "101|265598|AAPL|STK||0|?||SMART|USD|AAPL|NMS|BUY|0|MKT|0.0|0.0|DAY||DU1236109||0||1824933227|0|0|0|||||||||||0||-1||||||2147483647|0|0||3|0||0|None||0|0|0||0|0||||0|0|0|2147483647|2147483647||||IB|0|0||0|Filled|0|0|0|1.7976931348623157E308|1.7976931348623157E308|0|1|0||100|2147483647|0|Not an insider or substantial shareholder|0|0|9223372036854775807|20230306 12:28:30 America/Los_Angeles|Filled Size: 100|".to_owned(),
             "102|".to_owned(),
         ],
-    }));
+    }) as Box<dyn MessageBus>);
 
-    let mut client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
     let api_only = true;
-    let results = super::completed_orders(&mut client, api_only);
+    let results = super::completed_orders(&client, api_only);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
-    assert_eq!(request_messages[0].encode(), "99\01\0");
+    assert_eq!(request_messages[0].encode(), "99\x001\x00");
 
     assert!(results.is_ok(), "failed to request completed orders: {}", results.err().unwrap());
 
@@ -427,8 +429,8 @@ fn completed_orders() {
         assert_eq!(order.origin, 0, "order.origin");
         assert_eq!(order.order_ref, "", "order.order_ref");
         assert_eq!(order.perm_id, 1824933227, "order.perm_id");
-        assert_eq!(order.outside_rth, false, "order.outside_rth");
-        assert_eq!(order.hidden, false, "order.hidden");
+        assert!(!order.outside_rth, "order.outside_rth");
+        assert!(!order.hidden, "order.hidden");
         assert_eq!(order.discretionary_amt, 0.0, "order.discretionary_amt");
         assert_eq!(order.good_after_time, "", "order.good_after_time");
         assert_eq!(order.fa_group, "", "order.fa_group");
@@ -449,20 +451,20 @@ fn completed_orders() {
         assert_eq!(order.stock_range_lower, None, "order.stock_range_lower");
         assert_eq!(order.stock_range_upper, None, "order.stock_range_upper");
         assert_eq!(order.display_size, None, "order.display_size");
-        assert_eq!(order.sweep_to_fill, false, "order.sweep_to_fill");
-        assert_eq!(order.all_or_none, false, "order.all_or_none");
+        assert!(!order.sweep_to_fill, "order.sweep_to_fill");
+        assert!(!order.all_or_none, "order.all_or_none");
         assert_eq!(order.min_qty, None, "order.min_qty");
         assert_eq!(order.oca_type, 3, "order.oca_type");
-        assert_eq!(order.trigger_method, 0, "order.trigger_method");
+        assert_eq!(order.trigger_method, TriggerMethod::Default, "order.trigger_method");
         assert_eq!(order.volatility, None, "order.volatility");
         assert_eq!(order.volatility_type, Some(0), "order.volatility_type");
         assert_eq!(order.delta_neutral_order_type, "None", "order.delta_neutral_order_type");
         assert_eq!(order.delta_neutral_aux_price, None, "order.delta_neutral_aux_price");
         assert_eq!(order.delta_neutral_con_id, 0, "order.delta_neutral_con_id");
-        assert_eq!(order.delta_neutral_short_sale, false, "order.delta_neutral_short_sale");
+        assert!(!order.delta_neutral_short_sale, "order.delta_neutral_short_sale");
         assert_eq!(order.delta_neutral_short_sale_slot, 0, "order.delta_neutral_short_sale_slot");
         assert_eq!(order.delta_neutral_designated_location, "", "order.delta_neutral_designated_location");
-        assert_eq!(order.continuous_update, false, "order.continuous_update");
+        assert!(!order.continuous_update, "order.continuous_update");
         assert_eq!(order.reference_price_type, Some(0), "order.reference_price_type");
         assert_eq!(order.trail_stop_price, None, "order.trail_stop_price");
         assert_eq!(order.trailing_percent, None, "order.trailing_percent");
@@ -476,27 +478,27 @@ fn completed_orders() {
         assert_eq!(order.hedge_type, "", "order.hedge_type");
         assert_eq!(order.clearing_account, "", "order.clearing_account");
         assert_eq!(order.clearing_intent, "IB", "order.clearing_intent");
-        assert_eq!(order.not_held, false, "order.not_held");
+        assert!(!order.not_held, "order.not_held");
         assert_eq!(contract.delta_neutral_contract, None, "contract.delta_neutral_contract");
         assert_eq!(order.algo_strategy, "", "order.algo_strategy");
         assert_eq!(order.algo_params.len(), 0, "order.algo_params.len()");
-        assert_eq!(order.solicited, false, "order.solicited");
+        assert!(!order.solicited, "order.solicited");
         assert_eq!(order_state.status, "Filled", "order_state.status");
-        assert_eq!(order.randomize_size, false, "order.randomize_size");
-        assert_eq!(order.randomize_price, false, "order.randomize_price");
+        assert!(!order.randomize_size, "order.randomize_size");
+        assert!(!order.randomize_price, "order.randomize_price");
         assert_eq!(order.conditions.len(), 0, "order.conditions.len()");
         assert_eq!(order.trail_stop_price, None, "order.trail_stop_price");
         assert_eq!(order.limit_price_offset, None, "order.limit_price_offset");
         assert_eq!(order.cash_qty, Some(0.0), "order.cash_qty");
-        assert_eq!(order.dont_use_auto_price_for_hedge, true, "order.dont_use_auto_price_for_hedge");
-        assert_eq!(order.is_oms_container, false, "order.is_oms_container");
+        assert!(order.dont_use_auto_price_for_hedge, "order.dont_use_auto_price_for_hedge");
+        assert!(!order.is_oms_container, "order.is_oms_container");
         assert_eq!(order.auto_cancel_date, "", "order.auto_cancel_date");
         assert_eq!(order.filled_quantity, 100.0, "order.filled_quantity");
         assert_eq!(order.ref_futures_con_id, None, "order.ref_futures_con_id");
-        assert_eq!(order.auto_cancel_parent, false, "order.auto_cancel_parent");
+        assert!(!order.auto_cancel_parent, "order.auto_cancel_parent");
         assert_eq!(order.shareholder, "Not an insider or substantial shareholder", "order.shareholder");
-        assert_eq!(order.imbalance_only, false, "order.imbalance_only");
-        assert_eq!(order.route_marketable_to_bbo, false, "order.route_marketable_to_bbo");
+        assert!(!order.imbalance_only, "order.imbalance_only");
+        assert!(!order.route_marketable_to_bbo, "order.route_marketable_to_bbo");
         assert_eq!(order.parent_perm_id, None, "order.parent_perm_id");
         assert_eq!(
             order_state.completed_time, "20230306 12:28:30 America/Los_Angeles",
@@ -504,22 +506,152 @@ fn completed_orders() {
         );
         assert_eq!(order_state.completed_status, "Filled Size: 100", "order_state.completed_status");
     } else {
-        assert!(false, "expected order data");
+        panic!("expected order data");
     }
 }
 
+#[test]
+fn decode_what_if_order_state() {
+    let message = ResponseMessage::from(&"5|13|76792991|TSLA|STK||0|?||SMART|USD|TSLA|NMS|BUY|100|MKT|0.0|0.0|DAY||DU1236109||0||100|1376327563|0|0|0||1376327563.0/DU1236109/100||||||||||0||-1|0||||||2147483647|0|0|0||3|0|0||0|0||0|None||0||||?|0|0||0|0||||||0|0|0|2147483647|2147483647|||0||IB|0|0||0|1|PreSubmitted|1000.5|500.25|25000|1250.75|625.5|-1250.75|2251.25|1125.75|23749.25|1.0|1.7976931348623157E308|1.7976931348623157E308|USD|Order would exceed the daily loss limit|0|0|0|None|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|0||||0|1|0|0|0|||0||".replace('|', "\0"));
+
+    let order_data = decoders::decode_open_order(server_versions::SIZE_RULES, message).expect("failed to decode open order");
+    let order_state = &order_data.order_state;
+
+    assert!(order_data.order.what_if, "order.what_if");
+    assert_eq!(order_state.status, "PreSubmitted", "order_state.status");
+
+    assert_eq!(order_state.initial_margin_before, Some(1000.5), "order_state.initial_margin_before");
+    assert_eq!(
+        order_state.maintenance_margin_before,
+        Some(500.25),
+        "order_state.maintenance_margin_before"
+    );
+    assert_eq!(order_state.equity_with_loan_before, Some(25000.0), "order_state.equity_with_loan_before");
+
+    assert_eq!(order_state.initial_margin_change, Some(1250.75), "order_state.initial_margin_change");
+    assert_eq!(
+        order_state.maintenance_margin_change,
+        Some(625.5),
+        "order_state.maintenance_margin_change"
+    );
+    assert_eq!(order_state.equity_with_loan_change, Some(-1250.75), "order_state.equity_with_loan_change");
+
+    assert_eq!(order_state.initial_margin_after, Some(2251.25), "order_state.initial_margin_after");
+    assert_eq!(
+        order_state.maintenance_margin_after,
+        Some(1125.75),
+        "order_state.maintenance_margin_after"
+    );
+    assert_eq!(order_state.equity_with_loan_after, Some(23749.25), "order_state.equity_with_loan_after");
+
+    assert_eq!(order_state.commission, Some(1.0), "order_state.commission");
+    // unset doubles decode as None
+    assert_eq!(order_state.minimum_commission, None, "order_state.minimum_commission");
+    assert_eq!(order_state.maximum_commission, None, "order_state.maximum_commission");
+    assert_eq!(order_state.commission_currency, "USD", "order_state.commission_currency");
+    assert_eq!(
+        order_state.warning_text, "Order would exceed the daily loss limit",
+        "order_state.warning_text"
+    );
+}
+
+#[test]
+fn not_held_and_solicited_round_trip() {
+    let contract = Contract::stock("TSLA");
+
+    let mut order = order_builder::market_order(Action::Buy, 100.0);
+    order.not_held = true;
+    order.solicited = true;
+
+    let message = encoders::encode_place_order(server_versions::SIZE_RULES, 13, &contract, &order).expect("failed to encode place order");
+
+    assert_eq!(message[81], "1", "message.not_held");
+    assert_eq!(message[87], "1", "message.solicited");
+
+    let message = ResponseMessage::from(&"5|13|76792991|TSLA|STK||0|?||SMART|USD|TSLA|NMS|BUY|100|MKT|0.0|0.0|DAY||DU1236109||0||100|1376327563|0|0|0||1376327563.0/DU1236109/100||||||||||0||-1|0||||||2147483647|0|0|0||3|0|0||0|0||0|None||0||||?|0|0||0|0||||||0|0|0|2147483647|2147483647|||0||IB|1|0||1|0|PreSubmitted|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|||0|0|0|None|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|1.7976931348623157E308|0||||0|1|0|0|0|||0||".replace('|', "\0"));
+
+    let order_data = decoders::decode_open_order(server_versions::SIZE_RULES, message).expect("failed to decode open order");
+
+    assert!(order_data.order.not_held, "order.not_held");
+    assert!(order_data.order.solicited, "order.solicited");
+    assert!(!order_data.order.what_if, "order.what_if");
+    assert_eq!(order_data.order_state.status, "PreSubmitted", "order_state.status");
+}
+
+#[test]
+fn decode_completed_order_state() {
+    let message = ResponseMessage::from("101\x00265598\x00AAPL\x00STK\x00\x000\x00?\x00\x00SMART\x00USD\x00AAPL\x00NMS\x00SELL\x0050\x00LMT\x00185.5\x000.0\x00DAY\x00\x00DU1236109\x00\x000\x00\x001824933228\x000\x000\x000\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x000\x00\x00-1\x00\x00\x00\x00\x00\x002147483647\x000\x000\x00\x003\x000\x00\x000\x00None\x00\x000\x000\x000\x00\x000\x000\x00\x00\x00\x000\x000\x000\x002147483647\x002147483647\x00\x00\x00\x00IB\x000\x000\x00\x000\x00Cancelled\x000\x000\x000\x001.7976931348623157E308\x001.7976931348623157E308\x000\x001\x000\x00\x000\x002147483647\x000\x00Not an insider or substantial shareholder\x000\x000\x009223372036854775807\x0020230307 09:45:12 America/Los_Angeles\x00Cancelled by Trader\x00");
+
+    let order_data = decoders::decode_completed_order(server_versions::SIZE_RULES, message).expect("failed to decode completed order");
+
+    assert_eq!(order_data.order.action, Action::Sell, "order.action");
+    assert_eq!(order_data.order.total_quantity, 50.0, "order.total_quantity");
+    assert_eq!(order_data.order_state.status, "Cancelled", "order_state.status");
+    assert_eq!(order_data.order_state.warning_text, "", "order_state.warning_text");
+    assert_eq!(
+        order_data.order_state.completed_time, "20230307 09:45:12 America/Los_Angeles",
+        "order_state.completed_time"
+    );
+    assert_eq!(
+        order_data.order_state.completed_status, "Cancelled by Trader",
+        "order_state.completed_status"
+    );
+}
+
+#[test]
+fn reconstruct_order_tree() {
+    let completed_order = |perm_id: i64, parent_perm_id: i64| {
+        let message = ResponseMessage::from(&format!("101\x00265598\x00AAPL\x00STK\x00\x000\x00?\x00\x00SMART\x00USD\x00AAPL\x00NMS\x00SELL\x0050\x00LMT\x00185.5\x000.0\x00DAY\x00\x00DU1236109\x00\x000\x00\x00{perm_id}\x000\x000\x000\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x000\x00\x00-1\x00\x00\x00\x00\x00\x002147483647\x000\x000\x00\x003\x000\x00\x000\x00None\x00\x000\x000\x000\x00\x000\x000\x00\x00\x00\x000\x000\x000\x002147483647\x002147483647\x00\x00\x00\x00IB\x000\x000\x00\x000\x00Cancelled\x000\x000\x000\x001.7976931348623157E308\x001.7976931348623157E308\x000\x001\x000\x00\x000\x002147483647\x000\x00Not an insider or substantial shareholder\x000\x000\x00{parent_perm_id}\x0020230307 09:45:12 America/Los_Angeles\x00Cancelled by Trader\x00"));
+        decoders::decode_completed_order(server_versions::SIZE_RULES, message).expect("failed to decode completed order")
+    };
+
+    let orders = vec![
+        completed_order(1000, 9223372036854775807),
+        completed_order(1001, 1000),
+        completed_order(1002, 1000),
+        completed_order(2000, 9223372036854775807),
+    ];
+
+    assert_eq!(orders[0].order.parent_perm_id, None, "orders[0].order.parent_perm_id");
+    assert_eq!(orders[1].order.parent_perm_id, Some(1000), "orders[1].order.parent_perm_id");
+
+    let tree = order_tree(&orders);
+
+    assert_eq!(tree.len(), 2, "tree.len()");
+    assert_eq!(tree[&None], vec![1000, 2000], "standalone orders");
+    assert_eq!(tree[&Some(1000)], vec![1001, 1002], "children of 1000");
+    assert_eq!(tree.get(&Some(2000)), None, "children of 2000");
+
+    // open orders link children by order id
+    let open_order = |order_id: i32, perm_id: i32, parent_id: i32| OrderData {
+        order_id,
+        order: Order {
+            order_id,
+            perm_id,
+            parent_id,
+            ..Order::default()
+        },
+        ..OrderData::default()
+    };
+
+    let tree = order_tree(&[open_order(13, 3000, 0), open_order(14, 3001, 13), open_order(15, 3002, 13)]);
+
+    assert_eq!(tree[&None], vec![3000], "standalone orders");
+    assert_eq!(tree[&Some(3000)], vec![3001, 3002], "children of 3000");
+}
+
 #[test]
 fn open_orders() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec!["9|1|43||".to_owned()],
-    }));
+    }) as Box<dyn MessageBus>);
 
-    let mut client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
-    let results = super::open_orders(&mut client);
+    let results = super::open_orders(&client);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     assert_eq!(request_messages[0].encode_simple(), "5|1|");
 
@@ -528,16 +660,16 @@ fn open_orders() {
 
 #[test]
 fn all_open_orders() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec!["9|1|43||".to_owned()],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
     let results = client.all_open_orders();
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     assert_eq!(request_messages[0].encode_simple(), "16|1|");
 
@@ -546,17 +678,17 @@ fn all_open_orders() {
 
 #[test]
 fn auto_open_orders() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec!["9|1|43||".to_owned()],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
     let api_only = true;
     let results = client.auto_open_orders(api_only);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     assert_eq!(request_messages[0].encode_simple(), "15|1|1|");
 
@@ -565,10 +697,10 @@ fn auto_open_orders() {
 
 #[test]
 fn executions() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec!["9|1|43||".to_owned()],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
@@ -583,7 +715,7 @@ fn executions() {
     };
     let results = client.executions(filter);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     assert_eq!(
         request_messages[0].encode_simple(),
@@ -596,10 +728,10 @@ fn executions() {
 
 #[test]
 fn encode_limit_order() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec![],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
@@ -609,7 +741,7 @@ fn encode_limit_order() {
 
     let results = client.place_order(order_id, &contract, &order);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     assert_eq!(
         request_messages[0].encode_simple(),
@@ -621,10 +753,10 @@ fn encode_limit_order() {
 
 #[test]
 fn encode_combo_market_order() {
-    let message_bus = RefCell::new(Box::new(MessageBusStub {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
         request_messages: RefCell::new(vec![]),
         response_messages: vec![],
-    }));
+    }) as Box<dyn MessageBus>);
 
     let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
 
@@ -634,7 +766,7 @@ fn encode_combo_market_order() {
 
     let results = client.place_order(order_id, &contract, &order);
 
-    let request_messages = client.message_bus.borrow().request_messages();
+    let request_messages = client.message_bus().request_messages();
 
     assert_eq!(
         request_messages[0].encode_simple(),
@@ -643,3 +775,294 @@ fn encode_combo_market_order() {
 
     assert!(results.is_ok(), "failed to place order: {}", results.err().unwrap());
 }
+
+#[test]
+fn trigger_method_values() {
+    let trigger_methods = [
+        (TriggerMethod::Default, 0),
+        (TriggerMethod::DoubleBidAsk, 1),
+        (TriggerMethod::Last, 2),
+        (TriggerMethod::DoubleLast, 3),
+        (TriggerMethod::BidAsk, 4),
+        (TriggerMethod::LastOrBidAsk, 7),
+        (TriggerMethod::MidPoint, 8),
+    ];
+
+    for (trigger_method, value) in trigger_methods {
+        assert_eq!(trigger_method.to_field(), value.to_string(), "{trigger_method:?}.to_field()");
+        assert_eq!(TriggerMethod::from(value), trigger_method, "TriggerMethod::from({value})");
+    }
+}
+
+#[test]
+fn encode_price_condition() {
+    let condition = OrderCondition::Price(PriceCondition {
+        contract_id: 265598,
+        exchange: "SMART".to_owned(),
+        is_more: true,
+        price: 180.5,
+        trigger_method: TriggerMethod::DoubleLast,
+        is_conjunction: true,
+    });
+
+    let mut message = RequestMessage::new();
+    condition.push_fields(&mut message);
+
+    assert_eq!(message.encode_simple(), "1|a|1|180.5|265598|SMART|3|");
+}
+
+#[test]
+fn encode_limit_order_snapshot() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("AAPL");
+    let order = order_builder::limit_order(Action::Buy, 100.0, 180.5);
+
+    let message = client.encode_place_order(13, &contract, &order).expect("failed to encode place order");
+
+    // wire format expected by a server at version SIZE_RULES
+    let expected = "3|13|0|AAPL|STK||0|||SMART||USD|||||BUY|100|LMT|180.5||||||0||1|0|0|0|0|0|0|0||0||||||||0||-1|0|||0|||0|0||0||||||0|||||0|||||||||||0|||0|0|||0||0|0|0|0|||||||0|||||||||0|0|0|0|||0|";
+    assert_eq!(message.encode_simple(), expected);
+
+    // nothing is sent
+    assert_eq!(client.message_bus().request_messages().len(), 0, "request_messages.len()");
+}
+
+#[test]
+fn auction_strategy_values() {
+    let auction_strategies = [
+        (AuctionStrategy::Match, 1),
+        (AuctionStrategy::Improvement, 2),
+        (AuctionStrategy::Transparent, 3),
+    ];
+
+    for (auction_strategy, value) in auction_strategies {
+        assert_eq!(auction_strategy.to_field(), value.to_string(), "{auction_strategy:?}.to_field()");
+        assert_eq!(
+            Some(auction_strategy).to_field(),
+            value.to_string(),
+            "Some({auction_strategy:?}).to_field()"
+        );
+        assert_eq!(AuctionStrategy::from(value), auction_strategy, "AuctionStrategy::from({value})");
+    }
+
+    assert_eq!(None::<AuctionStrategy>.to_field(), "0", "None.to_field()");
+}
+
+#[test]
+fn verify_opening_auction_order_type() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+    let contract = Contract::stock("AAPL");
+
+    let order = order_builder::limit_on_open(Action::Buy, 100.0, 180.5);
+    assert!(
+        client.encode_place_order(13, &contract, &order).is_ok(),
+        "LMT with OPG should be accepted"
+    );
+
+    let order = order_builder::market_on_open(Action::Buy, 100.0);
+    assert!(
+        client.encode_place_order(13, &contract, &order).is_ok(),
+        "MKT with OPG should be accepted"
+    );
+
+    let mut order = order_builder::stop(Action::Sell, 100.0, 175.0);
+    order.tif = "OPG".to_owned();
+    let result = client.encode_place_order(13, &contract, &order);
+    assert!(
+        matches!(result, Err(Error::InvalidArgument(_))),
+        "STP with OPG should be rejected: {result:?}"
+    );
+
+    // closing auction orders can't take part in the opening auction
+    let mut order = order_builder::market_on_close(Action::Sell, 100.0);
+    order.tif = "OPG".to_owned();
+    let result = client.encode_place_order(13, &contract, &order);
+    assert!(
+        matches!(result, Err(Error::InvalidArgument(_))),
+        "MOC with OPG should be rejected: {result:?}"
+    );
+
+    let mut order = order_builder::limit_on_close(Action::Sell, 100.0, 180.5);
+    order.tif = "OPG".to_owned();
+    let result = client.encode_place_order(13, &contract, &order);
+    assert!(
+        matches!(result, Err(Error::InvalidArgument(_))),
+        "LOC with OPG should be rejected: {result:?}"
+    );
+}
+
+#[test]
+fn place_basket() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "3|22|PreSubmitted|0|300|0|1376327565|20|0|300||0||".to_owned(),
+            "3|20|PreSubmitted|0|100|0|1376327563|0|0|100||0||".to_owned(),
+            "3|21|PreSubmitted|0|200|0|1376327564|20|0|200||0||".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let parent_id = 20;
+    let mut second_leg = order_builder::market_order(Action::Sell, 200.0);
+    second_leg.parent_id = parent_id;
+    let mut third_leg = order_builder::market_order(Action::Sell, 300.0);
+    third_leg.parent_id = parent_id;
+
+    let basket = vec![
+        (parent_id, Contract::stock("SPY"), order_builder::market_order(Action::Buy, 100.0)),
+        (21, Contract::stock("AAPL"), second_leg),
+        (22, Contract::stock("MSFT"), third_leg),
+    ];
+
+    let notifications = client.place_basket(&basket).expect("failed to place basket");
+
+    // Assert Request
+
+    let request_messages = client.message_bus().request_messages();
+
+    assert_eq!(request_messages.len(), 3, "request_messages.len()");
+    for (i, (order_id, contract, _)) in basket.iter().enumerate() {
+        assert_eq!(request_messages[i][0], OutgoingMessages::PlaceOrder.to_field(), "message.type");
+        assert_eq!(request_messages[i][1], order_id.to_field(), "message.order_id");
+        assert_eq!(request_messages[i][3], contract.symbol, "message.symbol");
+    }
+
+    // only the last order transmits the basket
+    assert_eq!(request_messages[0][27], "0", "message.transmit");
+    assert_eq!(request_messages[1][27], "0", "message.transmit");
+    assert_eq!(request_messages[2][27], "1", "message.transmit");
+
+    // Assert Response
+
+    assert_eq!(notifications.len(), 3, "notifications.len()");
+    for ((order_id, _, order), mut order_notifications) in basket.iter().zip(notifications) {
+        match order_notifications.next() {
+            Some(OrderNotification::OrderStatus(order_status)) => {
+                assert_eq!(order_status.order_id, *order_id, "order_status.order_id");
+                assert_eq!(order_status.remaining, order.total_quantity, "order_status.remaining");
+            }
+            notification => panic!("expected order status for order {order_id}, got {notification:?}"),
+        }
+        assert!(order_notifications.next().is_none(), "unexpected notification for order {order_id}");
+    }
+}
+
+#[test]
+fn place_empty_basket() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    assert!(
+        matches!(client.place_basket(&[]), Err(Error::InvalidArgument(_))),
+        "empty basket should be rejected"
+    );
+}
+
+#[test]
+fn place_order_auto_round() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        // the stub replays all responses for every request, so responses to the contract details lookup come first
+        response_messages: vec![
+            "10|9000|TSLA|STK||0||SMART|USD|TSLA|NMS|NMS|76792991|0.01||LMT,MKT|SMART,ISLAND|1|0|TESLA INC|NASDAQ||Consumer, Cyclical|Auto Manufacturers|Auto-Cars/Light Trucks|US/Eastern|||||1|ISIN|US88160R1014|1|||||COMMON|1|1|100||".to_owned(),
+            "52|1|9000||".to_owned(),
+            "4|2|13|110|The price does not conform to the minimum price variation for this contract.|".to_owned(),
+            "3|13|PreSubmitted|0|100|0|1376327563|0|0|100||0||".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("TSLA");
+    let order = order_builder::limit_order(Action::Buy, 100.0, 180.253);
+
+    let notifications: Vec<OrderNotification> = client
+        .place_order_auto_round(13, &contract, &order)
+        .expect("failed to place order")
+        .collect();
+
+    // Assert Response
+
+    assert_eq!(notifications.len(), 1, "notifications.len()");
+    if let OrderNotification::OrderStatus(order_status) = &notifications[0] {
+        assert_eq!(order_status.order_id, 13, "order_status.order_id");
+        assert_eq!(order_status.status, "PreSubmitted", "order_status.status");
+    } else {
+        panic!("expected order status, got {:?}", notifications[0]);
+    }
+
+    // Assert Request
+
+    let request_messages = client.message_bus().request_messages();
+
+    assert_eq!(request_messages.len(), 3, "request_messages.len()");
+
+    assert_eq!(request_messages[0][0], OutgoingMessages::PlaceOrder.to_field(), "message.type");
+    assert_eq!(request_messages[0][1], "13", "message.order_id");
+    assert_eq!(request_messages[0][19], "180.253", "message.limit_price");
+
+    assert_eq!(request_messages[1][0], OutgoingMessages::RequestContractData.to_field(), "message.type");
+
+    // resubmitted once with the same order id
+    assert_eq!(request_messages[2][0], OutgoingMessages::PlaceOrder.to_field(), "message.type");
+    assert_eq!(request_messages[2][1], "13", "message.order_id");
+    assert_eq!(request_messages[2][19], "180.25", "message.limit_price");
+}
+
+#[test]
+fn place_order_auto_round_rejected_twice() {
+    let message_bus = Mutex::new(Box::new(MessageBusStub {
+        request_messages: RefCell::new(vec![]),
+        response_messages: vec![
+            "10|9000|TSLA|STK||0||SMART|USD|TSLA|NMS|NMS|76792991|0.01||LMT,MKT|SMART,ISLAND|1|0|TESLA INC|NASDAQ||Consumer, Cyclical|Auto Manufacturers|Auto-Cars/Light Trucks|US/Eastern|||||1|ISIN|US88160R1014|1|||||COMMON|1|1|100||".to_owned(),
+            "52|1|9000||".to_owned(),
+            "4|2|13|110|The price does not conform to the minimum price variation for this contract.|".to_owned(),
+            "4|2|13|110|The price does not conform to the minimum price variation for this contract.|".to_owned(),
+        ],
+    }) as Box<dyn MessageBus>);
+
+    let client = Client::stubbed(message_bus, server_versions::SIZE_RULES);
+
+    let contract = Contract::stock("TSLA");
+    let order = order_builder::limit_order(Action::Buy, 100.0, 180.253);
+
+    let notifications: Vec<OrderNotification> = client
+        .place_order_auto_round(13, &contract, &order)
+        .expect("failed to place order")
+        .collect();
+
+    assert_eq!(notifications.len(), 1, "notifications.len()");
+    if let OrderNotification::Message(message) = &notifications[0] {
+        assert_eq!(
+            message, "The price does not conform to the minimum price variation for this contract.",
+            "notification.message"
+        );
+    } else {
+        panic!("expected rejection message, got {:?}", notifications[0]);
+    }
+
+    // resubmitted exactly once
+    let request_messages = client.message_bus().request_messages();
+    let place_orders = request_messages
+        .iter()
+        .filter(|message| message[0] == OutgoingMessages::PlaceOrder.to_field())
+        .count();
+    assert_eq!(place_orders, 2, "place order requests");
+}
@@ -1,8 +1,13 @@
+use std::any::Any;
 use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
 use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
-use crossbeam::channel;
+use byteorder::{BigEndian, WriteBytesExt};
+use crossbeam::channel::{self, Receiver};
 
 use crate::client::transport::{GlobalResponseIterator, MessageBus, ResponseIterator};
 use crate::messages::{RequestMessage, ResponseMessage};
@@ -17,12 +22,14 @@ pub(crate) struct MessageBusStub {
 }
 
 impl MessageBus for MessageBusStub {
-    fn request_messages(&self) -> Vec<RequestMessage> {
-        self.request_messages.borrow().clone()
-    }
-
+    // Returns the stubbed responses in order, e.g. to simulate the connection handshake.
     fn read_message(&mut self) -> Result<ResponseMessage, Error> {
-        Ok(ResponseMessage::default())
+        if self.response_messages.is_empty() {
+            return Ok(ResponseMessage::default());
+        }
+
+        let message = self.response_messages.remove(0);
+        Ok(ResponseMessage::from(&message.replace('|', "\0")))
     }
 
     fn write_message(&mut self, message: &RequestMessage) -> Result<(), Error> {
@@ -42,6 +49,13 @@ impl MessageBus for MessageBusStub {
         mock_request(self, request_id, message)
     }
 
+    fn send_order_messages(&mut self, orders: &[(i32, RequestMessage)]) -> Result<Vec<ResponseIterator>, Error> {
+        orders
+            .iter()
+            .map(|(order_id, message)| mock_order_request(self, *order_id, message))
+            .collect()
+    }
+
     fn request_next_order_id(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
         mock_global_request(self, message)
     }
@@ -62,11 +76,19 @@ impl MessageBus for MessageBusStub {
         mock_global_request(self, message)
     }
 
-    fn write(&mut self, _packet: &str) -> Result<(), Error> {
-        Ok(())
+    fn request_market_depth_exchanges(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        mock_global_request(self, message)
+    }
+
+    fn request_current_time(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        mock_global_request(self, message)
     }
 
-    fn process_messages(&mut self, _server_version: i32) -> Result<(), Error> {
+    // Records raw writes, i.e. the handshake, as single field messages.
+    fn write(&mut self, packet: &str) -> Result<(), Error> {
+        let mut message = RequestMessage::new();
+        message.push_field(&packet);
+        self.request_messages.borrow_mut().push(message);
         Ok(())
     }
 }
@@ -84,6 +106,23 @@ fn mock_request(stub: &mut MessageBusStub, _request_id: i32, message: &RequestMe
     Ok(ResponseIterator::new(receiver, s1, None, None, Some(Duration::from_secs(5))))
 }
 
+// Responds with the stubbed responses addressed to order_id, i.e. OpenOrder and OrderStatus messages.
+fn mock_order_request(stub: &mut MessageBusStub, order_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+    stub.request_messages.borrow_mut().push(message.clone());
+
+    let (sender, receiver) = channel::unbounded();
+    let (s1, _r1) = channel::unbounded();
+
+    for message in &stub.response_messages {
+        let message = ResponseMessage::from(&message.replace('|', "\0"));
+        if message.peek_int(1)? == order_id {
+            sender.send(message).unwrap();
+        }
+    }
+
+    Ok(ResponseIterator::new(receiver, s1, None, None, Some(Duration::from_secs(5))))
+}
+
 fn mock_global_request(stub: &mut MessageBusStub, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
     stub.request_messages.borrow_mut().push(message.clone());
 
@@ -95,3 +134,117 @@ fn mock_global_request(stub: &mut MessageBusStub, message: &RequestMessage) -> R
 
     Ok(GlobalResponseIterator::new(Arc::new(receiver)))
 }
+
+/// Reads the requests recorded by the [MessageBusStub] a client was stubbed with.
+pub(crate) trait RequestMessages {
+    fn request_messages(&self) -> Vec<RequestMessage>;
+}
+
+impl RequestMessages for dyn MessageBus {
+    fn request_messages(&self) -> Vec<RequestMessage> {
+        let message_bus: &dyn Any = self;
+        match message_bus.downcast_ref::<MessageBusStub>() {
+            Some(stub) => stub.request_messages.borrow().clone(),
+            None => vec![],
+        }
+    }
+}
+
+/// A gateway accepting a single connection, for tests going through the transport.
+///
+/// The n-th request read, the handshake included, is answered with the n-th exchange of the script. Once the script is
+/// played, requests are read without answering until the client disconnects. Fields are separated by `|` in responses and
+/// in the recorded requests.
+pub(crate) struct FakeGateway {
+    pub address: String,
+    /// Requests as they are received.
+    pub requests: Receiver<String>,
+    server: JoinHandle<Vec<String>>,
+}
+
+pub(crate) fn fake_gateway(script: &[&[&str]]) -> FakeGateway {
+    serve(script, |stream| stream)
+}
+
+/// A [FakeGateway] accepting a TLS connection, with the certificate of `fixtures/tls/server.pem` issued by `fixtures/tls/ca.pem`.
+#[cfg(feature = "tls")]
+pub(crate) fn fake_tls_gateway(script: &[&[&str]]) -> FakeGateway {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+    let fixture = |name: &str| format!("{}/fixtures/tls/{name}", env!("CARGO_MANIFEST_DIR"));
+    let certificates = CertificateDer::pem_file_iter(fixture("server.pem"))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let key = PrivateKeyDer::from_pem_file(fixture("server-key.pem")).unwrap();
+
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .unwrap();
+    let config = Arc::new(config);
+
+    serve(script, move |stream| StreamOwned::new(ServerConnection::new(config).unwrap(), stream))
+}
+
+fn serve<S: Read + Write>(script: &[&[&str]], accept: impl FnOnce(TcpStream) -> S + Send + 'static) -> FakeGateway {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let script: Vec<Vec<String>> = script
+        .iter()
+        .map(|responses| responses.iter().map(|response| response.replace('|', "\0")).collect())
+        .collect();
+    let (requests_send, requests) = channel::unbounded();
+
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut stream = accept(stream);
+
+        let mut received = vec![];
+        while let Some(request) = read_request(&mut stream) {
+            requests_send.send(request.clone()).ok();
+
+            for response in script.get(received.len()).into_iter().flatten() {
+                stream.write_u32::<BigEndian>(response.len() as u32).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            received.push(request);
+        }
+        received
+    });
+
+    FakeGateway { address, requests, server }
+}
+
+impl FakeGateway {
+    pub fn port(&self) -> u16 {
+        self.address.rsplit(':').next().unwrap().parse().unwrap()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.server.is_finished()
+    }
+
+    /// Waits for the client to disconnect, then returns every request received.
+    pub fn join(self) -> Vec<String> {
+        self.server.join().unwrap()
+    }
+}
+
+// Reads a request, skipping the API prefix sent before the handshake. Returns None once the connection is closed.
+fn read_request(stream: &mut impl Read) -> Option<String> {
+    let mut size = [0_u8; 4];
+    stream.read_exact(&mut size).ok()?;
+    if &size == b"API\0" {
+        stream.read_exact(&mut size).ok()?;
+    }
+
+    let mut request = vec![0_u8; u32::from_be_bytes(size) as usize];
+    stream.read_exact(&mut request).ok()?;
+    Some(String::from_utf8(request).unwrap().replace('\0', "|"))
+}
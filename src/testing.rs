@@ -0,0 +1,286 @@
+//! A [Client] answering from scripted responses, for unit testing applications without TWS.
+//!
+//! Script the responses to each kind of request on a [StubMessageBus], then run the code under test against a
+//! [MockClient], which dereferences to the [Client] it drives. Responses are given as the fields of the messages
+//! TWS would send, separated by `|`, and each request recorded can be inspected afterwards.
+//!
+//! ```
+//! use ibapi::messages::OutgoingMessages;
+//! use ibapi::testing::{MockClient, StubMessageBus};
+//! use ibapi::Client;
+//!
+//! // code under test
+//! fn tick_size(client: &Client, market_rule_id: i32) -> f64 {
+//!     let market_rule = client.market_rule(market_rule_id).expect("request failed");
+//!     market_rule.price_increments[0].increment
+//! }
+//!
+//! let message_bus = StubMessageBus::new().respond(OutgoingMessages::RequestMarketRule, &["93|26|1|0|0.01|"]);
+//! let client = MockClient::new(message_bus).expect("startup failed");
+//!
+//! assert_eq!(tick_size(&client, 26), 0.01);
+//! assert_eq!(client.requests()[0].encode(), "91\026\0");
+//! ```
+//!
+//! The [conformance] module verifies the messages encoded and decoded against captures from the official API clients, and
+//! the [simulator] module provides a client trading with a paper broker.
+
+pub mod conformance;
+#[cfg(feature = "orders")]
+pub mod simulator;
+
+use std::collections::{HashMap, VecDeque};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use crossbeam::channel::{self, Receiver, Sender};
+
+use crate::client::{GlobalResponseIterator, MessageBus, ResponseIterator, Signal, MAX_SERVER_VERSION};
+use crate::messages::{OutgoingMessages, RequestMessage, ResponseMessage};
+use crate::{Client, Error};
+
+/// A [MessageBus] answering each request with the responses scripted for its kind, instead of exchanging messages with TWS.
+///
+/// Requests without scripted responses are recorded and answered with no responses, ending their iterators right away.
+/// Connection and error events are never published.
+#[derive(Debug)]
+pub struct StubMessageBus {
+    server_version: i32,
+    next_order_id: i32,
+    managed_accounts: String,
+    // messages read until the api is started
+    startup: VecDeque<ResponseMessage>,
+    // responses to each request, by message id of the request
+    responses: HashMap<i32, VecDeque<Vec<String>>>,
+    requests: Arc<Mutex<Vec<RequestMessage>>>,
+    // cancellations and releases of requests, discarded
+    signals: (Sender<Signal>, Receiver<Signal>),
+}
+
+impl Default for StubMessageBus {
+    fn default() -> Self {
+        StubMessageBus {
+            server_version: MAX_SERVER_VERSION,
+            next_order_id: 1,
+            managed_accounts: String::from("DU1234567"),
+            startup: VecDeque::new(),
+            responses: HashMap::new(),
+            requests: Arc::new(Mutex::new(Vec::new())),
+            signals: channel::unbounded(),
+        }
+    }
+}
+
+impl StubMessageBus {
+    /// Creates a bus reporting the latest server version supported, next order id 1 and managed account DU1234567.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the server version reported at startup.
+    pub fn server_version(mut self, server_version: i32) -> Self {
+        self.server_version = server_version;
+        self
+    }
+
+    /// Sets the next valid order id reported at startup.
+    pub fn next_order_id(mut self, next_order_id: i32) -> Self {
+        self.next_order_id = next_order_id;
+        self
+    }
+
+    /// Sets the comma separated accounts reported at startup.
+    pub fn managed_accounts(mut self, managed_accounts: &str) -> Self {
+        self.managed_accounts = managed_accounts.to_owned();
+        self
+    }
+
+    /// Scripts the responses to the next request of the given kind. Scripting the same kind again answers the following request.
+    ///
+    /// # Arguments
+    /// * `request`   - kind of request answered, its message id.
+    /// * `responses` - messages answering the request, with fields separated by `|`, e.g. `"93|26|1|0|0.01|"`.
+    pub fn respond(self, request: OutgoingMessages, responses: &[&str]) -> Self {
+        self.respond_to(request as i32, responses)
+    }
+
+    // Scripts the responses to the next request with the given message id.
+    pub(crate) fn respond_to(mut self, message_id: i32, responses: &[&str]) -> Self {
+        let responses = responses.iter().map(|response| response.to_string()).collect();
+        self.responses.entry(message_id).or_default().push_back(responses);
+        self
+    }
+
+    // Records the request and returns the responses scripted for it.
+    fn answer(&mut self, message: &RequestMessage) -> Receiver<ResponseMessage> {
+        self.requests.lock().unwrap().push(message.clone());
+
+        let message_id = message.fields().first().and_then(|field| field.parse::<i32>().ok());
+        let responses = message_id
+            .and_then(|message_id| self.responses.get_mut(&message_id))
+            .and_then(VecDeque::pop_front)
+            .unwrap_or_default();
+
+        // the sender is dropped once all responses are queued, ending the iterator after the last one
+        let (sender, receiver) = channel::unbounded();
+        for response in responses {
+            sender.send(response_message(&response)).unwrap();
+        }
+        receiver
+    }
+
+    fn respond_to_request(&mut self, request_id: Option<i32>, order_id: Option<i32>, message: &RequestMessage) -> ResponseIterator {
+        let responses = self.answer(message);
+        ResponseIterator::new(responses, self.signals.0.clone(), request_id, order_id, None)
+    }
+}
+
+impl MessageBus for StubMessageBus {
+    fn read_message(&mut self) -> Result<ResponseMessage, Error> {
+        self.startup.pop_front().ok_or(Error::Shutdown)
+    }
+
+    fn write_message(&mut self, message: &RequestMessage) -> Result<(), Error> {
+        self.requests.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+
+    fn send_generic_message(&mut self, request_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        Ok(self.respond_to_request(Some(request_id), None, message))
+    }
+
+    fn send_durable_message(&mut self, request_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        Ok(self.respond_to_request(Some(request_id), None, message))
+    }
+
+    fn send_order_message(&mut self, order_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        Ok(self.respond_to_request(None, Some(order_id), message))
+    }
+
+    fn request_next_order_id(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Ok(GlobalResponseIterator::new(Arc::new(self.answer(message))))
+    }
+
+    fn request_open_orders(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Ok(GlobalResponseIterator::new(Arc::new(self.answer(message))))
+    }
+
+    fn request_market_rule(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Ok(GlobalResponseIterator::new(Arc::new(self.answer(message))))
+    }
+
+    fn request_positions(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Ok(GlobalResponseIterator::new(Arc::new(self.answer(message))))
+    }
+
+    fn request_family_codes(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Ok(GlobalResponseIterator::new(Arc::new(self.answer(message))))
+    }
+
+    fn request_market_depth_exchanges(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Ok(GlobalResponseIterator::new(Arc::new(self.answer(message))))
+    }
+
+    fn request_current_time(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        Ok(GlobalResponseIterator::new(Arc::new(self.answer(message))))
+    }
+
+    // Answers the handshake with the configured server version.
+    fn write(&mut self, _packet: &str) -> Result<(), Error> {
+        self.startup
+            .push_back(response_message(&format!("{}|20230405 22:20:39 PST|", self.server_version)));
+        Ok(())
+    }
+
+    // Answers with the next valid order id and managed accounts, as TWS does once the api is started.
+    fn start_api(&mut self, _message: &RequestMessage) -> Result<(), Error> {
+        self.startup.push_back(response_message(&format!("9|1|{}|", self.next_order_id)));
+        self.startup.push_back(response_message(&format!("15|1|{}|", self.managed_accounts)));
+        Ok(())
+    }
+}
+
+fn response_message(fields: &str) -> ResponseMessage {
+    ResponseMessage::from_string(fields.replace('|', "\0"))
+}
+
+/// A [Client] started over a [StubMessageBus], recording the requests sent. Dereferences to the [Client], so it can be
+/// passed to code expecting one.
+#[derive(Debug)]
+pub struct MockClient {
+    client: Client,
+    requests: Arc<Mutex<Vec<RequestMessage>>>,
+}
+
+impl MockClient {
+    /// Starts a [Client] over the given bus.
+    ///
+    /// # Arguments
+    /// * `message_bus` - bus answering the client's requests.
+    pub fn new(message_bus: StubMessageBus) -> Result<MockClient, Error> {
+        let requests = Arc::clone(&message_bus.requests);
+        let client = Client::with_message_bus(Box::new(message_bus), 100)?;
+
+        // the startup is not a request of the code under test
+        requests.lock().unwrap().clear();
+
+        Ok(MockClient { client, requests })
+    }
+
+    /// Returns the requests sent so far, in order.
+    pub fn requests(&self) -> Vec<RequestMessage> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Deref for MockClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_versions;
+
+    #[test]
+    fn startup() {
+        let message_bus = StubMessageBus::new()
+            .server_version(server_versions::SIZE_RULES)
+            .next_order_id(90)
+            .managed_accounts("DU1,DU2");
+        let client = MockClient::new(message_bus).unwrap();
+
+        assert_eq!(client.server_version(), server_versions::SIZE_RULES);
+        assert_eq!(client.next_order_id(), 90);
+        assert_eq!(client.managed_accounts(), "DU1,DU2");
+        assert!(client.requests().is_empty());
+    }
+
+    #[test]
+    fn scripted_responses() {
+        let message_bus = StubMessageBus::new()
+            .respond(OutgoingMessages::RequestMarketRule, &["93|26|1|0|0.01|"])
+            .respond(OutgoingMessages::RequestMarketRule, &["93|27|1|0|0.05|"])
+            .respond(
+                OutgoingMessages::RequestContractData,
+                &["4|2|9000|200|No security definition has been found|"],
+            );
+        let client = MockClient::new(message_bus).unwrap();
+
+        assert_eq!(client.market_rule(26).unwrap().price_increments[0].increment, 0.01);
+        assert_eq!(client.market_rule(27).unwrap().price_increments[0].increment, 0.05);
+        assert!(client.market_rule(28).is_err(), "no responses left");
+
+        let result = client.contract_details(&crate::contracts::Contract::stock("XYZ")).err();
+        assert!(matches!(result, Some(Error::TwsError { code: 200, .. })), "{result:?}");
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[1].encode(), "91\x0027\x00");
+        assert_eq!(requests[3][0], "9");
+    }
+}
@@ -0,0 +1,380 @@
+//! Verifies the messages exchanged with TWS against messages captured from the official API clients.
+//!
+//! Each fixture names a case, i.e. a request made with fixed arguments, the server version it was captured at,
+//! the requests the official client sent for it and the messages TWS answered with. [verify] runs each case against a
+//! [MockClient], compares the requests encoded by this library with the captured ones field by field, then decodes
+//! the captured responses. Capturing the fixtures again once IB bumps the server version detects protocol drift.
+//!
+//! Fixtures are written one line per message, fields separated by `|`:
+//!
+//! ```text
+//! # lines starting with # are comments
+//! case market_rule 164
+//! > 91|26|
+//! < 93|26|1|0|0.01|
+//! ```
+//!
+//! Requests sent are prefixed by `>`, and responses received by `<`. The cases, and the arguments they're made with, are:
+//!
+//! | Case                  | Request                                                                       |
+//! |-----------------------|-------------------------------------------------------------------------------|
+//! | `contract_details`    | contract details of `Contract::stock("TSLA")`                                 |
+//! | `market_rule`         | market rule 26                                                                |
+//! | `server_time`         | current time                                                                  |
+//! | `positions`           | positions                                                                     |
+//! | `family_codes`        | family codes                                                                  |
+//! | `head_timestamp`      | head timestamp of `Contract::stock("MSFT")` trades, in regular trading hours  |
+//! | `historical_data`     | 2 days of hourly `Contract::stock("MSFT")` trades up to 2023-04-15 16:31:22 UTC, in regular trading hours |
+//! | `realtime_bars`       | 5 second bars of `Contract::stock("MSFT")` trades, in regular trading hours   |
+//! | `place_order`         | order 13, a market order buying 100 shares of TSLA on SMART in USD            |
+//! | `cancel_order`        | cancel order 13                                                               |
+//! | `next_valid_order_id` | next valid order id                                                           |
+//! | `open_orders`         | open orders                                                                   |
+//! | `global_cancel`       | cancel all orders                                                             |
+//!
+//! Cases of APIs behind a disabled feature are unknown.
+//!
+//! ```no_run
+//! use ibapi::testing::conformance;
+//!
+//! let fixtures = conformance::load_fixtures("fixtures/conformance.txt").expect("invalid fixtures");
+//! for mismatch in conformance::verify(&fixtures) {
+//!     println!("{mismatch}");
+//! }
+//! ```
+
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use super::{MockClient, StubMessageBus};
+use crate::contracts::Contract;
+use crate::messages::describe_for_server_version;
+use crate::{Client, Error};
+
+/// A request captured from an official API client, and the messages TWS answered with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixture {
+    /// Name of the case, e.g. `market_rule`.
+    pub case: String,
+    /// Server version negotiated when the messages were captured.
+    pub server_version: i32,
+    /// Requests sent, in order, fields separated by `|`.
+    pub requests: Vec<String>,
+    /// Responses to the first request, in order, fields separated by `|`.
+    pub responses: Vec<String>,
+    /// Line of the fixture in the file it was parsed from.
+    pub line: usize,
+}
+
+/// A difference between the messages of a [Fixture] and those encoded or decoded by this library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Name of the case.
+    pub case: String,
+    /// Line of the fixture.
+    pub line: usize,
+    /// What differs.
+    pub description: String,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, {}: {}", self.line, self.case, self.description)
+    }
+}
+
+/// Parses fixtures in the format described in the [module documentation](self).
+///
+/// # Arguments
+/// * `text` - fixtures, one message per line.
+pub fn parse_fixtures(text: &str) -> Result<Vec<Fixture>, Error> {
+    let mut fixtures: Vec<Fixture> = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix("case ") {
+            let mut words = header.split_whitespace();
+            let (Some(case), Some(server_version), None) = (words.next(), words.next(), words.next()) else {
+                return Err(Error::Parse(line_number, line.into(), "expected case <name> <server version>".into()));
+            };
+            let server_version = server_version
+                .parse()
+                .map_err(|_| Error::Parse(line_number, line.into(), "invalid server version".into()))?;
+
+            fixtures.push(Fixture {
+                case: case.to_owned(),
+                server_version,
+                requests: Vec::new(),
+                responses: Vec::new(),
+                line: line_number,
+            });
+            continue;
+        }
+
+        let Some(fixture) = fixtures.last_mut() else {
+            return Err(Error::Parse(line_number, line.into(), "message outside of a case".into()));
+        };
+        if let Some(request) = line.strip_prefix("> ") {
+            fixture.requests.push(request.to_owned());
+        } else if let Some(response) = line.strip_prefix("< ") {
+            fixture.responses.push(response.to_owned());
+        } else {
+            return Err(Error::Parse(
+                line_number,
+                line.into(),
+                "expected a case, a request (>) or a response (<)".into(),
+            ));
+        }
+    }
+
+    Ok(fixtures)
+}
+
+/// Reads and parses a fixtures file. See [parse_fixtures].
+///
+/// # Arguments
+/// * `path` - path of the fixtures file.
+pub fn load_fixtures(path: impl AsRef<Path>) -> Result<Vec<Fixture>, Error> {
+    parse_fixtures(&std::fs::read_to_string(path)?)
+}
+
+/// Returns the names of the cases fixtures can refer to, for the features enabled.
+pub fn cases() -> Vec<&'static str> {
+    all_cases().into_iter().map(|(name, _)| name).collect()
+}
+
+/// Verifies each fixture, returning the differences found. None means the library speaks the captured protocol.
+///
+/// # Arguments
+/// * `fixtures` - fixtures to verify, e.g. from [load_fixtures].
+pub fn verify(fixtures: &[Fixture]) -> Vec<Mismatch> {
+    fixtures
+        .iter()
+        .flat_map(|fixture| {
+            verify_fixture(fixture).into_iter().map(|description| Mismatch {
+                case: fixture.case.clone(),
+                line: fixture.line,
+                description,
+            })
+        })
+        .collect()
+}
+
+// Makes a request with fixed arguments, returning the number of items decoded from its responses.
+type Case = fn(&Client) -> Result<usize, Error>;
+
+fn all_cases() -> Vec<(&'static str, Case)> {
+    #[allow(unused_mut)]
+    let mut cases: Vec<(&'static str, Case)> = vec![
+        ("contract_details", |client| {
+            Ok(client.contract_details(&Contract::stock("TSLA"))?.count())
+        }),
+        ("market_rule", |client| client.market_rule(26).map(|_| 1)),
+        ("server_time", |client| client.server_time().map(|_| 1)),
+    ];
+
+    #[cfg(feature = "accounts")]
+    cases.extend([
+        ("positions", (|client| Ok(client.positions()?.count())) as Case),
+        ("family_codes", |client| Ok(client.family_codes()?.len())),
+    ]);
+
+    #[cfg(feature = "market_data")]
+    cases.extend([
+        (
+            "head_timestamp",
+            (|client| {
+                use crate::market_data::historical::WhatToShow;
+                client.head_timestamp(&Contract::stock("MSFT"), WhatToShow::Trades, true).map(|_| 1)
+            }) as Case,
+        ),
+        ("historical_data", |client| {
+            use crate::market_data::historical::{BarSize, ToDuration, WhatToShow};
+            use time::macros::datetime;
+            let end = datetime!(2023-04-15 16:31:22 UTC);
+            let data = client.historical_data(&Contract::stock("MSFT"), end, 2.days(), BarSize::Hour, WhatToShow::Trades, true)?;
+            Ok(data.bars.len())
+        }),
+        ("realtime_bars", |client| {
+            use crate::market_data::realtime::{BarSize, WhatToShow};
+            Ok(client
+                .realtime_bars(&Contract::stock("MSFT"), BarSize::Sec5, WhatToShow::Trades, true)?
+                .count())
+        }),
+    ]);
+
+    #[cfg(feature = "orders")]
+    cases.extend([
+        (
+            "place_order",
+            (|client| {
+                use crate::contracts::SecurityType;
+                use crate::orders::{order_builder, Action};
+                let contract = Contract {
+                    symbol: "TSLA".to_owned(),
+                    security_type: SecurityType::Stock,
+                    exchange: "SMART".to_owned(),
+                    currency: "USD".to_owned(),
+                    ..Contract::default()
+                };
+                Ok(client
+                    .place_order(13, &contract, &order_builder::market_order(Action::Buy, 100.0))?
+                    .count())
+            }) as Case,
+        ),
+        ("cancel_order", |client| Ok(client.cancel_order(13, "")?.count())),
+        ("next_valid_order_id", |client| client.next_valid_order_id().map(|_| 1)),
+        ("open_orders", |client| Ok(client.open_orders()?.count())),
+        ("global_cancel", |client| client.global_cancel().map(|_| 0)),
+    ]);
+
+    cases
+}
+
+fn verify_fixture(fixture: &Fixture) -> Vec<String> {
+    let Some((_, case)) = all_cases().into_iter().find(|(name, _)| *name == fixture.case) else {
+        return vec![format!("unknown case, expected one of {}", cases().join(", "))];
+    };
+
+    let mut mismatches = Vec::new();
+
+    // requests
+    let client = match MockClient::new(StubMessageBus::new().server_version(fixture.server_version)) {
+        Ok(client) => client,
+        Err(err) => return vec![format!("failed to start client: {err}")],
+    };
+    // without responses the request fails, only what was sent matters here
+    let _ = case(&client);
+    let sent: Vec<String> = client.requests().iter().map(|request| request.encode().replace('\0', "|")).collect();
+
+    for (i, expected) in fixture.requests.iter().enumerate() {
+        match sent.get(i) {
+            Some(encoded) => mismatches.extend(compare_fields(expected, encoded).map(|difference| format!("request {i}: {difference}"))),
+            None => mismatches.push(format!("request {i}: not sent")),
+        }
+    }
+
+    if fixture.responses.is_empty() {
+        return mismatches;
+    }
+
+    // responses
+    let Some(message_id) = sent.first().and_then(|request| request.split('|').next()?.parse().ok()) else {
+        mismatches.push("no request sent to answer".into());
+        return mismatches;
+    };
+    let responses: Vec<&str> = fixture.responses.iter().map(String::as_str).collect();
+    let message_bus = StubMessageBus::new()
+        .server_version(fixture.server_version)
+        .respond_to(message_id, &responses);
+    let client = match MockClient::new(message_bus) {
+        Ok(client) => client,
+        Err(err) => return vec![format!("failed to start client: {err}")],
+    };
+    match case(&client) {
+        Ok(0) => mismatches.push("no responses decoded".into()),
+        Ok(_) => (),
+        Err(err) => mismatches.push(format!("failed to decode responses: {err}")),
+    }
+
+    for (i, response) in fixture.responses.iter().enumerate() {
+        let decoded = describe_for_server_version(&response.replace('|', "\0"), fixture.server_version);
+
+        // only messages with a known layout, i.e. with labels past the message type, tell unknown fields apart.
+        // Empty fields left over are padding.
+        let labeled = decoded.fields.iter().filter(|field| field.label.is_some()).count();
+        let unknown: Vec<&str> = decoded
+            .fields
+            .iter()
+            .filter(|field| field.label.is_none() && !field.value.is_empty())
+            .map(|field| field.value.as_str())
+            .collect();
+        if labeled > 1 && !unknown.is_empty() {
+            mismatches.push(format!("response {i}: {} fields not known: {unknown:?}", unknown.len()));
+        }
+    }
+
+    mismatches
+}
+
+// Describes the first field that differs, and differences in the number of fields.
+fn compare_fields(expected: &str, encoded: &str) -> Option<String> {
+    let expected = fields(expected);
+    let encoded = fields(encoded);
+
+    if let Some(i) = expected.iter().zip(&encoded).position(|(expected, encoded)| expected != encoded) {
+        return Some(format!("field {i} is {:?}, expected {:?}", encoded[i], expected[i]));
+    }
+    if expected.len() != encoded.len() {
+        return Some(format!("{} fields encoded, expected {}", encoded.len(), expected.len()));
+    }
+    None
+}
+
+// Splits a message into its fields, each terminated by a separator.
+fn fields(message: &str) -> Vec<&str> {
+    message.strip_suffix('|').unwrap_or(message).split('|').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let fixtures =
+            parse_fixtures("# market rules\ncase market_rule 164\n> 91|26|\n< 93|26|1|0|0.01|\n\ncase server_time 164\n> 49|1|\n").unwrap();
+
+        assert_eq!(fixtures.len(), 2);
+        assert_eq!(
+            fixtures[0],
+            Fixture {
+                case: "market_rule".into(),
+                server_version: 164,
+                requests: vec!["91|26|".into()],
+                responses: vec!["93|26|1|0|0.01|".into()],
+                line: 2,
+            }
+        );
+        assert_eq!(fixtures[1].line, 6);
+
+        assert!(parse_fixtures("> 91|26|").is_err(), "message outside of a case");
+        assert!(parse_fixtures("case market_rule").is_err(), "missing server version");
+        assert!(parse_fixtures("case market_rule 164\n91|26|").is_err(), "missing direction");
+    }
+
+    #[test]
+    fn mismatches() {
+        let fixtures = parse_fixtures(
+            "case market_rule 164\n> 91|27|\n< 93|26|1|0|0.01|extra|\ncase market_rule 164\n> 91|26|1|\ncase options 164\ncase server_time 164\n> 49|1|\n< 49|1|not a time|",
+        )
+        .unwrap();
+
+        let mismatches: Vec<String> = verify(&fixtures).iter().map(|mismatch| mismatch.to_string()).collect();
+        assert_eq!(mismatches[0], r#"line 1, market_rule: request 0: field 1 is "26", expected "27""#);
+        assert_eq!(mismatches[1], r#"line 1, market_rule: response 0: 1 fields not known: ["extra"]"#);
+        assert_eq!(mismatches[2], "line 4, market_rule: request 0: 2 fields encoded, expected 3");
+        assert!(mismatches[3].starts_with("line 6, options: unknown case"), "{}", mismatches[3]);
+        assert!(
+            mismatches[4].starts_with("line 7, server_time: failed to decode responses"),
+            "{}",
+            mismatches[4]
+        );
+        assert_eq!(mismatches.len(), 5);
+    }
+
+    #[test]
+    #[cfg(all(feature = "accounts", feature = "market_data", feature = "orders"))]
+    fn fixtures() {
+        let fixtures = load_fixtures(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/conformance.txt")).unwrap();
+        assert_eq!(fixtures.len(), cases().len(), "a fixture for each case");
+
+        let mismatches: Vec<String> = verify(&fixtures).iter().map(|mismatch| mismatch.to_string()).collect();
+        assert!(mismatches.is_empty(), "{mismatches:#?}");
+    }
+}
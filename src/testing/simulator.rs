@@ -0,0 +1,892 @@
+//! A paper broker running in process, for developing and testing strategies offline.
+//!
+//! A [SimulatedClient] is a [Client] whose orders are filled against prices set with [SimulatedClient::set_price], or
+//! bars pushed with [SimulatedClient::push_bar], e.g. replayed from historical data. Orders go through the status
+//! transitions TWS reports, `PreSubmitted`, `Submitted` while resting, then `Filled` or `Cancelled`, with an execution and
+//! commission report for each fill. Positions and PnL are tracked per symbol.
+//!
+//! Time is kept by a [VirtualClock], starting at the Unix epoch and moved to the date of each bar pushed, so executions
+//! are timestamped and `GTD` orders expire deterministically, whatever the speed of the replay. Orders whose good till
+//! date the clock passed are cancelled as prices are updated.
+//!
+//! | Order type | Fills                                                           |
+//! |------------|-----------------------------------------------------------------|
+//! | `MKT`      | at the current price, or the open of the next bar               |
+//! | `LMT`      | once the price reaches the limit price, at the limit or better  |
+//! | `STP`      | once the price reaches the stop price, at the stop or worse     |
+//!
+//! Other order types are rejected. Orders are filled in full, and requests other than placing and cancelling orders,
+//! positions and realtime bars are answered as a [StubMessageBus] without scripted responses does.
+//!
+//! ```
+//! use ibapi::contracts::Contract;
+//! use ibapi::orders::{order_builder, Action, OrderNotification};
+//! use ibapi::testing::simulator::SimulatedClient;
+//!
+//! let client = SimulatedClient::new().expect("startup failed");
+//! client.set_price("AAPL", 185.0);
+//!
+//! let order = order_builder::market_order(Action::Buy, 100.0);
+//! let notifications = client.place_order(client.next_order_id(), &Contract::stock("AAPL"), &order).expect("order failed");
+//! for notification in notifications {
+//!     if let OrderNotification::OrderStatus(status) = notification {
+//!         println!("{} {}", status.status, status.filled);
+//!     }
+//! }
+//!
+//! client.set_price("AAPL", 190.0);
+//! assert_eq!(client.portfolio()[0].unrealized_pnl, 500.0);
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Deref;
+use std::sync::atomic::AtomicI32;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam::channel::{self, Receiver, Sender};
+
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use time_tz::{timezones, OffsetResult, PrimitiveDateTimeExt};
+
+use super::{response_message, StubMessageBus};
+use crate::client::{
+    Clock, ConnectionEvent, ErrorEvent, GlobalResponseIterator, MessageBus, MetricsObserver, ParseMode, ResponseIterator, SubscriptionBuffer,
+    VirtualClock,
+};
+#[cfg(feature = "market_data")]
+use crate::market_data::realtime::Bar;
+use crate::messages::{OutgoingMessages, RequestMessage, ResponseMessage};
+use crate::{Client, Error};
+
+// Fields of the place order request, as encoded at the latest server version.
+const ORDER_ID: usize = 1;
+const CONTRACT_ID: usize = 2;
+const SYMBOL: usize = 3;
+const SECURITY_TYPE: usize = 4;
+const CURRENCY: usize = 11;
+const ACTION: usize = 16;
+const QUANTITY: usize = 17;
+const ORDER_TYPE: usize = 18;
+const LIMIT_PRICE: usize = 19;
+const AUX_PRICE: usize = 20;
+const TIME_IN_FORCE: usize = 21;
+const GOOD_TILL_DATE: usize = 38;
+
+// Field of the realtime bars request holding the symbol.
+#[cfg(feature = "market_data")]
+const BARS_SYMBOL: usize = 4;
+
+const CLIENT_ID: i32 = 100;
+const ACCOUNT: &str = "DU1234567";
+// Sent by TWS for values not set.
+const UNSET_DOUBLE: &str = "1.7976931348623157E308";
+
+/// A position held with the simulated broker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedPosition {
+    pub symbol: String,
+    /// Number of shares held, negative when short.
+    pub position: f64,
+    /// Average price paid per share.
+    pub average_cost: f64,
+    /// PnL of the shares closed, net of commissions.
+    pub realized_pnl: f64,
+    /// PnL of the shares held, at the current price.
+    pub unrealized_pnl: f64,
+}
+
+/// A [Client] trading with a simulated broker. Dereferences to the [Client], so it can be passed to code expecting one.
+#[derive(Debug)]
+pub struct SimulatedClient {
+    client: Client,
+    broker: Arc<Mutex<Broker>>,
+    clock: VirtualClock,
+}
+
+impl SimulatedClient {
+    /// Starts a [Client] trading with a simulated broker, without commissions.
+    pub fn new() -> Result<SimulatedClient, Error> {
+        let clock = VirtualClock::new(OffsetDateTime::UNIX_EPOCH);
+        let broker = Arc::new(Mutex::new(Broker::new(clock.clone())));
+        let message_bus = SimulatedMessageBus {
+            stub: StubMessageBus::new().managed_accounts(ACCOUNT),
+            broker: Arc::clone(&broker),
+        };
+        let mut client = Client::with_message_bus(Box::new(message_bus), CLIENT_ID)?;
+        client.clock = Arc::new(clock.clone());
+
+        Ok(SimulatedClient { client, broker, clock })
+    }
+
+    /// Returns the clock of the simulation, also read by [Client::now].
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+
+    /// Sets the commission charged per share filled.
+    pub fn set_commission(&self, per_share: f64) {
+        self.broker.lock().unwrap().commission = per_share;
+    }
+
+    /// Sets the price of a symbol, filling the orders resting on it that the price reaches.
+    ///
+    /// # Arguments
+    /// * `symbol` - symbol of the contract, e.g. `AAPL`.
+    /// * `price`  - price it last traded at.
+    pub fn set_price(&self, symbol: &str, price: f64) {
+        let quote = Quote {
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+        };
+        self.broker.lock().unwrap().update(symbol, &quote);
+    }
+
+    /// Moves the clock to the bar's date and publishes the bar to the realtime bars subscriptions of a symbol, then fills the
+    /// orders resting on it that the bar's range reaches. Market orders fill at the bar's open, and the price of the symbol
+    /// becomes the bar's close.
+    ///
+    /// # Arguments
+    /// * `symbol` - symbol of the contract, e.g. `AAPL`.
+    /// * `bar`    - next bar, e.g. replayed from historical data.
+    #[cfg(feature = "market_data")]
+    pub fn push_bar(&self, symbol: &str, bar: &Bar) {
+        self.clock.set(bar.date);
+
+        let mut broker = self.broker.lock().unwrap();
+
+        broker.bar_subscriptions.retain(|subscription| {
+            if subscription.symbol != symbol {
+                return true;
+            }
+            let message = format!(
+                "50|3|{}|{}|{}|{}|{}|{}|{}|{}|{}|",
+                subscription.request_id,
+                bar.date.unix_timestamp(),
+                bar.open,
+                bar.high,
+                bar.low,
+                bar.close,
+                bar.volume,
+                bar.wap,
+                bar.count
+            );
+            // subscriptions cancelled are dropped
+            subscription.sender.send(response_message(&message)).is_ok()
+        });
+
+        let quote = Quote {
+            open: bar.open,
+            high: bar.high,
+            low: bar.low,
+            close: bar.close,
+        };
+        broker.update(symbol, &quote);
+    }
+
+    /// Returns the positions held, and closed, by symbol.
+    pub fn portfolio(&self) -> Vec<SimulatedPosition> {
+        let broker = self.broker.lock().unwrap();
+        broker
+            .holdings
+            .iter()
+            .map(|(symbol, holding)| {
+                let price = broker.prices.get(symbol).copied().unwrap_or(holding.average_cost);
+                SimulatedPosition {
+                    symbol: symbol.clone(),
+                    position: holding.position,
+                    average_cost: holding.average_cost,
+                    realized_pnl: holding.realized_pnl,
+                    unrealized_pnl: holding.position * (price - holding.average_cost),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Deref for SimulatedClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+// Range of prices traded since the last update.
+struct Quote {
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OrderType {
+    Market,
+    Limit(f64),
+    Stop(f64),
+}
+
+#[derive(Debug)]
+struct WorkingOrder {
+    order_id: i32,
+    contract_id: i32,
+    symbol: String,
+    security_type: String,
+    currency: String,
+    // 1 to buy, -1 to sell
+    side: f64,
+    quantity: f64,
+    order_type: OrderType,
+    // good till date of GTD orders
+    expires: Option<OffsetDateTime>,
+    // receives the order's status, executions and commission reports
+    sender: Sender<ResponseMessage>,
+}
+
+impl WorkingOrder {
+    fn parse(message: &RequestMessage, sender: Sender<ResponseMessage>) -> Result<WorkingOrder, String> {
+        let field = |i: usize| message.fields().get(i).map(String::as_str).unwrap_or_default();
+        let price = |i: usize| field(i).parse::<f64>().map_err(|_| format!("invalid price {:?}", field(i)));
+
+        let side = match field(ACTION) {
+            "BUY" => 1.0,
+            "SELL" | "SSHORT" => -1.0,
+            action => return Err(format!("unsupported action {action}")),
+        };
+        let order_type = match field(ORDER_TYPE) {
+            "MKT" => OrderType::Market,
+            "LMT" => OrderType::Limit(price(LIMIT_PRICE)?),
+            "STP" => OrderType::Stop(price(AUX_PRICE)?),
+            order_type => return Err(format!("unsupported order type {order_type}")),
+        };
+        let expires = match field(TIME_IN_FORCE) {
+            "GTD" => Some(parse_good_till_date(field(GOOD_TILL_DATE)).ok_or_else(|| format!("invalid good till date {:?}", field(GOOD_TILL_DATE)))?),
+            _ => None,
+        };
+
+        Ok(WorkingOrder {
+            order_id: field(ORDER_ID).parse().unwrap_or_default(),
+            contract_id: field(CONTRACT_ID).parse().unwrap_or_default(),
+            symbol: field(SYMBOL).to_owned(),
+            security_type: field(SECURITY_TYPE).to_owned(),
+            currency: field(CURRENCY).to_owned(),
+            side,
+            quantity: field(QUANTITY).parse().map_err(|_| format!("invalid quantity {:?}", field(QUANTITY)))?,
+            order_type,
+            expires,
+            sender,
+        })
+    }
+
+    // Returns the price the order fills at, if the quote reaches it.
+    fn fill_price(&self, quote: &Quote) -> Option<f64> {
+        let buy = self.side > 0.0;
+        match self.order_type {
+            OrderType::Market => Some(quote.open),
+            OrderType::Limit(limit) if buy => (quote.low <= limit).then(|| quote.open.min(limit)),
+            OrderType::Limit(limit) => (quote.high >= limit).then(|| quote.open.max(limit)),
+            OrderType::Stop(stop) if buy => (quote.high >= stop).then(|| quote.open.max(stop)),
+            OrderType::Stop(stop) => (quote.low <= stop).then(|| quote.open.min(stop)),
+        }
+    }
+
+    fn send_status(&self, status: &str, filled: f64, average_price: f64) {
+        let remaining = self.quantity - filled;
+        let message = format!(
+            "3|{}|{status}|{filled}|{remaining}|{average_price}|{}|0|{average_price}|{CLIENT_ID}||0||",
+            self.order_id,
+            self.perm_id()
+        );
+        let _ = self.sender.send(response_message(&message));
+    }
+
+    fn perm_id(&self) -> i32 {
+        1_000_000 + self.order_id
+    }
+}
+
+#[derive(Debug, Default)]
+struct Holding {
+    contract_id: i32,
+    security_type: String,
+    currency: String,
+    position: f64,
+    average_cost: f64,
+    realized_pnl: f64,
+}
+
+impl Holding {
+    // Applies a fill of signed quantity, returning the PnL realized by the shares it closed.
+    fn apply(&mut self, quantity: f64, price: f64) -> f64 {
+        let mut realized = 0.0;
+
+        if self.position == 0.0 || self.position.signum() == quantity.signum() {
+            self.average_cost = (self.position * self.average_cost + quantity * price) / (self.position + quantity);
+        } else {
+            let closed = quantity.abs().min(self.position.abs());
+            realized = closed * (price - self.average_cost) * self.position.signum();
+            // the fill reverses the position, the shares opened cost the fill's price
+            if quantity.abs() > self.position.abs() {
+                self.average_cost = price;
+            }
+        }
+
+        self.position += quantity;
+        if self.position == 0.0 {
+            self.average_cost = 0.0;
+        }
+        realized
+    }
+}
+
+#[cfg(feature = "market_data")]
+#[derive(Debug)]
+struct BarSubscription {
+    symbol: String,
+    request_id: i32,
+    sender: Sender<ResponseMessage>,
+}
+
+#[derive(Debug)]
+struct Broker {
+    clock: VirtualClock,
+    commission: f64,
+    executions: i32,
+    prices: HashMap<String, f64>,
+    orders: Vec<WorkingOrder>,
+    holdings: BTreeMap<String, Holding>,
+    #[cfg(feature = "market_data")]
+    bar_subscriptions: Vec<BarSubscription>,
+}
+
+impl Broker {
+    fn new(clock: VirtualClock) -> Broker {
+        Broker {
+            clock,
+            commission: 0.0,
+            executions: 0,
+            prices: HashMap::new(),
+            orders: Vec::new(),
+            holdings: BTreeMap::new(),
+            #[cfg(feature = "market_data")]
+            bar_subscriptions: Vec::new(),
+        }
+    }
+
+    fn place(&mut self, message: &RequestMessage, sender: Sender<ResponseMessage>) {
+        let order = match WorkingOrder::parse(message, sender.clone()) {
+            Ok(order) => order,
+            Err(reason) => {
+                let order_id = message.fields().get(ORDER_ID).cloned().unwrap_or_default();
+                let _ = sender.send(response_message(&format!("4|2|{order_id}|321|Error validating request: {reason}|")));
+                return;
+            }
+        };
+
+        // orders modified are replaced
+        self.orders.retain(|working| working.order_id != order.order_id);
+
+        order.send_status("PreSubmitted", 0.0, 0.0);
+
+        let fill_price = self.prices.get(&order.symbol).and_then(|&price| {
+            order.fill_price(&Quote {
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+            })
+        });
+        match fill_price {
+            Some(price) => self.fill(order, price),
+            None => {
+                order.send_status("Submitted", 0.0, 0.0);
+                self.orders.push(order);
+            }
+        }
+    }
+
+    fn cancel(&mut self, order_id: i32, sender: &Sender<ResponseMessage>) {
+        let Some(i) = self.orders.iter().position(|order| order.order_id == order_id) else {
+            let _ = sender.send(response_message(&format!(
+                "4|2|{order_id}|10147|OrderId {order_id} that needs to be cancelled is not found.|"
+            )));
+            return;
+        };
+
+        let order = self.orders.remove(i);
+        order.send_status("Cancelled", 0.0, 0.0);
+
+        let cancelled = WorkingOrder {
+            sender: sender.clone(),
+            ..order
+        };
+        cancelled.send_status("Cancelled", 0.0, 0.0);
+        let _ = sender.send(response_message(&format!("4|2|{order_id}|202|Order Canceled - reason:|")));
+    }
+
+    fn cancel_all(&mut self) {
+        for order in self.orders.drain(..) {
+            order.send_status("Cancelled", 0.0, 0.0);
+        }
+    }
+
+    // Cancels the orders whose good till date passed.
+    fn expire(&mut self) {
+        let now = self.clock.now();
+        let (expired, resting): (Vec<WorkingOrder>, Vec<WorkingOrder>) = self
+            .orders
+            .drain(..)
+            .partition(|order| order.expires.is_some_and(|expires| expires <= now));
+        self.orders = resting;
+
+        for order in expired {
+            order.send_status("Cancelled", 0.0, 0.0);
+        }
+    }
+
+    // Sets the price of the symbol, and fills the orders the quote reaches.
+    fn update(&mut self, symbol: &str, quote: &Quote) {
+        self.expire();
+        self.prices.insert(symbol.to_owned(), quote.close);
+
+        let (filled, resting): (Vec<WorkingOrder>, Vec<WorkingOrder>) = self
+            .orders
+            .drain(..)
+            .partition(|order| order.symbol == symbol && order.fill_price(quote).is_some());
+        self.orders = resting;
+
+        for order in filled {
+            if let Some(price) = order.fill_price(quote) {
+                self.fill(order, price);
+            }
+        }
+    }
+
+    // Fills the order in full, reporting the execution, its commission and the order's status.
+    fn fill(&mut self, order: WorkingOrder, price: f64) {
+        self.executions += 1;
+
+        let holding = self.holdings.entry(order.symbol.clone()).or_insert_with(|| Holding {
+            contract_id: order.contract_id,
+            security_type: order.security_type.clone(),
+            currency: order.currency.clone(),
+            ..Holding::default()
+        });
+        let commission = self.commission * order.quantity;
+        let realized = holding.apply(order.side * order.quantity, price);
+        holding.realized_pnl += realized - commission;
+
+        let execution_id = format!("0000sim.{:08x}.01.01", self.executions);
+        let side = if order.side > 0.0 { "BOT" } else { "SLD" };
+        let time = self
+            .clock
+            .now()
+            .format(format_description!("[year][month][day]  [hour]:[minute]:[second]"))
+            .unwrap_or_default();
+        let execution = format!(
+            "11|-1|{}|{}|{}|{}||0.0|||SIM|{}|{}||{execution_id}|{time}|{ACCOUNT}|SIM|{side}|{}|{price}|{}|{CLIENT_ID}|0|{}|{price}|||||2||",
+            order.order_id,
+            order.contract_id,
+            order.symbol,
+            order.security_type,
+            order.currency,
+            order.symbol,
+            order.quantity,
+            order.perm_id(),
+            order.quantity,
+        );
+        let realized = if realized != 0.0 {
+            (realized - commission).to_string()
+        } else {
+            UNSET_DOUBLE.to_owned()
+        };
+        let commission_report = format!(
+            "59|1|{execution_id}|{commission}|{}|{realized}|{UNSET_DOUBLE}|{UNSET_DOUBLE}||",
+            order.currency
+        );
+
+        let _ = order.sender.send(response_message(&execution));
+        let _ = order.sender.send(response_message(&commission_report));
+        order.send_status("Filled", order.quantity, price);
+        // the order's notifications end once it's filled
+    }
+
+    fn positions(&self) -> Vec<String> {
+        let mut messages: Vec<String> = self
+            .holdings
+            .iter()
+            .map(|(symbol, holding)| {
+                format!(
+                    "61|3|{ACCOUNT}|{}|{symbol}|{}||0||||{}|{symbol}||{}|{}|",
+                    holding.contract_id, holding.security_type, holding.currency, holding.position, holding.average_cost
+                )
+            })
+            .collect();
+        messages.push("62|1|".into());
+        messages
+    }
+}
+
+// Parses a good till date, e.g. 20230415 16:30:00 US/Eastern, in UTC when without a time zone.
+fn parse_good_till_date(good_till_date: &str) -> Option<OffsetDateTime> {
+    let mut parts = good_till_date.splitn(3, ' ');
+    let date_time = format!("{} {}", parts.next()?, parts.next()?);
+    let date_time = PrimitiveDateTime::parse(&date_time, format_description!("[year][month][day] [hour]:[minute]:[second]")).ok()?;
+
+    match parts.next() {
+        Some(time_zone) => match date_time.assume_timezone(timezones::get_by_name(time_zone)?) {
+            OffsetResult::Some(date_time) => Some(date_time),
+            _ => None,
+        },
+        None => Some(date_time.assume_utc()),
+    }
+}
+
+// Answers order, position and realtime bars requests from the broker, and other requests as the stub does.
+#[derive(Debug)]
+struct SimulatedMessageBus {
+    stub: StubMessageBus,
+    broker: Arc<Mutex<Broker>>,
+}
+
+impl SimulatedMessageBus {
+    fn order_message(&mut self, order_id: i32, message: &RequestMessage) -> ResponseIterator {
+        let (sender, receiver) = channel::unbounded();
+
+        let mut broker = self.broker.lock().unwrap();
+        match message_type(message) {
+            Some(OutgoingMessages::PlaceOrder) => broker.place(message, sender),
+            Some(OutgoingMessages::CancelOrder) => broker.cancel(order_id, &sender),
+            Some(OutgoingMessages::RequestGlobalCancel) => broker.cancel_all(),
+            _ => (),
+        }
+
+        ResponseIterator::new(receiver, self.stub.signals.0.clone(), None, Some(order_id), None)
+    }
+
+    // Subscribes to the bars pushed for the symbol requested. See SimulatedClient::push_bar.
+    #[cfg(feature = "market_data")]
+    fn subscribe_bars(&mut self, request_id: i32, message: &RequestMessage) -> ResponseIterator {
+        let (sender, receiver) = channel::unbounded();
+        self.broker.lock().unwrap().bar_subscriptions.push(BarSubscription {
+            symbol: message.fields().get(BARS_SYMBOL).cloned().unwrap_or_default(),
+            request_id,
+            sender,
+        });
+        ResponseIterator::new(receiver, self.stub.signals.0.clone(), Some(request_id), None, None)
+    }
+}
+
+fn message_type(message: &RequestMessage) -> Option<OutgoingMessages> {
+    let message_id = message.fields().first()?.parse::<i32>().ok()?;
+    [
+        OutgoingMessages::PlaceOrder,
+        OutgoingMessages::CancelOrder,
+        OutgoingMessages::RequestGlobalCancel,
+        OutgoingMessages::RequestRealTimeBars,
+    ]
+    .into_iter()
+    .find(|message_type| *message_type as i32 == message_id)
+}
+
+impl MessageBus for SimulatedMessageBus {
+    fn read_message(&mut self) -> Result<ResponseMessage, Error> {
+        self.stub.read_message()
+    }
+
+    fn write_message(&mut self, message: &RequestMessage) -> Result<(), Error> {
+        self.stub.write_message(message)
+    }
+
+    fn send_generic_message(&mut self, request_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        self.stub.send_generic_message(request_id, message)
+    }
+
+    fn send_durable_message(&mut self, request_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        #[cfg(feature = "market_data")]
+        if matches!(message_type(message), Some(OutgoingMessages::RequestRealTimeBars)) {
+            return Ok(self.subscribe_bars(request_id, message));
+        }
+
+        self.stub.send_durable_message(request_id, message)
+    }
+
+    fn send_order_message(&mut self, order_id: i32, message: &RequestMessage) -> Result<ResponseIterator, Error> {
+        Ok(self.order_message(order_id, message))
+    }
+
+    fn request_next_order_id(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.stub.request_next_order_id(message)
+    }
+
+    fn request_open_orders(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.stub.request_open_orders(message)
+    }
+
+    fn request_market_rule(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.stub.request_market_rule(message)
+    }
+
+    fn request_positions(&mut self, _message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        let (sender, receiver) = channel::unbounded();
+        for position in self.broker.lock().unwrap().positions() {
+            sender.send(response_message(&position)).unwrap();
+        }
+        Ok(GlobalResponseIterator::new(Arc::new(receiver)))
+    }
+
+    fn request_family_codes(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.stub.request_family_codes(message)
+    }
+
+    fn request_market_depth_exchanges(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.stub.request_market_depth_exchanges(message)
+    }
+
+    fn request_current_time(&mut self, message: &RequestMessage) -> Result<GlobalResponseIterator, Error> {
+        self.stub.request_current_time(message)
+    }
+
+    fn write(&mut self, packet: &str) -> Result<(), Error> {
+        self.stub.write(packet)
+    }
+
+    fn start_api(&mut self, message: &RequestMessage) -> Result<(), Error> {
+        self.stub.start_api(message)
+    }
+
+    fn process_messages(&mut self, server_version: i32) -> Result<(), Error> {
+        self.stub.process_messages(server_version)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.stub.flush()
+    }
+
+    fn set_parse_mode(&mut self, parse_mode: ParseMode) {
+        self.stub.set_parse_mode(parse_mode)
+    }
+
+    fn set_subscription_buffer(&mut self, subscription_buffer: Option<SubscriptionBuffer>) {
+        self.stub.set_subscription_buffer(subscription_buffer)
+    }
+
+    fn set_metrics_observer(&mut self, observer: Option<Arc<dyn MetricsObserver>>) {
+        self.stub.set_metrics_observer(observer)
+    }
+
+    fn track_next_order_id(&mut self, next_order_id: Arc<AtomicI32>) {
+        self.stub.track_next_order_id(next_order_id)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.stub.set_read_timeout(timeout)
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.stub.shutdown()
+    }
+
+    fn start_heartbeat(&mut self, message: &RequestMessage, interval: Duration, deadline: Duration) -> Result<(), Error> {
+        self.stub.start_heartbeat(message, interval, deadline)
+    }
+
+    fn connection_events(&mut self) -> Receiver<ConnectionEvent> {
+        self.stub.connection_events()
+    }
+
+    fn error_events(&mut self) -> Receiver<ErrorEvent> {
+        self.stub.error_events()
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.stub.is_healthy()
+    }
+
+    fn disconnect(&mut self) -> Result<(), Error> {
+        self.stub.disconnect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::Contract;
+    use crate::orders::{order_builder, Action, OrderNotification};
+
+    fn statuses(notifications: impl Iterator<Item = OrderNotification>) -> Vec<String> {
+        notifications
+            .filter_map(|notification| match notification {
+                OrderNotification::OrderStatus(status) => Some(status.status),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn market_order_fills_at_current_price() {
+        let client = SimulatedClient::new().unwrap();
+        client.set_commission(0.01);
+        client.set_price("AAPL", 185.0);
+
+        let order = order_builder::market_order(Action::Buy, 100.0);
+        let notifications: Vec<OrderNotification> = client.place_order(1, &Contract::stock("AAPL"), &order).unwrap().collect();
+
+        assert!(
+            matches!(&notifications[1], OrderNotification::ExecutionData(data) if data.execution.price == 185.0 && data.execution.side == "BOT"),
+            "{notifications:?}"
+        );
+        assert!(
+            matches!(&notifications[2], OrderNotification::CommissionReport(report) if report.commission == 1.0),
+            "{notifications:?}"
+        );
+        assert!(
+            matches!(&notifications[3], OrderNotification::OrderStatus(status) if status.status == "Filled" && status.average_fill_price == 185.0),
+            "{notifications:?}"
+        );
+
+        client.set_price("AAPL", 190.0);
+        let portfolio = client.portfolio();
+        assert_eq!(portfolio[0].position, 100.0);
+        assert_eq!(portfolio[0].average_cost, 185.0);
+        assert_eq!(portfolio[0].unrealized_pnl, 500.0);
+        assert_eq!(portfolio[0].realized_pnl, -1.0);
+    }
+
+    #[test]
+    fn limit_order_rests_until_price_reaches_it() {
+        let client = SimulatedClient::new().unwrap();
+        client.set_price("TSLA", 200.0);
+
+        let order = order_builder::limit_order(Action::Sell, 10.0, 210.0);
+        let notifications = client.place_order(1, &Contract::stock("TSLA"), &order).unwrap();
+
+        client.set_price("TSLA", 205.0);
+        client.set_price("TSLA", 212.0);
+
+        assert_eq!(statuses(notifications), ["PreSubmitted", "Submitted", "Filled"]);
+
+        let portfolio = client.portfolio();
+        assert_eq!(portfolio[0].position, -10.0);
+        assert_eq!(portfolio[0].average_cost, 212.0, "filled at a better price than the limit");
+
+        // buying back closes the short position
+        client.set_price("TSLA", 215.0);
+        let order = order_builder::market_order(Action::Buy, 10.0);
+        assert_eq!(
+            statuses(client.place_order(2, &Contract::stock("TSLA"), &order).unwrap()),
+            ["PreSubmitted", "Filled"]
+        );
+        assert_eq!(client.portfolio()[0].position, 0.0);
+        assert_eq!(client.portfolio()[0].realized_pnl, -30.0);
+    }
+
+    #[test]
+    fn cancel_order() {
+        let client = SimulatedClient::new().unwrap();
+
+        let order = order_builder::limit_order(Action::Buy, 10.0, 100.0);
+        let notifications = client.place_order(1, &Contract::stock("TSLA"), &order).unwrap();
+        client.cancel_order(1, "").unwrap().for_each(drop);
+
+        assert_eq!(statuses(notifications), ["PreSubmitted", "Submitted", "Cancelled"]);
+        assert!(client.portfolio().is_empty());
+    }
+
+    #[test]
+    fn unsupported_order_type() {
+        let client = SimulatedClient::new().unwrap();
+
+        let order = order_builder::trailing_stop(Action::Sell, 10.0, 1.0, 100.0);
+        let notifications: Vec<OrderNotification> = client.place_order(1, &Contract::stock("TSLA"), &order).unwrap().collect();
+
+        assert!(matches!(&notifications[..], [OrderNotification::Message(_)]), "{notifications:?}");
+    }
+
+    #[test]
+    #[cfg(feature = "accounts")]
+    fn positions() {
+        let client = SimulatedClient::new().unwrap();
+        client.set_price("AAPL", 185.0);
+
+        let order = order_builder::market_order(Action::Buy, 100.0);
+        client.place_order(1, &Contract::stock("AAPL"), &order).unwrap().for_each(drop);
+
+        let positions: Vec<_> = client.positions().unwrap().collect();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].contract.symbol, "AAPL");
+        assert_eq!(positions[0].position, 100.0);
+        assert_eq!(positions[0].average_cost, 185.0);
+    }
+
+    #[test]
+    #[cfg(feature = "market_data")]
+    fn replayed_bars() {
+        use crate::market_data::realtime::{BarSize, WhatToShow};
+        use time::macros::datetime;
+
+        let client = SimulatedClient::new().unwrap();
+
+        let mut bars = client
+            .realtime_bars(&Contract::stock("AAPL"), BarSize::Sec5, WhatToShow::Trades, false)
+            .unwrap();
+        let order = order_builder::limit_order(Action::Buy, 10.0, 184.0);
+        let notifications = client.place_order(1, &Contract::stock("AAPL"), &order).unwrap();
+
+        let bar = Bar {
+            date: datetime!(2023-04-15 16:31:20 UTC),
+            open: 185.0,
+            high: 185.5,
+            low: 183.5,
+            close: 184.5,
+            volume: 100.0,
+            wap: 184.6,
+            count: 5,
+        };
+        client.push_bar("AAPL", &bar);
+
+        let published = bars.next().unwrap();
+        assert_eq!((published.date, published.low, published.count), (bar.date, bar.low, bar.count));
+        assert_eq!(statuses(notifications), ["PreSubmitted", "Submitted", "Filled"]);
+        assert_eq!(client.portfolio()[0].average_cost, 184.0);
+        assert_eq!(client.portfolio()[0].unrealized_pnl, 5.0);
+    }
+
+    #[test]
+    #[cfg(feature = "market_data")]
+    fn good_till_date_orders_expire() {
+        use time::macros::datetime;
+
+        let client = SimulatedClient::new().unwrap();
+
+        let mut order = order_builder::limit_order(Action::Buy, 10.0, 100.0);
+        order.tif = "GTD".into();
+        order.good_till_date = "20230415 12:31:00 US/Eastern".into();
+        let notifications = client.place_order(1, &Contract::stock("AAPL"), &order).unwrap();
+
+        let mut bar = Bar {
+            date: datetime!(2023-04-15 16:30:55 UTC),
+            open: 101.0,
+            high: 101.0,
+            low: 101.0,
+            close: 101.0,
+            volume: 100.0,
+            wap: 101.0,
+            count: 5,
+        };
+        client.push_bar("AAPL", &bar);
+
+        // expires before the bar reaching the limit price
+        bar.date = datetime!(2023-04-15 16:31:00 UTC);
+        bar.low = 99.0;
+        client.push_bar("AAPL", &bar);
+
+        assert_eq!(client.now(), datetime!(2023-04-15 16:31:00 UTC));
+        assert_eq!(statuses(notifications), ["PreSubmitted", "Submitted", "Cancelled"]);
+        assert!(client.portfolio().is_empty());
+    }
+}